@@ -127,7 +127,7 @@ impl<'a, T: 'static + Copy + Debug + Into<LhsValue<'static>>> FieldBench<'a, T>
                         move |b: &mut Bencher, value: &T| {
                             let filter = scheme.parse(filter).unwrap();
 
-                            let filter = filter.compile();
+                            let filter = filter.compile().unwrap();
 
                             let mut exec_ctx = ExecutionContext::new(&scheme);
                             exec_ctx.set_field_value(field, *value).unwrap();
@@ -214,6 +214,7 @@ fn bench_string_function_comparison(c: &mut Criterion) {
                     params: vec![FunctionParam {
                         arg_kind: FunctionArgKind::Field,
                         val_type: Type::Bytes,
+                        literal_check: None,
                     }],
                     opt_params: vec![],
                     return_type: Type::Bytes,
@@ -226,6 +227,7 @@ fn bench_string_function_comparison(c: &mut Criterion) {
                     params: vec![FunctionParam {
                         arg_kind: FunctionArgKind::Field,
                         val_type: Type::Bytes,
+                        literal_check: None,
                     }],
                     opt_params: vec![],
                     return_type: Type::Bytes,
@@ -27,11 +27,9 @@ fn main() {
                 params: vec![FunctionParam {
                     arg_kind: FunctionArgKind::Field,
                     val_type: Type::Bytes,
+                    literal_check: None,
                 }],
-                opt_params: vec![FunctionOptParam {
-                    arg_kind: FunctionArgKind::Literal,
-                    default_value: "".into(),
-                }],
+                opt_params: vec![FunctionOptParam::new(FunctionArgKind::Literal, "")],
                 return_type: Type::Bytes,
                 implementation: FunctionImpl::new(panic_function),
             },
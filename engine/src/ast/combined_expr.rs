@@ -1,23 +1,36 @@
-use super::{simple_expr::SimpleExpr, Expr};
+use super::{simple_expr::SimpleExpr, Expr, ParseState};
 use crate::{
-    filter::CompiledExpr,
+    filter::{CompileContext, CompiledExpr},
     lex::{skip_space, Lex, LexResult, LexWith},
     scheme::{Field, Scheme},
 };
 use serde::Serialize;
 
-lex_enum!(#[derive(PartialOrd, Ord)] CombiningOp {
-    "or" | "||" => Or,
-    "xor" | "^^" => Xor,
-    "and" | "&&" => And,
-});
+lex_enum!(
+    /// A logical operator combining several expressions.
+    ///
+    /// Variants are ordered by their precedence, from lowest to highest.
+    #[derive(PartialOrd, Ord)] CombiningOp {
+        /// `or` / `||`
+        "or" | "||" => Or,
+        /// `xor` / `^^`
+        "xor" | "^^" => Xor,
+        /// `and` / `&&`
+        "and" | "&&" => And,
+    }
+);
 
+/// A top-level expression, possibly combining several simple expressions.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 #[serde(untagged)]
 pub enum CombinedExpr<'s> {
+    /// A single expression.
     Simple(SimpleExpr<'s>),
+    /// Two or more expressions combined with the same operator.
     Combining {
+        /// Operator combining the items.
         op: CombiningOp,
+        /// Expressions being combined.
         items: Vec<CombinedExpr<'s>>,
     },
 }
@@ -32,14 +45,14 @@ impl<'s> CombinedExpr<'s> {
 
     fn lex_more_with_precedence<'i>(
         self,
-        scheme: &'s Scheme,
+        state: &ParseState<'i, 's>,
         min_prec: Option<CombiningOp>,
         mut lookahead: (Option<CombiningOp>, &'i str),
     ) -> LexResult<'i, Self> {
         let mut lhs = self;
 
         while let Some(op) = lookahead.0 {
-            let mut rhs = SimpleExpr::lex_with(lookahead.1, scheme)
+            let mut rhs = SimpleExpr::lex_with_state(lookahead.1, state)
                 .map(|(op, input)| (CombinedExpr::Simple(op), input))?;
 
             loop {
@@ -49,7 +62,7 @@ impl<'s> CombinedExpr<'s> {
                 }
                 rhs = rhs
                     .0
-                    .lex_more_with_precedence(scheme, lookahead.0, lookahead)?;
+                    .lex_more_with_precedence(state, lookahead.0, lookahead)?;
             }
 
             match lhs {
@@ -76,13 +89,21 @@ impl<'s> CombinedExpr<'s> {
 
         Ok((lhs, lookahead.1))
     }
+
+    /// Lexes an expression sharing the state of the filter being parsed.
+    pub(crate) fn lex_with_state<'i>(
+        input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> LexResult<'i, Self> {
+        let (lhs, input) = SimpleExpr::lex_with_state(input, state)?;
+        let lookahead = Self::lex_combining_op(input);
+        CombinedExpr::Simple(lhs).lex_more_with_precedence(state, None, lookahead)
+    }
 }
 
 impl<'i, 's> LexWith<'i, &'s Scheme> for CombinedExpr<'s> {
     fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
-        let (lhs, input) = SimpleExpr::lex_with(input, scheme)?;
-        let lookahead = Self::lex_combining_op(input);
-        CombinedExpr::Simple(lhs).lex_more_with_precedence(scheme, None, lookahead)
+        Self::lex_with_state(input, &ParseState::new(scheme))
     }
 }
 
@@ -94,32 +115,74 @@ impl<'s> Expr<'s> for CombinedExpr<'s> {
         }
     }
 
-    fn compile(self) -> CompiledExpr<'s> {
+    fn compile_in(self, cx: &mut CompileContext<'s>) -> CompiledExpr<'s> {
         match self {
-            CombinedExpr::Simple(op) => op.compile(),
+            CombinedExpr::Simple(op) => op.compile_in(cx),
             CombinedExpr::Combining { op, items } => {
                 let items = items
                     .into_iter()
-                    .map(Expr::compile)
+                    .map(|item| item.compile_in(cx))
                     .collect::<Vec<_>>()
                     .into_boxed_slice();
 
                 match op {
-                    CombiningOp::And => {
-                        CompiledExpr::new(move |ctx| items.iter().all(|item| item.execute(ctx)))
-                    }
-                    CombiningOp::Or => {
-                        CompiledExpr::new(move |ctx| items.iter().any(|item| item.execute(ctx)))
-                    }
-                    CombiningOp::Xor => CompiledExpr::new(move |ctx| {
+                    CombiningOp::And => CompiledExpr::new(move |ctx, scope| {
+                        items.iter().all(|item| item.execute_in(ctx, scope))
+                    }),
+                    CombiningOp::Or => CompiledExpr::new(move |ctx, scope| {
+                        items.iter().any(|item| item.execute_in(ctx, scope))
+                    }),
+                    CombiningOp::Xor => CompiledExpr::new(move |ctx, scope| {
                         items
                             .iter()
-                            .fold(false, |acc, item| acc ^ item.execute(ctx))
+                            .fold(false, |acc, item| acc ^ item.execute_in(ctx, scope))
                     }),
                 }
             }
         }
     }
+
+    fn compile_three_valued_in(
+        self,
+        cx: &mut CompileContext<'s>,
+    ) -> CompiledExpr<'s, Option<bool>> {
+        match self {
+            CombinedExpr::Simple(op) => op.compile_three_valued_in(cx),
+            CombinedExpr::Combining { op, items } => {
+                let items = items
+                    .into_iter()
+                    .map(|item| item.compile_three_valued_in(cx))
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice();
+
+                // `and` and `or` are decided by a single `false` or `true`
+                // operand respectively regardless of unknown ones.
+                let short_circuit = match op {
+                    CombiningOp::And => false,
+                    CombiningOp::Or => true,
+                    CombiningOp::Xor => {
+                        return CompiledExpr::new(move |ctx, scope| {
+                            items.iter().try_fold(false, |acc, item| {
+                                Some(acc ^ item.execute_in(ctx, scope)?)
+                            })
+                        });
+                    }
+                };
+
+                CompiledExpr::new(move |ctx, scope| {
+                    let mut result = Some(!short_circuit);
+                    for item in items.iter() {
+                        match item.execute_in(ctx, scope) {
+                            Some(value) if value == short_circuit => return Some(value),
+                            Some(_) => {}
+                            None => result = None,
+                        }
+                    }
+                    result
+                })
+            }
+        }
+    }
 }
 
 #[test]
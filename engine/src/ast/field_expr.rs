@@ -1,35 +1,60 @@
 // use crate::filter::CompiledExpr;
-use super::{function_expr::FunctionCallExpr, Expr};
+use super::{
+    function_expr::{starts_with_call, FunctionCallExpr},
+    visitor::{walk_field_expr, Visitor},
+    Expr, ParseState,
+};
 use crate::{
-    filter::CompiledExpr,
+    bindings::Placeholder,
+    execution_context::ExecutionContext,
+    filter::{CompileContext, CompileError, CompiledExpr, ExecutionScope},
     heap_searcher::HeapSearcher,
-    lex::{skip_space, span, Lex, LexErrorKind, LexResult, LexWith},
-    range_set::RangeSet,
-    rhs_types::{Bytes, ExplicitIpRange, Regex},
-    scheme::{Field, Scheme},
+    lex::{expect, skip_space, span, take_while, Lex, LexErrorKind, LexResult, LexWith},
+    rhs_types::{
+        canonicalize_ip_ranges, unmap_ipv4, unmap_ipv4_ranges, Bytes, IntLiteral, Regex, StrLiteral,
+    },
+    scheme::{Field, List, Scheme},
     strict_partial_ord::StrictPartialOrd,
-    types::{GetType, LhsValue, RhsValue, RhsValues, Type},
+    types::{GetType, LhsValue, RhsValue, RhsValues, Type, TypeMismatchError},
+    value_set::ValueSet,
 };
-use fnv::FnvBuildHasher;
-use indexmap::IndexSet;
+use aho_corasick::AhoCorasick;
+use fnv::FnvHasher;
 use memmem::Searcher;
 use serde::{Serialize, Serializer};
-use std::{cmp::Ordering, net::IpAddr};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    hash::Hasher,
+    str,
+};
 
 const LESS: u8 = 0b001;
 const GREATER: u8 = 0b010;
 const EQUAL: u8 = 0b100;
 
-lex_enum!(#[repr(u8)] OrderingOp {
-    "eq" | "==" => Equal = EQUAL,
-    "ne" | "!=" => NotEqual = LESS | GREATER,
-    "ge" | ">=" => GreaterThanEqual = GREATER | EQUAL,
-    "le" | "<=" => LessThanEqual = LESS | EQUAL,
-    "gt" | ">" => GreaterThan = GREATER,
-    "lt" | "<" => LessThan = LESS,
-});
+lex_enum!(
+    /// A comparison operator.
+    #[repr(u8)] OrderingOp {
+        /// `eq` / `==`
+        "eq" | "==" => Equal = EQUAL,
+        /// `ne` / `!=`
+        "ne" | "!=" => NotEqual = LESS | GREATER,
+        /// `ge` / `>=`
+        "ge" | ">=" => GreaterThanEqual = GREATER | EQUAL,
+        /// `le` / `<=`
+        "le" | "<=" => LessThanEqual = LESS | EQUAL,
+        /// `gt` / `>`
+        "gt" | ">" => GreaterThan = GREATER,
+        /// `lt` / `<`
+        "lt" | "<" => LessThan = LESS,
+    }
+);
 
 impl OrderingOp {
+    /// Checks whether the operator holds for the given ordering of LHS
+    /// relative to RHS.
     pub fn matches(self, ordering: Ordering) -> bool {
         let mask = self as u8;
         let flag = match ordering {
@@ -40,6 +65,8 @@ impl OrderingOp {
         mask & flag != 0
     }
 
+    /// Same as [`OrderingOp::matches`], but treats incomparable values as
+    /// unequal.
     pub fn matches_opt(self, ordering: Option<Ordering>) -> bool {
         match ordering {
             Some(ordering) => self.matches(ordering),
@@ -49,13 +76,35 @@ impl OrderingOp {
     }
 }
 
-lex_enum!(IntOp {
-    "&" | "bitwise_and" => BitwiseAnd,
-});
+lex_enum!(
+    /// An operator specific to integers.
+    IntOp {
+        /// `&` / `bitwise_and`
+        "&" | "bitwise_and" => BitwiseAnd,
+        /// `|` / `bitwise_or`
+        "|" | "bitwise_or" => BitwiseOr,
+        /// `^` / `bitwise_xor`
+        "^" | "bitwise_xor" => BitwiseXor,
+    }
+);
+
+impl IntOp {
+    /// Applies the operator to the given operands.
+    pub fn apply(self, lhs: i32, rhs: i32) -> i32 {
+        match self {
+            IntOp::BitwiseAnd => lhs & rhs,
+            IntOp::BitwiseOr => lhs | rhs,
+            IntOp::BitwiseXor => lhs ^ rhs,
+        }
+    }
+}
 
 lex_enum!(BytesOp {
     "contains" => Contains,
     "~" | "matches" => Matches,
+    "ieq" => CaseInsensitiveEqual,
+    "startswith" => StartsWith,
+    "endswith" => EndsWith,
 });
 
 lex_enum!(ComparisonOp {
@@ -65,33 +114,98 @@ lex_enum!(ComparisonOp {
     BytesOp => Bytes,
 });
 
+/// An operation performed on the LHS of a [`FieldExpr`].
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 #[serde(untagged)]
-enum FieldOp {
+pub enum FieldOp<'s> {
+    /// A check of a boolean LHS.
     #[serde(serialize_with = "serialize_is_true")]
     IsTrue,
 
+    /// A comparison against a value of the same type.
     Ordering {
+        /// Comparison operator.
         op: OrderingOp,
+        /// Value to compare with.
         rhs: RhsValue,
     },
 
+    /// A comparison against another field of the same type, like
+    /// `ip.src == ip.dst`.
+    FieldOrdering {
+        /// Comparison operator.
+        op: OrderingOp,
+        /// Field to compare with.
+        rhs_field: Field<'s>,
+    },
+
+    /// A comparison against a placeholder of the same type, like
+    /// `http.host == ${host:Bytes}`, which has to be bound before
+    /// compilation.
+    PlaceholderOrdering {
+        /// Comparison operator.
+        op: OrderingOp,
+        /// Placeholder to compare with.
+        placeholder: Placeholder,
+    },
+
+    /// An integer operation, which holds if its result is not zero.
     Int {
+        /// Integer operator.
+        op: IntOp,
+        /// Integer operand.
+        rhs: IntLiteral,
+    },
+
+    /// An integer operation followed by a comparison of its result, like
+    /// `tcp.flags & 0x12 == 0x12`.
+    IntOrdering {
+        /// Integer operator.
         op: IntOp,
-        rhs: i32,
+        /// Integer operand.
+        rhs: IntLiteral,
+        /// Comparison operator applied to the result.
+        ordering: OrderingOp,
+        /// Value to compare the result with.
+        value: IntLiteral,
     },
 
+    /// `contains` with a byte string.
     #[serde(serialize_with = "serialize_contains")]
     Contains(Bytes),
 
+    /// `contains any` with a list of byte strings.
+    #[serde(serialize_with = "serialize_contains_any")]
+    ContainsAny(Vec<Bytes>),
+
+    /// `startswith` with a byte string.
+    #[serde(serialize_with = "serialize_starts_with")]
+    StartsWith(Bytes),
+
+    /// `endswith` with a byte string.
+    #[serde(serialize_with = "serialize_ends_with")]
+    EndsWith(Bytes),
+
+    /// `matches` with a regular expression.
     #[serde(serialize_with = "serialize_matches")]
     Matches(Regex),
 
+    /// `ieq` with a byte string, which ignores ASCII case.
+    #[serde(serialize_with = "serialize_case_insensitive_equal")]
+    CaseInsensitiveEqual(Bytes),
+
+    /// `in` with a list of values.
     #[serde(serialize_with = "serialize_one_of")]
     OneOf(RhsValues),
+
+    /// `in` with a named list registered in the scheme, like
+    /// `http.host in $malicious_domains`, whose values are set on the
+    /// execution context.
+    #[serde(serialize_with = "serialize_in_list")]
+    InList(List<'s>),
 }
 
-fn serialize_op_rhs<T: Serialize, S: Serializer>(
+fn serialize_op_rhs<T: Serialize + ?Sized, S: Serializer>(
     op: &'static str,
     rhs: &T,
     ser: S,
@@ -116,135 +230,631 @@ fn serialize_contains<S: Serializer>(rhs: &Bytes, ser: S) -> Result<S::Ok, S::Er
     serialize_op_rhs("Contains", rhs, ser)
 }
 
+fn serialize_contains_any<S: Serializer>(rhs: &[Bytes], ser: S) -> Result<S::Ok, S::Error> {
+    serialize_op_rhs("ContainsAny", rhs, ser)
+}
+
+fn serialize_starts_with<S: Serializer>(rhs: &Bytes, ser: S) -> Result<S::Ok, S::Error> {
+    serialize_op_rhs("StartsWith", rhs, ser)
+}
+
+fn serialize_ends_with<S: Serializer>(rhs: &Bytes, ser: S) -> Result<S::Ok, S::Error> {
+    serialize_op_rhs("EndsWith", rhs, ser)
+}
+
 fn serialize_matches<S: Serializer>(rhs: &Regex, ser: S) -> Result<S::Ok, S::Error> {
     serialize_op_rhs("Matches", rhs, ser)
 }
 
+fn serialize_case_insensitive_equal<S: Serializer>(rhs: &Bytes, ser: S) -> Result<S::Ok, S::Error> {
+    serialize_op_rhs("CaseInsensitiveEqual", rhs, ser)
+}
+
 fn serialize_one_of<S: Serializer>(rhs: &RhsValues, ser: S) -> Result<S::Ok, S::Error> {
     serialize_op_rhs("OneOf", rhs, ser)
 }
 
+fn serialize_in_list<S: Serializer>(rhs: &List<'_>, ser: S) -> Result<S::Ok, S::Error> {
+    serialize_op_rhs("InList", rhs, ser)
+}
+
+/// A byte range of a field value, like `tcp.payload[0..4]`.
+///
+/// Bounds are byte offsets and the end is exclusive. Ranges that extend past
+/// the end of the value are truncated to it.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct SliceExpr<'s> {
+    pub(crate) field: Field<'s>,
+    pub(crate) start: usize,
+    pub(crate) end: Option<usize>,
+}
+
+impl<'s> SliceExpr<'s> {
+    /// The sliced field.
+    pub fn field(&self) -> Field<'s> {
+        self.field
+    }
+
+    /// Offset of the first byte of the slice.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Offset after the last byte of the slice, or `None` if the slice
+    /// extends to the end of the value.
+    pub fn end(&self) -> Option<usize> {
+        self.end
+    }
+
+    fn slice<'a>(&self, value: LhsValue<'a>) -> LhsValue<'a> {
+        let bytes = match value {
+            LhsValue::Bytes(bytes) => bytes,
+            _ => unreachable!(),
+        };
+
+        let end = self.end.map_or(bytes.len(), |end| end.min(bytes.len()));
+        let start = self.start.min(end);
+
+        LhsValue::Bytes(match bytes {
+            Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[start..end]),
+            Cow::Owned(bytes) => Cow::Owned(bytes[start..end].to_vec()),
+        })
+    }
+
+    fn lex_range(input: &str) -> LexResult<'_, (usize, Option<usize>)> {
+        fn lex_offset(input: &str) -> LexResult<'_, Option<usize>> {
+            match take_while(input, "digit", |c| c.is_ascii_digit()) {
+                Ok((digits, rest)) => match digits.parse() {
+                    Ok(offset) => Ok((Some(offset), rest)),
+                    Err(err) => Err((LexErrorKind::ParseInt { err, radix: 10 }, digits)),
+                },
+                Err(_) => Ok((None, input)),
+            }
+        }
+
+        let initial_input = input;
+        let (start, input) = lex_offset(skip_space(input))?;
+        let input = expect(skip_space(input), "..")?;
+        let (end, input) = lex_offset(skip_space(input))?;
+        let start = start.unwrap_or(0);
+
+        if end.is_some_and(|end| end < start) {
+            return Err((
+                LexErrorKind::IncompatibleRangeBounds,
+                span(initial_input, input),
+            ));
+        }
+
+        let input = expect(skip_space(input), "]")?;
+        Ok(((start, end), input))
+    }
+}
+
+/// The left-hand side of a [`FieldExpr`].
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 #[serde(untagged)]
-pub(crate) enum LhsFieldExpr<'s> {
+pub enum LhsFieldExpr<'s> {
+    /// A field from the scheme.
     Field(Field<'s>),
+    /// A byte range of a field from the scheme.
+    Slice(SliceExpr<'s>),
+    /// A call of a function from the scheme.
     FunctionCallExpr(FunctionCallExpr<'s>),
 }
 
 impl<'s> LhsFieldExpr<'s> {
-    pub fn uses(&self, field: Field<'s>) -> bool {
+    pub(crate) fn uses(&self, field: Field<'s>) -> bool {
         match self {
             LhsFieldExpr::Field(f) => *f == field,
+            LhsFieldExpr::Slice(slice) => slice.field == field,
             LhsFieldExpr::FunctionCallExpr(call) => call.uses(field),
         }
     }
 
+    pub(crate) fn execute(&self, ctx: &'s ExecutionContext<'s>) -> LhsValue<'_> {
+        match self {
+            LhsFieldExpr::Field(field) => ctx.get_field_value_unchecked(*field),
+            LhsFieldExpr::Slice(slice) => slice.slice(ctx.get_field_value_unchecked(slice.field)),
+            LhsFieldExpr::FunctionCallExpr(call) => call.execute(ctx),
+        }
+    }
+
     fn compile_with<F: 's>(self, func: F) -> CompiledExpr<'s>
     where
         F: Fn(LhsValue<'_>) -> bool + Send + Sync,
+    {
+        self.compile_with_scope(move |x, _| func(x))
+    }
+
+    // Same as `compile_with`, but the function also gets the state of the
+    // filter execution.
+    fn compile_with_scope<F: 's>(self, func: F) -> CompiledExpr<'s>
+    where
+        F: Fn(LhsValue<'_>, &mut ExecutionScope) -> bool + Send + Sync,
     {
         match self {
             LhsFieldExpr::FunctionCallExpr(call) => {
-                CompiledExpr::new(move |ctx| func(call.execute(ctx)))
+                CompiledExpr::new(move |ctx, scope| func(call.execute(ctx), scope))
             }
             LhsFieldExpr::Field(f) => {
-                CompiledExpr::new(move |ctx| func(ctx.get_field_value_unchecked(f)))
+                CompiledExpr::new(move |ctx, scope| func(ctx.get_field_value_unchecked(f), scope))
             }
+            LhsFieldExpr::Slice(slice) => CompiledExpr::new(move |ctx, scope| {
+                func(
+                    slice.slice(ctx.get_field_value_unchecked(slice.field)),
+                    scope,
+                )
+            }),
         }
     }
 }
 
-impl<'i, 's> LexWith<'i, &'s Scheme> for LhsFieldExpr<'s> {
-    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
-        Ok(match FunctionCallExpr::lex_with(input, scheme) {
+impl<'s> LhsFieldExpr<'s> {
+    /// Lexes a field or a function call sharing the state of the filter
+    /// being parsed.
+    pub(crate) fn lex_with_state<'i>(
+        input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> LexResult<'i, Self> {
+        let scheme = state.scheme();
+        Ok(match FunctionCallExpr::lex_with_state(input, state) {
             Ok((call, input)) => (LhsFieldExpr::FunctionCallExpr(call), input),
+            // Don't mask the limit error as an unknown field
+            Err(err @ (LexErrorKind::NestingTooDeep { .. }, _)) => return Err(err),
+            // Nor errors in arguments of a known function
+            Err(err) if starts_with_call(input, scheme) => return Err(err),
             // Fallback to field
             Err(_) => {
+                let initial_input = input;
                 let (field, input) = Field::lex_with(input, scheme)?;
-                (LhsFieldExpr::Field(field), input)
+                match expect(input, "[") {
+                    Ok(input) => {
+                        let lhs_type = field.get_type();
+                        if lhs_type != Type::Bytes {
+                            return Err((
+                                LexErrorKind::UnsupportedOp { lhs_type },
+                                span(initial_input, input),
+                            ));
+                        }
+                        let ((start, end), input) = SliceExpr::lex_range(input)?;
+                        (LhsFieldExpr::Slice(SliceExpr { field, start, end }), input)
+                    }
+                    Err(_) => (LhsFieldExpr::Field(field), input),
+                }
             }
         })
     }
 }
 
+impl<'i, 's> LexWith<'i, &'s Scheme> for LhsFieldExpr<'s> {
+    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+        Self::lex_with_state(input, &ParseState::new(scheme))
+    }
+}
+
 impl<'s> GetType for LhsFieldExpr<'s> {
     fn get_type(&self) -> Type {
         match self {
             LhsFieldExpr::Field(field) => field.get_type(),
+            LhsFieldExpr::Slice(_) => Type::Bytes,
             LhsFieldExpr::FunctionCallExpr(call) => call.function.return_type,
         }
     }
 }
 
+/// A stable identifier of a [`FieldExpr`].
+///
+/// It's derived from the canonical serialized form of the expression, so it
+/// doesn't depend on formatting of the filter and stays the same when the
+/// filter is serialized and parsed again, or parsed on another machine.
+/// Identical comparisons share the same identifier.
+///
+/// It's displayed and serialized as 16 hexadecimal digits.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    /// Returns the numeric value of the identifier.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+/// An operation performed on a field or a function call.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 pub struct FieldExpr<'s> {
     lhs: LhsFieldExpr<'s>,
 
     #[serde(flatten)]
-    op: FieldOp,
+    op: FieldOp<'s>,
+
+    #[serde(skip_serializing_if = "is_false")]
+    negated: bool,
 }
 
-impl<'i, 's> LexWith<'i, &'s Scheme> for FieldExpr<'s> {
-    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl<'s> FieldExpr<'s> {
+    /// The left-hand side of the operation.
+    pub fn lhs(&self) -> &LhsFieldExpr<'s> {
+        &self.lhs
+    }
+
+    /// Mutable access to the left-hand side of the operation.
+    ///
+    /// The type of the left-hand side must be preserved.
+    pub fn lhs_mut(&mut self) -> &mut LhsFieldExpr<'s> {
+        &mut self.lhs
+    }
+
+    /// The operation.
+    pub fn op(&self) -> &FieldOp<'s> {
+        &self.op
+    }
+
+    /// Mutable access to the operation.
+    ///
+    /// The operation must remain applicable to the type of the left-hand
+    /// side.
+    pub fn op_mut(&mut self) -> &mut FieldOp<'s> {
+        &mut self.op
+    }
+
+    /// Whether the operation was written in a negated form, such as
+    /// `not in`, `not contains` or `!~`.
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    pub(crate) fn set_negated(&mut self, negated: bool) {
+        self.negated = negated;
+    }
+
+    /// Returns the stable identifier of the expression.
+    pub fn node_id(&self) -> NodeId {
+        // FNV is used because, unlike the default hasher, its output is
+        // guaranteed to stay the same across Rust versions.
+        let mut hasher = FnvHasher::default();
+        hasher.write(
+            serde_json::to_string(self)
+                .unwrap_or_else(|err| panic!("{} while serializing expression {:?}", err, self))
+                .as_bytes(),
+        );
+        NodeId(hasher.finish())
+    }
+}
+
+// Lexes a byte string literal, which must be valid UTF-8 if it's matched
+// against a `Str` value.
+fn lex_bytes(input: &str, is_str: bool) -> LexResult<'_, Bytes> {
+    if is_str {
+        StrLiteral::lex(input).map(|(s, input)| (s.into(), input))
+    } else {
+        Bytes::lex(input)
+    }
+}
+
+// Lexes the name of a constant used in place of a set, like `web_ports` in
+// `tcp.port in web_ports`.
+fn lex_constant_name(input: &str) -> Option<(&str, &str)> {
+    if !input.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    take_while(input, "constant character", |c| {
+        c.is_ascii_alphanumeric() || c == '_'
+    })
+    .ok()
+}
+
+impl<'s> FieldExpr<'s> {
+    /// Lexes a comparison sharing the state of the filter being parsed.
+    pub(crate) fn lex_with_state<'i>(
+        input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> LexResult<'i, Self> {
+        let scheme = state.scheme();
         let initial_input = input;
 
-        let (lhs, input) = LhsFieldExpr::lex_with(input, scheme)?;
+        let (lhs, input) = LhsFieldExpr::lex_with_state(input, state)?;
 
         let lhs_type = lhs.get_type();
 
-        let (op, input) = if lhs_type == Type::Bool {
-            (FieldOp::IsTrue, input)
+        let (op, negated, input) = if lhs_type == Type::Bool {
+            (FieldOp::IsTrue, false, input)
         } else {
-            let (op, input) = ComparisonOp::lex(skip_space(input))?;
+            let input = skip_space(input);
+
+            let (op, negated, input) = match expect(input, "!~") {
+                Ok(input) => (ComparisonOp::Bytes(BytesOp::Matches), true, input),
+                Err(_) => {
+                    // `not` must be followed by a space, so that it can't be
+                    // confused with a prefix of another word.
+                    let (negated, input) = match expect(input, "not") {
+                        Ok(rest) if skip_space(rest).len() < rest.len() => (true, skip_space(rest)),
+                        _ => (false, input),
+                    };
+                    let (op, input) = ComparisonOp::lex(input)?;
+                    (op, negated, input)
+                }
+            };
 
             let input_after_op = input;
 
+            if negated {
+                match op {
+                    ComparisonOp::In | ComparisonOp::Bytes(_) => {}
+                    ComparisonOp::Ordering(_) | ComparisonOp::Int(_) => {
+                        return Err((
+                            LexErrorKind::UnsupportedNegation,
+                            span(initial_input, input_after_op),
+                        ));
+                    }
+                }
+            }
+
             let input = skip_space(input);
 
-            match (lhs_type, op) {
+            let (op, input) = match (lhs_type, op) {
+                (_, ComparisonOp::In) | (_, ComparisonOp::Int(_)) | (_, ComparisonOp::Bytes(_))
+                    if input.starts_with("${") =>
+                {
+                    let rest = Placeholder::lex(input).map_or(&input[2..], |(_, rest)| rest);
+                    return Err((LexErrorKind::UnsupportedPlaceholder, span(input, rest)));
+                }
+                (_, ComparisonOp::In) if input.starts_with('$') => {
+                    let (list, rest) = List::lex_with(input, scheme)?;
+                    let list_type = list.get_type();
+                    if list_type != lhs_type {
+                        return Err((
+                            LexErrorKind::TypeMismatch(TypeMismatchError {
+                                expected: lhs_type,
+                                actual: list_type,
+                            }),
+                            span(input, rest),
+                        ));
+                    }
+                    (FieldOp::InList(list), rest)
+                }
                 (_, ComparisonOp::In) => {
-                    let (rhs, input) = RhsValues::lex_with(input, lhs_type)?;
+                    let (rhs, input) = match lex_constant_name(input) {
+                        Some((name, rest)) => {
+                            let values = scheme.get_constant(name).map_err(|err| {
+                                (LexErrorKind::UnknownConstant(err), span(input, rest))
+                            })?;
+                            let rhs_type = values.get_type();
+                            if rhs_type != lhs_type {
+                                return Err((
+                                    LexErrorKind::TypeMismatch(TypeMismatchError {
+                                        expected: lhs_type,
+                                        actual: rhs_type,
+                                    }),
+                                    span(input, rest),
+                                ));
+                            }
+                            (values.clone(), rest)
+                        }
+                        None => RhsValues::lex_with(input, (lhs_type, state.values()))?,
+                    };
+                    let rhs = match rhs {
+                        RhsValues::Ip(ranges) if scheme.ipv4_mapped_equivalence() => {
+                            RhsValues::Ip(canonicalize_ip_ranges(unmap_ipv4_ranges(ranges)))
+                        }
+                        RhsValues::Ip(ranges) => RhsValues::Ip(canonicalize_ip_ranges(ranges)),
+                        rhs => rhs,
+                    };
                     (FieldOp::OneOf(rhs), input)
                 }
+                (_, ComparisonOp::Ordering(op)) if input.starts_with("${") => {
+                    let (placeholder, rest) = Placeholder::lex(input)?;
+                    let rhs_type = placeholder.get_type();
+                    if rhs_type != lhs_type {
+                        return Err((
+                            LexErrorKind::TypeMismatch(TypeMismatchError {
+                                expected: lhs_type,
+                                actual: rhs_type,
+                            }),
+                            span(input, rest),
+                        ));
+                    }
+                    (FieldOp::PlaceholderOrdering { op, placeholder }, rest)
+                }
                 (_, ComparisonOp::Ordering(op)) => {
-                    let (rhs, input) = RhsValue::lex_with(input, lhs_type)?;
-                    (FieldOp::Ordering { op, rhs }, input)
+                    let literal = RhsValue::lex_with(input, (lhs_type, state.values()));
+                    // Names of fields can look like literals (e.g. `ab` or
+                    // `dead.beef` are also valid bytes), so a field is
+                    // preferred unless the literal is longer, like `ab:cd`.
+                    let field = Field::lex_with(input, scheme)
+                        .ok()
+                        .filter(|(_, field_rest)| {
+                            literal
+                                .as_ref()
+                                .map_or(true, |(_, rest)| rest.len() >= field_rest.len())
+                        });
+                    match (field, literal) {
+                        (Some((rhs_field, rest)), _) => {
+                            let rhs_type = rhs_field.get_type();
+                            if rhs_type != lhs_type {
+                                return Err((
+                                    LexErrorKind::TypeMismatch(TypeMismatchError {
+                                        expected: lhs_type,
+                                        actual: rhs_type,
+                                    }),
+                                    span(input, rest),
+                                ));
+                            }
+                            (FieldOp::FieldOrdering { op, rhs_field }, rest)
+                        }
+                        (None, Ok((RhsValue::Ip(addr), input)))
+                            if scheme.ipv4_mapped_equivalence() =>
+                        {
+                            (
+                                FieldOp::Ordering {
+                                    op,
+                                    rhs: RhsValue::Ip(unmap_ipv4(addr)),
+                                },
+                                input,
+                            )
+                        }
+                        (None, Ok((rhs, input))) => (FieldOp::Ordering { op, rhs }, input),
+                        // Report the literal's error if there's no such field.
+                        (None, Err(err)) => return Err(err),
+                    }
                 }
                 (Type::Int, ComparisonOp::Int(op)) => {
-                    let (rhs, input) = i32::lex(input)?;
-                    (FieldOp::Int { op, rhs }, input)
-                }
-                (Type::Bytes, ComparisonOp::Bytes(op)) => match op {
-                    BytesOp::Contains => {
-                        let (bytes, input) = Bytes::lex(input)?;
-                        (FieldOp::Contains(bytes), input)
+                    let (rhs, input) = IntLiteral::lex_with(input, state.values())?;
+                    match OrderingOp::lex(skip_space(input)) {
+                        Ok((ordering, input)) => {
+                            let (value, input) =
+                                IntLiteral::lex_with(skip_space(input), state.values())?;
+                            (
+                                FieldOp::IntOrdering {
+                                    op,
+                                    rhs,
+                                    ordering,
+                                    value,
+                                },
+                                input,
+                            )
+                        }
+                        Err(_) => (FieldOp::Int { op, rhs }, input),
                     }
-                    BytesOp::Matches => {
-                        let (regex, input) = Regex::lex(input)?;
-                        (FieldOp::Matches(regex), input)
+                }
+                (Type::Bytes, ComparisonOp::Bytes(op)) | (Type::Str, ComparisonOp::Bytes(op)) => {
+                    let is_str = lhs_type == Type::Str;
+                    match op {
+                        BytesOp::Contains => match expect(input, "any") {
+                            Ok(rest) => match RhsValues::lex_with(
+                                skip_space(rest),
+                                (lhs_type, state.values()),
+                            )? {
+                                (RhsValues::Bytes(values), input) => {
+                                    (FieldOp::ContainsAny(values), input)
+                                }
+                                (RhsValues::Str(values), input) => (
+                                    FieldOp::ContainsAny(
+                                        values.into_iter().map(Into::into).collect(),
+                                    ),
+                                    input,
+                                ),
+                                _ => unreachable!(),
+                            },
+                            Err(_) => {
+                                let (bytes, input) = lex_bytes(input, is_str)?;
+                                (FieldOp::Contains(bytes), input)
+                            }
+                        },
+                        BytesOp::StartsWith => {
+                            let (bytes, input) = lex_bytes(input, is_str)?;
+                            (FieldOp::StartsWith(bytes), input)
+                        }
+                        BytesOp::EndsWith => {
+                            let (bytes, input) = lex_bytes(input, is_str)?;
+                            (FieldOp::EndsWith(bytes), input)
+                        }
+                        BytesOp::Matches => {
+                            let (regex, input) =
+                                Regex::lex_with_unicode(input, is_str || scheme.unicode_regexes())?;
+                            (FieldOp::Matches(regex), input)
+                        }
+                        BytesOp::CaseInsensitiveEqual => {
+                            let (bytes, input) = lex_bytes(input, is_str)?;
+                            (FieldOp::CaseInsensitiveEqual(bytes), input)
+                        }
                     }
-                },
+                }
                 _ => {
                     return Err((
                         LexErrorKind::UnsupportedOp { lhs_type },
                         span(initial_input, input_after_op),
                     ));
                 }
-            }
+            };
+
+            (op, negated, input)
         };
 
-        Ok((FieldExpr { lhs, op }, input))
+        let expr = FieldExpr { lhs, op, negated };
+
+        state.record_span(&expr, span(initial_input, input));
+
+        Ok((expr, input))
+    }
+}
+
+impl<'i, 's> LexWith<'i, &'s Scheme> for FieldExpr<'s> {
+    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+        Self::lex_with_state(input, &ParseState::new(scheme))
     }
 }
 
 impl<'s> Expr<'s> for FieldExpr<'s> {
     fn uses(&self, field: Field<'s>) -> bool {
         self.lhs.uses(field)
+            || match self.op {
+                FieldOp::FieldOrdering { rhs_field, .. } => rhs_field == field,
+                _ => false,
+            }
+    }
+
+    fn compile_three_valued_in(
+        self,
+        cx: &mut CompileContext<'s>,
+    ) -> CompiledExpr<'s, Option<bool>> {
+        struct FieldCollector<'s>(Vec<Field<'s>>);
+
+        impl<'s> Visitor<'s> for FieldCollector<'s> {
+            fn visit_field(&mut self, field: &Field<'s>) {
+                self.0.push(*field);
+            }
+        }
+
+        // The result is unknown if any of the fields, including ones passed
+        // to functions, is missing.
+        let mut collector = FieldCollector(Vec::new());
+        walk_field_expr(&mut collector, &self);
+        let fields = collector.0.into_boxed_slice();
+
+        let expr = self.compile_in(cx);
+
+        CompiledExpr::new(move |ctx, scope| {
+            if fields.iter().all(|&field| ctx.has_field_value(field)) {
+                Some(expr.execute_in(ctx, scope))
+            } else {
+                None
+            }
+        })
     }
 
-    fn compile(self) -> CompiledExpr<'s> {
+    fn compile_in(self, cx: &mut CompileContext<'s>) -> CompiledExpr<'s> {
+        if self.negated {
+            let expr = FieldExpr {
+                negated: false,
+                ..self
+            }
+            .compile_in(cx);
+            return CompiledExpr::new(move |ctx, scope| !expr.execute_in(ctx, scope));
+        }
+
         let lhs = self.lhs;
 
+        if let LhsFieldExpr::FunctionCallExpr(call) = &lhs {
+            call.prepare();
+        }
+
         macro_rules! cast_value {
             ($value:expr, $ty:ident) => {
                 match $value {
@@ -254,54 +864,112 @@ impl<'s> Expr<'s> for FieldExpr<'s> {
             };
         }
 
+        // Byte operators also accept strings.
+        macro_rules! cast_bytes {
+            ($value:expr) => {
+                match &$value {
+                    LhsValue::Bytes(value) => &value[..],
+                    LhsValue::Str(value) => value.as_bytes(),
+                    _ => unreachable!(),
+                }
+            };
+        }
+
         match self.op {
             FieldOp::IsTrue => lhs.compile_with(move |x| cast_value!(x, Bool)),
             FieldOp::Ordering { op, rhs } => {
                 lhs.compile_with(move |x| op.matches_opt(x.strict_partial_cmp(&rhs)))
             }
-            FieldOp::Int {
-                op: IntOp::BitwiseAnd,
+            FieldOp::FieldOrdering { op, rhs_field } => CompiledExpr::new(move |ctx, _| {
+                let rhs = ctx.get_field_value_unchecked(rhs_field);
+                op.matches_opt(lhs.execute(ctx).strict_partial_cmp(&rhs))
+            }),
+            FieldOp::PlaceholderOrdering { placeholder, .. } => {
+                cx.fail(CompileError::UnboundPlaceholder(
+                    placeholder.name().to_owned(),
+                ));
+                CompiledExpr::new(|_, _| false)
+            }
+            FieldOp::Int { op, rhs } => {
+                let rhs = rhs.value();
+                lhs.compile_with(move |x| op.apply(cast_value!(x, Int), rhs) != 0)
+            }
+            FieldOp::IntOrdering {
+                op,
                 rhs,
-            } => lhs.compile_with(move |x| cast_value!(x, Int) & rhs != 0),
+                ordering,
+                value,
+            } => {
+                let rhs = rhs.value();
+                let value = value.value();
+                lhs.compile_with(move |x| {
+                    ordering.matches(op.apply(cast_value!(x, Int), rhs).cmp(&value))
+                })
+            }
             FieldOp::Contains(bytes) => {
                 let searcher = HeapSearcher::new(bytes);
 
-                lhs.compile_with(move |x| searcher.search_in(&cast_value!(x, Bytes)).is_some())
+                lhs.compile_with(move |x| searcher.search_in(cast_bytes!(x)).is_some())
             }
-            FieldOp::Matches(regex) => {
-                lhs.compile_with(move |x| regex.is_match(&cast_value!(x, Bytes)))
-            }
-            FieldOp::OneOf(values) => match values {
-                RhsValues::Ip(ranges) => {
-                    let mut v4 = Vec::new();
-                    let mut v6 = Vec::new();
-                    for range in ranges {
-                        match range.clone().into() {
-                            ExplicitIpRange::V4(range) => v4.push(range),
-                            ExplicitIpRange::V6(range) => v6.push(range),
-                        }
-                    }
-                    let v4 = RangeSet::from(v4);
-                    let v6 = RangeSet::from(v6);
+            FieldOp::ContainsAny(values) => {
+                // All patterns are searched for in a single pass.
+                let automaton = AhoCorasick::new(values.iter().map(|bytes| &bytes[..]));
 
-                    lhs.compile_with(move |x| match cast_value!(x, Ip) {
-                        IpAddr::V4(addr) => v4.contains(&addr),
-                        IpAddr::V6(addr) => v6.contains(&addr),
-                    })
+                lhs.compile_with(move |x| automaton.is_match(cast_bytes!(x)))
+            }
+            FieldOp::StartsWith(bytes) => {
+                lhs.compile_with(move |x| cast_bytes!(x).starts_with(&bytes))
+            }
+            FieldOp::EndsWith(bytes) => lhs.compile_with(move |x| cast_bytes!(x).ends_with(&bytes)),
+            FieldOp::Matches(regex) => {
+                let regex_set = match &lhs {
+                    LhsFieldExpr::Field(field) => cx.regex_sets.find(*field, &regex),
+                    LhsFieldExpr::Slice(_) | LhsFieldExpr::FunctionCallExpr(_) => None,
+                };
+
+                match regex_set {
+                    Some((set, index)) => lhs.compile_with_scope(move |x, scope| {
+                        let text = cast_bytes!(x);
+                        scope
+                            .spend_regex_budget(text.len())
+                            .unwrap_or_else(|| set.is_match(index, text, &mut scope.regex_sets))
+                    }),
+                    None => lhs.compile_with_scope(move |x, scope| {
+                        let text = cast_bytes!(x);
+                        scope
+                            .spend_regex_budget(text.len())
+                            .unwrap_or_else(|| regex.is_match(text))
+                    }),
                 }
-                RhsValues::Int(values) => {
-                    let values: RangeSet<_> = values.iter().cloned().collect();
-
-                    lhs.compile_with(move |x| values.contains(&cast_value!(x, Int)))
+            }
+            FieldOp::CaseInsensitiveEqual(bytes) => {
+                if lhs.get_type() == Type::Str {
+                    // Strings are compared by their lowercase forms.
+                    let lowercase = str::from_utf8(&bytes)
+                        .unwrap()
+                        .chars()
+                        .flat_map(char::to_lowercase)
+                        .collect::<Vec<_>>();
+
+                    lhs.compile_with(move |x| {
+                        cast_value!(x, Str)
+                            .chars()
+                            .flat_map(char::to_lowercase)
+                            .eq(lowercase.iter().cloned())
+                    })
+                } else {
+                    lhs.compile_with(move |x| cast_value!(x, Bytes).eq_ignore_ascii_case(&bytes))
                 }
-                RhsValues::Bytes(values) => {
-                    let values: IndexSet<Box<[u8]>, FnvBuildHasher> =
-                        values.into_iter().map(Into::into).collect();
+            }
+            FieldOp::OneOf(values) => {
+                let values = ValueSet::new(values);
 
-                    lhs.compile_with(move |x| values.contains(&cast_value!(x, Bytes) as &[u8]))
-                }
-                RhsValues::Bool(_) => unreachable!(),
-            },
+                lhs.compile_with(move |x| values.contains(&x))
+            }
+            FieldOp::InList(list) => CompiledExpr::new(move |ctx, _| {
+                ctx.get_list_values_unchecked(list)
+                    .contains(&lhs.execute(ctx))
+            }),
         }
     }
 }
@@ -315,11 +983,12 @@ mod tests {
         functions::{
             Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionOptParam, FunctionParam,
         },
-        rhs_types::IpRange,
+        rhs_types::{ExplicitIpRange, IpRange, MacAddr, MacPrefix},
+        scheme::{UnknownConstantError, UnknownListError},
     };
     use cidr::{Cidr, IpCidr};
     use lazy_static::lazy_static;
-    use std::net::IpAddr;
+    use std::{net::IpAddr, time::Duration};
 
     fn echo_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
         args.next().unwrap()
@@ -356,6 +1025,8 @@ mod tests {
                 ip.addr: Ip,
                 ssl: Bool,
                 tcp.port: Int,
+                http.latency: Duration,
+                eth.src: Mac,
             };
             scheme
                 .add_function(
@@ -364,6 +1035,7 @@ mod tests {
                         params: vec![FunctionParam {
                             arg_kind: FunctionArgKind::Field,
                             val_type: Type::Bytes,
+                            literal_check: None,
                         }],
                         opt_params: vec![],
                         return_type: Type::Bytes,
@@ -378,6 +1050,7 @@ mod tests {
                         params: vec![FunctionParam {
                             arg_kind: FunctionArgKind::Field,
                             val_type: Type::Bytes,
+                            literal_check: None,
                         }],
                         opt_params: vec![],
                         return_type: Type::Bytes,
@@ -391,14 +1064,8 @@ mod tests {
                     Function {
                         params: vec![],
                         opt_params: vec![
-                            FunctionOptParam {
-                                arg_kind: FunctionArgKind::Field,
-                                default_value: "".into(),
-                            },
-                            FunctionOptParam {
-                                arg_kind: FunctionArgKind::Literal,
-                                default_value: "".into(),
-                            },
+                            FunctionOptParam::new(FunctionArgKind::Field, ""),
+                            FunctionOptParam::new(FunctionArgKind::Literal, ""),
                         ],
                         return_type: Type::Bytes,
                         implementation: FunctionImpl::new(concat_function),
@@ -419,7 +1086,8 @@ mod tests {
             FieldExpr::lex_with("ssl", &SCHEME),
             FieldExpr {
                 lhs: LhsFieldExpr::Field(field("ssl")),
-                op: FieldOp::IsTrue
+                op: FieldOp::IsTrue,
+                negated: false,
             }
         );
 
@@ -453,6 +1121,7 @@ mod tests {
                         0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80
                     ]))
                 },
+                negated: false,
             }
         );
 
@@ -505,6 +1174,7 @@ mod tests {
                             vec![0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80].into()
                         ),
                     },
+                    negated: false,
                 }
             );
 
@@ -528,6 +1198,7 @@ mod tests {
                         op: OrderingOp::LessThan,
                         rhs: RhsValue::Bytes(vec![0x12].into()),
                     },
+                    negated: false,
                 }
             );
 
@@ -548,7 +1219,8 @@ mod tests {
                 op: FieldOp::Ordering {
                     op: OrderingOp::Equal,
                     rhs: RhsValue::Bytes("example.org".to_owned().into())
-                }
+                },
+                negated: false,
             }
         );
 
@@ -572,97 +1244,893 @@ mod tests {
     }
 
     #[test]
-    fn test_bitwise_and() {
+    fn test_bytes_slice() {
         let expr = assert_ok!(
-            FieldExpr::lex_with("tcp.port & 1", &SCHEME),
+            FieldExpr::lex_with("http.host[0..4] == 47:45:54:20", &SCHEME),
             FieldExpr {
-                lhs: LhsFieldExpr::Field(field("tcp.port")),
-                op: FieldOp::Int {
-                    op: IntOp::BitwiseAnd,
-                    rhs: 1,
-                }
+                lhs: LhsFieldExpr::Slice(SliceExpr {
+                    field: field("http.host"),
+                    start: 0,
+                    end: Some(4),
+                }),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::Equal,
+                    rhs: RhsValue::Bytes(vec![0x47, 0x45, 0x54, 0x20].into()),
+                },
+                negated: false,
             }
         );
 
         assert_json!(
             expr,
             {
-                "lhs": "tcp.port",
-                "op": "BitwiseAnd",
-                "rhs": 1
+                "lhs": {
+                    "field": "http.host",
+                    "start": 0,
+                    "end": 4
+                },
+                "op": "Equal",
+                "rhs": [0x47, 0x45, 0x54, 0x20]
             }
         );
 
         let expr = expr.compile();
         let ctx = &mut ExecutionContext::new(&SCHEME);
 
-        ctx.set_field_value("tcp.port", 80).unwrap();
+        ctx.set_field_value("http.host", "GET /").unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "POST /").unwrap();
         assert_eq!(expr.execute(ctx), false);
 
-        ctx.set_field_value("tcp.port", 443).unwrap();
-        assert_eq!(expr.execute(ctx), true);
-    }
+        ctx.set_field_value("http.host", "GET").unwrap();
+        assert_eq!(expr.execute(ctx), false);
 
-    #[test]
-    fn test_int_in() {
         let expr = assert_ok!(
-            FieldExpr::lex_with(r#"tcp.port in { 80 443 2082..2083 }"#, &SCHEME),
+            FieldExpr::lex_with(r#"http.host[ 4 .. ] contains ".""#, &SCHEME),
             FieldExpr {
-                lhs: LhsFieldExpr::Field(field("tcp.port")),
-                op: FieldOp::OneOf(RhsValues::Int(vec![80..=80, 443..=443, 2082..=2083])),
-            }
-        );
-
-        assert_json!(
-            expr,
-            {
-                "lhs": "tcp.port",
-                "op": "OneOf",
-                "rhs": [
-                    { "start": 80, "end": 80 },
-                    { "start": 443, "end": 443 },
-                    { "start": 2082, "end": 2083 },
-                ]
+                lhs: LhsFieldExpr::Slice(SliceExpr {
+                    field: field("http.host"),
+                    start: 4,
+                    end: None,
+                }),
+                op: FieldOp::Contains(".".to_owned().into()),
+                negated: false,
             }
         );
 
         let expr = expr.compile();
-        let ctx = &mut ExecutionContext::new(&SCHEME);
-
-        ctx.set_field_value("tcp.port", 80).unwrap();
-        assert_eq!(expr.execute(ctx), true);
 
-        ctx.set_field_value("tcp.port", 8080).unwrap();
+        ctx.set_field_value("http.host", "www.example").unwrap();
         assert_eq!(expr.execute(ctx), false);
 
-        ctx.set_field_value("tcp.port", 443).unwrap();
+        ctx.set_field_value("http.host", "www.example.org").unwrap();
         assert_eq!(expr.execute(ctx), true);
 
-        ctx.set_field_value("tcp.port", 2081).unwrap();
+        ctx.set_field_value("http.host", "ww").unwrap();
         assert_eq!(expr.execute(ctx), false);
 
-        ctx.set_field_value("tcp.port", 2082).unwrap();
-        assert_eq!(expr.execute(ctx), true);
-
-        ctx.set_field_value("tcp.port", 2083).unwrap();
-        assert_eq!(expr.execute(ctx), true);
-
-        ctx.set_field_value("tcp.port", 2084).unwrap();
-        assert_eq!(expr.execute(ctx), false);
+        assert_ok!(
+            FieldExpr::lex_with(r#"http.host[..3] == "www""#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Slice(SliceExpr {
+                    field: field("http.host"),
+                    start: 0,
+                    end: Some(3),
+                }),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::Equal,
+                    rhs: RhsValue::Bytes("www".to_owned().into()),
+                },
+                negated: false,
+            }
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port[0..1] == 1", &SCHEME),
+            LexErrorKind::UnsupportedOp {
+                lhs_type: Type::Int
+            },
+            "tcp.port["
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("http.host[4..2] == 1", &SCHEME),
+            LexErrorKind::IncompatibleRangeBounds,
+            "4..2"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("http.host[0..2 == 1", &SCHEME),
+            LexErrorKind::ExpectedLiteral("]"),
+            "== 1"
+        );
+    }
+
+    #[test]
+    fn test_field_ordering() {
+        let scheme = &Scheme! {
+            ip.src: Ip,
+            ip.dst: Ip,
+            tcp.port: Int,
+            ab: Bytes,
+            cafe: Bytes,
+            dead.beef: Bytes,
+        };
+        let field = |name| scheme.get_field_index(name).unwrap();
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("ip.src == ip.dst", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("ip.src")),
+                op: FieldOp::FieldOrdering {
+                    op: OrderingOp::Equal,
+                    rhs_field: field("ip.dst"),
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "ip.src",
+                "op": "Equal",
+                "rhs_field": "ip.dst"
+            }
+        );
+
+        assert!(expr.uses(field("ip.dst")));
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(scheme);
+
+        ctx.set_field_value("ip.src", IpAddr::from([10, 0, 0, 1]))
+            .unwrap();
+        ctx.set_field_value("ip.dst", IpAddr::from([10, 0, 0, 2]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("ip.dst", IpAddr::from([10, 0, 0, 1]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("ip.src < ip.dst", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("ip.src")),
+                op: FieldOp::FieldOrdering {
+                    op: OrderingOp::LessThan,
+                    rhs_field: field("ip.dst"),
+                },
+                negated: false,
+            }
+        );
+
+        let expr = expr.compile();
+
+        ctx.set_field_value("ip.dst", IpAddr::from([10, 0, 0, 2]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        // Fields of different IP versions are incomparable.
+        ctx.set_field_value("ip.dst", IpAddr::from([0, 0, 0, 0, 0, 0, 0, 2]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        // Fields take precedence over literals that look the same.
+        for &(filter, op, rhs) in &[
+            ("ab == ab", OrderingOp::Equal, "ab"),
+            ("ab < cafe", OrderingOp::LessThan, "cafe"),
+            ("ab > dead.beef", OrderingOp::GreaterThan, "dead.beef"),
+        ] {
+            assert_ok!(
+                FieldExpr::lex_with(filter, scheme),
+                FieldExpr {
+                    lhs: LhsFieldExpr::Field(field("ab")),
+                    op: FieldOp::FieldOrdering {
+                        op,
+                        rhs_field: field(rhs),
+                    },
+                    negated: false,
+                }
+            );
+        }
+
+        // Longer literals are still literals.
+        assert_ok!(
+            FieldExpr::lex_with("ab == ab:cd", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("ab")),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::Equal,
+                    rhs: RhsValue::Bytes(vec![0xab, 0xcd].into()),
+                },
+                negated: false,
+            }
+        );
+
+        assert_ok!(
+            FieldExpr::lex_with("ab == ac", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("ab")),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::Equal,
+                    rhs: RhsValue::Bytes(vec![0xac].into()),
+                },
+                negated: false,
+            }
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("ip.src == tcp.port", scheme),
+            LexErrorKind::TypeMismatch(TypeMismatchError {
+                expected: Type::Ip,
+                actual: Type::Int,
+            }),
+            "tcp.port"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port == tcp.dst", scheme),
+            LexErrorKind::ExpectedName("digit"),
+            "tcp.dst"
+        );
+    }
+
+    #[test]
+    fn test_placeholder_ordering() {
+        let scheme = &Scheme! { http.host: Bytes, tcp.port: Int };
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port >= ${min_port:Int}", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(scheme.get_field_index("tcp.port").unwrap()),
+                op: FieldOp::PlaceholderOrdering {
+                    op: OrderingOp::GreaterThanEqual,
+                    placeholder: Placeholder::lex("${min_port:Int}").unwrap().0,
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "tcp.port",
+                "op": "GreaterThanEqual",
+                "placeholder": {
+                    "name": "min_port",
+                    "type": "Int"
+                }
+            }
+        );
+
+        // compiling an unbound placeholder fails instead of panicking
+        let mut cx = CompileContext::default();
+        expr.compile_in(&mut cx);
+        assert_eq!(
+            cx.finish(),
+            Err(CompileError::UnboundPlaceholder("min_port".into()))
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("http.host == ${port:Int}", scheme),
+            LexErrorKind::TypeMismatch(TypeMismatchError {
+                expected: Type::Bytes,
+                actual: Type::Int,
+            }),
+            "${port:Int}"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("http.host contains ${host:Bytes}", scheme),
+            LexErrorKind::UnsupportedPlaceholder,
+            "${host:Bytes}"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port in ${ports:Int}", scheme),
+            LexErrorKind::UnsupportedPlaceholder,
+            "${ports:Int}"
+        );
+    }
+
+    #[test]
+    fn test_bitwise_and() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port & 1", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::Int {
+                    op: IntOp::BitwiseAnd,
+                    rhs: 1.into(),
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "tcp.port",
+                "op": "BitwiseAnd",
+                "rhs": 1
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("tcp.port", 80).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("tcp.port", 443).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+    }
+
+    #[test]
+    fn test_bitwise_ordering() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port & 0x12 == 0x12", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::IntOrdering {
+                    op: IntOp::BitwiseAnd,
+                    rhs: 0x12.into(),
+                    ordering: OrderingOp::Equal,
+                    value: 0x12.into(),
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "tcp.port",
+                "op": "BitwiseAnd",
+                "rhs": 18,
+                "ordering": "Equal",
+                "value": 18
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("tcp.port", 0x02).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("tcp.port", 0x13).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port bitwise_or 1 gt 8", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::IntOrdering {
+                    op: IntOp::BitwiseOr,
+                    rhs: 1.into(),
+                    ordering: OrderingOp::GreaterThan,
+                    value: 8.into(),
+                },
+                negated: false,
+            }
+        );
+
+        let expr = expr.compile();
+
+        ctx.set_field_value("tcp.port", 8).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("tcp.port", 7).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port ^ 80", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::Int {
+                    op: IntOp::BitwiseXor,
+                    rhs: 80.into(),
+                },
+                negated: false,
+            }
+        );
+
+        let expr = expr.compile();
+
+        ctx.set_field_value("tcp.port", 80).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("tcp.port", 443).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port & 1 == x", &SCHEME),
+            LexErrorKind::ExpectedName("digit"),
+            "x"
+        );
+    }
+
+    #[test]
+    fn test_int_in() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"tcp.port in { 80 443 2082..2083 }"#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::OneOf(RhsValues::Int(vec![
+                    80.into()..=80.into(),
+                    443.into()..=443.into(),
+                    2082.into()..=2083.into()
+                ])),
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "tcp.port",
+                "op": "OneOf",
+                "rhs": [
+                    { "start": 80, "end": 80 },
+                    { "start": 443, "end": 443 },
+                    { "start": 2082, "end": 2083 },
+                ]
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("tcp.port", 80).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("tcp.port", 8080).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("tcp.port", 443).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("tcp.port", 2081).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("tcp.port", 2082).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("tcp.port", 2083).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("tcp.port", 2084).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+    }
+
+    #[test]
+    fn test_bytes_in() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.host in { "example.org" "example.com" }"#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("http.host")),
+                op: FieldOp::OneOf(RhsValues::Bytes(
+                    ["example.org", "example.com",]
+                        .iter()
+                        .map(|&s| s.to_string().into())
+                        .collect()
+                )),
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "http.host",
+                "op": "OneOf",
+                "rhs": [
+                    "example.org",
+                    "example.com",
+                ]
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("http.host", "example.com").unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "example.org").unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "example.net").unwrap();
+        assert_eq!(expr.execute(ctx), false);
+    }
+
+    #[test]
+    fn test_ip_in() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with(
+                r#"ip.addr in { 127.0.0.0/8 ::1 10.0.0.0..10.0.255.255 }"#,
+                &SCHEME
+            ),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("ip.addr")),
+                op: FieldOp::OneOf(RhsValues::Ip(vec![
+                    IpRange::Cidr(IpCidr::new([10, 0, 0, 0].into(), 16).unwrap()),
+                    IpRange::Cidr(IpCidr::new([127, 0, 0, 0].into(), 8).unwrap()),
+                    IpRange::Cidr(IpCidr::new_host([0, 0, 0, 0, 0, 0, 0, 1].into())),
+                ])),
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "ip.addr",
+                "op": "OneOf",
+                "rhs": [
+                    "10.0.0.0/16",
+                    "127.0.0.0/8",
+                    "::1",
+                ]
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("ip.addr", IpAddr::from([127, 0, 0, 1]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("ip.addr", IpAddr::from([127, 0, 0, 3]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("ip.addr", IpAddr::from([255, 255, 255, 255]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("ip.addr", IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("ip.addr", IpAddr::from([0, 0, 0, 0, 0, 0, 0, 2]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+    }
+
+    #[test]
+    fn test_in_except() {
+        assert_ok!(
+            FieldExpr::lex_with(
+                "ip.addr in { 10.0.0.0/8 192.168.0.0/16 except 10.1.2.0/24 192.168.0.0/16 }",
+                &SCHEME
+            ),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("ip.addr")),
+                op: FieldOp::OneOf(RhsValues::Ip(vec![
+                    IpRange::Explicit(ExplicitIpRange::V4(
+                        [10, 0, 0, 0].into()..=[10, 1, 1, 255].into()
+                    )),
+                    IpRange::Explicit(ExplicitIpRange::V4(
+                        [10, 1, 3, 0].into()..=[10, 255, 255, 255].into()
+                    )),
+                ])),
+                negated: false,
+            }
+        );
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port in { 1..1024 8080 except 22 80..443 }", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::OneOf(RhsValues::Int(vec![
+                    1.into()..=21.into(),
+                    23.into()..=79.into(),
+                    444.into()..=1024.into(),
+                    8080.into()..=8080.into(),
+                ])),
+                negated: false,
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        for &(port, expected) in &[
+            (1, true),
+            (22, false),
+            (80, false),
+            (443, false),
+            (444, true),
+        ] {
+            ctx.set_field_value("tcp.port", port).unwrap();
+            assert_eq!(expr.execute(ctx), expected, "{}", port);
+        }
+
+        assert_ok!(
+            FieldExpr::lex_with("tcp.port in { except 22 }", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::OneOf(RhsValues::Int(vec![])),
+                negated: false,
+            }
+        );
+
+        assert_err!(
+            FieldExpr::lex_with(r#"http.host in { "a" "b" except "b" }"#, &SCHEME),
+            LexErrorKind::UnsupportedExclusion {
+                lhs_type: Type::Bytes
+            },
+            "except"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port in { 1..10 except 2 except 3 }", &SCHEME),
+            LexErrorKind::ParseInt {
+                err: "e".parse::<i32>().unwrap_err(),
+                radix: 10
+            },
+            "e"
+        );
+    }
+
+    #[test]
+    fn test_in_list() {
+        let mut scheme = Scheme! { http.host: Bytes, ip.src: Ip, tcp.port: Int };
+        scheme
+            .add_list("malicious_domains".into(), Type::Bytes)
+            .unwrap();
+        scheme.add_list("tor_exits".into(), Type::Ip).unwrap();
+        let scheme = &scheme;
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with("http.host in $malicious_domains", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(scheme.get_field_index("http.host").unwrap()),
+                op: FieldOp::InList(scheme.get_list("malicious_domains").unwrap()),
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "http.host",
+                "op": "InList",
+                "rhs": "malicious_domains"
+            }
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port in $malicious_domains", scheme),
+            LexErrorKind::TypeMismatch(TypeMismatchError {
+                expected: Type::Int,
+                actual: Type::Bytes,
+            }),
+            "$malicious_domains"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("http.host in $unknown", scheme),
+            LexErrorKind::UnknownList(UnknownListError),
+            "$unknown"
+        );
+
+        let host = expr.compile();
+        let ip = FieldExpr::lex_with("ip.src in $tor_exits", scheme)
+            .unwrap()
+            .0
+            .compile();
+
+        let ctx = &mut ExecutionContext::new(scheme);
+
+        assert_eq!(
+            ctx.set_list_values("tor_exits", RhsValues::Int(vec![])),
+            Err(TypeMismatchError {
+                expected: Type::Ip,
+                actual: Type::Int,
+            })
+        );
+
+        ctx.set_list_values(
+            "malicious_domains",
+            RhsValues::Bytes(vec!["evil.example".to_owned().into()]),
+        )
+        .unwrap();
+        ctx.set_list_values(
+            "tor_exits",
+            RhsValues::Ip(vec![IpRange::Cidr(
+                IpCidr::new([203, 0, 113, 0].into(), 24).unwrap(),
+            )]),
+        )
+        .unwrap();
+
+        ctx.set_field_value("http.host", "evil.example").unwrap();
+        ctx.set_field_value("ip.src", IpAddr::from([203, 0, 113, 7]))
+            .unwrap();
+        assert_eq!(host.execute(ctx), true);
+        assert_eq!(ip.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "example.org").unwrap();
+        ctx.set_field_value("ip.src", IpAddr::from([10, 0, 0, 1]))
+            .unwrap();
+        assert_eq!(host.execute(ctx), false);
+        assert_eq!(ip.execute(ctx), false);
+
+        // Lists can be updated without recompiling filters.
+        ctx.set_list_values(
+            "malicious_domains",
+            RhsValues::Bytes(vec!["example.org".to_owned().into()]),
+        )
+        .unwrap();
+        assert_eq!(host.execute(ctx), true);
+    }
+
+    #[test]
+    fn test_in_constant() {
+        let mut scheme = Scheme! { http.host: Bytes, tcp.port: Int };
+        let web_ports = RhsValues::Int(vec![80.into()..=80.into(), 443.into()..=443.into()]);
+        scheme
+            .add_constant("web_ports".into(), web_ports.clone())
+            .unwrap();
+        let scheme = &scheme;
+
+        assert_ok!(
+            FieldExpr::lex_with("tcp.port in web_ports", scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(scheme.get_field_index("tcp.port").unwrap()),
+                op: FieldOp::OneOf(web_ports),
+                negated: false,
+            }
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("http.host in web_ports", scheme),
+            LexErrorKind::TypeMismatch(TypeMismatchError {
+                expected: Type::Bytes,
+                actual: Type::Int,
+            }),
+            "web_ports"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port in unknown_ports", scheme),
+            LexErrorKind::UnknownConstant(UnknownConstantError),
+            "unknown_ports"
+        );
+    }
+
+    #[test]
+    fn test_contains_bytes() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.host contains "abc""#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("http.host")),
+                op: FieldOp::Contains("abc".to_owned().into()),
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "http.host",
+                "op": "Contains",
+                "rhs": "abc",
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("http.host", "example.org").unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("http.host", "abc.net.au").unwrap();
+        assert_eq!(expr.execute(ctx), true);
+    }
+
+    #[test]
+    fn test_negated() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with("tcp.port not in { 80 443 }", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("tcp.port")),
+                op: FieldOp::OneOf(RhsValues::Int(vec![
+                    80.into()..=80.into(),
+                    443.into()..=443.into(),
+                ])),
+                negated: true,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "tcp.port",
+                "op": "OneOf",
+                "rhs": [
+                    { "start": 80, "end": 80 },
+                    { "start": 443, "end": 443 },
+                ],
+                "negated": true,
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("tcp.port", 80).unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("tcp.port", 8080).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.host not  contains "abc""#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("http.host")),
+                op: FieldOp::Contains("abc".to_owned().into()),
+                negated: true,
+            }
+        );
+
+        let expr = expr.compile();
+
+        ctx.set_field_value("http.host", "example.org").unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "abc.net.au").unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        let mut expected = FieldExpr::lex_with(r#"http.host matches "^a.c$""#, &SCHEME)
+            .unwrap()
+            .0;
+        expected.negated = true;
+
+        assert_ok!(
+            FieldExpr::lex_with(r#"http.host !~ "^a.c$""#, &SCHEME),
+            expected.clone()
+        );
+        assert_ok!(
+            FieldExpr::lex_with(r#"http.host not ~ "^a.c$""#, &SCHEME),
+            expected
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port not == 80", &SCHEME),
+            LexErrorKind::UnsupportedNegation,
+            "tcp.port not =="
+        );
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port !~ 80", &SCHEME),
+            LexErrorKind::UnsupportedOp {
+                lhs_type: Type::Int
+            },
+            "tcp.port !~"
+        );
+
+        assert_err!(
+            FieldExpr::lex_with(r#"http.host notcontains "abc""#, &SCHEME),
+            LexErrorKind::ExpectedName("ComparisonOp"),
+            "notcontains \"abc\""
+        );
     }
 
     #[test]
-    fn test_bytes_in() {
+    fn test_contains_str() {
         let expr = assert_ok!(
-            FieldExpr::lex_with(r#"http.host in { "example.org" "example.com" }"#, &SCHEME),
+            FieldExpr::lex_with(r#"http.host contains 6F:72:67"#, &SCHEME),
             FieldExpr {
                 lhs: LhsFieldExpr::Field(field("http.host")),
-                op: FieldOp::OneOf(RhsValues::Bytes(
-                    ["example.org", "example.com",]
-                        .iter()
-                        .map(|&s| s.to_string().into())
-                        .collect()
-                )),
+                op: FieldOp::Contains(vec![0x6F, 0x72, 0x67].into()),
+                negated: false,
             }
         );
 
@@ -670,11 +2138,8 @@ mod tests {
             expr,
             {
                 "lhs": "http.host",
-                "op": "OneOf",
-                "rhs": [
-                    "example.org",
-                    "example.com",
-                ]
+                "op": "Contains",
+                "rhs": [0x6F, 0x72, 0x67],
             }
         );
 
@@ -682,78 +2147,67 @@ mod tests {
         let ctx = &mut ExecutionContext::new(&SCHEME);
 
         ctx.set_field_value("http.host", "example.com").unwrap();
-        assert_eq!(expr.execute(ctx), true);
+        assert_eq!(expr.execute(ctx), false);
 
         ctx.set_field_value("http.host", "example.org").unwrap();
         assert_eq!(expr.execute(ctx), true);
-
-        ctx.set_field_value("http.host", "example.net").unwrap();
-        assert_eq!(expr.execute(ctx), false);
     }
 
     #[test]
-    fn test_ip_in() {
+    fn test_starts_with_ends_with() {
         let expr = assert_ok!(
-            FieldExpr::lex_with(
-                r#"ip.addr in { 127.0.0.0/8 ::1 10.0.0.0..10.0.255.255 }"#,
-                &SCHEME
-            ),
+            FieldExpr::lex_with(r#"http.host startswith "www.""#, &SCHEME),
             FieldExpr {
-                lhs: LhsFieldExpr::Field(field("ip.addr")),
-                op: FieldOp::OneOf(RhsValues::Ip(vec![
-                    IpRange::Cidr(IpCidr::new([127, 0, 0, 0].into(), 8).unwrap()),
-                    IpRange::Cidr(IpCidr::new_host([0, 0, 0, 0, 0, 0, 0, 1].into())),
-                    IpRange::Explicit(ExplicitIpRange::V4(
-                        [10, 0, 0, 0].into()..=[10, 0, 255, 255].into()
-                    )),
-                ])),
+                lhs: LhsFieldExpr::Field(field("http.host")),
+                op: FieldOp::StartsWith("www.".to_owned().into()),
+                negated: false,
             }
         );
 
         assert_json!(
             expr,
             {
-                "lhs": "ip.addr",
-                "op": "OneOf",
-                "rhs": [
-                    "127.0.0.0/8",
-                    "::1",
-                    { "start": "10.0.0.0", "end": "10.0.255.255" },
-                ]
+                "lhs": "http.host",
+                "op": "StartsWith",
+                "rhs": "www."
             }
         );
 
         let expr = expr.compile();
         let ctx = &mut ExecutionContext::new(&SCHEME);
 
-        ctx.set_field_value("ip.addr", IpAddr::from([127, 0, 0, 1]))
-            .unwrap();
-        assert_eq!(expr.execute(ctx), true);
-
-        ctx.set_field_value("ip.addr", IpAddr::from([127, 0, 0, 3]))
-            .unwrap();
+        ctx.set_field_value("http.host", "www.example.org").unwrap();
         assert_eq!(expr.execute(ctx), true);
 
-        ctx.set_field_value("ip.addr", IpAddr::from([255, 255, 255, 255]))
-            .unwrap();
+        ctx.set_field_value("http.host", "example.www.").unwrap();
         assert_eq!(expr.execute(ctx), false);
 
-        ctx.set_field_value("ip.addr", IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]))
-            .unwrap();
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.host endswith ".org""#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("http.host")),
+                op: FieldOp::EndsWith(".org".to_owned().into()),
+                negated: false,
+            }
+        );
+
+        let expr = expr.compile();
+
+        ctx.set_field_value("http.host", "www.example.org").unwrap();
         assert_eq!(expr.execute(ctx), true);
 
-        ctx.set_field_value("ip.addr", IpAddr::from([0, 0, 0, 0, 0, 0, 0, 2]))
-            .unwrap();
+        ctx.set_field_value("http.host", "www.org.example").unwrap();
         assert_eq!(expr.execute(ctx), false);
     }
 
     #[test]
-    fn test_contains_bytes() {
+    fn test_contains_any() {
         let expr = assert_ok!(
-            FieldExpr::lex_with(r#"http.host contains "abc""#, &SCHEME),
+            FieldExpr::lex_with(r#"http.host contains any { "admin" 2e:2e }"#, &SCHEME),
             FieldExpr {
                 lhs: LhsFieldExpr::Field(field("http.host")),
-                op: FieldOp::Contains("abc".to_owned().into())
+                op: FieldOp::ContainsAny(vec!["admin".to_owned().into(), vec![0x2e, 0x2e].into()]),
+                negated: false,
             }
         );
 
@@ -761,8 +2215,8 @@ mod tests {
             expr,
             {
                 "lhs": "http.host",
-                "op": "Contains",
-                "rhs": "abc",
+                "op": "ContainsAny",
+                "rhs": ["admin", [0x2e, 0x2e]]
             }
         );
 
@@ -772,17 +2226,28 @@ mod tests {
         ctx.set_field_value("http.host", "example.org").unwrap();
         assert_eq!(expr.execute(ctx), false);
 
-        ctx.set_field_value("http.host", "abc.net.au").unwrap();
+        ctx.set_field_value("http.host", "admin.example.org")
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "example..org").unwrap();
         assert_eq!(expr.execute(ctx), true);
+
+        assert_err!(
+            FieldExpr::lex_with(r#"http.host contains any "admin""#, &SCHEME),
+            LexErrorKind::ExpectedLiteral("{"),
+            r#""admin""#
+        );
     }
 
     #[test]
-    fn test_contains_str() {
+    fn test_bytes_case_insensitive_equal() {
         let expr = assert_ok!(
-            FieldExpr::lex_with(r#"http.host contains 6F:72:67"#, &SCHEME),
+            FieldExpr::lex_with(r#"http.host ieq "Example.COM""#, &SCHEME),
             FieldExpr {
                 lhs: LhsFieldExpr::Field(field("http.host")),
-                op: FieldOp::Contains(vec![0x6F, 0x72, 0x67].into()),
+                op: FieldOp::CaseInsensitiveEqual("Example.COM".to_owned().into()),
+                negated: false,
             }
         );
 
@@ -790,8 +2255,8 @@ mod tests {
             expr,
             {
                 "lhs": "http.host",
-                "op": "Contains",
-                "rhs": [0x6F, 0x72, 0x67],
+                "op": "CaseInsensitiveEqual",
+                "rhs": "Example.COM"
             }
         );
 
@@ -799,10 +2264,194 @@ mod tests {
         let ctx = &mut ExecutionContext::new(&SCHEME);
 
         ctx.set_field_value("http.host", "example.com").unwrap();
-        assert_eq!(expr.execute(ctx), false);
+        assert_eq!(expr.execute(ctx), true);
 
-        ctx.set_field_value("http.host", "example.org").unwrap();
+        ctx.set_field_value("http.host", "EXAMPLE.com").unwrap();
         assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.host", "example.org").unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("http.host", "example.com.").unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        assert_err!(
+            FieldExpr::lex_with("tcp.port ieq 1", &SCHEME),
+            LexErrorKind::UnsupportedOp {
+                lhs_type: Type::Int
+            },
+            "tcp.port ieq"
+        );
+    }
+
+    #[test]
+    fn test_str() {
+        let scheme = &Scheme! { http.host: Str, http.path: Bytes };
+
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.host == "straße""#, scheme),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(scheme.get_field_index("http.host").unwrap()),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::Equal,
+                    rhs: RhsValue::Str("straße".to_owned().into()),
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "http.host",
+                "op": "Equal",
+                "rhs": "straße"
+            }
+        );
+
+        assert_err!(
+            FieldExpr::lex_with(r#"http.host contains "\xff""#, scheme),
+            LexErrorKind::InvalidUtf8,
+            r#""\xff""#
+        );
+
+        fn set_host(ctx: &mut ExecutionContext<'_>, host: &'static str) {
+            ctx.set_field_value("http.host", LhsValue::from(host).into_str().unwrap())
+                .unwrap()
+        }
+
+        let ctx = &mut ExecutionContext::new(scheme);
+
+        assert_eq!(
+            ctx.set_field_value("http.host", "straße"),
+            Err(TypeMismatchError {
+                expected: Type::Str,
+                actual: Type::Bytes,
+            })
+        );
+
+        let ieq = FieldExpr::lex_with(r#"http.host ieq "STRAßE.Été""#, scheme)
+            .unwrap()
+            .0
+            .compile();
+        let one_of = FieldExpr::lex_with(r#"http.host in { "straße.été" "example.org" }"#, scheme)
+            .unwrap()
+            .0
+            .compile();
+
+        set_host(ctx, "straße.été");
+        assert_eq!(ieq.execute(ctx), true);
+        assert_eq!(one_of.execute(ctx), true);
+
+        set_host(ctx, "strasse.ete");
+        assert_eq!(ieq.execute(ctx), false);
+        assert_eq!(one_of.execute(ctx), false);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_str_regexes() {
+        let scheme = &Scheme! { http.host: Str, http.path: Bytes };
+
+        fn set_host(ctx: &mut ExecutionContext<'_>, host: &'static str) {
+            ctx.set_field_value("http.host", LhsValue::from(host).into_str().unwrap())
+                .unwrap()
+        }
+
+        let ctx = &mut ExecutionContext::new(scheme);
+
+        let matches = FieldExpr::lex_with(r#"http.host matches "^\w+\.\w+$""#, scheme)
+            .unwrap()
+            .0
+            .compile();
+
+        set_host(ctx, "straße.été");
+        assert_eq!(matches.execute(ctx), true);
+
+        set_host(ctx, "strasse.ete");
+        assert_eq!(matches.execute(ctx), true);
+
+        // Bytes are matched by regexes without Unicode support.
+        let matches = FieldExpr::lex_with(r#"http.path matches "^\w+$""#, scheme)
+            .unwrap()
+            .0
+            .compile();
+        ctx.set_field_value("http.path", "été").unwrap();
+        assert_eq!(matches.execute(ctx), false);
+    }
+
+    #[test]
+    fn test_unicode_regexes() {
+        let scheme = &Scheme! { http.host: Bytes };
+        let mut unicode_scheme = Scheme! { http.host: Bytes };
+        unicode_scheme.set_unicode_regexes(true);
+        let unicode_scheme = &unicode_scheme;
+
+        let ascii = FieldExpr::lex_with(r#"http.host matches "^\w+$""#, scheme)
+            .unwrap()
+            .0;
+        match ascii.op() {
+            FieldOp::Matches(regex) => assert!(!regex.is_unicode()),
+            op => panic!("unexpected operator {:?}", op),
+        }
+
+        let suffixed = FieldExpr::lex_with(r#"http.host matches "(?i)^été$"u"#, scheme)
+            .unwrap()
+            .0;
+        let configured = FieldExpr::lex_with(r#"http.host matches "(?i)^été$""#, unicode_scheme)
+            .unwrap()
+            .0;
+        assert_eq!(suffixed.op(), configured.op());
+
+        // The suffix is kept, so that the filter means the same in any scheme.
+        assert_eq!(
+            unicode_scheme
+                .parse(r#"http.host matches "(?i)^été$""#)
+                .unwrap()
+                .to_string(),
+            r#"http.host ~ "(?i)^été$"u"#
+        );
+
+        // Serialized form and identifiers depend on the mode too.
+        let unicode = FieldExpr::lex_with(r#"http.host matches "^\w+$"u"#, scheme)
+            .unwrap()
+            .0;
+        assert_ne!(
+            serde_json::to_value(&ascii).unwrap(),
+            serde_json::to_value(&unicode).unwrap()
+        );
+        assert_ne!(ascii.node_id(), unicode.node_id());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_unicode_regex_matching() {
+        let scheme = &Scheme! { http.host: Bytes };
+        let mut unicode_scheme = Scheme! { http.host: Bytes };
+        unicode_scheme.set_unicode_regexes(true);
+        let unicode_scheme = &unicode_scheme;
+
+        let ascii = FieldExpr::lex_with(r#"http.host matches "^\w+$""#, scheme)
+            .unwrap()
+            .0
+            .compile();
+        let suffixed = FieldExpr::lex_with(r#"http.host matches "(?i)^été$"u"#, scheme)
+            .unwrap()
+            .0
+            .compile();
+        let configured = FieldExpr::lex_with(r#"http.host ~ "^\w+$""#, unicode_scheme)
+            .unwrap()
+            .0
+            .compile();
+
+        let ctx = &mut ExecutionContext::new(scheme);
+        ctx.set_field_value("http.host", "ÉTÉ").unwrap();
+        assert_eq!(ascii.execute(ctx), false);
+        assert_eq!(suffixed.execute(ctx), true);
+
+        let ctx = &mut ExecutionContext::new(unicode_scheme);
+        ctx.set_field_value("http.host", "été").unwrap();
+        assert_eq!(configured.execute(ctx), true);
     }
 
     #[test]
@@ -813,8 +2462,9 @@ mod tests {
                 lhs: LhsFieldExpr::Field(field("tcp.port")),
                 op: FieldOp::Ordering {
                     op: OrderingOp::LessThan,
-                    rhs: RhsValue::Int(8000)
+                    rhs: RhsValue::Int(8000.into())
                 },
+                negated: false,
             }
         );
 
@@ -837,6 +2487,152 @@ mod tests {
         assert_eq!(expr.execute(ctx), false);
     }
 
+    #[test]
+    fn test_duration_compare() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.latency >= 1s500ms"#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("http.latency")),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::GreaterThanEqual,
+                    rhs: RhsValue::Duration(Duration::from_millis(1500))
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "http.latency",
+                "op": "GreaterThanEqual",
+                "rhs": { "secs": 1, "nanos": 500_000_000 },
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("http.latency", Duration::from_millis(200))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("http.latency", Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        assert_err!(
+            FieldExpr::lex_with("http.latency & 1", &SCHEME),
+            LexErrorKind::UnsupportedOp {
+                lhs_type: Type::Duration
+            },
+            "http.latency &"
+        );
+    }
+
+    #[test]
+    fn test_duration_in() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with(r#"http.latency in { 0s..100ms 1h }"#, &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("http.latency")),
+                op: FieldOp::OneOf(RhsValues::Duration(vec![
+                    Duration::from_secs(0)..=Duration::from_millis(100),
+                    Duration::from_secs(3600)..=Duration::from_secs(3600),
+                ])),
+                negated: false,
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("http.latency", Duration::from_millis(50))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("http.latency", Duration::from_millis(101))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("http.latency", Duration::from_secs(3600))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+    }
+
+    #[test]
+    fn test_mac_compare() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with("eth.src == 00:1A:2b:3c:4d:5e", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("eth.src")),
+                op: FieldOp::Ordering {
+                    op: OrderingOp::Equal,
+                    rhs: RhsValue::Mac(MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e])),
+                },
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "eth.src",
+                "op": "Equal",
+                "rhs": "00:1a:2b:3c:4d:5e",
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("eth.src", MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("eth.src", MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5f]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+    }
+
+    #[test]
+    fn test_mac_in() {
+        let expr = assert_ok!(
+            FieldExpr::lex_with("eth.src in { 00:1a:2b::/24 ff:ff:ff:ff:ff:ff }", &SCHEME),
+            FieldExpr {
+                lhs: LhsFieldExpr::Field(field("eth.src")),
+                op: FieldOp::OneOf(RhsValues::Mac(vec![
+                    MacPrefix::lex("00:1a:2b::/24").unwrap().0,
+                    MacPrefix::lex("ff:ff:ff:ff:ff:ff/48").unwrap().0,
+                ])),
+                negated: false,
+            }
+        );
+
+        assert_json!(
+            expr,
+            {
+                "lhs": "eth.src",
+                "op": "OneOf",
+                "rhs": ["00:1a:2b:00:00:00/24", "ff:ff:ff:ff:ff:ff/48"],
+            }
+        );
+
+        let expr = expr.compile();
+        let ctx = &mut ExecutionContext::new(&SCHEME);
+
+        ctx.set_field_value("eth.src", MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), true);
+
+        ctx.set_field_value("eth.src", MacAddr([0x00, 0x1a, 0x2c, 0x00, 0x00, 0x00]))
+            .unwrap();
+        assert_eq!(expr.execute(ctx), false);
+
+        ctx.set_field_value("eth.src", MacAddr([0xff; 6])).unwrap();
+        assert_eq!(expr.execute(ctx), true);
+    }
+
     #[test]
     fn test_bytes_compare_with_echo_function() {
         let expr = assert_ok!(
@@ -848,11 +2644,15 @@ mod tests {
                     args: vec![FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
                         field("http.host")
                     ))],
+                    skipped: vec![],
+                    defaults: vec![],
+                    prepared: Default::default(),
                 }),
                 op: FieldOp::Ordering {
                     op: OrderingOp::Equal,
                     rhs: RhsValue::Bytes("example.org".to_owned().into())
-                }
+                },
+                negated: false,
             }
         );
 
@@ -894,11 +2694,15 @@ mod tests {
                     args: vec![FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
                         field("http.host")
                     ))],
+                    skipped: vec![],
+                    defaults: vec![],
+                    prepared: Default::default(),
                 }),
                 op: FieldOp::Ordering {
                     op: OrderingOp::Equal,
                     rhs: RhsValue::Bytes("example.org".to_owned().into())
-                }
+                },
+                negated: false,
             }
         );
 
@@ -940,11 +2744,15 @@ mod tests {
                     args: vec![FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
                         field("http.host")
                     ))],
+                    skipped: vec![],
+                    defaults: vec!["".into()],
+                    prepared: Default::default(),
                 }),
                 op: FieldOp::Ordering {
                     op: OrderingOp::Equal,
                     rhs: RhsValue::Bytes("example.org".to_owned().into())
-                }
+                },
+                negated: false,
             }
         );
 
@@ -986,11 +2794,15 @@ mod tests {
                             ".org".to_owned()
                         ))),
                     ],
+                    skipped: vec![],
+                    defaults: vec![],
+                    prepared: Default::default(),
                 }),
                 op: FieldOp::Ordering {
                     op: OrderingOp::Equal,
                     rhs: RhsValue::Bytes("example.org".to_owned().into())
-                }
+                },
+                negated: false,
             }
         );
 
@@ -1,52 +1,90 @@
-use super::field_expr::LhsFieldExpr;
+use super::{field_expr::LhsFieldExpr, NestingGuard, ParseState};
 use crate::{
     execution_context::ExecutionContext,
-    functions::{Function, FunctionArgKind, FunctionParam},
+    functions::{Function, FunctionArgKind, FunctionParam, FunctionState},
     lex::{expect, skip_space, span, take, take_while, LexError, LexErrorKind, LexResult, LexWith},
     scheme::{Field, Scheme},
     types::{GetType, LhsValue, RhsValue, TypeMismatchError},
 };
 use serde::Serialize;
+use std::{
+    any::Any,
+    fmt,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::OnceLock,
+};
+
+/// Payload of a panic raised by a function implementation, which records the
+/// name of the function, so that callers catching the panic can attribute it.
+pub(crate) struct FunctionPanic {
+    pub(crate) function: String,
+    pub(crate) payload: Box<dyn Any + Send>,
+}
+
+impl FunctionPanic {
+    // Passes on a panic of the given function, unless it has been already
+    // attributed to a function called by it.
+    fn resume(function: &str, payload: Box<dyn Any + Send>) -> ! {
+        if payload.is::<FunctionPanic>() {
+            resume_unwind(payload);
+        }
+        resume_unwind(Box::new(FunctionPanic {
+            function: function.to_owned(),
+            payload,
+        }))
+    }
+}
+
+/// Runs a filter execution that doesn't attribute panics of functions, so
+/// that they are passed on with their original payloads.
+pub(crate) fn forward_function_panics<T>(execute: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(execute)).unwrap_or_else(|payload| {
+        resume_unwind(match payload.downcast::<FunctionPanic>() {
+            Ok(panic) => panic.payload,
+            Err(payload) => payload,
+        })
+    })
+}
 
+/// An argument of a [`FunctionCallExpr`].
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 #[serde(tag = "kind", content = "value")]
-pub(crate) enum FunctionCallArgExpr<'s> {
+pub enum FunctionCallArgExpr<'s> {
+    /// A field or a nested function call.
     LhsFieldExpr(LhsFieldExpr<'s>),
+    /// A literal value.
     Literal(RhsValue),
 }
 
 impl<'s> FunctionCallArgExpr<'s> {
-    pub fn uses(&self, field: Field<'s>) -> bool {
+    pub(crate) fn uses(&self, field: Field<'s>) -> bool {
         match self {
             FunctionCallArgExpr::LhsFieldExpr(lhs) => lhs.uses(field),
             FunctionCallArgExpr::Literal(_) => false,
         }
     }
 
-    pub fn execute(&'s self, ctx: &'s ExecutionContext<'s>) -> LhsValue<'s> {
+    pub(crate) fn execute(&'s self, ctx: &'s ExecutionContext<'s>) -> LhsValue<'s> {
         match self {
-            FunctionCallArgExpr::LhsFieldExpr(lhs) => match lhs {
-                LhsFieldExpr::Field(field) => ctx.get_field_value_unchecked(*field),
-                LhsFieldExpr::FunctionCallExpr(call) => call.execute(ctx),
-            },
+            FunctionCallArgExpr::LhsFieldExpr(lhs) => lhs.execute(ctx),
             FunctionCallArgExpr::Literal(literal) => literal.into(),
         }
     }
 }
 
-struct SchemeFunctionParam<'s, 'a> {
-    scheme: &'s Scheme,
+struct SchemeFunctionParam<'i, 's, 'a> {
+    state: &'a ParseState<'i, 's>,
     param: &'a FunctionParam,
     index: usize,
 }
 
-impl<'i, 's, 'a> LexWith<'i, SchemeFunctionParam<'s, 'a>> for FunctionCallArgExpr<'s> {
-    fn lex_with(input: &'i str, ctx: SchemeFunctionParam<'s, 'a>) -> LexResult<'i, Self> {
+impl<'i, 's, 'a> LexWith<'i, SchemeFunctionParam<'i, 's, 'a>> for FunctionCallArgExpr<'s> {
+    fn lex_with(input: &'i str, ctx: SchemeFunctionParam<'i, 's, 'a>) -> LexResult<'i, Self> {
         let initial_input = input;
 
         match ctx.param.arg_kind {
             FunctionArgKind::Field => {
-                let (lhs, input) = LhsFieldExpr::lex_with(input, ctx.scheme)?;
+                let (lhs, input) = LhsFieldExpr::lex_with_state(input, ctx.state)?;
                 if lhs.get_type() != ctx.param.val_type {
                     Err((
                         LexErrorKind::InvalidArgumentType {
@@ -63,42 +101,213 @@ impl<'i, 's, 'a> LexWith<'i, SchemeFunctionParam<'s, 'a>> for FunctionCallArgExp
                 }
             }
             FunctionArgKind::Literal => {
-                let (rhs_value, input) = RhsValue::lex_with(input, ctx.param.val_type)?;
+                let (rhs_value, input) =
+                    RhsValue::lex_with(input, (ctx.param.val_type, ctx.state.values()))?;
+                if let Some(literal_check) = &ctx.param.literal_check {
+                    literal_check.check(&rhs_value).map_err(|message| {
+                        (
+                            LexErrorKind::InvalidArgumentValue {
+                                index: ctx.index,
+                                message,
+                            },
+                            span(initial_input, input),
+                        )
+                    })?;
+                }
                 Ok((FunctionCallArgExpr::Literal(rhs_value), input))
             }
         }
     }
 }
 
+// State of the implementation prepared for a call. It's derived from the
+// arguments, so it's ignored when comparing calls.
+#[derive(Clone, Default)]
+pub(crate) struct PreparedState(OnceLock<Option<FunctionState>>);
+
+impl PartialEq for PreparedState {
+    fn eq(&self, _other: &PreparedState) -> bool {
+        true
+    }
+}
+
+impl Eq for PreparedState {}
+
+impl fmt::Debug for PreparedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreparedState")
+    }
+}
+
+/// A call of a function from the scheme.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
-pub(crate) struct FunctionCallExpr<'s> {
-    pub name: String,
+pub struct FunctionCallExpr<'s> {
+    pub(crate) name: String,
     #[serde(skip)]
-    pub function: &'s Function,
-    pub args: Vec<FunctionCallArgExpr<'s>>,
+    pub(crate) function: &'s Function,
+    pub(crate) args: Vec<FunctionCallArgExpr<'s>>,
+    // Indices of parameters omitted before an argument passed by name.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skipped: Vec<usize>,
+    // Values of omitted optional arguments, resolved during parsing.
+    #[serde(skip)]
+    pub(crate) defaults: Vec<LhsValue<'static>>,
+    // Prepared when the call is compiled, or executed for the first time.
+    #[serde(skip)]
+    pub(crate) prepared: PreparedState,
 }
 
 impl<'s> FunctionCallExpr<'s> {
-    pub fn new(name: &str, function: &'s Function) -> Self {
+    pub(crate) fn new(name: &str, function: &'s Function) -> Self {
         Self {
             name: name.into(),
             function,
             args: Vec::default(),
+            skipped: Vec::default(),
+            defaults: Vec::default(),
+            prepared: PreparedState::default(),
         }
     }
 
-    pub fn uses(&self, field: Field<'s>) -> bool {
+    /// Name of the called function.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Definition of the called function.
+    pub fn function(&self) -> &'s Function {
+        self.function
+    }
+
+    /// Arguments passed explicitly, in order of the function parameters.
+    ///
+    /// Omitted optional arguments are not included, see
+    /// [`skipped_params`](FunctionCallExpr::skipped_params).
+    pub fn args(&self) -> &[FunctionCallArgExpr<'s>] {
+        &self.args
+    }
+
+    /// Indices of optional parameters that were omitted even though
+    /// arguments of some of the following ones were passed by name.
+    pub fn skipped_params(&self) -> &[usize] {
+        &self.skipped
+    }
+
+    /// Returns the name each explicit argument has to be passed by, if
+    /// any, which is the case for arguments following skipped parameters.
+    pub(crate) fn arg_names(&self) -> impl Iterator<Item = Option<&str>> {
+        let function = self.function;
+        let skipped = &self.skipped;
+        let mut index = 0;
+        (0..self.args.len()).map(move |position| {
+            while skipped.contains(&index) {
+                index += 1;
+            }
+            let param = index;
+            index += 1;
+            if param == position {
+                None
+            } else {
+                function.opt_params[param - function.params.len()]
+                    .name
+                    .as_deref()
+            }
+        })
+    }
+
+    /// Mutable access to the arguments.
+    ///
+    /// Types of the arguments and their kinds must be preserved, and
+    /// literals are not [validated](::FunctionParam::literal_check) again.
+    /// Defaults of omitted optional arguments are not recomputed.
+    pub fn args_mut(&mut self) -> &mut [FunctionCallArgExpr<'s>] {
+        self.prepared = PreparedState::default();
+        &mut self.args
+    }
+
+    pub(crate) fn uses(&self, field: Field<'s>) -> bool {
         self.args.iter().any(|arg| arg.uses(field))
     }
 
-    pub fn execute(&self, ctx: &'s ExecutionContext<'s>) -> LhsValue<'_> {
-        self.function.implementation.execute(
-            self.args.iter().map(|arg| arg.execute(ctx)).chain(
-                self.function.opt_params[self.args.len() - self.function.params.len()..]
-                    .iter()
-                    .map(|opt_arg| opt_arg.default_value.as_ref()),
-            ),
-        )
+    // Prepares the state of the implementation for this call and the nested
+    // ones, so that it's not done on execution.
+    pub(crate) fn prepare(&self) {
+        for arg in &self.args {
+            if let FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::FunctionCallExpr(call)) = arg {
+                call.prepare();
+            }
+        }
+        self.state();
+    }
+
+    fn state(&self) -> Option<&FunctionState> {
+        self.prepared
+            .0
+            .get_or_init(|| {
+                let literals = self
+                    .positional(
+                        self.args.iter().map(|arg| match arg {
+                            FunctionCallArgExpr::Literal(literal) => Some(literal.into()),
+                            FunctionCallArgExpr::LhsFieldExpr(_) => None,
+                        }),
+                        self.defaults.iter().map(|value| Some(value.as_ref())),
+                    )
+                    .collect::<Vec<_>>();
+                self.function.implementation.prepare(&literals)
+            })
+            .as_ref()
+    }
+
+    // Merges explicit arguments with defaults in order of the parameters.
+    // Defaults of skipped parameters precede the ones of trailing
+    // parameters, so they are interleaved with explicit arguments.
+    fn positional<'a, T: 'a>(
+        &'a self,
+        mut args: impl Iterator<Item = T> + 'a,
+        mut defaults: impl Iterator<Item = T> + 'a,
+    ) -> impl Iterator<Item = T> + 'a {
+        let mut skipped = self.skipped.iter().peekable();
+        (0..self.args.len() + self.defaults.len()).map(move |index| {
+            if skipped.peek() == Some(&&index) {
+                skipped.next();
+                defaults.next()
+            } else {
+                args.next().or_else(|| defaults.next())
+            }
+            .unwrap()
+        })
+    }
+
+    pub(crate) fn execute(&self, ctx: &'s ExecutionContext<'s>) -> LhsValue<'_> {
+        let state = self.state();
+        let args = self.args.iter().map(|arg| arg.execute(ctx));
+        let defaults = self.defaults.iter().map(LhsValue::as_ref);
+        let implementation = &self.function.implementation;
+
+        // Unwinding through here costs nothing unless the function panics,
+        // in which case the panic is attributed to it.
+        catch_unwind(AssertUnwindSafe(|| {
+            if self.skipped.is_empty() {
+                implementation.execute_prepared(state, args.chain(defaults))
+            } else {
+                implementation.execute_prepared(state, self.positional(args, defaults))
+            }
+        }))
+        .unwrap_or_else(|payload| FunctionPanic::resume(&self.name, payload))
+    }
+}
+
+// Returns whether the input starts with a call of a registered function, in
+// which case errors in its arguments are reported instead of falling back to
+// a field.
+pub(crate) fn starts_with_call(input: &str, scheme: &Scheme) -> bool {
+    match take_while(input, "function character", |c| {
+        c.is_ascii_alphanumeric() || c == '_'
+    }) {
+        Ok((name, rest)) => {
+            expect(skip_space(rest), "(").is_ok() && scheme.get_function_overloads(name).is_ok()
+        }
+        Err(_) => false,
     }
 }
 
@@ -112,8 +321,13 @@ fn invalid_args_count<'i>(function: &Function, input: &'i str) -> LexError<'i> {
     )
 }
 
-impl<'i, 's> LexWith<'i, &'s Scheme> for FunctionCallExpr<'s> {
-    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+impl<'s> FunctionCallExpr<'s> {
+    /// Lexes a function call sharing the state of the filter being parsed.
+    pub(crate) fn lex_with_state<'i>(
+        input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> LexResult<'i, Self> {
+        let scheme = state.scheme();
         let initial_input = input;
 
         let (name, mut input) = take_while(input, "function character", |c| {
@@ -126,86 +340,212 @@ impl<'i, 's> LexWith<'i, &'s Scheme> for FunctionCallExpr<'s> {
 
         input = skip_space(input);
 
-        let function = scheme
-            .get_function(name)
+        let overloads = scheme
+            .get_function_overloads(name)
             .map_err(|err| (LexErrorKind::UnknownFunction(err), initial_input))?;
 
-        let mut function_call = FunctionCallExpr::new(name, function);
+        let _guard = NestingGuard::enter(state, span(initial_input, input))?;
 
-        for i in 0..function.params.len() {
-            if i == 0 {
-                if take(input, 1)?.0 == ")" {
-                    break;
+        // Arguments are lexed with each signature, and the call is resolved
+        // to the only one that accepts them.
+        let mut resolved = None;
+        let mut error: Option<LexError<'i>> = None;
+
+        for function in overloads {
+            match lex_args(input, state, name, function) {
+                Ok((function_call, rest)) => {
+                    if resolved.is_some() {
+                        return Err((
+                            LexErrorKind::AmbiguousFunctionCall(name.to_owned()),
+                            span(initial_input, rest),
+                        ));
+                    }
+                    resolved = Some((function_call, rest));
                 }
-            } else {
-                input = expect(input, ",")
-                    .map_err(|(_, input)| invalid_args_count(&function, input))?;
+                // Report the error of the signature that matched the longest
+                // part of the arguments.
+                Err(err) => match &error {
+                    Some(prev) if prev.1.as_ptr() >= err.1.as_ptr() => {}
+                    _ => error = Some(err),
+                },
             }
+        }
 
-            input = skip_space(input);
+        resolved.ok_or_else(|| error.unwrap())
+    }
+}
 
-            let arg = FunctionCallArgExpr::lex_with(
-                input,
-                SchemeFunctionParam {
-                    scheme,
-                    param: &function.params[i],
-                    index: i,
-                },
-            )?;
+impl<'i, 's> LexWith<'i, &'s Scheme> for FunctionCallExpr<'s> {
+    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+        Self::lex_with_state(input, &ParseState::new(scheme))
+    }
+}
+
+fn take_arg_name(input: &str) -> Option<(&str, &str)> {
+    let (name, rest) = take_while(input, "argument name", |c| {
+        c.is_ascii_alphanumeric() || c == '_'
+    })
+    .ok()?;
+    let rest = expect(skip_space(rest), ":").ok()?;
+    Some((name, skip_space(rest)))
+}
+
+// Returns the index of the optional parameter passed by name and the input
+// following the name.
+fn lex_arg_name<'i>(input: &'i str, function: &Function) -> Option<(usize, &'i str)> {
+    let (name, rest) = take_arg_name(input)?;
+    let index = function
+        .opt_params
+        .iter()
+        .position(|param| param.name.as_deref() == Some(name))?;
+    Some((index, rest))
+}
 
-            function_call.args.push(arg.0);
+fn lex_args<'i, 's>(
+    mut input: &'i str,
+    state: &ParseState<'i, 's>,
+    name: &str,
+    function: &'s Function,
+) -> LexResult<'i, FunctionCallExpr<'s>> {
+    let args_input = input;
+    let mut function_call = FunctionCallExpr::new(name, function);
 
-            input = skip_space(arg.1);
+    for i in 0..function.params.len() {
+        if i == 0 {
+            if take(input, 1)?.0 == ")" {
+                break;
+            }
+        } else {
+            input = expect(input, ",").map_err(|(_, input)| invalid_args_count(function, input))?;
         }
 
-        if function_call.args.len() != function.params.len() {
-            return Err(invalid_args_count(&function, input));
+        input = skip_space(input);
+
+        let arg = FunctionCallArgExpr::lex_with(
+            input,
+            SchemeFunctionParam {
+                state,
+                param: &function.params[i],
+                index: i,
+            },
+        )?;
+
+        function_call.args.push(arg.0);
+
+        input = skip_space(arg.1);
+    }
+
+    if function_call.args.len() != function.params.len() {
+        return Err(invalid_args_count(function, input));
+    }
+
+    // Optional arguments are passed by position first, and then by name in
+    // any order.
+    let mut opt_args = function.opt_params.iter().map(|_| None).collect::<Vec<_>>();
+    let mut count = function_call.args.len();
+    let mut named = false;
+
+    while let Some(c) = input.chars().next() {
+        if c == ')' {
+            break;
+        }
+        // ',' is expected only if the current optional argument
+        // is not the first one in the list of specified arguments.
+        if count != 0 {
+            input = expect(input, ",")?;
         }
 
-        let mut index = 0;
+        input = skip_space(input);
 
-        while let Some(c) = input.chars().next() {
-            if c == ')' {
-                break;
+        let initial_input = input;
+
+        let index = match lex_arg_name(input, function) {
+            Some((index, rest)) => {
+                named = true;
+                input = rest;
+                index
             }
-            // ',' is expected only if the current optional argument
-            // is not the first one in the list of specified arguments.
-            if !function_call.args.is_empty() {
-                input = expect(input, ",")?;
+            None if named => {
+                return Err(match take_arg_name(input) {
+                    Some((name, _)) => (LexErrorKind::UnknownArgumentName(name.to_owned()), name),
+                    None => (LexErrorKind::ExpectedName("argument name"), input),
+                });
             }
+            None if count - function.params.len() < opt_args.len() => count - function.params.len(),
+            None => return Err(invalid_args_count(function, input)),
+        };
 
-            input = skip_space(input);
+        let opt_param = &function.opt_params[index];
 
-            let opt_param = function
-                .opt_params
-                .get(index)
-                .ok_or_else(|| invalid_args_count(&function, input))?;
+        if opt_args[index].is_some() {
+            return Err((
+                LexErrorKind::DuplicateArgument(function.params.len() + index),
+                span(initial_input, input),
+            ));
+        }
 
-            let param = FunctionParam {
-                arg_kind: opt_param.arg_kind.clone(),
-                val_type: opt_param.default_value.get_type(),
-            };
+        let param = FunctionParam {
+            arg_kind: opt_param.arg_kind.clone(),
+            val_type: opt_param.default_value.get_type(),
+            literal_check: None,
+        };
 
-            let (arg, rest) = FunctionCallArgExpr::lex_with(
-                input,
-                SchemeFunctionParam {
-                    scheme,
-                    param: &param,
-                    index: function.params.len() + index,
-                },
-            )?;
+        let (arg, rest) = FunctionCallArgExpr::lex_with(
+            input,
+            SchemeFunctionParam {
+                state,
+                param: &param,
+                index: function.params.len() + index,
+            },
+        )?;
 
-            function_call.args.push(arg);
+        opt_args[index] = Some(arg);
 
-            input = skip_space(rest);
+        input = skip_space(rest);
 
-            index += 1;
-        }
+        count += 1;
+    }
+
+    input = expect(input, ")")?;
+
+    // Defaults see every argument at the position of its parameter, so
+    // they're computed before optional arguments are moved into the call.
+    let positional = function_call
+        .args
+        .iter()
+        .map(Some)
+        .chain(opt_args.iter().map(Option::as_ref))
+        .collect::<Vec<_>>();
+
+    let defaults: Vec<_> = function
+        .opt_params
+        .iter()
+        .zip(&opt_args)
+        .filter(|(_, arg)| arg.is_none())
+        .map(|(opt_param, _)| opt_param.resolve_default(state.scheme(), &positional))
+        .collect::<Result<_, _>>()
+        .map_err(|mismatch| {
+            (
+                LexErrorKind::TypeMismatch(mismatch),
+                span(args_input, input),
+            )
+        })?;
 
-        input = expect(input, ")")?;
+    let last = opt_args.iter().rposition(Option::is_some);
 
-        Ok((function_call, input))
+    for (index, arg) in opt_args.into_iter().enumerate() {
+        match arg {
+            Some(arg) => function_call.args.push(arg),
+            None if last.is_some_and(|last| index < last) => {
+                function_call.skipped.push(function.params.len() + index)
+            }
+            None => {}
+        }
     }
+
+    function_call.defaults = defaults;
+
+    Ok((function_call, input))
 }
 
 #[test]
@@ -236,11 +576,12 @@ fn test_function() {
                         params: vec![FunctionParam {
                             arg_kind: FunctionArgKind::Field,
                             val_type: Type::Bytes,
+                            literal_check: None,
                         }],
-                        opt_params: vec![FunctionOptParam {
-                            arg_kind: FunctionArgKind::Literal,
-                            default_value: LhsValue::Int(10),
-                        }],
+                        opt_params: vec![FunctionOptParam::new(
+                            FunctionArgKind::Literal,
+                            LhsValue::Int(10),
+                        )],
                         return_type: Type::Bytes,
                         implementation: FunctionImpl::new(echo_function),
                     },
@@ -258,6 +599,9 @@ fn test_function() {
             args: vec![FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
                 SCHEME.get_field_index("http.host").unwrap()
             ))],
+            skipped: vec![],
+            defaults: vec![LhsValue::Int(10)],
+            prepared: Default::default(),
         },
         ";"
     );
@@ -302,9 +646,15 @@ fn test_function() {
                     args: vec![FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
                         SCHEME.get_field_index("http.host").unwrap()
                     ))],
+                    skipped: vec![],
+                    defaults: vec![LhsValue::Int(10)],
+                    prepared: Default::default(),
                 })
             )]
             .to_vec(),
+            skipped: vec![],
+            defaults: vec![LhsValue::Int(10)],
+            prepared: Default::default(),
         },
         ";"
     );
@@ -342,6 +692,13 @@ fn test_function() {
         "10"
     );
 
+    // the error isn't masked by the fallback to a field named `echo`
+    assert_err!(
+        LhsFieldExpr::lex_with("echo ( 10 );", &SCHEME),
+        LexErrorKind::UnknownField(UnknownFieldError),
+        "10"
+    );
+
     assert_err!(
         FunctionCallExpr::lex_with("echo ( ip.addr );", &SCHEME),
         LexErrorKind::InvalidArgumentType {
@@ -363,3 +720,371 @@ fn test_function() {
         "\"test\" );"
     );
 }
+
+#[test]
+fn test_computed_default() {
+    use crate::{
+        execution_context::ExecutionContext,
+        functions::{FunctionArgs, FunctionDefaultImpl, FunctionImpl, FunctionOptParam},
+        types::Type,
+    };
+
+    fn repeat_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        let value = args.next().unwrap();
+        let _separator = args.next().unwrap();
+        let count = match args.next().unwrap() {
+            LhsValue::Int(count) => count as usize,
+            _ => unreachable!(),
+        };
+        match value {
+            LhsValue::Bytes(bytes) => LhsValue::Bytes(bytes.repeat(count).into()),
+            _ => unreachable!(),
+        }
+    }
+
+    // Defaults the count to the length of an explicit separator, or to 2.
+    fn default_count<'s>(
+        _: &'s Scheme,
+        args: &[Option<&FunctionCallArgExpr<'s>>],
+    ) -> LhsValue<'static> {
+        match args.get(1) {
+            Some(Some(FunctionCallArgExpr::Literal(RhsValue::Bytes(separator)))) => {
+                LhsValue::Int(separator.len() as i32)
+            }
+            _ => LhsValue::Int(2),
+        }
+    }
+
+    let mut scheme = Scheme! { http.host: Bytes };
+    scheme
+        .add_function(
+            "repeat".into(),
+            Function {
+                params: vec![FunctionParam {
+                    arg_kind: FunctionArgKind::Field,
+                    val_type: Type::Bytes,
+                    literal_check: None,
+                }],
+                opt_params: vec![
+                    FunctionOptParam::new(FunctionArgKind::Literal, ""),
+                    FunctionOptParam::new(FunctionArgKind::Literal, LhsValue::Int(2))
+                        .with_default_fn(FunctionDefaultImpl::new(default_count)),
+                    FunctionOptParam::new(FunctionArgKind::Literal, "").with_name("suffix"),
+                ],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::new(repeat_function),
+            },
+        )
+        .unwrap();
+
+    // Computes a default of the wrong type.
+    let fallback = LhsValue::from("x");
+    scheme
+        .add_function(
+            "broken".into(),
+            Function {
+                params: vec![],
+                opt_params: vec![FunctionOptParam::new(FunctionArgKind::Literal, 0)
+                    .with_default_fn(FunctionDefaultImpl::new(move |_, _| fallback.clone()))],
+                return_type: Type::Int,
+                implementation: FunctionImpl::new(repeat_function),
+            },
+        )
+        .unwrap();
+
+    let defaults = |filter| {
+        FunctionCallExpr::lex_with(filter, &scheme)
+            .unwrap()
+            .0
+            .defaults
+    };
+
+    assert_eq!(
+        defaults("repeat(http.host)"),
+        [LhsValue::from(""), LhsValue::Int(2), LhsValue::from("")]
+    );
+    assert_eq!(
+        defaults(r#"repeat(http.host, "abc")"#),
+        [LhsValue::Int(3), LhsValue::from("")]
+    );
+    assert_eq!(
+        defaults(r#"repeat(http.host, "abc", 1)"#),
+        [LhsValue::from("")]
+    );
+
+    // a later argument passed by name isn't mistaken for a skipped one
+    assert_eq!(
+        defaults(r#"repeat(http.host, suffix: "xyz")"#),
+        [LhsValue::from(""), LhsValue::Int(2)]
+    );
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("http.host", "ab").unwrap();
+
+    let expr = FunctionCallExpr::lex_with(r#"repeat(http.host, "abc")"#, &scheme)
+        .unwrap()
+        .0;
+    assert_eq!(expr.execute(&ctx), LhsValue::from("ababab"));
+
+    // computed defaults of another type are rejected
+    assert_err!(
+        FunctionCallExpr::lex_with("broken()", &scheme),
+        LexErrorKind::TypeMismatch(TypeMismatchError {
+            expected: Type::Int,
+            actual: Type::Bytes,
+        }),
+        ")"
+    );
+}
+
+#[test]
+fn test_overloads() {
+    use crate::{
+        execution_context::ExecutionContext,
+        functions::{FunctionArgs, FunctionImpl, FunctionOptParam},
+        types::Type,
+    };
+
+    fn len_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        match args.next().unwrap() {
+            LhsValue::Bytes(bytes) => LhsValue::Int(bytes.len() as i32),
+            LhsValue::Ip(addr) if addr.is_ipv4() => LhsValue::Int(4),
+            LhsValue::Ip(_) => LhsValue::Int(16),
+            _ => unreachable!(),
+        }
+    }
+
+    let len = |val_type| Function {
+        params: vec![FunctionParam {
+            arg_kind: FunctionArgKind::Field,
+            val_type,
+            literal_check: None,
+        }],
+        opt_params: vec![],
+        return_type: Type::Int,
+        implementation: FunctionImpl::new(len_function),
+    };
+
+    let mut scheme = Scheme! {
+        http.host: Bytes,
+        ip.addr: Ip,
+        tcp.port: Int,
+    };
+    scheme
+        .add_function_overload("len".into(), len(Type::Bytes))
+        .unwrap();
+    scheme
+        .add_function_overload("len".into(), len(Type::Ip))
+        .unwrap();
+    scheme
+        .add_function_overload(
+            "len".into(),
+            Function {
+                opt_params: vec![FunctionOptParam::new(
+                    FunctionArgKind::Literal,
+                    LhsValue::Int(0),
+                )],
+                ..len(Type::Bytes)
+            },
+        )
+        .unwrap();
+
+    let overloads = scheme.get_function_overloads("len").unwrap();
+
+    let expr = assert_ok!(
+        FunctionCallExpr::lex_with("len(ip.addr)", &scheme),
+        FunctionCallExpr {
+            name: String::from("len"),
+            function: &overloads[1],
+            args: vec![FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
+                scheme.get_field_index("ip.addr").unwrap()
+            ))],
+            skipped: vec![],
+            defaults: vec![],
+            prepared: Default::default(),
+        }
+    );
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("ip.addr", std::net::IpAddr::from([10, 0, 0, 1]))
+        .unwrap();
+    assert_eq!(expr.execute(&ctx), LhsValue::Int(4));
+
+    assert_eq!(
+        FunctionCallExpr::lex_with("len(http.host, 1)", &scheme)
+            .unwrap()
+            .0
+            .function,
+        &overloads[2]
+    );
+
+    // the first of the signatures that fail at the same position is reported
+    assert_err!(
+        FunctionCallExpr::lex_with("len(tcp.port)", &scheme),
+        LexErrorKind::InvalidArgumentType {
+            index: 0,
+            mismatch: TypeMismatchError {
+                actual: Type::Int,
+                expected: Type::Bytes,
+            },
+        },
+        "tcp.port"
+    );
+
+    assert_err!(
+        FunctionCallExpr::lex_with("len(http.host);", &scheme),
+        LexErrorKind::AmbiguousFunctionCall("len".into()),
+        "len(http.host)"
+    );
+}
+
+#[test]
+fn test_named_args() {
+    use crate::{
+        execution_context::ExecutionContext,
+        functions::{FunctionArgs, FunctionImpl, FunctionOptParam},
+        types::Type,
+    };
+
+    fn repeat_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        let value = args.next().unwrap();
+        let separator = args.next().unwrap();
+        let count = args.next().unwrap();
+        match (value, separator, count) {
+            (LhsValue::Bytes(value), LhsValue::Bytes(separator), LhsValue::Int(count)) => {
+                LhsValue::Bytes(vec![&*value; count as usize].join(&*separator).into())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    let mut scheme = Scheme! { http.host: Bytes };
+    scheme
+        .add_function(
+            "repeat".into(),
+            Function {
+                params: vec![FunctionParam {
+                    arg_kind: FunctionArgKind::Field,
+                    val_type: Type::Bytes,
+                    literal_check: None,
+                }],
+                opt_params: vec![
+                    FunctionOptParam::new(FunctionArgKind::Literal, "").with_name("separator"),
+                    FunctionOptParam::new(FunctionArgKind::Literal, LhsValue::Int(2))
+                        .with_name("count"),
+                ],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::new(repeat_function),
+            },
+        )
+        .unwrap();
+
+    let function = scheme.get_function("repeat").unwrap();
+    let host = || {
+        FunctionCallArgExpr::LhsFieldExpr(LhsFieldExpr::Field(
+            scheme.get_field_index("http.host").unwrap(),
+        ))
+    };
+
+    let expr = assert_ok!(
+        FunctionCallExpr::lex_with("repeat(http.host, count: 3)", &scheme),
+        FunctionCallExpr {
+            name: String::from("repeat"),
+            function,
+            args: vec![
+                host(),
+                FunctionCallArgExpr::Literal(RhsValue::Int(3.into()))
+            ],
+            skipped: vec![1],
+            defaults: vec!["".into()],
+            prepared: Default::default(),
+        }
+    );
+
+    assert_json!(
+        expr,
+        {
+            "name": "repeat",
+            "args": [
+                {
+                    "kind": "LhsFieldExpr",
+                    "value": "http.host"
+                },
+                {
+                    "kind": "Literal",
+                    "value": 3
+                }
+            ],
+            "skipped": [1]
+        }
+    );
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("http.host", "ab").unwrap();
+    assert_eq!(expr.execute(&ctx), LhsValue::from("ababab"));
+
+    // named arguments are stored in the order of the parameters
+    assert_ok!(
+        FunctionCallExpr::lex_with(r#"repeat(http.host, count: 3, separator: "-")"#, &scheme),
+        FunctionCallExpr {
+            name: String::from("repeat"),
+            function,
+            args: vec![
+                host(),
+                FunctionCallArgExpr::Literal(RhsValue::Bytes("-".to_owned().into())),
+                FunctionCallArgExpr::Literal(RhsValue::Int(3.into())),
+            ],
+            skipped: vec![],
+            defaults: vec![],
+            prepared: Default::default(),
+        }
+    );
+
+    assert_ok!(
+        FunctionCallExpr::lex_with(r#"repeat(http.host, "-", count: 3)"#, &scheme),
+        FunctionCallExpr {
+            name: String::from("repeat"),
+            function,
+            args: vec![
+                host(),
+                FunctionCallArgExpr::Literal(RhsValue::Bytes("-".to_owned().into())),
+                FunctionCallArgExpr::Literal(RhsValue::Int(3.into())),
+            ],
+            skipped: vec![],
+            defaults: vec![],
+            prepared: Default::default(),
+        }
+    );
+
+    let ast = scheme
+        .parse(r#"repeat(http.host, count: 3) == "ababab""#)
+        .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        r#"repeat(http.host, count: 3) == "ababab""#
+    );
+
+    assert_err!(
+        FunctionCallExpr::lex_with("repeat(http.host, count: 3, 4)", &scheme),
+        LexErrorKind::ExpectedName("argument name"),
+        "4)"
+    );
+
+    assert_err!(
+        FunctionCallExpr::lex_with("repeat(http.host, count: 3, times: 4)", &scheme),
+        LexErrorKind::UnknownArgumentName("times".into()),
+        "times"
+    );
+
+    assert_err!(
+        FunctionCallExpr::lex_with("repeat(http.host, count: 3, count: 4)", &scheme),
+        LexErrorKind::DuplicateArgument(2),
+        "count: "
+    );
+
+    assert_err!(
+        FunctionCallExpr::lex_with(r#"repeat(http.host, "", separator: "-")"#, &scheme),
+        LexErrorKind::DuplicateArgument(1),
+        "separator: "
+    );
+}
@@ -2,19 +2,281 @@ mod combined_expr;
 mod field_expr;
 mod function_expr;
 mod simple_expr;
+pub mod visitor;
 
-use self::combined_expr::CombinedExpr;
+pub(crate) use self::{
+    function_expr::{forward_function_panics, FunctionPanic},
+    simple_expr::unshare_expr,
+};
+
+use self::visitor::Visitor;
+pub use self::{
+    combined_expr::{CombinedExpr, CombiningOp},
+    field_expr::{FieldExpr, FieldOp, IntOp, LhsFieldExpr, NodeId, OrderingOp, SliceExpr},
+    function_expr::{FunctionCallArgExpr, FunctionCallExpr},
+    simple_expr::{SimpleExpr, UnaryOp},
+};
+#[cfg(feature = "profiling")]
+use crate::profile::{ProfiledNodes, Profiler};
 use crate::{
-    filter::{CompiledExpr, Filter},
-    lex::{LexResult, LexWith},
+    ast_json::{from_json, to_json, AstJsonError},
+    bindings::{bind, BindError, Bindings, Placeholder},
+    cost::{estimate_cost, node_costs, reorder_by_cost, CostModel},
+    equivalence::{constant_outcome, implies, EquivalenceError},
+    execution_context::ExecutionContext,
+    filter::{
+        CompileContext, CompileError, CompiledExpr, Filter, SchemeMismatchError, ThreeValuedFilter,
+    },
+    fingerprint::{fingerprint, Fingerprint},
+    format::{format_expr, FormatOptions},
+    int_formatting::IntFormattingSerializer,
+    lex::{expect, skip_space, take_while, LexError, LexErrorKind, LexResult, LexState, LexWith},
+    lint::{fix_lints, lint, Lint},
+    rhs_types::IntFormatting,
     scheme::{Field, Scheme, UnknownFieldError},
+    simplify::{simplify, specialize},
+    spans::ExprSpans,
+    sql::{to_sql, SqlDialect, SqlError},
+    trace::TraceExpr,
+};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Debug, Display},
+    sync::Arc,
 };
-use serde::Serialize;
-use std::fmt::{self, Debug};
 
-trait Expr<'s>: Sized + Eq + Debug + for<'i> LexWith<'i, &'s Scheme> + Serialize {
+/// Maximum number of simple expressions that expansions of `let` definitions
+/// can add to a filter.
+///
+/// Definitions can refer to previous ones several times each, so a few short
+/// lines could otherwise expand into billions of expressions.
+pub(crate) const MAX_EXPANDED_EXPRS: usize = 1 << 16;
+
+struct Definition<'s> {
+    name: String,
+    expr: Arc<CombinedExpr<'s>>,
+    // Number of simple expressions in the definition, including expansions
+    // of the definitions it refers to.
+    size: usize,
+    // How many levels deep the definition is nested.
+    depth: usize,
+}
+
+/// State of parsing a single filter, shared by lexers of all its
+/// expressions.
+///
+/// Besides the [`LexState`] of values, it tracks the nesting depth of
+/// expressions, `let` definitions visible so far and, optionally, spans of
+/// comparisons.
+///
+/// Each definition is lexed once, and references to it are expanded into
+/// parenthesized expressions sharing its AST, so that compilation can
+/// evaluate it once for all of them.
+pub(crate) struct ParseState<'i, 's> {
+    scheme: &'s Scheme,
+    values: LexState<'i>,
+    depth: Cell<usize>,
+    // Maximum nesting depth reached since it was last measured.
+    max_depth: Cell<usize>,
+    definitions: RefCell<Vec<Definition<'s>>>,
+    // Number of simple expressions lexed so far, including expansions.
+    exprs: Cell<usize>,
+    // Number of simple expressions added by expansions so far.
+    expanded_exprs: Cell<usize>,
+    // The whole input and spans of comparisons found in it so far, if they
+    // are recorded.
+    spans: Option<(&'i str, RefCell<ExprSpans>)>,
+}
+
+impl<'i, 's> ParseState<'i, 's> {
+    pub fn new(scheme: &'s Scheme) -> Self {
+        ParseState {
+            scheme,
+            values: LexState::new(scheme.int_lex_options()),
+            depth: Cell::new(0),
+            max_depth: Cell::new(0),
+            definitions: RefCell::default(),
+            exprs: Cell::new(0),
+            expanded_exprs: Cell::new(0),
+            spans: None,
+        }
+    }
+
+    /// Records spans of comparisons lexed from the given input.
+    pub fn with_spans(self, input: &'i str) -> Self {
+        ParseState {
+            spans: Some((input, RefCell::default())),
+            ..self
+        }
+    }
+
+    pub fn scheme(&self) -> &'s Scheme {
+        self.scheme
+    }
+
+    /// State of lexing values, like literals and sets.
+    pub fn values(&self) -> &LexState<'i> {
+        &self.values
+    }
+
+    /// Reports a warning about the given span of the input.
+    pub fn warn(&self, kind: LexErrorKind, span: &'i str) {
+        self.values.warn(kind, span);
+    }
+
+    /// Records the span of a lexed comparison if spans are being recorded.
+    pub fn record_span(&self, expr: &FieldExpr<'_>, span: &'i str) {
+        if let Some((input, spans)) = &self.spans {
+            spans.borrow_mut().record(input, expr.node_id(), span);
+        }
+    }
+
+    /// Returns warnings and, if they were recorded, spans of comparisons.
+    pub fn finish(self) -> (Vec<(LexErrorKind, &'i str)>, Option<ExprSpans>) {
+        (
+            self.values.into_warnings(),
+            self.spans.map(|(_, spans)| spans.into_inner()),
+        )
+    }
+
+    // Returns the result of `f` along with how many levels deeper than the
+    // current one it has nested.
+    fn measure_nesting_depth<T>(&self, f: impl FnOnce() -> T) -> (T, usize) {
+        let base = self.depth.get();
+        let outer_max = self.max_depth.replace(base);
+        let res = f();
+        let inner_max = self.max_depth.get();
+        self.max_depth.set(outer_max.max(inner_max));
+        (res, inner_max - base)
+    }
+
+    /// Counts a lexed simple expression.
+    pub fn add_expr(&self) {
+        self.exprs.set(self.exprs.get() + 1);
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.definitions
+            .borrow()
+            .iter()
+            .any(|definition| definition.name == name)
+    }
+
+    /// Expands a reference to a definition at the start of the input, if
+    /// there's one.
+    pub fn lex_reference(&self, input: &'i str) -> Option<LexResult<'i, SimpleExpr<'s>>> {
+        let (name, rest) = take_while(input, "identifier", |c| {
+            c.is_ascii_alphanumeric() || c == '_' || c == '.'
+        })
+        .ok()?;
+        let definitions = self.definitions.borrow();
+        let definition = definitions
+            .iter()
+            .find(|definition| definition.name == name)?;
+        Some(self.expand(definition, name).map(|expr| (expr, rest)))
+    }
+
+    fn expand(
+        &self,
+        definition: &Definition<'s>,
+        span: &'i str,
+    ) -> Result<SimpleExpr<'s>, LexError<'i>> {
+        let expanded_exprs = self.expanded_exprs.get() + definition.size;
+        if expanded_exprs > MAX_EXPANDED_EXPRS {
+            return Err((
+                LexErrorKind::ExpansionTooLarge {
+                    limit: MAX_EXPANDED_EXPRS,
+                },
+                span,
+            ));
+        }
+        let _guard = NestingGuard::enter_expansion(self, span, definition.depth)?;
+        self.expanded_exprs.set(expanded_exprs);
+        self.exprs.set(self.exprs.get() + definition.size);
+        Ok(SimpleExpr::Parenthesized(Arc::clone(&definition.expr)))
+    }
+
+    // Lexes a `let name = expr;` definition and makes it visible to the
+    // following expressions.
+    fn lex_definition(&self, input: &'i str) -> LexResult<'i, ()> {
+        let scheme = self.scheme;
+        let input = skip_space(expect(input, "let")?);
+        let (name, input) = take_while(input, "definition name", |c| {
+            c.is_ascii_alphanumeric() || c == '_'
+        })?;
+        let is_defined = scheme.get_field_index(name).is_ok()
+            || scheme.get_function_overloads(name).is_ok()
+            || self.is_defined(name);
+        if is_defined {
+            return Err((LexErrorKind::DuplicateDefinition(name.to_owned()), name));
+        }
+        let input = skip_space(expect(skip_space(input), "=")?);
+        let exprs = self.exprs.get();
+        let (res, depth) = self.measure_nesting_depth(|| CombinedExpr::lex_with_state(input, self));
+        let (expr, rest) = res?;
+        let rest = expect(skip_space(rest), ";")?;
+        self.definitions.borrow_mut().push(Definition {
+            name: name.to_owned(),
+            expr: Arc::new(expr),
+            size: self.exprs.get() - exprs,
+            depth,
+        });
+        Ok(((), rest))
+    }
+}
+
+/// Tracks nesting depth of expressions being lexed.
+///
+/// Lexing, compilation and execution of nested expressions are all recursive,
+/// so without a limit adversarial inputs like `((((...))))` could overflow
+/// the stack.
+pub(crate) struct NestingGuard<'a>(&'a Cell<usize>);
+
+impl<'a> NestingGuard<'a> {
+    pub fn enter<'i>(state: &'a ParseState<'i, '_>, span: &'i str) -> Result<Self, LexError<'i>> {
+        Self::enter_expansion(state, span, 0)
+    }
+
+    /// Enters an expansion of a `let` definition, whose own expression is
+    /// nested `inner_depth` levels deep.
+    pub fn enter_expansion<'i>(
+        state: &'a ParseState<'i, '_>,
+        span: &'i str,
+        inner_depth: usize,
+    ) -> Result<Self, LexError<'i>> {
+        let depth = &state.depth;
+        let limit = state.scheme.max_nesting_depth();
+        if depth.get() + inner_depth >= limit {
+            return Err((LexErrorKind::NestingTooDeep { limit }, span));
+        }
+        depth.set(depth.get() + 1);
+        state
+            .max_depth
+            .set(state.max_depth.get().max(depth.get() + inner_depth));
+        Ok(NestingGuard(depth))
+    }
+}
+
+impl<'a> Drop for NestingGuard<'a> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+pub(crate) trait Expr<'s>:
+    Sized + Eq + Debug + for<'i> LexWith<'i, &'s Scheme> + Serialize
+{
     fn uses(&self, field: Field<'s>) -> bool;
-    fn compile(self) -> CompiledExpr<'s>;
+    fn compile_in(self, cx: &mut CompileContext<'s>) -> CompiledExpr<'s>;
+    fn compile_three_valued_in(self, cx: &mut CompileContext<'s>)
+        -> CompiledExpr<'s, Option<bool>>;
+
+    // Compiles an expression on its own, outside of a filter.
+    fn compile(self) -> CompiledExpr<'s> {
+        self.compile_in(&mut CompileContext::default())
+    }
 }
 
 /// A parsed filter AST.
@@ -37,10 +299,40 @@ impl<'s> Debug for FilterAst<'s> {
     }
 }
 
+impl<'s> Display for FilterAst<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_canonical_string())
+    }
+}
+
+impl<'s> FilterAst<'s> {
+    /// Lexes a filter, recording warnings and spans in the given state.
+    pub(crate) fn lex_with_state<'i>(
+        mut input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> LexResult<'i, Self> {
+        // `let` must be followed by a space, so that it can't be confused
+        // with a prefix of a field name. It's not a keyword at all in schemes
+        // that have a field or a function with this name.
+        let scheme = state.scheme();
+        let is_keyword =
+            scheme.get_field_index("let").is_err() && scheme.get_function_overloads("let").is_err();
+        while let Ok(rest) = expect(input, "let") {
+            if !is_keyword || skip_space(rest).len() == rest.len() {
+                break;
+            }
+            let ((), rest) = state.lex_definition(input)?;
+            input = skip_space(rest);
+        }
+
+        let (op, input) = CombinedExpr::lex_with_state(input, state)?;
+        Ok((FilterAst { scheme, op }, input))
+    }
+}
+
 impl<'i, 's> LexWith<'i, &'s Scheme> for FilterAst<'s> {
     fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
-        let (op, input) = CombinedExpr::lex_with(input, scheme)?;
-        Ok((FilterAst { scheme, op }, input))
+        Self::lex_with_state(input, &ParseState::new(scheme))
     }
 }
 
@@ -54,8 +346,639 @@ impl<'s> FilterAst<'s> {
             .map(|field| self.op.uses(field))
     }
 
-    /// Compiles a [`FilterAst`] into a [`Filter`].
-    pub fn compile(self) -> Filter<'s> {
-        Filter::new(self.op.compile(), self.scheme)
+    /// Returns the root expression of the AST.
+    ///
+    /// This is the entry point for traversal with a
+    /// [`Visitor`](visitor::Visitor).
+    pub fn expression(&self) -> &CombinedExpr<'s> {
+        &self.op
+    }
+
+    /// Returns the root expression of the AST for in-place rewrites with a
+    /// [`VisitorMut`](visitor::VisitorMut).
+    pub fn expression_mut(&mut self) -> &mut CombinedExpr<'s> {
+        &mut self.op
+    }
+
+    /// Returns the scheme the AST was parsed with.
+    pub fn scheme(&self) -> &'s Scheme {
+        self.scheme
+    }
+
+    pub(crate) fn into_expression(self) -> CombinedExpr<'s> {
+        self.op
+    }
+
+    /// Produces a canonical textual dump of the AST.
+    ///
+    /// This is the pretty-printed form of the JSON serialization, which
+    /// doesn't depend on formatting of the original filter, and is meant to be
+    /// stored as a golden file in snapshot tests.
+    pub fn snapshot(&self) -> String {
+        // AST serialization must never fail.
+        serde_json::to_string_pretty(self)
+            .unwrap_or_else(|err| panic!("{} while serializing filter {:#?}", err, self))
+    }
+
+    /// Converts the AST into a stable, versioned JSON representation, meant
+    /// for long-term storage.
+    ///
+    /// Unlike the regular serialization, which mirrors the AST types and
+    /// changes shape between versions of the crate, the representation is
+    /// guaranteed to be accepted by [`FilterAst::from_json`] in all future
+    /// versions. It's an object with the `version` of the representation,
+    /// currently `1`, and the root `expression` node. Each node has a `kind`:
+    ///
+    /// - `and`, `or` and `xor` combine two or more `operands`;
+    /// - `not` negates its `operand`, which can't be `and`, `or` or `xor`;
+    /// - `group` is an `operand` in parentheses;
+    /// - `comparison` is a comparison or a check of a field or a function
+    ///   call, like `tcp.port in { 80 443 }`. Its `source` is in the
+    ///   [canonical form](FilterAst::to_canonical_string), which is stable
+    ///   too, and `fields` are names of all fields it uses, without
+    ///   duplicates and in order of first appearance.
+    ///
+    /// For example, `ssl and not tcp.port == 80` is represented as:
+    ///
+    /// ```json
+    /// {
+    ///   "version": 1,
+    ///   "expression": {
+    ///     "kind": "and",
+    ///     "operands": [
+    ///       { "kind": "comparison", "fields": ["ssl"], "source": "ssl" },
+    ///       {
+    ///         "kind": "not",
+    ///         "operand": { "kind": "comparison", "fields": ["tcp.port"], "source": "tcp.port == 80" }
+    ///       }
+    ///     ]
+    ///   }
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value {
+        to_json(&self.op)
+    }
+
+    /// Converts the representation produced by [`FilterAst::to_json`] back
+    /// into an AST, validating it against the given scheme.
+    ///
+    /// Comparisons are parsed like in [`Scheme::parse`](::Scheme::parse),
+    /// so they are rejected if they refer to unknown fields, functions or
+    /// lists, or if types of the fields have changed since the AST was
+    /// stored.
+    pub fn from_json(value: &Value, scheme: &'s Scheme) -> Result<Self, AstJsonError> {
+        let op = from_json(value, scheme)?;
+        Ok(FilterAst { scheme, op })
+    }
+
+    /// Serializes the AST with integer literals represented according to the
+    /// given [`IntFormatting`].
+    ///
+    /// Regular serialization of [`FilterAst`] always uses
+    /// [`IntFormatting::Numeric`].
+    pub fn serialize_with<S: Serializer>(
+        &self,
+        int_formatting: IntFormatting,
+        ser: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.op
+            .serialize(IntFormattingSerializer::new(ser, int_formatting))
+    }
+
+    /// Returns identifiers of all comparisons in the filter, in order of
+    /// appearance.
+    ///
+    /// See [`NodeId`] for details.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        struct NodeIdCollector(Vec<NodeId>);
+
+        impl<'s> Visitor<'s> for NodeIdCollector {
+            fn visit_field_expr(&mut self, expr: &FieldExpr<'s>) {
+                self.0.push(expr.node_id());
+            }
+        }
+
+        let mut collector = NodeIdCollector(Vec::new());
+        collector.visit_expr(&self.op);
+        collector.0
+    }
+
+    /// Returns all fields referenced by the filter, including ones in
+    /// function arguments, slices and on the right-hand side of comparisons,
+    /// without duplicates and in order of first appearance.
+    ///
+    /// This is useful to populate only the fields that are needed to execute
+    /// the filter.
+    pub fn used_fields(&self) -> Vec<Field<'s>> {
+        struct FieldCollector<'s>(Vec<Field<'s>>);
+
+        impl<'s> Visitor<'s> for FieldCollector<'s> {
+            fn visit_field(&mut self, field: &Field<'s>) {
+                if !self.0.contains(field) {
+                    self.0.push(*field);
+                }
+            }
+        }
+
+        let mut collector = FieldCollector(Vec::new());
+        collector.visit_expr(&self.op);
+        collector.0
+    }
+
+    /// Returns names of all functions called by the filter, including nested
+    /// calls, without duplicates and in order of first appearance.
+    pub fn used_functions(&self) -> Vec<&'s str> {
+        struct FunctionCollector<'s> {
+            scheme: &'s Scheme,
+            names: Vec<&'s str>,
+        }
+
+        impl<'s> Visitor<'s> for FunctionCollector<'s> {
+            fn visit_function_call_expr(&mut self, expr: &FunctionCallExpr<'s>) {
+                let name = self.scheme.get_function_name(expr.name());
+                if !self.names.contains(&name) {
+                    self.names.push(name);
+                }
+                visitor::walk_function_call_expr(self, expr);
+            }
+        }
+
+        let mut collector = FunctionCollector {
+            scheme: self.scheme,
+            names: Vec::new(),
+        };
+        collector.visit_expr(&self.op);
+        collector.names
+    }
+
+    /// Returns all placeholders used by the filter, without duplicates and in
+    /// order of first appearance.
+    pub fn placeholders(&self) -> Vec<Placeholder> {
+        struct PlaceholderCollector(Vec<Placeholder>);
+
+        impl<'s> Visitor<'s> for PlaceholderCollector {
+            fn visit_field_op(&mut self, op: &FieldOp<'s>) {
+                if let FieldOp::PlaceholderOrdering { placeholder, .. } = op {
+                    if !self.0.contains(placeholder) {
+                        self.0.push(placeholder.clone());
+                    }
+                }
+            }
+        }
+
+        let mut collector = PlaceholderCollector(Vec::new());
+        collector.visit_expr(&self.op);
+        collector.0
+    }
+
+    /// Replaces all placeholders with values from the given bindings.
+    ///
+    /// Placeholders are validated when the filter is parsed, so the same AST
+    /// can be cloned and bound many times, and then compiled, without
+    /// parsing the filter again. Bindings of placeholders the filter doesn't
+    /// use are ignored.
+    ///
+    /// The filter is left intact if any placeholder is not bound or is bound
+    /// to a value of another type.
+    pub fn bind(&mut self, bindings: &Bindings) -> Result<(), BindError> {
+        let mut op = self.op.clone();
+        bind(&mut op, bindings)?;
+        self.op = op;
+        Ok(())
+    }
+
+    /// Formats the filter in the canonical form.
+    ///
+    /// The canonical form doesn't depend on formatting of the original
+    /// filter: it's a single line with the default [`FormatOptions`].
+    /// Parentheses, the radix of integer literals and the format of byte
+    /// strings are kept as written.
+    ///
+    /// The output for a given AST only changes between versions of the crate
+    /// when the defaults of [`FormatOptions`] do, which is considered a
+    /// breaking change.
+    pub fn to_canonical_string(&self) -> String {
+        self.format(&FormatOptions::default())
+    }
+
+    /// Formats the filter with the given options.
+    ///
+    /// Parsing the result back produces an equal AST, as long as the AST was
+    /// produced by the parser. Nested logical operators added by rewrites are
+    /// parenthesized where needed, and come back as parenthesized
+    /// expressions.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        format_expr(&self.op, options)
     }
+
+    /// Returns a stable 128-bit hash of the filter, which can be used to
+    /// deduplicate and cache filters across processes and hosts.
+    ///
+    /// The hash is computed over a canonical form of the AST that doesn't
+    /// depend on formatting or spelling of the filter: fields are identified
+    /// by name rather than by their index in the scheme, integer literals by
+    /// value, byte strings by content, redundant parentheses and nested
+    /// logical operators of the same kind are ignored, and sets are sorted,
+    /// deduplicated and, for integers and IP addresses, merged into minimal
+    /// ranges. Order of operands of logical operators is significant.
+    ///
+    /// Fingerprints of the same filter are guaranteed to stay the same
+    /// across versions of the crate. Changes to the algorithm are considered
+    /// breaking.
+    pub fn fingerprint(&self) -> Fingerprint {
+        fingerprint(&self.op)
+    }
+
+    /// Translates the filter into an SQL `WHERE` clause condition.
+    ///
+    /// Fields are translated to quoted column names of the same name.
+    /// Function calls and values without an SQL representation in the given
+    /// dialect result in an error.
+    pub fn to_sql(&self, dialect: SqlDialect) -> Result<String, SqlError> {
+        to_sql(&self.op, dialect)
+    }
+
+    /// Checks the filter for patterns that have cheaper equivalents, like
+    /// regular expressions that match plain strings, and for likely
+    /// mistakes, like comparisons that are always true or operands of `or`
+    /// that can never match on their own.
+    ///
+    /// Overlapping values in sets are merged while parsing, so they are
+    /// reported as [parse warnings](::Scheme::parse_with_warnings) instead.
+    pub fn lint(&self) -> Vec<Lint> {
+        lint(&self.op, self.scheme)
+    }
+
+    /// Rewrites the filter to apply fixes for all
+    /// [fixable](Lint::is_fixable) lints, and returns all lints found,
+    /// including the ones that weren't fixed.
+    pub fn fix_lints(&mut self) -> Vec<Lint> {
+        fix_lints(&mut self.op, self.scheme)
+    }
+
+    /// Estimates the cost of executing the filter according to the given
+    /// model.
+    ///
+    /// This is the cost of evaluating every comparison in the filter, so it's
+    /// an upper bound that doesn't take short-circuiting into account.
+    pub fn estimate_cost<M: CostModel + ?Sized>(&self, model: &M) -> u64 {
+        estimate_cost(&self.op, model)
+    }
+
+    /// Returns estimated costs of all comparisons in the filter, in order of
+    /// appearance, along with their identifiers.
+    ///
+    /// See [`FilterAst::estimate_cost`] for details.
+    pub fn node_costs<M: CostModel + ?Sized>(&self, model: &M) -> Vec<(NodeId, u64)> {
+        node_costs(&self.op, model)
+    }
+
+    /// Reorders operands of `and` and `or` so that cheaper ones are evaluated
+    /// first, according to the given model.
+    ///
+    /// Operands with equal costs keep their relative order, so more
+    /// selective checks can still be put first manually. Functions are
+    /// assumed to be pure.
+    pub fn reorder_by_cost<M: CostModel + ?Sized>(&mut self, model: &M) {
+        reorder_by_cost(&mut self.op, model)
+    }
+
+    /// Checks whether the filter matches exactly the same contexts as another
+    /// one parsed with the same scheme.
+    ///
+    /// Logical structure of the filters is checked exhaustively, while
+    /// comparisons of the same field with integer literals, and equality
+    /// checks of the same field with byte strings, are related through
+    /// representative values of the field. Other comparisons are considered
+    /// independent, so filters that are equivalent only due to relations
+    /// between them, like `x contains "ab"` and `x contains "a"`, are reported
+    /// as different.
+    pub fn is_equivalent_to(&self, other: &FilterAst<'s>) -> Result<bool, EquivalenceError> {
+        if self.scheme != other.scheme {
+            return Err(EquivalenceError::SchemeMismatch);
+        }
+        implies(&self.op, &other.op, self.scheme, true)
+    }
+
+    /// Checks whether the filter matches all contexts matched by another one
+    /// parsed with the same scheme.
+    ///
+    /// See [`FilterAst::is_equivalent_to`] for limitations.
+    pub fn subsumes(&self, other: &FilterAst<'s>) -> Result<bool, EquivalenceError> {
+        if self.scheme != other.scheme {
+            return Err(EquivalenceError::SchemeMismatch);
+        }
+        implies(&other.op, &self.op, self.scheme, false)
+    }
+
+    /// Checks whether the filter can't match any context, like
+    /// `tcp.port == 80 and tcp.port == 443`.
+    ///
+    /// See [`FilterAst::is_equivalent_to`] for limitations.
+    pub fn never_matches(&self) -> Result<bool, EquivalenceError> {
+        Ok(constant_outcome(&self.op, self.scheme)? == Some(false))
+    }
+
+    /// Checks whether the filter matches all contexts, like
+    /// `tcp.port != 80 or tcp.port != 443`.
+    ///
+    /// See [`FilterAst::is_equivalent_to`] for limitations.
+    pub fn always_matches(&self) -> Result<bool, EquivalenceError> {
+        Ok(constant_outcome(&self.op, self.scheme)? == Some(true))
+    }
+
+    /// Rewrites the filter into a simpler equivalent form.
+    ///
+    /// This evaluates comparisons on function calls that don't depend on any
+    /// fields, removes operands of logical operators that don't affect the
+    /// result, double negations and redundant parentheses, and merges
+    /// adjacent `in` checks of the same field or function call combined with
+    /// `or`. Functions are assumed to be pure.
+    ///
+    /// If the whole filter turns out to be constant, it's left intact since
+    /// the AST can't represent constants, and its value is returned instead.
+    pub fn simplify(&mut self) -> Option<bool> {
+        match simplify(self.op.clone(), self.scheme) {
+            Ok(op) => {
+                self.op = op;
+                None
+            }
+            Err(value) => Some(value),
+        }
+    }
+
+    /// Rewrites the filter into a residual one over fields that don't have
+    /// values in the given context, like [`FilterAst::simplify`] does with
+    /// function calls.
+    ///
+    /// Comparisons that only depend on fields with values in the context are
+    /// evaluated, and the result is simplified. This allows splitting
+    /// execution into stages as values of fields become available: the
+    /// residual filter matches a context with the remaining values if and
+    /// only if the original filter matches it with all values.
+    ///
+    /// If the whole filter is decided by the known values, it's left intact
+    /// and its value is returned instead.
+    pub fn specialize(
+        &mut self,
+        ctx: &ExecutionContext<'s>,
+    ) -> Result<Option<bool>, SchemeMismatchError> {
+        if self.scheme != ctx.scheme() {
+            return Err(SchemeMismatchError);
+        }
+        Ok(match specialize(self.op.clone(), ctx) {
+            Ok(op) => {
+                self.op = op;
+                None
+            }
+            Err(value) => Some(value),
+        })
+    }
+
+    /// Compiles a [`FilterAst`] into a [`Filter`], or fails if any of its
+    /// placeholders are not [bound](FilterAst::bind) or it calls functions
+    /// that [can't be executed](::Scheme::is_function_available).
+    pub fn compile(self) -> Result<Filter<'s>, CompileError> {
+        self.check_compilable()?;
+        let mut cx = CompileContext::new(&self.op);
+        let root_expr = self.op.compile_in(&mut cx);
+        cx.finish()?;
+        Ok(Filter::new(root_expr, self.scheme))
+    }
+
+    // Checks that all parts of the filter can be compiled.
+    pub(crate) fn check_compilable(&self) -> Result<(), CompileError> {
+        if let Some(placeholder) = self.placeholders().first() {
+            return Err(CompileError::UnboundPlaceholder(
+                placeholder.name().to_owned(),
+            ));
+        }
+        if let Some(function) = self.unavailable_function() {
+            return Err(CompileError::FunctionUnavailable { function });
+        }
+        Ok(())
+    }
+
+    // Returns the name of the first function called by the filter which
+    // can't be executed, if any.
+    fn unavailable_function(&self) -> Option<String> {
+        struct FunctionFinder<'s> {
+            scheme: &'s Scheme,
+            found: Option<String>,
+        }
+
+        impl<'s> Visitor<'s> for FunctionFinder<'s> {
+            fn visit_function_call_expr(&mut self, expr: &FunctionCallExpr<'s>) {
+                if self.found.is_none() && !self.scheme.is_function_available(expr.name()) {
+                    self.found = Some(expr.name().to_owned());
+                }
+                visitor::walk_function_call_expr(self, expr);
+            }
+        }
+
+        let mut finder = FunctionFinder {
+            scheme: self.scheme,
+            found: None,
+        };
+        finder.visit_expr(&self.op);
+        finder.found
+    }
+
+    /// Compiles a [`FilterAst`] into a [`Filter`] that additionally retains a
+    /// copy of the AST to support
+    /// [`Filter::execute_with_trace`](::Filter::execute_with_trace).
+    pub fn compile_with_trace(self) -> Result<Filter<'s>, CompileError> {
+        let trace_expr = TraceExpr::new(self.op.clone());
+        Ok(self.compile()?.with_trace(trace_expr))
+    }
+
+    /// Compiles a [`FilterAst`] into a [`Filter`] that counts evaluations of
+    /// each comparison and the time spent on them, which are reported by
+    /// [`Filter::profile`](::Filter::profile).
+    ///
+    /// Counters are shared by identical comparisons and between threads.
+    /// Positions in the source are taken from `spans`, if the filter was
+    /// parsed with [`Scheme::parse_with_spans`](::Scheme::parse_with_spans).
+    #[cfg(feature = "profiling")]
+    pub fn compile_with_profile(self, spans: ExprSpans) -> Result<Filter<'s>, CompileError> {
+        self.check_compilable()?;
+        let mut cx = CompileContext::new(&self.op);
+        cx.profiled_nodes = Some(ProfiledNodes::default());
+        let root_expr = self.op.compile_in(&mut cx);
+        cx.finish()?;
+        let profiler = Profiler::new(cx.profiled_nodes.unwrap_or_default(), spans);
+        Ok(Filter::new(root_expr, self.scheme).with_profiler(profiler))
+    }
+
+    /// Compiles a [`FilterAst`] into a [`ThreeValuedFilter`], which treats
+    /// comparisons on fields without values as unknown.
+    pub fn compile_three_valued(self) -> Result<ThreeValuedFilter<'s>, CompileError> {
+        self.check_compilable()?;
+        let mut cx = CompileContext::new(&self.op);
+        let root_expr = self.op.compile_three_valued_in(&mut cx);
+        cx.finish()?;
+        Ok(ThreeValuedFilter::new(root_expr, self.scheme))
+    }
+}
+
+#[test]
+fn test_node_ids() {
+    let scheme = &Scheme! { tcp.port: Int, ssl: Bool };
+
+    let ids = scheme
+        .parse("tcp.port == 443 and not ssl")
+        .unwrap()
+        .node_ids();
+
+    assert_eq!(ids.len(), 2);
+    assert_ne!(ids[0], ids[1]);
+
+    assert_eq!(
+        scheme
+            .parse("(ssl) || tcp.port eq 0x1bb")
+            .unwrap()
+            .node_ids(),
+        [ids[1], ids[0]]
+    );
+
+    assert_ne!(scheme.parse("tcp.port == 80").unwrap().node_ids(), [ids[0]]);
+
+    assert_eq!(ids[0].to_string(), format!("{:016x}", ids[0].as_u64()));
+    assert_eq!(serde_json::to_value(&ids[0]).unwrap(), ids[0].to_string());
+}
+
+#[test]
+fn test_definitions() {
+    let scheme = &Scheme! { ip.src: Ip, http.host: Bytes, tcp.port: Int, ssl: Bool };
+
+    assert_eq!(
+        scheme.parse(
+            r#"let is_internal = ip.src in {10.0.0.0/8 192.168.0.0/16};
+               is_internal and http.host == "x""#
+        ),
+        scheme.parse(r#"(ip.src in {10.0.0.0/8 192.168.0.0/16}) and http.host == "x""#)
+    );
+
+    // definitions can refer to the previous ones
+    let ast = scheme
+        .parse("let web = tcp.port in {80 443}; let secure_web=web and ssl; not web or secure_web")
+        .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "not (tcp.port in { 80 443 }) or ((tcp.port in { 80 443 }) and ssl)"
+    );
+
+    assert_err!(
+        FilterAst::lex_with("let ssl = tcp.port == 80; ssl", scheme),
+        LexErrorKind::DuplicateDefinition("ssl".into()),
+        "ssl"
+    );
+
+    assert_err!(
+        FilterAst::lex_with("let a = ssl; let a = not ssl; a", scheme),
+        LexErrorKind::DuplicateDefinition("a".into()),
+        "a"
+    );
+
+    assert_err!(
+        FilterAst::lex_with("let a = ssl a", scheme),
+        LexErrorKind::ExpectedLiteral(";"),
+        "a"
+    );
+
+    assert_err!(
+        FilterAst::lex_with("let a = b; a", scheme),
+        LexErrorKind::UnknownField(UnknownFieldError),
+        "b"
+    );
+
+    // definitions are only visible to their filter
+    assert_err!(
+        FilterAst::lex_with("a", scheme),
+        LexErrorKind::UnknownField(UnknownFieldError),
+        "a"
+    );
+
+    // `let` is a field in schemes that have one
+    let mut let_scheme = Scheme! { ssl: Bool };
+    let_scheme
+        .add_field("let".into(), crate::types::Type::Bool)
+        .unwrap();
+    assert_eq!(
+        let_scheme.parse("let and not ssl").unwrap().uses("let"),
+        Ok(true)
+    );
+
+    // each definition doubling the previous one would expand into about 2^30
+    // expressions
+    let mut input = "let d0 = ssl;".to_owned();
+    for i in 1..30 {
+        input += &format!(" let d{} = (d{}) or (d{});", i, i - 1, i - 1);
+    }
+    input += " d29";
+    match FilterAst::lex_with(&input, scheme) {
+        Err((LexErrorKind::ExpansionTooLarge { limit }, span)) => {
+            assert_eq!(limit, MAX_EXPANDED_EXPRS);
+            assert!(span.starts_with('d'));
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn test_serialize_with() {
+    use crate::rhs_types::IntRadix;
+
+    struct Formatted<'a, 's>(&'a FilterAst<'s>, IntFormatting);
+
+    impl<'a, 's> Serialize for Formatted<'a, 's> {
+        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize_with(self.1, ser)
+        }
+    }
+
+    let scheme = &Scheme! { tcp.port: Int };
+    let ast = scheme.parse("tcp.port in { 0x0050 0443..1000 }").unwrap();
+
+    // literals are equal regardless of formatting
+    assert_eq!(
+        scheme.parse("tcp.port == 0x50"),
+        scheme.parse("tcp.port == 80")
+    );
+
+    assert_json!(
+        ast,
+        {
+            "lhs": "tcp.port",
+            "op": "OneOf",
+            "rhs": [
+                { "start": 80, "end": 80 },
+                { "start": 291, "end": 1000 },
+            ]
+        }
+    );
+
+    assert_json!(
+        Formatted(&ast, IntFormatting::Preserve),
+        {
+            "lhs": "tcp.port",
+            "op": "OneOf",
+            "rhs": [
+                { "start": "0x0050", "end": "0x0050" },
+                { "start": "0443", "end": "1000" },
+            ]
+        }
+    );
+
+    assert_json!(
+        Formatted(&ast, IntFormatting::Normalize(IntRadix::Hexadecimal)),
+        {
+            "lhs": "tcp.port",
+            "op": "OneOf",
+            "rhs": [
+                { "start": "0x50", "end": "0x50" },
+                { "start": "0x123", "end": "0x3e8" },
+            ]
+        }
+    );
 }
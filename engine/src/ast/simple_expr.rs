@@ -1,36 +1,65 @@
-use super::{combined_expr::CombinedExpr, field_expr::FieldExpr, CompiledExpr, Expr};
+use super::{
+    combined_expr::CombinedExpr,
+    field_expr::{FieldExpr, FieldOp, OrderingOp},
+    CompiledExpr, Expr, NestingGuard, ParseState,
+};
 use crate::{
-    lex::{expect, skip_space, Lex, LexResult, LexWith},
+    filter::CompileContext,
+    lex::{expect, skip_space, span, Lex, LexErrorKind, LexResult, LexWith},
+    rhs_types::IntLiteral,
     scheme::{Field, Scheme},
+    types::Type,
 };
 use serde::Serialize;
+use std::sync::Arc;
 
-lex_enum!(UnaryOp {
-    "not" | "!" => Not,
-});
+lex_enum!(
+    /// A unary logical operator.
+    UnaryOp {
+        /// `not` / `!`
+        "not" | "!" => Not,
+    }
+);
 
+/// An expression that doesn't need precedence handling.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 #[serde(untagged)]
 pub enum SimpleExpr<'s> {
+    /// A comparison or a check of a field or a function call.
     Field(FieldExpr<'s>),
-    Parenthesized(Box<CombinedExpr<'s>>),
+    /// An expression in parentheses.
+    ///
+    /// The expression is reference-counted rather than boxed, so that all
+    /// expansions of the same `let` definition can share it. Use
+    /// [`Arc::make_mut`] to modify it in place.
+    Parenthesized(Arc<CombinedExpr<'s>>),
+    /// A unary operator applied to an expression.
     Unary {
+        /// Operator to apply.
         op: UnaryOp,
+        /// Operand.
         arg: Box<SimpleExpr<'s>>,
     },
 }
 
-impl<'i, 's> LexWith<'i, &'s Scheme> for SimpleExpr<'s> {
-    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
-        Ok(if let Ok(input) = expect(input, "(") {
-            let input = skip_space(input);
-            let (op, input) = CombinedExpr::lex_with(input, scheme)?;
+impl<'s> SimpleExpr<'s> {
+    /// Lexes an expression sharing the state of the filter being parsed.
+    pub(crate) fn lex_with_state<'i>(
+        input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> LexResult<'i, Self> {
+        state.add_expr();
+        Ok(if let Ok(rest) = expect(input, "(") {
+            let _guard = NestingGuard::enter(state, span(input, rest))?;
+            let input = skip_space(rest);
+            let (op, input) = CombinedExpr::lex_with_state(input, state)?;
             let input = skip_space(input);
             let input = expect(input, ")")?;
-            (SimpleExpr::Parenthesized(Box::new(op)), input)
-        } else if let Ok((op, input)) = UnaryOp::lex(input) {
-            let input = skip_space(input);
-            let (arg, input) = SimpleExpr::lex_with(input, scheme)?;
+            (SimpleExpr::Parenthesized(Arc::new(op)), input)
+        } else if let Ok((op, rest)) = UnaryOp::lex(input) {
+            let _guard = NestingGuard::enter(state, span(input, rest))?;
+            let input = skip_space(rest);
+            let (arg, input) = SimpleExpr::lex_with_state(input, state)?;
             (
                 SimpleExpr::Unary {
                     op,
@@ -38,13 +67,86 @@ impl<'i, 's> LexWith<'i, &'s Scheme> for SimpleExpr<'s> {
                 },
                 input,
             )
+        } else if let Some(res) = state.lex_reference(input) {
+            res?
         } else {
-            let (op, input) = FieldExpr::lex_with(input, scheme)?;
+            let (op, input) = FieldExpr::lex_with_state(input, state)?;
+            if *op.op() == FieldOp::IsTrue {
+                return lex_bool_int_comparison(op, input, state);
+            }
             (SimpleExpr::Field(op), input)
         })
     }
 }
 
+/// Takes a parenthesized expression out of its `Arc`, cloning it if it's
+/// shared with expansions of the same `let` definition.
+pub(crate) fn unshare_expr(expr: Arc<CombinedExpr<'_>>) -> CombinedExpr<'_> {
+    Arc::try_unwrap(expr).unwrap_or_else(|expr| (*expr).clone())
+}
+
+impl<'i, 's> LexWith<'i, &'s Scheme> for SimpleExpr<'s> {
+    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+        Self::lex_with_state(input, &ParseState::new(scheme))
+    }
+}
+
+// Handles `bool_field == 1` style comparisons following a check of a `Bool`
+// field. In lenient mode they are converted to a check or its negation.
+fn lex_bool_int_comparison<'i, 's>(
+    expr: FieldExpr<'s>,
+    input: &'i str,
+    state: &ParseState<'i, 's>,
+) -> LexResult<'i, SimpleExpr<'s>> {
+    let op_input = skip_space(input);
+
+    let (op, rest) = match OrderingOp::lex(op_input) {
+        Ok(res) => res,
+        Err(_) => return Ok((SimpleExpr::Field(expr), input)),
+    };
+
+    if !state.scheme().lenient_bool_comparisons()
+        || (op != OrderingOp::Equal && op != OrderingOp::NotEqual)
+    {
+        return Err((
+            LexErrorKind::UnsupportedOp {
+                lhs_type: Type::Bool,
+            },
+            span(op_input, rest),
+        ));
+    }
+
+    let value_input = skip_space(rest);
+    let (value, rest) = IntLiteral::lex_with(value_input, state.values())?;
+
+    let is_true = match value.value() {
+        0 => op == OrderingOp::NotEqual,
+        1 => op == OrderingOp::Equal,
+        _ => {
+            return Err((
+                LexErrorKind::ExpectedName("0 or 1"),
+                span(value_input, rest),
+            ));
+        }
+    };
+
+    state.warn(LexErrorKind::BoolIntComparison, span(op_input, rest));
+
+    let expr = SimpleExpr::Field(expr);
+
+    Ok((
+        if is_true {
+            expr
+        } else {
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg: Box::new(expr),
+            }
+        },
+        rest,
+    ))
+}
+
 impl<'s> Expr<'s> for SimpleExpr<'s> {
     fn uses(&self, field: Field<'s>) -> bool {
         match self {
@@ -54,16 +156,49 @@ impl<'s> Expr<'s> for SimpleExpr<'s> {
         }
     }
 
-    fn compile(self) -> CompiledExpr<'s> {
+    fn compile_in(self, cx: &mut CompileContext<'s>) -> CompiledExpr<'s> {
         match self {
-            SimpleExpr::Field(op) => op.compile(),
-            SimpleExpr::Parenthesized(op) => op.compile(),
+            #[cfg(feature = "profiling")]
+            SimpleExpr::Field(op) => crate::profile::compile_instrumented(op, cx),
+            #[cfg(not(feature = "profiling"))]
+            SimpleExpr::Field(op) => op.compile_in(cx),
+            SimpleExpr::Parenthesized(op) => match cx.shared_exprs.find(&op) {
+                Some(id) => {
+                    let op = unshare_expr(op).compile_in(cx);
+                    CompiledExpr::new(move |ctx, scope| {
+                        if let Some(&result) = scope.shared_exprs.get(&id) {
+                            return result;
+                        }
+                        let result = op.execute_in(ctx, scope);
+                        scope.shared_exprs.insert(id, result);
+                        result
+                    })
+                }
+                None => unshare_expr(op).compile_in(cx),
+            },
             SimpleExpr::Unary {
                 op: UnaryOp::Not,
                 arg,
             } => {
-                let arg = arg.compile();
-                CompiledExpr::new(move |ctx| !arg.execute(ctx))
+                let arg = arg.compile_in(cx);
+                CompiledExpr::new(move |ctx, scope| !arg.execute_in(ctx, scope))
+            }
+        }
+    }
+
+    fn compile_three_valued_in(
+        self,
+        cx: &mut CompileContext<'s>,
+    ) -> CompiledExpr<'s, Option<bool>> {
+        match self {
+            SimpleExpr::Field(op) => op.compile_three_valued_in(cx),
+            SimpleExpr::Parenthesized(op) => unshare_expr(op).compile_three_valued_in(cx),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => {
+                let arg = arg.compile_three_valued_in(cx);
+                CompiledExpr::new(move |ctx, scope| arg.execute_in(ctx, scope).map(|value| !value))
             }
         }
     }
@@ -97,7 +232,7 @@ fn test() {
         assert_eq!(expr.execute(ctx), true);
     }
 
-    let parenthesized_expr = |expr| SimpleExpr::Parenthesized(Box::new(CombinedExpr::Simple(expr)));
+    let parenthesized_expr = |expr| SimpleExpr::Parenthesized(Arc::new(CombinedExpr::Simple(expr)));
 
     {
         let expr = assert_ok!(
@@ -174,3 +309,46 @@ fn test() {
         not_expr(parenthesized_expr(not_expr(not_expr(t_expr()))))
     );
 }
+
+#[test]
+fn test_bool_int_comparison() {
+    let mut scheme = Scheme! { ssl: Bool, tcp.port: Int };
+
+    let err = scheme.parse("ssl == 1").unwrap_err();
+    assert_eq!(err.message(), "cannot use this operation type Bool");
+    assert_eq!(err.token(), "==");
+
+    scheme.set_lenient_bool_comparisons(true);
+
+    let (ast, warnings) = scheme
+        .parse_with_warnings("ssl == 1 and tcp.port == 80")
+        .unwrap();
+    assert_eq!(ast, scheme.parse("ssl and tcp.port == 80").unwrap());
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| (warning.token(), warning.span()))
+            .collect::<Vec<_>>(),
+        [("== 1", 4..8)]
+    );
+
+    for (filter, expected) in &[
+        ("ssl != 0", "ssl"),
+        ("ssl eq 0", "not ssl"),
+        ("ssl != 1 or ssl", "not ssl or ssl"),
+        ("not ssl == 0", "not not ssl"),
+    ] {
+        let (ast, warnings) = scheme.parse_with_warnings(filter).unwrap();
+        assert_eq!(ast, scheme.parse(expected).unwrap());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    let err = scheme.parse("ssl == 2").unwrap_err();
+    assert_eq!(err.message(), "expected 0 or 1");
+    assert_eq!(err.token(), "2");
+
+    let err = scheme.parse("ssl < 1").unwrap_err();
+    assert_eq!(err.token(), "<");
+
+    assert_eq!(scheme.parse_with_warnings("ssl").unwrap().1, []);
+}
@@ -0,0 +1,400 @@
+//! Traversal of a parsed [`FilterAst`](::FilterAst).
+//!
+//! Implement [`Visitor`] (or [`VisitorMut`] for in-place rewrites) and
+//! override methods for the nodes of interest. Default implementations of
+//! each `visit_*` method call the corresponding `walk_*` function which
+//! visits children of the node, so overridden methods should call it too
+//! to continue traversal into the subtree.
+//!
+//! ```
+//! use wirefilter::{visitor::Visitor, Field, Scheme};
+//!
+//! struct FieldCollector<'s>(Vec<Field<'s>>);
+//!
+//! impl<'s> Visitor<'s> for FieldCollector<'s> {
+//!     fn visit_field(&mut self, field: &Field<'s>) {
+//!         self.0.push(*field);
+//!     }
+//! }
+//!
+//! let scheme = Scheme! { http.host: Bytes, tcp.port: Int };
+//! let ast = scheme.parse(r#"http.host == "example.org" && tcp.port == 443"#).unwrap();
+//!
+//! let mut collector = FieldCollector(Vec::new());
+//! collector.visit_expr(ast.expression());
+//!
+//! let names: Vec<_> = collector.0.iter().map(|field| field.name()).collect();
+//! assert_eq!(names, ["http.host", "tcp.port"]);
+//! ```
+
+use super::{
+    combined_expr::CombinedExpr,
+    field_expr::{FieldExpr, FieldOp, LhsFieldExpr},
+    function_expr::{FunctionCallArgExpr, FunctionCallExpr},
+    simple_expr::SimpleExpr,
+};
+use crate::{scheme::Field, types::RhsValue};
+use std::sync::Arc;
+
+/// Read-only traversal of an AST.
+pub trait Visitor<'s> {
+    /// Visits a top-level or a parenthesized expression.
+    fn visit_expr(&mut self, expr: &CombinedExpr<'s>) {
+        walk_expr(self, expr)
+    }
+
+    /// Visits a simple expression.
+    fn visit_simple_expr(&mut self, expr: &SimpleExpr<'s>) {
+        walk_simple_expr(self, expr)
+    }
+
+    /// Visits an operation on a field or a function call.
+    fn visit_field_expr(&mut self, expr: &FieldExpr<'s>) {
+        walk_field_expr(self, expr)
+    }
+
+    /// Visits a field or a function call.
+    fn visit_lhs_field_expr(&mut self, expr: &LhsFieldExpr<'s>) {
+        walk_lhs_field_expr(self, expr)
+    }
+
+    /// Visits a field.
+    fn visit_field(&mut self, _field: &Field<'s>) {}
+
+    /// Visits an operation of a [`FieldExpr`].
+    fn visit_field_op(&mut self, _op: &FieldOp<'s>) {}
+
+    /// Visits a function call.
+    fn visit_function_call_expr(&mut self, expr: &FunctionCallExpr<'s>) {
+        walk_function_call_expr(self, expr)
+    }
+
+    /// Visits an argument of a function call.
+    fn visit_function_call_arg_expr(&mut self, expr: &FunctionCallArgExpr<'s>) {
+        walk_function_call_arg_expr(self, expr)
+    }
+
+    /// Visits a literal passed as a function argument.
+    fn visit_literal(&mut self, _literal: &RhsValue) {}
+}
+
+/// Visits children of a [`CombinedExpr`].
+pub fn walk_expr<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, expr: &CombinedExpr<'s>) {
+    match expr {
+        CombinedExpr::Simple(expr) => visitor.visit_simple_expr(expr),
+        CombinedExpr::Combining { items, .. } => {
+            for item in items {
+                visitor.visit_expr(item);
+            }
+        }
+    }
+}
+
+/// Visits children of a [`SimpleExpr`].
+pub fn walk_simple_expr<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, expr: &SimpleExpr<'s>) {
+    match expr {
+        SimpleExpr::Field(expr) => visitor.visit_field_expr(expr),
+        SimpleExpr::Parenthesized(expr) => visitor.visit_expr(expr),
+        SimpleExpr::Unary { arg, .. } => visitor.visit_simple_expr(arg),
+    }
+}
+
+/// Visits children of a [`FieldExpr`].
+pub fn walk_field_expr<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, expr: &FieldExpr<'s>) {
+    visitor.visit_lhs_field_expr(expr.lhs());
+    visitor.visit_field_op(expr.op());
+    if let FieldOp::FieldOrdering { rhs_field, .. } = expr.op() {
+        visitor.visit_field(rhs_field);
+    }
+}
+
+/// Visits children of a [`LhsFieldExpr`].
+pub fn walk_lhs_field_expr<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, expr: &LhsFieldExpr<'s>) {
+    match expr {
+        LhsFieldExpr::Field(field) => visitor.visit_field(field),
+        LhsFieldExpr::Slice(slice) => visitor.visit_field(&slice.field),
+        LhsFieldExpr::FunctionCallExpr(call) => visitor.visit_function_call_expr(call),
+    }
+}
+
+/// Visits children of a [`FunctionCallExpr`].
+pub fn walk_function_call_expr<'s, V: Visitor<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &FunctionCallExpr<'s>,
+) {
+    for arg in expr.args() {
+        visitor.visit_function_call_arg_expr(arg);
+    }
+}
+
+/// Visits children of a [`FunctionCallArgExpr`].
+pub fn walk_function_call_arg_expr<'s, V: Visitor<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &FunctionCallArgExpr<'s>,
+) {
+    match expr {
+        FunctionCallArgExpr::LhsFieldExpr(expr) => visitor.visit_lhs_field_expr(expr),
+        FunctionCallArgExpr::Literal(literal) => visitor.visit_literal(literal),
+    }
+}
+
+/// In-place traversal of an AST.
+///
+/// Rewrites must preserve types of fields, function calls and literals, as
+/// well as kinds of function arguments, because the AST is not type-checked
+/// again before compilation.
+pub trait VisitorMut<'s> {
+    /// Visits a top-level or a parenthesized expression.
+    fn visit_expr(&mut self, expr: &mut CombinedExpr<'s>) {
+        walk_expr_mut(self, expr)
+    }
+
+    /// Visits a simple expression.
+    fn visit_simple_expr(&mut self, expr: &mut SimpleExpr<'s>) {
+        walk_simple_expr_mut(self, expr)
+    }
+
+    /// Visits an operation on a field or a function call.
+    fn visit_field_expr(&mut self, expr: &mut FieldExpr<'s>) {
+        walk_field_expr_mut(self, expr)
+    }
+
+    /// Visits a field or a function call.
+    fn visit_lhs_field_expr(&mut self, expr: &mut LhsFieldExpr<'s>) {
+        walk_lhs_field_expr_mut(self, expr)
+    }
+
+    /// Visits a field.
+    fn visit_field(&mut self, _field: &mut Field<'s>) {}
+
+    /// Visits an operation of a [`FieldExpr`].
+    fn visit_field_op(&mut self, _op: &mut FieldOp<'s>) {}
+
+    /// Visits a function call.
+    fn visit_function_call_expr(&mut self, expr: &mut FunctionCallExpr<'s>) {
+        walk_function_call_expr_mut(self, expr)
+    }
+
+    /// Visits an argument of a function call.
+    fn visit_function_call_arg_expr(&mut self, expr: &mut FunctionCallArgExpr<'s>) {
+        walk_function_call_arg_expr_mut(self, expr)
+    }
+
+    /// Visits a literal passed as a function argument.
+    fn visit_literal(&mut self, _literal: &mut RhsValue) {}
+}
+
+/// Visits children of a [`CombinedExpr`] mutably.
+pub fn walk_expr_mut<'s, V: VisitorMut<'s> + ?Sized>(visitor: &mut V, expr: &mut CombinedExpr<'s>) {
+    match expr {
+        CombinedExpr::Simple(expr) => visitor.visit_simple_expr(expr),
+        CombinedExpr::Combining { items, .. } => {
+            for item in items {
+                visitor.visit_expr(item);
+            }
+        }
+    }
+}
+
+/// Visits children of a [`SimpleExpr`] mutably.
+pub fn walk_simple_expr_mut<'s, V: VisitorMut<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut SimpleExpr<'s>,
+) {
+    match expr {
+        SimpleExpr::Field(expr) => visitor.visit_field_expr(expr),
+        SimpleExpr::Parenthesized(expr) => visitor.visit_expr(Arc::make_mut(expr)),
+        SimpleExpr::Unary { arg, .. } => visitor.visit_simple_expr(arg),
+    }
+}
+
+/// Visits children of a [`FieldExpr`] mutably.
+pub fn walk_field_expr_mut<'s, V: VisitorMut<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut FieldExpr<'s>,
+) {
+    visitor.visit_lhs_field_expr(expr.lhs_mut());
+    visitor.visit_field_op(expr.op_mut());
+    if let FieldOp::FieldOrdering { rhs_field, .. } = expr.op_mut() {
+        visitor.visit_field(rhs_field);
+    }
+}
+
+/// Visits children of a [`LhsFieldExpr`] mutably.
+pub fn walk_lhs_field_expr_mut<'s, V: VisitorMut<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut LhsFieldExpr<'s>,
+) {
+    match expr {
+        LhsFieldExpr::Field(field) => visitor.visit_field(field),
+        LhsFieldExpr::Slice(slice) => visitor.visit_field(&mut slice.field),
+        LhsFieldExpr::FunctionCallExpr(call) => visitor.visit_function_call_expr(call),
+    }
+}
+
+/// Visits children of a [`FunctionCallExpr`] mutably.
+pub fn walk_function_call_expr_mut<'s, V: VisitorMut<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut FunctionCallExpr<'s>,
+) {
+    for arg in expr.args_mut() {
+        visitor.visit_function_call_arg_expr(arg);
+    }
+}
+
+/// Visits children of a [`FunctionCallArgExpr`] mutably.
+pub fn walk_function_call_arg_expr_mut<'s, V: VisitorMut<'s> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut FunctionCallArgExpr<'s>,
+) {
+    match expr {
+        FunctionCallArgExpr::LhsFieldExpr(expr) => visitor.visit_lhs_field_expr(expr),
+        FunctionCallArgExpr::Literal(literal) => visitor.visit_literal(literal),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        functions::{Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionParam},
+        rhs_types::Bytes,
+        scheme::Scheme,
+        types::{LhsValue, Type},
+    };
+
+    fn echo_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    fn scheme() -> Scheme {
+        let mut scheme = Scheme! {
+            http.host: Bytes,
+            http.path: Bytes,
+            tcp.port: Int,
+            ssl: Bool,
+        };
+        scheme
+            .add_function(
+                "concat".into(),
+                Function {
+                    params: vec![
+                        FunctionParam {
+                            arg_kind: FunctionArgKind::Field,
+                            val_type: Type::Bytes,
+                            literal_check: None,
+                        },
+                        FunctionParam {
+                            arg_kind: FunctionArgKind::Literal,
+                            val_type: Type::Bytes,
+                            literal_check: None,
+                        },
+                    ],
+                    opt_params: vec![],
+                    return_type: Type::Bytes,
+                    implementation: FunctionImpl::new(echo_function),
+                },
+            )
+            .unwrap();
+        scheme
+    }
+
+    #[test]
+    fn test_visitor() {
+        #[derive(Default)]
+        struct Collector {
+            fields: Vec<String>,
+            functions: Vec<String>,
+            literals: usize,
+        }
+
+        impl<'s> Visitor<'s> for Collector {
+            fn visit_field(&mut self, field: &Field<'s>) {
+                self.fields.push(field.name().to_owned());
+            }
+
+            fn visit_function_call_expr(&mut self, expr: &FunctionCallExpr<'s>) {
+                self.functions.push(expr.name().to_owned());
+                walk_function_call_expr(self, expr);
+            }
+
+            fn visit_literal(&mut self, _literal: &RhsValue) {
+                self.literals += 1;
+            }
+        }
+
+        let scheme = scheme();
+        let ast = scheme
+            .parse(r#"ssl and (tcp.port == 443 or not concat(http.path, "/") == "/a/")"#)
+            .unwrap();
+
+        let mut collector = Collector::default();
+        collector.visit_expr(ast.expression());
+
+        assert_eq!(collector.fields, ["ssl", "tcp.port", "http.path"]);
+        assert_eq!(collector.functions, ["concat"]);
+        assert_eq!(collector.literals, 1);
+    }
+
+    #[test]
+    fn test_used_fields() {
+        let scheme = scheme();
+        let ast = scheme
+            .parse(
+                r#"ssl and (
+                    http.path[1..] == "a"
+                    or concat(http.host, "/") == "/a/"
+                    or concat(http.path, "x") == "y"
+                ) and tcp.port == 443 and not ssl"#,
+            )
+            .unwrap();
+
+        let names = ast
+            .used_fields()
+            .iter()
+            .map(|field| field.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["ssl", "http.path", "http.host", "tcp.port"]);
+        assert_eq!(ast.used_functions(), ["concat"]);
+
+        let ast = scheme.parse("tcp.port == 80").unwrap();
+        assert_eq!(ast.used_functions(), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        struct Rewriter;
+
+        impl<'s> VisitorMut<'s> for Rewriter {
+            fn visit_field_op(&mut self, op: &mut FieldOp<'s>) {
+                if let FieldOp::Ordering {
+                    rhs: RhsValue::Int(value),
+                    ..
+                } = op
+                {
+                    *value = (value.value() + 8000).into();
+                }
+            }
+
+            fn visit_literal(&mut self, literal: &mut RhsValue) {
+                if let RhsValue::Bytes(value) = literal {
+                    *value = Bytes::new(value.to_ascii_uppercase(), value.format());
+                }
+            }
+        }
+
+        let scheme = scheme();
+        let mut ast = scheme
+            .parse(r#"tcp.port == 80 and concat(http.host, "x") == "a""#)
+            .unwrap();
+
+        Rewriter.visit_expr(ast.expression_mut());
+
+        assert_eq!(
+            ast,
+            scheme
+                .parse(r#"tcp.port == 8080 and concat(http.host, "X") == "a""#)
+                .unwrap()
+        );
+    }
+}
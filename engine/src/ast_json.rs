@@ -0,0 +1,377 @@
+use crate::{
+    ast::{visitor::Visitor, CombinedExpr, CombiningOp, FieldExpr, SimpleExpr, UnaryOp},
+    format::{format_expr, FormatOptions},
+    lex::{complete, LexWith},
+    scheme::{Field, Scheme},
+};
+use failure::Fail;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// Bumped whenever the representation below changes, which is a breaking
+// change. Documents of older versions keep being accepted.
+const VERSION: u64 = 1;
+
+/// An error that occurs if a JSON document can't be converted back to a
+/// filter by [`FilterAst::from_json`](::FilterAst::from_json).
+#[derive(Debug, PartialEq, Fail)]
+pub enum AstJsonError {
+    /// The document has a version this crate doesn't know about.
+    #[fail(display = "unsupported version {}", _0)]
+    UnsupportedVersion(u64),
+
+    /// A node doesn't follow the representation, like a node of an unknown
+    /// kind or without a required property.
+    #[fail(display = "invalid node at {}: {}", path, message)]
+    InvalidNode {
+        /// JSON pointer to the node.
+        path: String,
+        /// What's wrong with the node.
+        message: String,
+    },
+
+    /// A comparison is not valid in the scheme, like one of an unknown field
+    /// or of a field whose type has changed.
+    #[fail(
+        display = "invalid comparison {:?} at {}: {}",
+        comparison, path, message
+    )]
+    InvalidComparison {
+        /// JSON pointer to the node.
+        path: String,
+        /// Source of the comparison.
+        comparison: String,
+        /// Why the comparison was rejected.
+        message: String,
+    },
+}
+
+fn invalid_node(path: &str, message: impl ToString) -> AstJsonError {
+    AstJsonError::InvalidNode {
+        path: path.to_owned(),
+        message: message.to_string(),
+    }
+}
+
+// Returns names of fields used by a comparison, without duplicates and in
+// order of first appearance.
+fn field_names<'s>(expr: &FieldExpr<'s>) -> Vec<&'s str> {
+    struct FieldNameCollector<'s>(Vec<&'s str>);
+
+    impl<'s> Visitor<'s> for FieldNameCollector<'s> {
+        fn visit_field(&mut self, field: &Field<'s>) {
+            if !self.0.contains(&field.name()) {
+                self.0.push(field.name());
+            }
+        }
+    }
+
+    let mut collector = FieldNameCollector(Vec::new());
+    collector.visit_field_expr(expr);
+    collector.0
+}
+
+fn combining_kind(op: CombiningOp) -> &'static str {
+    match op {
+        CombiningOp::And => "and",
+        CombiningOp::Or => "or",
+        CombiningOp::Xor => "xor",
+    }
+}
+
+fn combined_to_json(expr: &CombinedExpr<'_>) -> Value {
+    match expr {
+        CombinedExpr::Simple(expr) => simple_to_json(expr),
+        CombinedExpr::Combining { op, items } => json!({
+            "kind": combining_kind(*op),
+            "operands": items.iter().map(combined_to_json).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+fn simple_to_json(expr: &SimpleExpr<'_>) -> Value {
+    match expr {
+        SimpleExpr::Field(field_expr) => json!({
+            "kind": "comparison",
+            "fields": field_names(field_expr),
+            "source": format_expr(&CombinedExpr::Simple(expr.clone()), &FormatOptions::default()),
+        }),
+        SimpleExpr::Parenthesized(expr) => json!({
+            "kind": "group",
+            "operand": combined_to_json(expr),
+        }),
+        SimpleExpr::Unary {
+            op: UnaryOp::Not,
+            arg,
+        } => json!({
+            "kind": "not",
+            "operand": simple_to_json(arg),
+        }),
+    }
+}
+
+/// Converts an expression into the versioned JSON representation.
+pub(crate) fn to_json(expr: &CombinedExpr<'_>) -> Value {
+    json!({
+        "version": VERSION,
+        "expression": combined_to_json(expr),
+    })
+}
+
+struct Decoder<'s> {
+    scheme: &'s Scheme,
+    depth: usize,
+}
+
+impl<'s> Decoder<'s> {
+    fn enter(&mut self, path: &str) -> Result<(), AstJsonError> {
+        let limit = self.scheme.max_nesting_depth();
+        if self.depth >= limit {
+            return Err(invalid_node(
+                path,
+                format_args!("nesting depth exceeds the limit of {}", limit),
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn property<'v>(value: &'v Value, path: &str, name: &str) -> Result<&'v Value, AstJsonError> {
+        value
+            .get(name)
+            .ok_or_else(|| invalid_node(path, format_args!("missing property {}", name)))
+    }
+
+    fn kind<'v>(value: &'v Value, path: &str) -> Result<&'v str, AstJsonError> {
+        Self::property(value, path, "kind")?
+            .as_str()
+            .ok_or_else(|| invalid_node(path, "kind is not a string"))
+    }
+
+    fn combined(&mut self, value: &Value, path: &str) -> Result<CombinedExpr<'s>, AstJsonError> {
+        let op = match Self::kind(value, path)? {
+            "and" => CombiningOp::And,
+            "or" => CombiningOp::Or,
+            "xor" => CombiningOp::Xor,
+            _ => return self.simple(value, path).map(CombinedExpr::Simple),
+        };
+
+        let operands = Self::property(value, path, "operands")?
+            .as_array()
+            .ok_or_else(|| invalid_node(path, "operands are not an array"))?;
+        if operands.len() < 2 {
+            return Err(invalid_node(path, "fewer than two operands"));
+        }
+
+        self.enter(path)?;
+        let items = operands
+            .iter()
+            .enumerate()
+            .map(|(i, operand)| self.combined(operand, &format!("{}/operands/{}", path, i)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.depth -= 1;
+
+        Ok(CombinedExpr::Combining { op, items })
+    }
+
+    fn simple(&mut self, value: &Value, path: &str) -> Result<SimpleExpr<'s>, AstJsonError> {
+        let operand_path = format!("{}/operand", path);
+
+        match Self::kind(value, path)? {
+            "comparison" => self.comparison(value, path).map(SimpleExpr::Field),
+            "group" => {
+                self.enter(path)?;
+                let expr = self.combined(Self::property(value, path, "operand")?, &operand_path)?;
+                self.depth -= 1;
+                Ok(SimpleExpr::Parenthesized(Arc::new(expr)))
+            }
+            "not" => {
+                let operand = Self::property(value, path, "operand")?;
+                match Self::kind(operand, &operand_path)? {
+                    "and" | "or" | "xor" => {
+                        return Err(invalid_node(
+                            &operand_path,
+                            "operand of not must be a comparison, a group or not",
+                        ));
+                    }
+                    _ => {}
+                }
+                self.enter(path)?;
+                let arg = self.simple(operand, &operand_path)?;
+                self.depth -= 1;
+                Ok(SimpleExpr::Unary {
+                    op: UnaryOp::Not,
+                    arg: Box::new(arg),
+                })
+            }
+            kind => Err(invalid_node(path, format_args!("unknown kind {}", kind))),
+        }
+    }
+
+    fn comparison(&mut self, value: &Value, path: &str) -> Result<FieldExpr<'s>, AstJsonError> {
+        let source = Self::property(value, path, "source")?
+            .as_str()
+            .ok_or_else(|| invalid_node(path, "source is not a string"))?;
+        let fields = Self::property(value, path, "fields")?
+            .as_array()
+            .and_then(|fields| fields.iter().map(Value::as_str).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| invalid_node(path, "fields are not an array of strings"))?;
+
+        let invalid_comparison = |message: String| AstJsonError::InvalidComparison {
+            path: path.to_owned(),
+            comparison: source.to_owned(),
+            message,
+        };
+
+        let scheme = self.scheme;
+        let expr = complete(FieldExpr::lex_with(source, scheme))
+            .map_err(|(kind, _)| invalid_comparison(kind.to_string()))?;
+
+        // Fields are listed so that consumers don't have to parse sources,
+        // and they must agree with them.
+        let used = field_names(&expr);
+        if used != fields {
+            return Err(invalid_comparison(format!(
+                "listed fields {:?} don't match used fields {:?}",
+                fields, used
+            )));
+        }
+
+        Ok(expr)
+    }
+}
+
+/// Converts the versioned JSON representation back into an expression,
+/// validating it against the scheme.
+pub(crate) fn from_json<'s>(
+    value: &Value,
+    scheme: &'s Scheme,
+) -> Result<CombinedExpr<'s>, AstJsonError> {
+    let version = Decoder::property(value, "", "version")?
+        .as_u64()
+        .ok_or_else(|| invalid_node("", "version is not an unsigned integer"))?;
+    if version != VERSION {
+        return Err(AstJsonError::UnsupportedVersion(version));
+    }
+
+    let mut decoder = Decoder { scheme, depth: 0 };
+    decoder.combined(Decoder::property(value, "", "expression")?, "/expression")
+}
+
+#[test]
+fn test_ast_json() {
+    use crate::ast::FilterAst;
+
+    let scheme = Scheme! { http.host: Bytes, tcp.port: Int, ssl: Bool };
+    let ast = scheme
+        .parse(
+            r#"ssl and not (tcp.port in {80 443} or http.host contains "a") xor tcp.port == 0x1F"#,
+        )
+        .unwrap();
+
+    let value = ast.to_json();
+    assert_eq!(
+        value,
+        json!({
+            "version": 1,
+            "expression": {
+                "kind": "xor",
+                "operands": [
+                    {
+                        "kind": "and",
+                        "operands": [
+                            { "kind": "comparison", "fields": ["ssl"], "source": "ssl" },
+                            {
+                                "kind": "not",
+                                "operand": {
+                                    "kind": "group",
+                                    "operand": {
+                                        "kind": "or",
+                                        "operands": [
+                                            {
+                                                "kind": "comparison",
+                                                "fields": ["tcp.port"],
+                                                "source": "tcp.port in { 80 443 }"
+                                            },
+                                            {
+                                                "kind": "comparison",
+                                                "fields": ["http.host"],
+                                                "source": "http.host contains \"a\""
+                                            }
+                                        ]
+                                    }
+                                }
+                            }
+                        ]
+                    },
+                    { "kind": "comparison", "fields": ["tcp.port"], "source": "tcp.port == 0x1F" }
+                ]
+            }
+        })
+    );
+    assert_eq!(FilterAst::from_json(&value, &scheme), Ok(ast));
+
+    let from_json = |value: Value| FilterAst::from_json(&value, &scheme);
+
+    assert_eq!(
+        from_json(json!({ "version": 2, "expression": {} })),
+        Err(AstJsonError::UnsupportedVersion(2))
+    );
+
+    assert_eq!(
+        from_json(json!({
+            "version": 1,
+            "expression": { "kind": "and", "operands": [{ "kind": "any" }, { "kind": "any" }] }
+        })),
+        Err(AstJsonError::InvalidNode {
+            path: "/expression/operands/0".into(),
+            message: "unknown kind any".into(),
+        })
+    );
+
+    assert_eq!(
+        from_json(json!({
+            "version": 1,
+            "expression": {
+                "kind": "not",
+                "operand": {
+                    "kind": "or",
+                    "operands": [
+                        { "kind": "comparison", "fields": ["ssl"], "source": "ssl" },
+                        { "kind": "comparison", "fields": ["ssl"], "source": "ssl" }
+                    ]
+                }
+            }
+        })),
+        Err(AstJsonError::InvalidNode {
+            path: "/expression/operand".into(),
+            message: "operand of not must be a comparison, a group or not".into(),
+        })
+    );
+
+    // comparisons are validated against the scheme
+    match from_json(json!({
+        "version": 1,
+        "expression": { "kind": "comparison", "fields": ["ip.src"], "source": "ip.src == 10.0.0.1" }
+    })) {
+        Err(AstJsonError::InvalidComparison {
+            path, comparison, ..
+        }) => {
+            assert_eq!(path, "/expression");
+            assert_eq!(comparison, "ip.src == 10.0.0.1");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    assert_eq!(
+        from_json(json!({
+            "version": 1,
+            "expression": { "kind": "comparison", "fields": ["ssl"], "source": "tcp.port == 80" }
+        })),
+        Err(AstJsonError::InvalidComparison {
+            path: "/expression".into(),
+            comparison: "tcp.port == 80".into(),
+            message: r#"listed fields ["ssl"] don't match used fields ["tcp.port"]"#.into(),
+        })
+    );
+}
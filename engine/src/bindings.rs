@@ -0,0 +1,255 @@
+use crate::{
+    ast::{visitor::VisitorMut, CombinedExpr, FieldExpr, FieldOp},
+    lex::{expect, take_while, Lex, LexResult},
+    types::{GetType, RhsValue, Type, TypeMismatchError},
+};
+use failure::Fail;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// A typed placeholder for a value, like `${host:Bytes}`, that is bound
+/// after parsing with [`FilterAst::bind`](::FilterAst::bind).
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Placeholder {
+    name: String,
+    #[serde(rename = "type")]
+    ty: Type,
+}
+
+impl Placeholder {
+    /// Name of the placeholder.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl GetType for Placeholder {
+    fn get_type(&self) -> Type {
+        self.ty
+    }
+}
+
+impl Display for Placeholder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "${{{}:{:?}}}", self.name, self.ty)
+    }
+}
+
+impl<'i> Lex<'i> for Placeholder {
+    fn lex(input: &str) -> LexResult<'_, Self> {
+        let input = expect(input, "${")?;
+        let (name, input) = take_while(input, "placeholder name", |c| {
+            c.is_ascii_alphanumeric() || c == '_'
+        })?;
+        let input = expect(input, ":")?;
+        let (ty, input) = Type::lex(input)?;
+        let input = expect(input, "}")?;
+        Ok((
+            Placeholder {
+                name: name.to_owned(),
+                ty,
+            },
+            input,
+        ))
+    }
+}
+
+/// Values of placeholders to bind a filter with.
+#[derive(Debug, Default, Clone)]
+pub struct Bindings {
+    values: HashMap<String, RhsValue>,
+}
+
+impl Bindings {
+    /// Creates an empty set of bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of a placeholder, replacing the previous one.
+    pub fn set(&mut self, name: impl Into<String>, value: RhsValue) -> &mut Self {
+        self.values.insert(name.into(), value);
+        self
+    }
+
+    /// Returns the value of a placeholder, if it's set.
+    pub fn get(&self, name: &str) -> Option<&RhsValue> {
+        self.values.get(name)
+    }
+}
+
+/// An error that occurs if a filter can't be bound with the given
+/// [`Bindings`].
+#[derive(Debug, PartialEq, Fail)]
+pub enum BindError {
+    /// A placeholder used by the filter has no value.
+    #[fail(display = "placeholder {} is not bound", _0)]
+    Unbound(String),
+
+    /// A placeholder has a value of a different type.
+    #[fail(display = "invalid value of placeholder {}: {}", name, mismatch)]
+    TypeMismatch {
+        /// Name of the placeholder.
+        name: String,
+        /// Type mismatch error.
+        #[cause]
+        mismatch: TypeMismatchError,
+    },
+}
+
+// Replaces placeholders with bound values, stopping at the first error.
+struct Binder<'b> {
+    bindings: &'b Bindings,
+    error: Option<BindError>,
+}
+
+impl<'s, 'b> VisitorMut<'s> for Binder<'b> {
+    fn visit_field_expr(&mut self, expr: &mut FieldExpr<'s>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let (op, placeholder) = match expr.op() {
+            FieldOp::PlaceholderOrdering { op, placeholder } => (*op, placeholder),
+            _ => return,
+        };
+
+        let rhs = match self.bindings.get(placeholder.name()) {
+            Some(rhs) => rhs,
+            None => {
+                self.error = Some(BindError::Unbound(placeholder.name().to_owned()));
+                return;
+            }
+        };
+
+        if rhs.get_type() != placeholder.get_type() {
+            self.error = Some(BindError::TypeMismatch {
+                name: placeholder.name().to_owned(),
+                mismatch: TypeMismatchError {
+                    expected: placeholder.get_type(),
+                    actual: rhs.get_type(),
+                },
+            });
+            return;
+        }
+
+        *expr.op_mut() = FieldOp::Ordering {
+            op,
+            rhs: rhs.clone(),
+        };
+    }
+}
+
+pub(crate) fn bind<'s>(expr: &mut CombinedExpr<'s>, bindings: &Bindings) -> Result<(), BindError> {
+    let mut binder = Binder {
+        bindings,
+        error: None,
+    };
+    binder.visit_expr(expr);
+    match binder.error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_lex() {
+    use crate::lex::LexErrorKind;
+
+    let placeholder = assert_ok!(
+        Placeholder::lex("${host:Bytes};"),
+        Placeholder {
+            name: "host".into(),
+            ty: Type::Bytes,
+        },
+        ";"
+    );
+
+    assert_eq!(placeholder.to_string(), "${host:Bytes}");
+    assert_json!(placeholder, { "name": "host", "type": "Bytes" });
+
+    assert_err!(
+        Placeholder::lex("${:Int}"),
+        LexErrorKind::ExpectedName("placeholder name"),
+        ":Int}"
+    );
+
+    assert_err!(
+        Placeholder::lex("${port:Integer}"),
+        LexErrorKind::ExpectedLiteral("}"),
+        "eger}"
+    );
+
+    assert_err!(
+        Placeholder::lex("${port:Float}"),
+        LexErrorKind::ExpectedName("type"),
+        "Float}"
+    );
+}
+
+#[test]
+fn test_bind() {
+    use crate::{execution_context::ExecutionContext, filter::CompileError, rhs_types::IntLiteral};
+
+    let scheme = &Scheme! { http.host: Bytes, tcp.port: Int };
+
+    let template = scheme
+        .parse("http.host == ${host:Bytes} and tcp.port in { 80 443 } or tcp.port == ${port:Int} or http.host != ${host:Bytes}")
+        .unwrap();
+
+    assert_eq!(
+        template.placeholders(),
+        [
+            Placeholder::lex("${host:Bytes}").unwrap().0,
+            Placeholder::lex("${port:Int}").unwrap().0,
+        ]
+    );
+
+    assert_eq!(
+        template.to_string(),
+        "http.host == ${host:Bytes} and tcp.port in { 80 443 } or tcp.port == ${port:Int} or http.host != ${host:Bytes}"
+    );
+
+    let mut bindings = Bindings::new();
+    bindings.set("host", RhsValue::Bytes("example.org".to_owned().into()));
+
+    assert_eq!(
+        template.clone().compile().err(),
+        Some(CompileError::UnboundPlaceholder("host".into()))
+    );
+
+    let mut ast = template.clone();
+    assert_eq!(ast.bind(&bindings), Err(BindError::Unbound("port".into())));
+    assert_eq!(ast, template);
+
+    bindings.set("port", RhsValue::Bytes("8080".to_owned().into()));
+    assert_eq!(
+        ast.bind(&bindings),
+        Err(BindError::TypeMismatch {
+            name: "port".into(),
+            mismatch: TypeMismatchError {
+                expected: Type::Int,
+                actual: Type::Bytes,
+            },
+        })
+    );
+
+    bindings.set("port", RhsValue::Int(IntLiteral::from(8080)));
+    ast.bind(&bindings).unwrap();
+    assert!(ast.placeholders().is_empty());
+    assert_eq!(
+        ast,
+        scheme
+            .parse(r#"http.host == "example.org" and tcp.port in { 80 443 } or tcp.port == 8080 or http.host != "example.org""#)
+            .unwrap()
+    );
+
+    let filter = ast.compile().unwrap();
+    let mut ctx = ExecutionContext::new(scheme);
+    ctx.set_field_value("http.host", "example.org").unwrap();
+    ctx.set_field_value("tcp.port", 8080).unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(true));
+}
@@ -0,0 +1,326 @@
+use crate::{
+    scheme::Scheme,
+    types::{GetType, Type},
+};
+use std::ops::Range;
+
+/// Kind of a [`Completion`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CompletionKind {
+    /// Name of a field.
+    Field,
+    /// Name of a function, which has to be followed by `(`.
+    Function,
+    /// Name of a list with the `$` prefix.
+    List,
+    /// Name of a constant set of values.
+    Constant,
+    /// Comparison operator, like `==` or `contains`.
+    ComparisonOperator,
+    /// Logical operator, like `and` or `not`.
+    LogicalOperator,
+    /// Parentheses, braces, brackets and commas.
+    Punctuation,
+}
+
+/// A token that can be inserted at the cursor.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Completion {
+    /// Text of the token.
+    pub text: String,
+    /// What the token is.
+    pub kind: CompletionKind,
+}
+
+/// Completions of a partially typed filter returned by
+/// [`Scheme::complete`](::Scheme::complete).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Completions {
+    /// Range of byte offsets of the partially typed token before the cursor,
+    /// which is replaced by a completion. It's empty if the cursor doesn't
+    /// follow a token.
+    pub span: Range<usize>,
+    /// Tokens that can be inserted, in the order of fields, functions, lists
+    /// and constants as registered in the scheme, followed by operators.
+    pub items: Vec<Completion>,
+}
+
+const COMPARISON_OPERATORS: &[&str] = &[
+    "==",
+    "!=",
+    "<",
+    "<=",
+    ">",
+    ">=",
+    "eq",
+    "ne",
+    "lt",
+    "le",
+    "gt",
+    "ge",
+    "in",
+    "contains",
+    "any",
+    "matches",
+    "~",
+    "!~",
+    "ieq",
+    "startswith",
+    "endswith",
+    "&",
+    "|",
+    "^",
+    "bitwise_and",
+    "bitwise_or",
+    "bitwise_xor",
+];
+
+const LOGICAL_OPERATORS: &[&str] = &["not", "!", "and", "or", "xor", "&&", "||", "^^"];
+
+const PUNCTUATION: &[&str] = &["(", ")", "{", "}", "[", ","];
+
+// Returns the offset of the token being typed at the end of the input, which
+// is a name or an operator made of symbols.
+fn token_start(input: &str) -> usize {
+    let start = input
+        .trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+        .len();
+    if start < input.len() {
+        start
+    } else {
+        input
+            .trim_end_matches(|c: char| "=!<>~&|^".contains(c))
+            .len()
+    }
+}
+
+// Checks whether a token can follow the input by parsing them with a
+// trailing character that isn't valid anywhere, so that the parser always
+// fails, and checking that it fails only after the token.
+fn can_follow(scheme: &Scheme, input: &str, token: &str) -> bool {
+    let end = input.len() + token.len();
+    match scheme.parse(&format!("{}{} \u{0}", input, token)) {
+        Ok(_) => true,
+        Err(err) => err.span().start >= end,
+    }
+}
+
+pub(crate) fn completions(scheme: &Scheme, input: &str, cursor: usize) -> Completions {
+    // Nothing can be inserted outside of the input or inside of a character.
+    if !input.is_char_boundary(cursor) {
+        return Completions {
+            span: cursor..cursor,
+            items: Vec::new(),
+        };
+    }
+
+    let input = &input[..cursor];
+    let start = token_start(input);
+    let (input, prefix) = input.split_at(start);
+
+    // Nothing can follow input that is already invalid.
+    if !can_follow(scheme, input, "") {
+        return Completions {
+            span: start..cursor,
+            items: Vec::new(),
+        };
+    }
+
+    // Names of the same kind are accepted in the same places as long as they
+    // have the same types, so they are grouped by those, and only one name of
+    // each group is checked against the input.
+    let names = scheme
+        .iter_fields()
+        .map(|field| {
+            let types = vec![field.get_type()];
+            (field.name().to_owned(), CompletionKind::Field, Some(types))
+        })
+        .chain(scheme.function_names().map(|name| {
+            let types = scheme
+                .get_function_overloads(name)
+                .into_iter()
+                .flatten()
+                .map(|function| function.return_type)
+                .collect();
+            (name.to_owned(), CompletionKind::Function, Some(types))
+        }))
+        .chain(scheme.list_names().map(|name| {
+            let types = scheme
+                .get_list(name)
+                .iter()
+                .map(GetType::get_type)
+                .collect();
+            (format!("${}", name), CompletionKind::List, Some(types))
+        }))
+        .chain(scheme.constant_names().map(|name| {
+            let types = scheme
+                .get_constant(name)
+                .iter()
+                .map(|values| values.get_type())
+                .collect();
+            (name.to_owned(), CompletionKind::Constant, Some(types))
+        }));
+    let operators = COMPARISON_OPERATORS
+        .iter()
+        .map(|&op| (op, CompletionKind::ComparisonOperator))
+        .chain(
+            LOGICAL_OPERATORS
+                .iter()
+                .map(|&op| (op, CompletionKind::LogicalOperator)),
+        )
+        .chain(
+            PUNCTUATION
+                .iter()
+                .map(|&op| (op, CompletionKind::Punctuation)),
+        )
+        .map(|(op, kind)| (op.to_owned(), kind, None));
+
+    let mut groups: Vec<(CompletionKind, Vec<Type>, bool)> = Vec::new();
+
+    let accepts = |text: &str, kind: CompletionKind| match kind {
+        CompletionKind::Function => can_follow(scheme, input, &format!("{}(", text)),
+        _ => can_follow(scheme, input, text),
+    };
+
+    let items = names
+        .chain(operators)
+        .filter(|(text, kind, types)| {
+            if !text.starts_with(prefix) {
+                return false;
+            }
+            let types = match types {
+                Some(types) => types,
+                None => return accepts(text, *kind),
+            };
+            match groups
+                .iter()
+                .find(|(other_kind, other_types, _)| other_kind == kind && other_types == types)
+            {
+                Some(&(.., accepted)) => accepted,
+                None => {
+                    let accepted = accepts(text, *kind);
+                    groups.push((*kind, types.clone(), accepted));
+                    accepted
+                }
+            }
+        })
+        .map(|(text, kind, _)| Completion { text, kind })
+        .collect();
+
+    Completions {
+        span: start..cursor,
+        items,
+    }
+}
+
+#[test]
+fn test_complete() {
+    use crate::{
+        functions::{Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionParam},
+        types::{LhsValue, RhsValues, Type},
+    };
+
+    fn lower<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let mut scheme = Scheme! { http.host: Bytes, tcp.port: Int, ssl: Bool };
+    scheme
+        .add_function(
+            "lower".into(),
+            Function {
+                params: vec![FunctionParam {
+                    arg_kind: FunctionArgKind::Field,
+                    val_type: Type::Bytes,
+                    literal_check: None,
+                }],
+                opt_params: vec![],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::new(lower),
+            },
+        )
+        .unwrap();
+    scheme.add_list("hosts".into(), Type::Bytes).unwrap();
+    scheme
+        .add_constant(
+            "web_ports".into(),
+            RhsValues::Int(vec![80.into()..=80.into()]),
+        )
+        .unwrap();
+
+    let complete = |input: &str| {
+        let completions = scheme.complete(input, input.len());
+        let items = completions
+            .items
+            .into_iter()
+            .map(|item| (item.text, item.kind))
+            .collect::<Vec<_>>();
+        (completions.span, items)
+    };
+    let texts = |input: &str| {
+        complete(input)
+            .1
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        texts(""),
+        ["http.host", "tcp.port", "ssl", "lower", "not", "!", "("]
+    );
+
+    assert_eq!(
+        complete("tcp.port == 80 or ht"),
+        (
+            18..20,
+            vec![("http.host".to_owned(), CompletionKind::Field)]
+        )
+    );
+
+    // operators depend on the type of the field
+    let int_ops = texts("tcp.port ");
+    assert!(int_ops.contains(&"==".to_owned()));
+    assert!(int_ops.contains(&"in".to_owned()));
+    assert!(int_ops.contains(&"&".to_owned()));
+    assert!(int_ops.contains(&"not".to_owned()));
+    assert!(!int_ops.contains(&"contains".to_owned()));
+    assert!(!int_ops.contains(&"&&".to_owned()));
+    assert!(!int_ops.contains(&"http.host".to_owned()));
+
+    let bytes_ops = texts("lower(http.host) ");
+    assert!(bytes_ops.contains(&"contains".to_owned()));
+    assert!(bytes_ops.contains(&"matches".to_owned()));
+    assert!(!bytes_ops.contains(&"&".to_owned()));
+
+    assert_eq!(complete("tcp.port =").0, 9..10);
+    assert_eq!(texts("tcp.port ="), ["=="]);
+
+    // arguments of functions and right hand sides are type-checked too
+    assert_eq!(texts("lower("), ["http.host", "lower"]);
+    assert_eq!(texts("lower(http.host"), ["http.host"]);
+    let after_arg = texts("lower(http.host ");
+    assert!(after_arg.contains(&")".to_owned()));
+    assert!(!after_arg.contains(&"http.host".to_owned()));
+    assert_eq!(texts("http.host in "), ["$hosts", "{"]);
+    assert_eq!(
+        complete("http.host in $h"),
+        (13..15, vec![("$hosts".to_owned(), CompletionKind::List)])
+    );
+    assert_eq!(texts("tcp.port in $h"), Vec::<String>::new());
+    assert_eq!(texts("tcp.port == "), ["tcp.port"]);
+    assert_eq!(texts("tcp.port in "), ["web_ports", "{"]);
+
+    assert_eq!(texts("http.host contains \"a\" a"), ["and"]);
+    assert_eq!(texts("(ssl "), ["and", "or", "xor", "&&", "||", "^^", ")"]);
+
+    // nothing can be inserted into a string
+    assert_eq!(texts("http.host == \"a "), Vec::<String>::new());
+
+    // text after the cursor is ignored
+    assert_eq!(scheme.complete("ss and tcp.port == 80", 2).items.len(), 1);
+
+    // cursors outside of the input or inside of a character have nothing
+    assert!(scheme.complete("ssl", 4).items.is_empty());
+    assert!(scheme.complete("http.host == \"é", 15).items.is_empty());
+}
@@ -0,0 +1,298 @@
+use crate::{
+    ast::{
+        CombinedExpr, CombiningOp, FieldExpr, FieldOp, FunctionCallArgExpr, LhsFieldExpr, NodeId,
+        SimpleExpr,
+    },
+    scheme::Field,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Relative costs of evaluating parts of a filter, used by
+/// [`FilterAst::estimate_cost`](::FilterAst::estimate_cost) and
+/// [`FilterAst::reorder_by_cost`](::FilterAst::reorder_by_cost).
+///
+/// Costs are abstract units that only make sense relative to each other.
+/// All methods have defaults, so implementations only need to override the
+/// ones they have better knowledge of.
+pub trait CostModel {
+    /// Returns the cost of applying an operator to a value of unit weight.
+    ///
+    /// By default regular expressions are the most expensive, followed by
+    /// substring searches, prefix and suffix checks and set lookups, with
+    /// plain comparisons being the cheapest.
+    fn op_cost(&self, op: &FieldOp<'_>) -> u64 {
+        match op {
+            FieldOp::IsTrue
+            | FieldOp::Ordering { .. }
+            | FieldOp::FieldOrdering { .. }
+            | FieldOp::PlaceholderOrdering { .. }
+            | FieldOp::Int { .. }
+            | FieldOp::IntOrdering { .. } => 1,
+            FieldOp::CaseInsensitiveEqual(_)
+            | FieldOp::StartsWith(_)
+            | FieldOp::EndsWith(_)
+            | FieldOp::OneOf(_)
+            | FieldOp::InList(_) => 2,
+            FieldOp::Contains(_) => 10,
+            FieldOp::ContainsAny(_) => 15,
+            FieldOp::Matches(_) => 100,
+        }
+    }
+
+    /// Returns the weight of a field, which multiplies costs of operators
+    /// applied to it, e.g. proportional to the typical size of its value.
+    fn field_weight(&self, _field: Field<'_>) -> u64 {
+        1
+    }
+
+    /// Returns the cost of calling a function, excluding its arguments.
+    fn function_cost(&self, _name: &str) -> u64 {
+        5
+    }
+}
+
+/// The default [`CostModel`] with configurable per-field weights.
+#[derive(Debug, Default, Clone)]
+pub struct DefaultCostModel {
+    field_weights: HashMap<String, u64>,
+}
+
+impl DefaultCostModel {
+    /// Creates a model where all fields have unit weight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the weight of the field with the given name.
+    pub fn set_field_weight(&mut self, name: &str, weight: u64) {
+        self.field_weights.insert(name.to_owned(), weight);
+    }
+}
+
+impl CostModel for DefaultCostModel {
+    fn field_weight(&self, field: Field<'_>) -> u64 {
+        self.field_weights.get(field.name()).cloned().unwrap_or(1)
+    }
+}
+
+// Returns the cost of computing the LHS value and its weight.
+fn lhs_cost<M: CostModel + ?Sized>(lhs: &LhsFieldExpr<'_>, model: &M) -> (u64, u64) {
+    match lhs {
+        LhsFieldExpr::Field(field) => (0, model.field_weight(*field)),
+        LhsFieldExpr::Slice(slice) => (0, model.field_weight(slice.field())),
+        LhsFieldExpr::FunctionCallExpr(call) => call.args().iter().fold(
+            (model.function_cost(call.name()), 1),
+            |(cost, weight), arg| match arg {
+                FunctionCallArgExpr::LhsFieldExpr(lhs) => {
+                    let (arg_cost, arg_weight) = lhs_cost(lhs, model);
+                    (cost.saturating_add(arg_cost), weight.max(arg_weight))
+                }
+                FunctionCallArgExpr::Literal(_) => (cost, weight),
+            },
+        ),
+    }
+}
+
+fn field_expr_cost<M: CostModel + ?Sized>(expr: &FieldExpr<'_>, model: &M) -> u64 {
+    let (cost, mut weight) = lhs_cost(expr.lhs(), model);
+    if let FieldOp::FieldOrdering { rhs_field, .. } = expr.op() {
+        weight = weight.max(model.field_weight(*rhs_field));
+    }
+    cost.saturating_add(model.op_cost(expr.op()).saturating_mul(weight))
+}
+
+fn simple_expr_cost<M: CostModel + ?Sized>(expr: &SimpleExpr<'_>, model: &M) -> u64 {
+    match expr {
+        SimpleExpr::Field(expr) => field_expr_cost(expr, model),
+        SimpleExpr::Parenthesized(expr) => estimate_cost(expr, model),
+        SimpleExpr::Unary { arg, .. } => simple_expr_cost(arg, model),
+    }
+}
+
+// Returns the cost of evaluating all comparisons in the expression, i.e.
+// without taking short-circuiting into account.
+pub(crate) fn estimate_cost<M: CostModel + ?Sized>(expr: &CombinedExpr<'_>, model: &M) -> u64 {
+    match expr {
+        CombinedExpr::Simple(expr) => simple_expr_cost(expr, model),
+        CombinedExpr::Combining { items, .. } => items.iter().fold(0, |cost, item| {
+            cost.saturating_add(estimate_cost(item, model))
+        }),
+    }
+}
+
+fn collect_node_costs<M: CostModel + ?Sized>(
+    expr: &CombinedExpr<'_>,
+    model: &M,
+    costs: &mut Vec<(NodeId, u64)>,
+) {
+    fn collect_simple<M: CostModel + ?Sized>(
+        expr: &SimpleExpr<'_>,
+        model: &M,
+        costs: &mut Vec<(NodeId, u64)>,
+    ) {
+        match expr {
+            SimpleExpr::Field(expr) => costs.push((expr.node_id(), field_expr_cost(expr, model))),
+            SimpleExpr::Parenthesized(expr) => collect_node_costs(expr, model, costs),
+            SimpleExpr::Unary { arg, .. } => collect_simple(arg, model, costs),
+        }
+    }
+
+    match expr {
+        CombinedExpr::Simple(expr) => collect_simple(expr, model, costs),
+        CombinedExpr::Combining { items, .. } => {
+            for item in items {
+                collect_node_costs(item, model, costs);
+            }
+        }
+    }
+}
+
+pub(crate) fn node_costs<M: CostModel + ?Sized>(
+    expr: &CombinedExpr<'_>,
+    model: &M,
+) -> Vec<(NodeId, u64)> {
+    let mut costs = Vec::new();
+    collect_node_costs(expr, model, &mut costs);
+    costs
+}
+
+fn reorder_simple<M: CostModel + ?Sized>(expr: &mut SimpleExpr<'_>, model: &M) {
+    match expr {
+        SimpleExpr::Field(_) => {}
+        SimpleExpr::Parenthesized(expr) => reorder_by_cost(Arc::make_mut(expr), model),
+        SimpleExpr::Unary { arg, .. } => reorder_simple(arg, model),
+    }
+}
+
+// Sorts operands of `and` and `or` by their cost, so that cheaper ones are
+// evaluated first and more expensive ones are more likely to be skipped.
+// The sort is stable, so operands of equal cost keep their relative order.
+pub(crate) fn reorder_by_cost<M: CostModel + ?Sized>(expr: &mut CombinedExpr<'_>, model: &M) {
+    match expr {
+        CombinedExpr::Simple(expr) => reorder_simple(expr, model),
+        CombinedExpr::Combining { op, items } => {
+            for item in items.iter_mut() {
+                reorder_by_cost(item, model);
+            }
+            match op {
+                CombiningOp::And | CombiningOp::Or => {
+                    items.sort_by_cached_key(|item| estimate_cost(item, model));
+                }
+                CombiningOp::Xor => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        functions::{Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionParam},
+        scheme::Scheme,
+        types::{LhsValue, Type},
+    };
+
+    fn lower_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    fn scheme() -> Scheme {
+        let mut scheme = Scheme! {
+            tcp.port: Int,
+            ssl: Bool,
+            http.host: Bytes,
+            http.body: Bytes,
+        };
+        scheme
+            .add_function(
+                "lower".into(),
+                Function {
+                    params: vec![FunctionParam {
+                        arg_kind: FunctionArgKind::Field,
+                        val_type: Type::Bytes,
+                        literal_check: None,
+                    }],
+                    opt_params: vec![],
+                    return_type: Type::Bytes,
+                    implementation: FunctionImpl::new(lower_function),
+                },
+            )
+            .unwrap();
+        scheme
+    }
+
+    fn model() -> DefaultCostModel {
+        let mut model = DefaultCostModel::new();
+        model.set_field_weight("http.body", 20);
+        model
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let scheme = scheme();
+        let model = model();
+        let cost = |filter| scheme.parse(filter).unwrap().estimate_cost(&model);
+
+        assert_eq!(cost("tcp.port == 80"), 1);
+        assert_eq!(cost("tcp.port in { 80 443 }"), 2);
+        assert_eq!(cost(r#"http.host contains "a""#), 10);
+        assert_eq!(cost(r#"http.host matches "a""#), 100);
+        assert_eq!(cost(r#"http.body matches "a""#), 2000);
+        assert_eq!(cost(r#"http.body[0..4] == "abcd""#), 20);
+        assert_eq!(cost(r#"lower(http.host) == "a""#), 6);
+        assert_eq!(cost(r#"lower(http.body) contains "a""#), 205);
+        assert_eq!(cost("http.host == http.body"), 20);
+        assert_eq!(
+            cost(r#"not (ssl or (tcp.port == 80 and http.host ~ "a"))"#),
+            102
+        );
+
+        let ast = scheme
+            .parse(r#"http.body ~ "a" and tcp.port == 80"#)
+            .unwrap();
+        let costs = ast
+            .node_costs(&model)
+            .into_iter()
+            .map(|(_, cost)| cost)
+            .collect::<Vec<_>>();
+        assert_eq!(costs, vec![2000, 1]);
+        assert_eq!(
+            ast.node_costs(&model)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            ast.node_ids()
+        );
+    }
+
+    #[test]
+    fn test_reorder_by_cost() {
+        let scheme = scheme();
+        let model = model();
+        let reorder = |filter| {
+            let mut ast = scheme.parse(filter).unwrap();
+            ast.reorder_by_cost(&model);
+            ast.to_canonical_string()
+        };
+
+        assert_eq!(
+            reorder(r#"http.body ~ "a" and tcp.port == 80"#),
+            r#"tcp.port == 80 and http.body ~ "a""#
+        );
+        assert_eq!(
+            reorder(
+                r#"(http.host contains "a" or ssl) and not (http.body ~ "a" or tcp.port == 80)"#
+            ),
+            r#"(ssl or http.host contains "a") and not (tcp.port == 80 or http.body ~ "a")"#
+        );
+        assert_eq!(
+            reorder("ssl and tcp.port == 443 and tcp.port != 80"),
+            "ssl and tcp.port == 443 and tcp.port != 80"
+        );
+        assert_eq!(
+            reorder(r#"http.host ~ "a" xor ssl"#),
+            r#"http.host ~ "a" xor ssl"#
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use crate::{
+    ast::{
+        CombinedExpr, CombiningOp, Expr, FieldExpr, FieldOp, LhsFieldExpr, OrderingOp, SimpleExpr,
+        UnaryOp,
+    },
+    execution_context::ExecutionContext,
+    scheme::{Field, Scheme},
+    types::{LhsValue, RhsValue, RhsValues},
+};
+use failure::Fail;
+use fnv::FnvHashMap;
+use std::{collections::BTreeSet, ops::RangeInclusive, sync::Arc};
+
+// Upper bound on the number of combinations of comparison outcomes that are
+// checked before giving up.
+const MAX_WORLDS: usize = 1 << 20;
+
+/// An error that occurs if filters can't be compared with
+/// [`FilterAst::is_equivalent_to`](::FilterAst::is_equivalent_to) or
+/// [`FilterAst::subsumes`](::FilterAst::subsumes).
+#[derive(Debug, PartialEq, Eq, Fail)]
+pub enum EquivalenceError {
+    /// Filters were parsed with different schemes.
+    #[fail(display = "filters belong to different schemes")]
+    SchemeMismatch,
+
+    /// Filters have too many unrelated comparisons to check all their
+    /// combinations.
+    #[fail(display = "filters are too complex to be compared")]
+    TooComplex,
+}
+
+// The boolean skeleton of a filter over indices of distinct comparisons.
+enum Formula {
+    Atom(usize),
+    Not(Box<Formula>),
+    Combining {
+        op: CombiningOp,
+        items: Vec<Formula>,
+    },
+}
+
+impl Formula {
+    fn eval(&self, atoms: &[bool]) -> bool {
+        match self {
+            Formula::Atom(index) => atoms[*index],
+            Formula::Not(arg) => !arg.eval(atoms),
+            Formula::Combining { op, items } => match op {
+                CombiningOp::And => items.iter().all(|item| item.eval(atoms)),
+                CombiningOp::Or => items.iter().any(|item| item.eval(atoms)),
+                CombiningOp::Xor => items.iter().fold(false, |acc, item| acc ^ item.eval(atoms)),
+            },
+        }
+    }
+}
+
+// Representative values of a field: comparisons from the filters have the
+// same outcome for all values between adjacent boundaries, so it's enough to
+// check the boundaries and values right next to them.
+enum Points {
+    Int(BTreeSet<i32>),
+    Bytes(BTreeSet<Vec<u8>>),
+}
+
+impl Points {
+    fn add_int_range(points: &mut BTreeSet<i32>, range: RangeInclusive<i32>) {
+        let (start, end) = range.into_inner();
+        points.extend(&[start.saturating_sub(1), start, end, end.saturating_add(1)]);
+    }
+
+    // Returns representative values if outcome of the comparison is fully
+    // determined by the value of the field.
+    fn collect(points: &mut FnvHashMap<usize, Points>, expr: &FieldExpr<'_>) -> bool {
+        let field = match expr.lhs() {
+            LhsFieldExpr::Field(field) => field.index(),
+            LhsFieldExpr::Slice(_) | LhsFieldExpr::FunctionCallExpr(_) => return false,
+        };
+
+        match expr.op() {
+            FieldOp::Ordering {
+                rhs: RhsValue::Int(value),
+                ..
+            } => {
+                if let Points::Int(points) = points
+                    .entry(field)
+                    .or_insert_with(|| Points::Int(BTreeSet::new()))
+                {
+                    Self::add_int_range(points, value.value()..=value.value());
+                }
+                true
+            }
+            FieldOp::OneOf(RhsValues::Int(ranges)) => {
+                if let Points::Int(points) = points
+                    .entry(field)
+                    .or_insert_with(|| Points::Int(BTreeSet::new()))
+                {
+                    for range in ranges {
+                        Self::add_int_range(points, range.start().value()..=range.end().value());
+                    }
+                }
+                true
+            }
+            FieldOp::Ordering {
+                op: OrderingOp::Equal,
+                rhs: RhsValue::Bytes(value),
+            }
+            | FieldOp::Ordering {
+                op: OrderingOp::NotEqual,
+                rhs: RhsValue::Bytes(value),
+            } => {
+                if let Points::Bytes(points) = points
+                    .entry(field)
+                    .or_insert_with(|| Points::Bytes(BTreeSet::new()))
+                {
+                    points.insert(value.to_vec());
+                }
+                true
+            }
+            FieldOp::OneOf(RhsValues::Bytes(values)) => {
+                if let Points::Bytes(points) = points
+                    .entry(field)
+                    .or_insert_with(|| Points::Bytes(BTreeSet::new()))
+                {
+                    points.extend(values.iter().map(|value| value.to_vec()));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn into_values(self) -> Vec<LhsValue<'static>> {
+        match self {
+            // Fields compared only with empty sets still need a value.
+            Points::Int(points) if points.is_empty() => vec![LhsValue::Int(0)],
+            Points::Int(points) => points.into_iter().map(LhsValue::Int).collect(),
+            Points::Bytes(points) => {
+                // A value longer than all literals is different from each
+                // of them.
+                let other = vec![0; points.iter().map(Vec::len).max().unwrap_or(0) + 1];
+                points
+                    .into_iter()
+                    .chain(Some(other))
+                    .map(LhsValue::from)
+                    .collect()
+            }
+        }
+    }
+}
+
+// Outcomes of comparisons that depend only on the value of a single field,
+// for each representative value.
+struct Dimension {
+    atoms: Vec<usize>,
+    outcomes: Vec<Vec<bool>>,
+}
+
+#[derive(Default)]
+struct Atoms<'s> {
+    indices: FnvHashMap<String, usize>,
+    exprs: Vec<FieldExpr<'s>>,
+}
+
+impl<'s> Atoms<'s> {
+    fn formula(&mut self, expr: &CombinedExpr<'s>) -> Formula {
+        match expr {
+            CombinedExpr::Simple(expr) => self.simple_formula(expr),
+            CombinedExpr::Combining { op, items } => Formula::Combining {
+                op: *op,
+                items: items.iter().map(|item| self.formula(item)).collect(),
+            },
+        }
+    }
+
+    fn simple_formula(&mut self, expr: &SimpleExpr<'s>) -> Formula {
+        match expr {
+            SimpleExpr::Field(expr) => {
+                // Serialized form is canonical, see `FilterSet`.
+                let key = serde_json::to_string(expr)
+                    .unwrap_or_else(|err| panic!("{} while serializing predicate {:?}", err, expr));
+                let exprs = &mut self.exprs;
+                Formula::Atom(*self.indices.entry(key).or_insert_with(|| {
+                    exprs.push(expr.clone());
+                    exprs.len() - 1
+                }))
+            }
+            SimpleExpr::Parenthesized(expr) => self.formula(expr),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => Formula::Not(Box::new(self.simple_formula(arg))),
+        }
+    }
+
+    // Splits comparisons into groups over the same field, where outcomes
+    // can be computed from representative values, and independent ones.
+    fn dimensions(&self, scheme: &'s Scheme) -> (Vec<Dimension>, Vec<usize>) {
+        let mut points = FnvHashMap::default();
+        let mut field_atoms = FnvHashMap::<usize, (Field<'s>, Vec<usize>)>::default();
+        let mut free = Vec::new();
+
+        for (index, expr) in self.exprs.iter().enumerate() {
+            if Points::collect(&mut points, expr) {
+                if let LhsFieldExpr::Field(field) = expr.lhs() {
+                    field_atoms
+                        .entry(field.index())
+                        .or_insert_with(|| (*field, Vec::new()))
+                        .1
+                        .push(index);
+                }
+            } else {
+                free.push(index);
+            }
+        }
+
+        let dimensions = field_atoms
+            .into_iter()
+            .map(|(_, (field, atoms))| {
+                let compiled = atoms
+                    .iter()
+                    .map(|&index| self.exprs[index].clone().compile())
+                    .collect::<Vec<_>>();
+
+                let outcomes = points
+                    .remove(&field.index())
+                    .unwrap()
+                    .into_values()
+                    .into_iter()
+                    .map(|value| {
+                        let mut ctx = ExecutionContext::new(scheme);
+                        ctx.set_field_value(field.name(), value).unwrap();
+                        compiled.iter().map(|expr| expr.execute(&ctx)).collect()
+                    })
+                    .collect();
+
+                Dimension { atoms, outcomes }
+            })
+            .collect();
+
+        (dimensions, free)
+    }
+}
+
+// Checks whether `lhs` implies `rhs` (or, if `both_ways`, that they're
+// equivalent) for all possible combinations of comparison outcomes.
+//
+// Comparisons that aren't related through representative values are treated
+// as independent, so the check can only produce false negatives.
+pub(crate) fn implies<'s>(
+    lhs: &CombinedExpr<'s>,
+    rhs: &CombinedExpr<'s>,
+    scheme: &'s Scheme,
+    both_ways: bool,
+) -> Result<bool, EquivalenceError> {
+    let mut atoms = Atoms::default();
+    let lhs = atoms.formula(lhs);
+    let rhs = atoms.formula(rhs);
+
+    let (dimensions, free) = atoms.dimensions(scheme);
+
+    let worlds = dimensions
+        .iter()
+        .map(|dimension| dimension.outcomes.len())
+        .chain(free.iter().map(|_| 2))
+        .try_fold(1usize, |acc, size| acc.checked_mul(size))
+        .filter(|&worlds| worlds <= MAX_WORLDS)
+        .ok_or(EquivalenceError::TooComplex)?;
+
+    let mut values = vec![false; atoms.exprs.len()];
+
+    for mut world in 0..worlds {
+        for dimension in &dimensions {
+            let outcomes = &dimension.outcomes[world % dimension.outcomes.len()];
+            world /= dimension.outcomes.len();
+            for (&atom, &outcome) in dimension.atoms.iter().zip(outcomes) {
+                values[atom] = outcome;
+            }
+        }
+
+        for &atom in &free {
+            values[atom] = world % 2 == 1;
+            world /= 2;
+        }
+
+        let lhs = lhs.eval(&values);
+        let rhs = rhs.eval(&values);
+
+        if (lhs && !rhs) || (both_ways && rhs && !lhs) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+// Returns the outcome of an expression if it's the same for all possible
+// combinations of comparison outcomes.
+pub(crate) fn constant_outcome<'s>(
+    expr: &CombinedExpr<'s>,
+    scheme: &'s Scheme,
+) -> Result<Option<bool>, EquivalenceError> {
+    let negated = CombinedExpr::Simple(SimpleExpr::Unary {
+        op: UnaryOp::Not,
+        arg: Box::new(SimpleExpr::Parenthesized(Arc::new(expr.clone()))),
+    });
+
+    Ok(if implies(expr, &negated, scheme, false)? {
+        Some(false)
+    } else if implies(&negated, expr, scheme, false)? {
+        Some(true)
+    } else {
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_equivalence() {
+        let scheme = Scheme! {
+            tcp.port: Int,
+            http.host: Bytes,
+            ssl: Bool,
+            tcp.flags: Int,
+        };
+
+        let equivalent = |lhs, rhs| {
+            scheme
+                .parse(lhs)
+                .unwrap()
+                .is_equivalent_to(&scheme.parse(rhs).unwrap())
+                .unwrap()
+        };
+
+        assert!(equivalent(
+            "ssl and tcp.port == 80",
+            "tcp.port eq 80 && ssl"
+        ));
+        assert!(equivalent(
+            "not (ssl or tcp.port == 80)",
+            "not ssl and tcp.port != 80"
+        ));
+        assert!(equivalent("tcp.port > 80", "tcp.port >= 81"));
+        assert!(equivalent(
+            "tcp.port in { 80..90 } and tcp.port != 85",
+            "tcp.port in { 80..84 86..90 }"
+        ));
+        assert!(equivalent(
+            r#"http.host == "a" or http.host == "b""#,
+            r#"http.host in { "b" "a" }"#
+        ));
+        assert!(equivalent("ssl xor ssl", "tcp.port == 1 and tcp.port == 2"));
+        assert!(equivalent(
+            "tcp.flags & 2 or tcp.flags & 2 and ssl",
+            "tcp.flags & 2"
+        ));
+
+        assert!(!equivalent("tcp.port > 80", "tcp.port > 81"));
+        assert!(!equivalent(r#"http.host == "a""#, r#"http.host != "b""#));
+        assert!(!equivalent("ssl", "not ssl"));
+        assert!(!equivalent("tcp.port in { }", "ssl"));
+    }
+
+    #[test]
+    fn test_constant_outcome() {
+        let scheme = Scheme! { tcp.port: Int, ssl: Bool };
+
+        let outcome = |filter| {
+            let ast = scheme.parse(filter).unwrap();
+            (ast.never_matches().unwrap(), ast.always_matches().unwrap())
+        };
+
+        assert_eq!(outcome("tcp.port == 80 and tcp.port == 443"), (true, false));
+        assert_eq!(outcome("ssl and tcp.port in { }"), (true, false));
+        assert_eq!(
+            outcome("not ssl and (ssl or tcp.port < 0) and tcp.port > 10"),
+            (true, false)
+        );
+        assert_eq!(outcome("tcp.port != 80 or tcp.port != 443"), (false, true));
+        assert_eq!(outcome("ssl or not ssl"), (false, true));
+        assert_eq!(outcome("tcp.port in { 80 443 } and ssl"), (false, false));
+    }
+
+    #[test]
+    fn test_subsumes() {
+        let scheme = Scheme! { tcp.port: Int, ssl: Bool };
+
+        let subsumes = |lhs, rhs| {
+            scheme
+                .parse(lhs)
+                .unwrap()
+                .subsumes(&scheme.parse(rhs).unwrap())
+                .unwrap()
+        };
+
+        assert!(subsumes("tcp.port > 80", "tcp.port in { 443 8080 }"));
+        assert!(subsumes("ssl or tcp.port == 80", "ssl and tcp.port == 443"));
+        assert!(!subsumes("tcp.port in { 443 8080 }", "tcp.port > 80"));
+        assert!(!subsumes("ssl", "tcp.port == 443"));
+    }
+
+    #[test]
+    fn test_errors() {
+        use super::EquivalenceError;
+
+        let scheme1 = Scheme! { tcp.port: Int };
+        let scheme2 = Scheme! { tcp.port: Int };
+
+        assert_eq!(
+            scheme1
+                .parse("tcp.port == 80")
+                .unwrap()
+                .is_equivalent_to(&scheme2.parse("tcp.port == 80").unwrap()),
+            Err(EquivalenceError::SchemeMismatch)
+        );
+
+        let scheme = Scheme! { http.host: Bytes };
+        let filter = (0..21)
+            .map(|i| format!(r#"http.host contains "{}""#, i))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        let ast = scheme.parse(&filter).unwrap();
+
+        assert_eq!(
+            ast.is_equivalent_to(&ast),
+            Err(EquivalenceError::TooComplex)
+        );
+    }
+}
@@ -1,16 +1,105 @@
 use crate::{
-    scheme::{Field, Scheme},
-    types::{GetType, LhsValue, TypeMismatchError},
+    lex::{complete, Lex},
+    list_matcher::ListMatcher,
+    rhs_types::{unmap_ipv4, unmap_ipv4_ranges, MacAddr},
+    scheme::{Field, List, Scheme},
+    types::{GetType, LhsValue, RhsValues, Type, TypeMismatchError, TypedValue},
+    value_set::ValueSet,
 };
+use failure::Fail;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    convert::TryFrom,
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+/// An error that occurs if a JSON value can't be converted to the type of the
+/// corresponding field.
+#[derive(Debug, PartialEq, Fail)]
+#[fail(
+    display = "JSON value of field {} can't be converted to {:?}",
+    field, expected
+)]
+pub struct JsonValueError {
+    /// Name of the field.
+    pub field: String,
+    /// Type of the field.
+    pub expected: Type,
+}
+
+// Converts a JSON value to a field value, borrowing strings where possible.
+pub(crate) fn lhs_value_from_json(value: &Value, ty: Type) -> Option<LhsValue<'_>> {
+    Some(match ty {
+        Type::Ip => LhsValue::Ip(value.as_str()?.parse().ok()?),
+        Type::Bytes => LhsValue::Bytes(Cow::Borrowed(value.as_str()?.as_bytes())),
+        Type::Str => LhsValue::Str(Cow::Borrowed(value.as_str()?)),
+        Type::Int => LhsValue::Int(i32::try_from(value.as_i64()?).ok()?),
+        Type::Bool => LhsValue::Bool(value.as_bool()?),
+        // Either a number of nanoseconds or a string like `1h30m`.
+        Type::Duration => LhsValue::Duration(match value.as_u64() {
+            Some(nanos) => Duration::from_nanos(nanos),
+            None => complete(Duration::lex(value.as_str()?)).ok()?,
+        }),
+        Type::Mac => LhsValue::Mac(MacAddr::deserialize(value).ok()?),
+    })
+}
+
+// Converts IPv4-mapped IPv6 addresses to IPv4 for schemes that treat them
+// as equivalent.
+fn unmap_ipv4_value(value: LhsValue<'_>) -> LhsValue<'_> {
+    match value {
+        LhsValue::Ip(addr) => LhsValue::Ip(unmap_ipv4(addr)),
+        value => value,
+    }
+}
+
+// A field value computed on first access.
+//
+// Provider is invoked at most once, even if the context is shared between
+// threads that execute filters concurrently.
+struct LazyValue<'e> {
+    // Values are required to be owned to keep the context covariant.
+    provider: Box<dyn 'e + Fn() -> LhsValue<'static> + Send + Sync>,
+    value: OnceLock<LhsValue<'static>>,
+}
+
+impl<'e> LazyValue<'e> {
+    fn get(&self) -> &LhsValue<'static> {
+        self.value.get_or_init(|| (self.provider)())
+    }
+}
+
+enum FieldValue<'e> {
+    Value(LhsValue<'e>),
+    Lazy(LazyValue<'e>),
+}
 
 /// An execution context stores an associated [`Scheme`](struct@Scheme) and a
 /// set of runtime values to execute [`Filter`](::Filter) against.
 ///
 /// It acts as a map in terms of public API, but provides a constant-time
 /// index-based access to values for a filter during execution.
+///
+/// Values may borrow data for the lifetime of the context, so per-request
+/// bytes can be allocated in an arena or a buffer owned by the caller and
+/// freed all at once after the context is dropped, instead of being copied
+/// into owned values:
+///
+/// ```
+/// # use wirefilter::{ExecutionContext, Scheme};
+/// # let scheme = Scheme! { http.host: Bytes };
+/// let buffer = String::from("example.org");
+/// let mut ctx = ExecutionContext::new(&scheme);
+/// ctx.set_field_value("http.host", buffer.as_str()).unwrap();
+/// ```
 pub struct ExecutionContext<'e> {
     scheme: &'e Scheme,
-    values: Box<[Option<LhsValue<'e>>]>,
+    values: Box<[Option<FieldValue<'e>>]>,
+    lists: Box<[Option<ValueSet>]>,
 }
 
 impl<'e> ExecutionContext<'e> {
@@ -20,7 +109,8 @@ impl<'e> ExecutionContext<'e> {
     pub fn new<'s: 'e>(scheme: &'s Scheme) -> Self {
         ExecutionContext {
             scheme,
-            values: vec![None; scheme.get_field_count()].into(),
+            values: (0..scheme.get_field_count()).map(|_| None).collect(),
+            lists: (0..scheme.get_list_count()).map(|_| None).collect(),
         }
     }
 
@@ -29,6 +119,12 @@ impl<'e> ExecutionContext<'e> {
         self.scheme
     }
 
+    pub(crate) fn has_field_value(&self, field: Field<'_>) -> bool {
+        debug_assert!(self.scheme() == field.scheme());
+
+        self.values[field.index()].is_some()
+    }
+
     pub(crate) fn get_field_value_unchecked(&'e self, field: Field<'e>) -> LhsValue<'e> {
         // This is safe because this code is reachable only from Filter::execute
         // which already performs the scheme compatibility check, but check that
@@ -38,13 +134,16 @@ impl<'e> ExecutionContext<'e> {
         // For now we panic in this, but later we are going to align behaviour
         // with wireshark: resolve all subexpressions that don't have RHS value
         // to `false`.
-        let lhs_value = self.values[field.index()].as_ref().unwrap_or_else(|| {
+        let field_value = self.values[field.index()].as_ref().unwrap_or_else(|| {
             panic!(
                 "Field {} was registered but not given a value",
                 field.name()
             );
         });
-        lhs_value.as_ref()
+        match field_value {
+            FieldValue::Value(value) => value.as_ref(),
+            FieldValue::Lazy(lazy) => lazy.get().as_ref(),
+        }
     }
 
     /// Sets a runtime value for a given field name.
@@ -60,7 +159,12 @@ impl<'e> ExecutionContext<'e> {
         let value_type = value.get_type();
 
         if field_type == value_type {
-            self.values[field.index()] = Some(value);
+            let value = if self.scheme.ipv4_mapped_equivalence() {
+                unmap_ipv4_value(value)
+            } else {
+                value
+            };
+            self.values[field.index()] = Some(FieldValue::Value(value));
             Ok(())
         } else {
             Err(TypeMismatchError {
@@ -69,6 +173,244 @@ impl<'e> ExecutionContext<'e> {
             })
         }
     }
+
+    /// Sets a provider that computes a runtime value for a given field name.
+    ///
+    /// The provider is invoked only when a filter accesses the field during
+    /// execution, and at most once per context, so it's useful for values that
+    /// are expensive to compute but needed only by a few filters.
+    ///
+    /// Computed values must own their data. Their type is known from the
+    /// return type of the provider, like `i32` for `Int` fields or `String`
+    /// for `Bytes` ones, so a provider of values of another type is rejected
+    /// here rather than when the field is accessed.
+    pub fn set_field_lazy<V, F>(&mut self, name: &str, provider: F) -> Result<(), TypeMismatchError>
+    where
+        V: Into<LhsValue<'static>> + TypedValue,
+        F: 'e + Fn() -> V + Send + Sync,
+    {
+        let field = self.scheme.get_field_index(name).unwrap();
+        let field_type = field.get_type();
+
+        if V::TYPE != field_type {
+            return Err(TypeMismatchError {
+                expected: field_type,
+                actual: V::TYPE,
+            });
+        }
+
+        let unmap_ipv4 = self.scheme.ipv4_mapped_equivalence();
+
+        let provider = move || {
+            let value = provider().into();
+            if unmap_ipv4 {
+                unmap_ipv4_value(value)
+            } else {
+                value
+            }
+        };
+
+        self.values[field.index()] = Some(FieldValue::Lazy(LazyValue {
+            provider: Box::new(provider),
+            value: OnceLock::new(),
+        }));
+        Ok(())
+    }
+
+    /// Sets runtime values of fields from a JSON document.
+    ///
+    /// Nested objects are flattened into dot-separated names, so both
+    /// `{"http": {"host": "example.org"}}` and `{"http.host": "example.org"}`
+    /// set the `http.host` field. Keys that don't correspond to any field and
+    /// `null` values are ignored. Values of `Bytes` fields are borrowed from
+    /// the document.
+    pub fn set_from_json(&mut self, document: &'e Value) -> Result<(), JsonValueError> {
+        self.set_from_json_with_prefix(&mut String::new(), document)
+    }
+
+    fn set_from_json_with_prefix(
+        &mut self,
+        name: &mut String,
+        value: &'e Value,
+    ) -> Result<(), JsonValueError> {
+        if let Some(object) = value.as_object() {
+            let prefix_len = name.len();
+            for (key, value) in object {
+                if prefix_len != 0 {
+                    name.push('.');
+                }
+                name.push_str(key);
+                self.set_from_json_with_prefix(name, value)?;
+                name.truncate(prefix_len);
+            }
+            return Ok(());
+        }
+
+        if value.is_null() {
+            return Ok(());
+        }
+
+        let field = match self.scheme.get_field_index(name) {
+            Ok(field) => field,
+            Err(_) => return Ok(()),
+        };
+
+        let field_type = field.get_type();
+
+        let value = lhs_value_from_json(value, field_type).ok_or_else(|| JsonValueError {
+            field: name.clone(),
+            expected: field_type,
+        })?;
+        let value = if self.scheme.ipv4_mapped_equivalence() {
+            unmap_ipv4_value(value)
+        } else {
+            value
+        };
+
+        self.values[field.index()] = Some(FieldValue::Value(value));
+        Ok(())
+    }
+
+    /// Sets values of a named list registered in the scheme.
+    ///
+    /// Values are prepared for lookups once, so a context with large lists
+    /// should be reused for many executions rather than recreated.
+    pub fn set_list_values(
+        &mut self,
+        name: &str,
+        values: RhsValues,
+    ) -> Result<(), TypeMismatchError> {
+        let list = self.scheme.get_list(name).unwrap();
+
+        let list_type = list.get_type();
+        let values_type = values.get_type();
+
+        if list_type != values_type {
+            return Err(TypeMismatchError {
+                expected: list_type,
+                actual: values_type,
+            });
+        }
+
+        let values = match values {
+            RhsValues::Ip(ranges) if self.scheme.ipv4_mapped_equivalence() => {
+                RhsValues::Ip(unmap_ipv4_ranges(ranges))
+            }
+            values => values,
+        };
+
+        self.lists[list.index()] = Some(ValueSet::new(values));
+        Ok(())
+    }
+
+    /// Sets a custom matcher for a named list of `Bytes` or `Str` values.
+    ///
+    /// Matchers can be shared between contexts and replaced without
+    /// recompiling filters, like when a list is refreshed.
+    pub fn set_list_matcher(
+        &mut self,
+        name: &str,
+        matcher: Arc<dyn ListMatcher>,
+    ) -> Result<(), TypeMismatchError> {
+        let list = self.scheme.get_list(name).unwrap();
+
+        match list.get_type() {
+            Type::Bytes | Type::Str => {
+                self.lists[list.index()] = Some(ValueSet::Matcher(matcher));
+                Ok(())
+            }
+            list_type => Err(TypeMismatchError {
+                expected: list_type,
+                actual: Type::Bytes,
+            }),
+        }
+    }
+
+    pub(crate) fn get_list_values_unchecked(&self, list: List<'_>) -> &ValueSet {
+        debug_assert!(self.scheme() == list.scheme());
+
+        self.lists[list.index()]
+            .as_ref()
+            .unwrap_or_else(|| panic!("List {} was registered but not given values", list.name()))
+    }
+
+    /// Removes values of all fields and lists.
+    ///
+    /// The storage of the context is retained, so it can be reused for
+    /// another set of values without reallocating.
+    pub fn clear(&mut self) {
+        for value in self.values.iter_mut() {
+            *value = None;
+        }
+        for list in self.lists.iter_mut() {
+            *list = None;
+        }
+    }
+}
+
+/// A pool of reusable storage for [`ExecutionContext`]s of a scheme.
+///
+/// Returning contexts to the pool after use avoids allocating a new one for
+/// each set of values, which is noticeable when executing filters at a high
+/// rate. The pool can be shared between threads.
+pub struct ExecutionContextPool<'s> {
+    scheme: &'s Scheme,
+    storage: Mutex<Vec<PooledStorage>>,
+}
+
+// Storage of a returned context. Field values are kept as an empty vector,
+// so that it doesn't borrow from values of the context it came from.
+type PooledStorage = (Vec<Option<FieldValue<'static>>>, Box<[Option<ValueSet>]>);
+
+// Empties a vector of field values and reuses its allocation for values of
+// another lifetime.
+fn recycle_values<'a, 'b>(mut values: Vec<Option<FieldValue<'a>>>) -> Vec<Option<FieldValue<'b>>> {
+    values.clear();
+    let mut values = ManuallyDrop::new(values);
+    // SAFETY: the vector is empty, and types that differ only in lifetimes
+    // have the same layout, so the allocation fits values of either.
+    unsafe { Vec::from_raw_parts(values.as_mut_ptr().cast(), 0, values.capacity()) }
+}
+
+impl<'s> ExecutionContextPool<'s> {
+    /// Creates an empty pool for a given scheme.
+    pub fn new(scheme: &'s Scheme) -> Self {
+        ExecutionContextPool {
+            scheme,
+            storage: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns an empty context, reusing storage of a previously returned
+    /// one if there is any.
+    pub fn get(&self) -> ExecutionContext<'s> {
+        match self.storage.lock().unwrap().pop() {
+            Some((values, lists)) => {
+                let mut values = recycle_values(values);
+                values.resize_with(self.scheme.get_field_count(), || None);
+                ExecutionContext {
+                    scheme: self.scheme,
+                    values: values.into_boxed_slice(),
+                    lists,
+                }
+            }
+            None => ExecutionContext::new(self.scheme),
+        }
+    }
+
+    /// Clears a context and returns its storage to the pool.
+    ///
+    /// Contexts of other schemes are dropped instead.
+    pub fn put(&self, mut ctx: ExecutionContext<'_>) {
+        if ctx.scheme() != self.scheme {
+            return;
+        }
+
+        ctx.clear();
+
+        let values = recycle_values(ctx.values.into_vec());
+        self.storage.lock().unwrap().push((values, ctx.lists));
+    }
 }
 
 #[test]
@@ -87,3 +429,276 @@ fn test_field_value_type_mismatch() {
         })
     );
 }
+
+#[test]
+fn test_set_from_json() {
+    let scheme = Scheme! {
+        http.host: Bytes,
+        http.ssl: Bool,
+        ip.src: Ip,
+        tcp.port: Int,
+        eth.src: Mac,
+        latency: Duration,
+    };
+
+    let document = serde_json::json!({
+        "http": {
+            "host": "example.org",
+            "ssl": true,
+            "unknown": [1, 2, 3],
+        },
+        "ip.src": "10.0.0.1",
+        "tcp": { "port": 443 },
+        "eth": { "src": "aa:bb:cc:dd:ee:ff" },
+        "latency": "1m30s",
+    });
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_from_json(&document).unwrap();
+
+    let value = |name| ctx.get_field_value_unchecked(scheme.get_field_index(name).unwrap());
+
+    assert_eq!(value("http.host"), LhsValue::from(&b"example.org"[..]));
+    assert_eq!(value("http.ssl"), LhsValue::Bool(true));
+    assert_eq!(value("ip.src"), LhsValue::Ip([10, 0, 0, 1].into()));
+    assert_eq!(value("tcp.port"), LhsValue::Int(443));
+    assert_eq!(
+        value("eth.src"),
+        LhsValue::Mac(MacAddr([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]))
+    );
+    assert_eq!(
+        value("latency"),
+        LhsValue::Duration(Duration::from_secs(90))
+    );
+
+    // the value is borrowed from the document
+    match value("http.host") {
+        LhsValue::Bytes(Cow::Borrowed(bytes)) => {
+            assert_eq!(
+                bytes.as_ptr(),
+                document["http"]["host"].as_str().unwrap().as_ptr()
+            )
+        }
+        value => panic!("unexpected value {:?}", value),
+    }
+
+    let document = serde_json::json!({ "tcp": { "port": 1u64 << 40 } });
+    let mut ctx = ExecutionContext::new(&scheme);
+
+    assert_eq!(
+        ctx.set_from_json(&document),
+        Err(JsonValueError {
+            field: "tcp.port".into(),
+            expected: Type::Int,
+        })
+    );
+}
+
+#[test]
+fn test_clear() {
+    let scheme = Scheme! { tcp.port: Int, ssl: Bool };
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("tcp.port", 80).unwrap();
+    ctx.set_field_lazy("ssl", || true).unwrap();
+
+    let port = scheme.get_field_index("tcp.port").unwrap();
+    let ssl = scheme.get_field_index("ssl").unwrap();
+
+    assert!(ctx.has_field_value(port));
+    assert!(ctx.has_field_value(ssl));
+
+    ctx.clear();
+
+    assert!(!ctx.has_field_value(port));
+    assert!(!ctx.has_field_value(ssl));
+
+    ctx.set_field_value("tcp.port", 443).unwrap();
+    assert_eq!(ctx.get_field_value_unchecked(port), LhsValue::Int(443));
+}
+
+#[test]
+fn test_pool() {
+    let scheme = Scheme! { http.host: Bytes };
+    let pool = ExecutionContextPool::new(&scheme);
+
+    let host = String::from("example.org");
+
+    let mut ctx = pool.get();
+    ctx.set_field_value("http.host", host.as_str()).unwrap();
+    let storage = ctx.values.as_ptr() as usize;
+    pool.put(ctx);
+
+    drop(host);
+
+    // the storage is reused and doesn't keep the previous value
+    let ctx = pool.get();
+    assert_eq!(ctx.values.as_ptr() as usize, storage);
+    assert!(!ctx.has_field_value(scheme.get_field_index("http.host").unwrap()));
+
+    // a context is taken from the pool only once
+    assert_ne!(pool.get().values.as_ptr() as usize, storage);
+
+    // contexts of other schemes are not pooled
+    let other = Scheme! { http.host: Bytes };
+    pool.put(ExecutionContext::new(&other));
+    pool.put(ctx);
+    assert_eq!(pool.storage.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_set_field_lazy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let scheme = Scheme! {
+        http.host: Bytes,
+        tcp.port: Int,
+    };
+
+    let calls = AtomicUsize::new(0);
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("tcp.port", 80).unwrap();
+    ctx.set_field_lazy("http.host", || {
+        calls.fetch_add(1, Ordering::SeqCst);
+        "example.org"
+    })
+    .unwrap();
+
+    let execute = |filter| {
+        scheme
+            .parse(filter)
+            .unwrap()
+            .compile()
+            .unwrap()
+            .execute(&ctx)
+    };
+
+    // the field is not accessed
+    assert_eq!(
+        execute(r#"tcp.port == 80 or http.host == "example.org""#),
+        Ok(true)
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    // the field is accessed several times, but computed only once
+    assert_eq!(
+        execute(r#"http.host contains "example" and http.host != "example.com""#),
+        Ok(true)
+    );
+    assert_eq!(execute(r#"http.host == "example.org""#), Ok(true));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_set_field_lazy_type_mismatch() {
+    let scheme = Scheme! { tcp.port: Int };
+
+    let mut ctx = ExecutionContext::new(&scheme);
+
+    assert_eq!(
+        ctx.set_field_lazy("tcp.port", || true),
+        Err(TypeMismatchError {
+            expected: Type::Int,
+            actual: Type::Bool,
+        })
+    );
+    assert!(!ctx.has_field_value(scheme.get_field_index("tcp.port").unwrap()));
+}
+
+#[test]
+fn test_ipv4_mapped_equivalence() {
+    use std::net::IpAddr;
+
+    let mut scheme = Scheme! { ip.src: Ip, ip.dst: Ip };
+    let mapped: IpAddr = "::ffff:203.0.113.7".parse().unwrap();
+
+    let execute = |scheme: &Scheme, ctx: &ExecutionContext<'_>, filter| {
+        scheme
+            .parse(filter)
+            .unwrap()
+            .compile()
+            .unwrap()
+            .execute(ctx)
+    };
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("ip.src", mapped).unwrap();
+    assert_eq!(execute(&scheme, &ctx, "ip.src == 203.0.113.7"), Ok(false));
+    assert_eq!(
+        execute(&scheme, &ctx, "ip.src in { 203.0.113.0/24 }"),
+        Ok(false)
+    );
+    drop(ctx);
+
+    scheme.set_ipv4_mapped_equivalence(true);
+
+    let document = serde_json::json!({ "ip": { "src": "::ffff:10.0.0.1" } });
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("ip.src", mapped).unwrap();
+    ctx.set_field_lazy("ip.dst", move || mapped).unwrap();
+
+    for filter in &[
+        "ip.src == 203.0.113.7",
+        "ip.src == ::ffff:203.0.113.7",
+        "ip.src in { 203.0.113.0/24 }",
+        "ip.src in { 203.0.113.1..203.0.113.9 }",
+        "ip.src in { ::ffff:203.0.113.0/120 }",
+        "ip.src in { ::/0 }",
+        "ip.src > 203.0.113.6",
+        "ip.dst == ip.src",
+        "ip.dst in { 203.0.113.0/24 }",
+    ] {
+        assert_eq!(execute(&scheme, &ctx, filter), Ok(true), "{}", filter);
+    }
+
+    assert_eq!(
+        execute(&scheme, &ctx, "ip.src in { ::1 10.0.0.0/8 }"),
+        Ok(false)
+    );
+
+    ctx.set_from_json(&document).unwrap();
+    assert_eq!(execute(&scheme, &ctx, "ip.src in { 10.0.0.0/8 }"), Ok(true));
+}
+
+#[test]
+fn test_set_list_matcher() {
+    use crate::list_matcher::{BloomMatcher, HashSetMatcher};
+
+    let mut scheme = Scheme! { http.host: Str, tcp.port: Int };
+    scheme.add_list("hosts".into(), Type::Str).unwrap();
+    scheme.add_list("ports".into(), Type::Int).unwrap();
+
+    let filter = scheme
+        .parse("http.host in $hosts")
+        .unwrap()
+        .compile()
+        .unwrap();
+
+    let hosts: HashSetMatcher = ["evil.example"].iter().collect();
+    let hosts: Arc<dyn ListMatcher> = Arc::new(hosts);
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    assert_eq!(
+        ctx.set_list_matcher("ports", hosts.clone()),
+        Err(TypeMismatchError {
+            expected: Type::Int,
+            actual: Type::Bytes,
+        })
+    );
+
+    ctx.set_list_matcher("hosts", hosts.clone()).unwrap();
+    ctx.set_field_value("http.host", LhsValue::Str("evil.example".into()))
+        .unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(true));
+
+    // The value passes the filter, but not the verifier.
+    let verifier: HashSetMatcher = ["other.example"].iter().collect();
+    let bloom = BloomMatcher::from_values(&["evil.example"], 0.01);
+    ctx.set_list_matcher("hosts", Arc::new(bloom.clone()))
+        .unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(true));
+    ctx.set_list_matcher("hosts", Arc::new(bloom.with_verifier(Arc::new(verifier))))
+        .unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(false));
+}
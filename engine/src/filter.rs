@@ -1,5 +1,20 @@
-use crate::{execution_context::ExecutionContext, scheme::Scheme};
+#[cfg(feature = "profiling")]
+use crate::profile::{NodeProfile, ProfiledNodes, Profiler};
+use crate::{
+    ast::{forward_function_panics, CombinedExpr, FunctionPanic},
+    execution_context::ExecutionContext,
+    regex_budget::{RegexBudget, ScopeBudget},
+    regex_sets::{RegexSetResults, RegexSets},
+    scheme::Scheme,
+    shared_exprs::{SharedExprResults, SharedExprs},
+    trace::{Trace, TraceExpr},
+};
 use failure::Fail;
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 /// An error that occurs if filter and provided [`ExecutionContext`] have
 /// different [schemes](struct@Scheme).
@@ -7,23 +22,151 @@ use failure::Fail;
 #[fail(display = "execution context doesn't match the scheme with which filter was parsed")]
 pub struct SchemeMismatchError;
 
+/// An error that occurs if a [`FilterAst`](::FilterAst) can't be compiled.
+#[derive(Debug, PartialEq, Fail)]
+pub enum CompileError {
+    /// A placeholder used by the filter is not
+    /// [bound](::FilterAst::bind).
+    #[fail(display = "placeholder {} is not bound", _0)]
+    UnboundPlaceholder(String),
+
+    /// Filter calls a function without an implementation, like functions of
+    /// deserialized schemes.
+    #[fail(display = "function {} can't be executed", function)]
+    FunctionUnavailable {
+        /// Name of the function.
+        function: String,
+    },
+}
+
+/// An error that occurs during [`Filter::execute_catching`].
+#[derive(Debug, PartialEq, Fail)]
+pub enum ExecutionError {
+    /// Filter and provided context have different schemes.
+    #[fail(display = "{}", _0)]
+    SchemeMismatch(#[cause] SchemeMismatchError),
+
+    /// A function called by the filter panicked.
+    #[fail(display = "function {} panicked: {}", function, message)]
+    FunctionPanicked {
+        /// Name of the innermost function that panicked.
+        function: String,
+        /// Panic message.
+        message: String,
+    },
+
+    /// Filter execution panicked outside of any function.
+    #[fail(display = "filter execution panicked: {}", _0)]
+    Panicked(String),
+
+    /// Filter was quarantined after a previous panic.
+    #[fail(display = "filter is quarantined after a previous panic")]
+    Quarantined,
+}
+
+impl From<SchemeMismatchError> for ExecutionError {
+    fn from(err: SchemeMismatchError) -> Self {
+        ExecutionError::SchemeMismatch(err)
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => (*message).to_owned(),
+            Err(_) => "Box<Any>".to_owned(),
+        },
+    }
+}
+
 // Each AST expression node gets compiled into CompiledExpr. Therefore, Filter
 // essentialy is a public API facade for a tree of CompiledExprs. When filter
 // gets executed it calls `execute` method on its root expression which then
 // under the hood propagates field values to its leafs by recursively calling
 // their `execute` methods and aggregating results into a single boolean value
-// as recursion unwinds.
-pub(crate) struct CompiledExpr<'s>(Box<dyn 's + Fn(&ExecutionContext) -> bool + Sync + Send>);
+// (or `Option<bool>` with three-valued logic) as recursion unwinds.
+pub(crate) struct CompiledExpr<'s, T = bool>(
+    Box<dyn 's + Fn(&ExecutionContext, &mut ExecutionScope) -> T + Sync + Send>,
+);
 
-impl<'s> CompiledExpr<'s> {
+impl<'s, T> CompiledExpr<'s, T> {
     /// Creates a compiled expression IR from a generic closure.
-    pub(crate) fn new(closure: impl 's + Fn(&ExecutionContext) -> bool + Sync + Send) -> Self {
+    pub(crate) fn new(
+        closure: impl 's + Fn(&ExecutionContext, &mut ExecutionScope) -> T + Sync + Send,
+    ) -> Self {
         CompiledExpr(Box::new(closure))
     }
 
-    /// Executes a filter against a provided context with values.
-    pub fn execute(&self, ctx: &ExecutionContext) -> bool {
-        self.0(ctx)
+    /// Executes an expression as a part of a filter execution.
+    pub(crate) fn execute_in(&self, ctx: &ExecutionContext, scope: &mut ExecutionScope) -> T {
+        self.0(ctx, scope)
+    }
+
+    /// Executes an expression on its own against a provided context with
+    /// values.
+    pub fn execute(&self, ctx: &ExecutionContext) -> T {
+        self.execute_in(ctx, &mut ExecutionScope::default())
+    }
+}
+
+/// Filter-wide state available to all nodes while a filter is being
+/// compiled.
+#[derive(Default)]
+pub(crate) struct CompileContext<'s> {
+    pub(crate) regex_sets: RegexSets,
+    pub(crate) shared_exprs: SharedExprs<'s>,
+    // Counters of comparisons, if the filter is compiled with profiling.
+    #[cfg(feature = "profiling")]
+    pub(crate) profiled_nodes: Option<ProfiledNodes>,
+    // First node that couldn't be compiled, if any.
+    error: Option<CompileError>,
+}
+
+impl<'s> CompileContext<'s> {
+    pub(crate) fn new(expr: &CombinedExpr<'s>) -> Self {
+        CompileContext {
+            regex_sets: RegexSets::new(expr),
+            shared_exprs: SharedExprs::new(expr),
+            #[cfg(feature = "profiling")]
+            profiled_nodes: None,
+            error: None,
+        }
+    }
+
+    /// Records that a node can't be compiled, which fails the compilation of
+    /// the whole filter.
+    pub(crate) fn fail(&mut self, error: CompileError) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Returns the first error recorded during the compilation, if any.
+    pub(crate) fn finish(&mut self) -> Result<(), CompileError> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+/// State of a single filter execution, shared by all nodes of the filter.
+#[derive(Default)]
+pub(crate) struct ExecutionScope {
+    pub(crate) regex_sets: RegexSetResults,
+    pub(crate) shared_exprs: SharedExprResults,
+    regex_budget: Option<ScopeBudget>,
+}
+
+impl ExecutionScope {
+    pub(crate) fn new(regex_budget: Option<RegexBudget>) -> Self {
+        ExecutionScope {
+            regex_budget: regex_budget.map(ScopeBudget::new),
+            ..Default::default()
+        }
+    }
+
+    /// Spends the regex budget of the execution, if any, on matching an input
+    /// of the given length, or returns the result of the comparison if it
+    /// doesn't fit.
+    pub(crate) fn spend_regex_budget(&mut self, len: usize) -> Option<bool> {
+        self.regex_budget.as_mut()?.spend(len)
     }
 }
 
@@ -45,18 +188,235 @@ impl<'s> CompiledExpr<'s> {
 pub struct Filter<'s> {
     root_expr: CompiledExpr<'s>,
     scheme: &'s Scheme,
+    quarantine_on_panic: bool,
+    quarantined: AtomicBool,
+    trace_expr: Option<TraceExpr<'s>>,
+    regex_budget: Option<RegexBudget>,
+    #[cfg(feature = "profiling")]
+    profiler: Option<Profiler>,
 }
 
 impl<'s> Filter<'s> {
     /// Creates a compiled expression IR from a generic closure.
     pub(crate) fn new(root_expr: CompiledExpr<'s>, scheme: &'s Scheme) -> Self {
-        Filter { root_expr, scheme }
+        Filter {
+            root_expr,
+            scheme,
+            quarantine_on_panic: false,
+            quarantined: AtomicBool::new(false),
+            trace_expr: None,
+            regex_budget: None,
+            #[cfg(feature = "profiling")]
+            profiler: None,
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    pub(crate) fn with_profiler(mut self, profiler: Profiler) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    pub(crate) fn with_trace(mut self, trace_expr: TraceExpr<'s>) -> Self {
+        self.trace_expr = Some(trace_expr);
+        self
+    }
+
+    /// Sets whether a panic caught by [`Filter::execute_catching`] should
+    /// quarantine the filter, making further calls fail with
+    /// [`ExecutionError::Quarantined`] without executing it.
+    pub fn set_quarantine_on_panic(&mut self, enabled: bool) {
+        self.quarantine_on_panic = enabled;
+    }
+
+    /// Sets a limit on the amount of regex matching done by each execution
+    /// of the filter, or removes it with `None`.
+    pub fn set_regex_budget(&mut self, budget: Option<RegexBudget>) {
+        self.regex_budget = budget;
+    }
+
+    /// Checks whether the filter was quarantined after a panic.
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined.load(Ordering::Relaxed)
+    }
+
+    /// Executes a filter like [`Filter::execute`], but converts panics, for
+    /// example in user-provided functions, into errors.
+    ///
+    /// Panics can be caught only when the crate is built with
+    /// `panic = "unwind"` (the default). The panic hook is still invoked as
+    /// usual.
+    pub fn execute_catching(&self, ctx: &ExecutionContext<'s>) -> Result<bool, ExecutionError> {
+        if self.is_quarantined() {
+            return Err(ExecutionError::Quarantined);
+        }
+
+        if self.scheme != ctx.scheme() {
+            return Err(SchemeMismatchError.into());
+        }
+
+        // Compiled filters don't have any interior state that could be left
+        // broken by a panic.
+        catch_unwind(AssertUnwindSafe(|| self.execute_in_scope(ctx))).map_err(|payload| {
+            if self.quarantine_on_panic {
+                self.quarantined.store(true, Ordering::Relaxed);
+            }
+            match payload.downcast::<FunctionPanic>() {
+                Ok(panic) => ExecutionError::FunctionPanicked {
+                    function: panic.function,
+                    message: panic_message(panic.payload),
+                },
+                Err(payload) => ExecutionError::Panicked(panic_message(payload)),
+            }
+        })
     }
 
     /// Executes a filter against a provided context with values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a function called by the filter panics. Use
+    /// [`Filter::execute_catching`] to get errors instead.
     pub fn execute(&self, ctx: &ExecutionContext<'s>) -> Result<bool, SchemeMismatchError> {
         if self.scheme == ctx.scheme() {
-            Ok(self.root_expr.execute(ctx))
+            Ok(self.execute_unchecked(ctx))
+        } else {
+            Err(SchemeMismatchError)
+        }
+    }
+
+    fn execute_unchecked(&self, ctx: &ExecutionContext<'s>) -> bool {
+        forward_function_panics(|| self.execute_in_scope(ctx))
+    }
+
+    fn execute_in_scope(&self, ctx: &ExecutionContext<'s>) -> bool {
+        self.root_expr
+            .execute_in(ctx, &mut ExecutionScope::new(self.regex_budget))
+    }
+
+    /// Executes a filter and returns a [`Trace`] of all evaluated
+    /// subexpressions, which explains why the filter did or didn't match.
+    ///
+    /// Returns `None` if the filter wasn't compiled with
+    /// [`FilterAst::compile_with_trace`](::FilterAst::compile_with_trace).
+    pub fn execute_with_trace<'a>(
+        &'a self,
+        ctx: &'a ExecutionContext<'s>,
+    ) -> Result<Option<Trace<'a, 's>>, SchemeMismatchError> {
+        if self.scheme == ctx.scheme() {
+            Ok(self.trace_expr.as_ref().map(|trace_expr| {
+                forward_function_panics(|| {
+                    trace_expr.execute(ctx, &mut ExecutionScope::new(self.regex_budget))
+                })
+            }))
+        } else {
+            Err(SchemeMismatchError)
+        }
+    }
+
+    /// Returns statistics of all comparisons accumulated since compilation or
+    /// the last [`Filter::reset_profile`], starting with the slowest ones.
+    ///
+    /// Returns `None` if the filter wasn't compiled with
+    /// [`FilterAst::compile_with_profile`](::FilterAst::compile_with_profile).
+    #[cfg(feature = "profiling")]
+    pub fn profile(&self) -> Option<Vec<NodeProfile>> {
+        self.profiler.as_ref().map(Profiler::report)
+    }
+
+    /// Resets statistics reported by [`Filter::profile`].
+    #[cfg(feature = "profiling")]
+    pub fn reset_profile(&self) {
+        if let Some(profiler) = &self.profiler {
+            profiler.reset();
+        }
+    }
+
+    /// Executes a filter against a batch of contexts.
+    ///
+    /// Scheme compatibility is checked once for the whole batch, so either all
+    /// contexts are evaluated or none of them is.
+    pub fn execute_batch(
+        &self,
+        contexts: &[ExecutionContext<'s>],
+    ) -> Result<Vec<bool>, SchemeMismatchError> {
+        let mut results = Vec::with_capacity(contexts.len());
+        self.execute_batch_into(contexts, &mut results)?;
+        Ok(results)
+    }
+
+    /// Same as [`Filter::execute_batch`], but appends results to an existing
+    /// buffer, which allows to reuse its allocation between batches.
+    pub fn execute_batch_into(
+        &self,
+        contexts: &[ExecutionContext<'s>],
+        results: &mut Vec<bool>,
+    ) -> Result<(), SchemeMismatchError> {
+        if contexts.iter().any(|ctx| self.scheme != ctx.scheme()) {
+            return Err(SchemeMismatchError);
+        }
+        results.reserve(contexts.len());
+        results.extend(contexts.iter().map(|ctx| self.execute_unchecked(ctx)));
+        Ok(())
+    }
+
+    /// Lazily executes a filter against each context yielded by an iterator.
+    pub fn execute_iter<'a, I>(
+        &'a self,
+        contexts: I,
+    ) -> Box<dyn Iterator<Item = Result<bool, SchemeMismatchError>> + 'a>
+    where
+        I: IntoIterator<Item = &'a ExecutionContext<'s>>,
+        I::IntoIter: 'a,
+    {
+        Box::new(contexts.into_iter().map(move |ctx| self.execute(ctx)))
+    }
+}
+
+/// A filter compiled with
+/// [`FilterAst::compile_three_valued`](::FilterAst::compile_three_valued).
+///
+/// Unlike [`Filter`], it doesn't require all used fields to be set in the
+/// context. Any comparison that depends on a field without a value, including
+/// via function arguments, evaluates to "unknown", which then propagates
+/// through logical operators like SQL `NULL` does:
+///
+/// * `not` of unknown is unknown;
+/// * `and` is `false` if any operand is `false`, otherwise unknown if any
+///   operand is unknown;
+/// * `or` is `true` if any operand is `true`, otherwise unknown if any
+///   operand is unknown;
+/// * `xor` is unknown if any operand is unknown.
+pub struct ThreeValuedFilter<'s> {
+    root_expr: CompiledExpr<'s, Option<bool>>,
+    scheme: &'s Scheme,
+    regex_budget: Option<RegexBudget>,
+}
+
+impl<'s> ThreeValuedFilter<'s> {
+    pub(crate) fn new(root_expr: CompiledExpr<'s, Option<bool>>, scheme: &'s Scheme) -> Self {
+        ThreeValuedFilter {
+            root_expr,
+            scheme,
+            regex_budget: None,
+        }
+    }
+
+    /// Sets a limit on the amount of regex matching done by each execution
+    /// of the filter, or removes it with `None`.
+    pub fn set_regex_budget(&mut self, budget: Option<RegexBudget>) {
+        self.regex_budget = budget;
+    }
+
+    /// Executes a filter against a provided context with values.
+    ///
+    /// Returns `None` if the result is unknown because of missing fields.
+    pub fn execute(&self, ctx: &ExecutionContext<'s>) -> Result<Option<bool>, SchemeMismatchError> {
+        if self.scheme == ctx.scheme() {
+            Ok(forward_function_panics(|| {
+                self.root_expr
+                    .execute_in(ctx, &mut ExecutionScope::new(self.regex_budget))
+            }))
         } else {
             Err(SchemeMismatchError)
         }
@@ -65,19 +425,304 @@ impl<'s> Filter<'s> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Filter, SchemeMismatchError};
+    use super::{ExecutionError, Filter, SchemeMismatchError, ThreeValuedFilter};
     use crate::execution_context::ExecutionContext;
 
     #[test]
     fn test_scheme_mismatch() {
         let scheme1 = Scheme! { foo: Int };
         let scheme2 = Scheme! { foo: Int, bar: Int };
-        let filter = scheme1.parse("foo == 42").unwrap().compile();
+        let filter = scheme1.parse("foo == 42").unwrap().compile().unwrap();
         let ctx = ExecutionContext::new(&scheme2);
 
         assert_eq!(filter.execute(&ctx), Err(SchemeMismatchError));
     }
 
+    #[test]
+    fn test_execute_batch() {
+        let scheme = Scheme! { foo: Int };
+        let filter = scheme.parse("foo > 10").unwrap().compile().unwrap();
+
+        let contexts = [5, 42, 11]
+            .iter()
+            .map(|&value| {
+                let mut ctx = ExecutionContext::new(&scheme);
+                ctx.set_field_value("foo", value).unwrap();
+                ctx
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(filter.execute_batch(&contexts), Ok(vec![false, true, true]));
+
+        let mut results = vec![true];
+        filter
+            .execute_batch_into(&contexts[..1], &mut results)
+            .unwrap();
+        assert_eq!(results, [true, false]);
+
+        assert_eq!(
+            filter.execute_iter(&contexts).collect::<Vec<_>>(),
+            [Ok(false), Ok(true), Ok(true)]
+        );
+
+        let other_scheme = Scheme! { foo: Int };
+        let mut other_ctx = ExecutionContext::new(&other_scheme);
+        other_ctx.set_field_value("foo", 42).unwrap();
+
+        let mut contexts = contexts;
+        contexts.push(other_ctx);
+
+        assert_eq!(filter.execute_batch(&contexts), Err(SchemeMismatchError));
+        assert_eq!(
+            filter.execute_iter(&contexts).last(),
+            Some(Err(SchemeMismatchError))
+        );
+    }
+
+    #[test]
+    fn test_execute_catching() {
+        use crate::{
+            functions::{Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionParam},
+            types::{LhsValue, Type},
+        };
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        fn explode<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+            match args.next() {
+                Some(LhsValue::Int(0)) => panic!("boom"),
+                arg => arg.unwrap(),
+            }
+        }
+
+        let mut scheme = Scheme! { foo: Int, bar: Int };
+        scheme
+            .add_function(
+                "explode".into(),
+                Function {
+                    params: vec![FunctionParam {
+                        arg_kind: FunctionArgKind::Field,
+                        val_type: Type::Int,
+                        literal_check: None,
+                    }],
+                    opt_params: vec![],
+                    return_type: Type::Int,
+                    implementation: FunctionImpl::new(explode),
+                },
+            )
+            .unwrap();
+
+        let mut filter = scheme
+            .parse("explode(foo) == 1")
+            .unwrap()
+            .compile()
+            .unwrap();
+        filter.set_quarantine_on_panic(true);
+
+        let mut ctx = ExecutionContext::new(&scheme);
+        ctx.set_field_value("foo", 1).unwrap();
+
+        assert_eq!(filter.execute_catching(&ctx), Ok(true));
+
+        ctx.set_field_value("foo", 0).unwrap();
+
+        assert_eq!(
+            filter.execute_catching(&ctx),
+            Err(ExecutionError::FunctionPanicked {
+                function: "explode".into(),
+                message: "boom".into(),
+            })
+        );
+        assert!(filter.is_quarantined());
+
+        ctx.set_field_value("foo", 1).unwrap();
+
+        assert_eq!(
+            filter.execute_catching(&ctx),
+            Err(ExecutionError::Quarantined)
+        );
+
+        // missing value for `bar`
+        let filter = scheme.parse("bar == 1").unwrap().compile().unwrap();
+
+        assert_eq!(
+            filter.execute_catching(&ctx),
+            Err(ExecutionError::Panicked(
+                "Field bar was registered but not given a value".into()
+            ))
+        );
+        assert!(!filter.is_quarantined());
+
+        // panics are passed on as is when they are not caught
+        let filter = scheme
+            .parse("explode(foo) == 1")
+            .unwrap()
+            .compile()
+            .unwrap();
+        ctx.set_field_value("foo", 0).unwrap();
+
+        let payload = catch_unwind(AssertUnwindSafe(|| filter.execute(&ctx))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+    }
+
+    #[test]
+    fn test_execute_with_trace() {
+        use crate::{
+            ast::{CombiningOp, FieldExpr},
+            lex::{complete, LexWith},
+            trace::Trace,
+            types::LhsValue,
+        };
+        use std::time::Duration;
+
+        // Durations are not deterministic, so they are reset before comparison.
+        fn reset_elapsed(trace: &mut Trace<'_, '_>) {
+            match trace {
+                Trace::Comparison { elapsed, .. } => *elapsed = Duration::default(),
+                Trace::Not { arg, elapsed, .. } => {
+                    reset_elapsed(arg);
+                    *elapsed = Duration::default();
+                }
+                Trace::Combining { items, elapsed, .. } => {
+                    items.iter_mut().for_each(reset_elapsed);
+                    *elapsed = Duration::default();
+                }
+            }
+        }
+
+        let scheme = Scheme! { http.host: Bytes, tcp.port: Int, ssl: Bool };
+        let mut ctx = ExecutionContext::new(&scheme);
+        ctx.set_field_value("http.host", "example.org").unwrap();
+        ctx.set_field_value("tcp.port", 80).unwrap();
+        ctx.set_field_value("ssl", false).unwrap();
+
+        let filter_str = r#"tcp.port == 443 or (not ssl and http.host == "example.org") or ssl"#;
+
+        let filter = scheme.parse(filter_str).unwrap().compile().unwrap();
+        assert_eq!(filter.execute_with_trace(&ctx), Ok(None));
+
+        let filter = scheme
+            .parse(filter_str)
+            .unwrap()
+            .compile_with_trace()
+            .unwrap();
+        let mut trace = filter.execute_with_trace(&ctx).unwrap().unwrap();
+        reset_elapsed(&mut trace);
+
+        let expr = |s| complete(FieldExpr::lex_with(s, &scheme)).unwrap();
+
+        assert_eq!(
+            trace,
+            Trace::Combining {
+                op: CombiningOp::Or,
+                items: vec![
+                    Trace::Comparison {
+                        expr: &expr("tcp.port == 443"),
+                        value: LhsValue::Int(80),
+                        result: false,
+                        elapsed: Duration::default(),
+                    },
+                    Trace::Combining {
+                        op: CombiningOp::And,
+                        items: vec![
+                            Trace::Not {
+                                arg: Box::new(Trace::Comparison {
+                                    expr: &expr("ssl"),
+                                    value: LhsValue::Bool(false),
+                                    result: false,
+                                    elapsed: Duration::default(),
+                                }),
+                                result: true,
+                                elapsed: Duration::default(),
+                            },
+                            Trace::Comparison {
+                                expr: &expr(r#"http.host == "example.org""#),
+                                value: LhsValue::from("example.org"),
+                                result: true,
+                                elapsed: Duration::default(),
+                            },
+                        ],
+                        skipped: 0,
+                        result: true,
+                        elapsed: Duration::default(),
+                    },
+                ],
+                skipped: 1,
+                result: true,
+                elapsed: Duration::default(),
+            }
+        );
+        assert_eq!(filter.execute(&ctx), Ok(trace.result()));
+    }
+
+    #[test]
+    fn test_three_valued() {
+        let scheme = Scheme! {
+            http.host: Bytes,
+            http.ua: Bytes,
+            tcp.port: Int,
+        };
+        let mut ctx = ExecutionContext::new(&scheme);
+        ctx.set_field_value("tcp.port", 80).unwrap();
+
+        let execute = |ctx: &ExecutionContext<'_>, filter| {
+            scheme
+                .parse(filter)
+                .unwrap()
+                .compile_three_valued()
+                .unwrap()
+                .execute(ctx)
+                .unwrap()
+        };
+
+        assert_eq!(execute(&ctx, "tcp.port == 80"), Some(true));
+        assert_eq!(execute(&ctx, r#"http.host == "example.org""#), None);
+        assert_eq!(execute(&ctx, r#"not http.host == "example.org""#), None);
+
+        assert_eq!(
+            execute(&ctx, r#"tcp.port == 80 or http.host == "a""#),
+            Some(true)
+        );
+        assert_eq!(
+            execute(&ctx, r#"tcp.port == 443 or http.host == "a""#),
+            None
+        );
+        assert_eq!(
+            execute(&ctx, r#"tcp.port == 443 and http.host == "a""#),
+            Some(false)
+        );
+        assert_eq!(
+            execute(&ctx, r#"tcp.port == 80 and http.host == "a""#),
+            None
+        );
+        assert_eq!(
+            execute(&ctx, r#"tcp.port == 80 xor http.host == "a""#),
+            None
+        );
+        assert_eq!(
+            execute(&ctx, "tcp.port == 80 xor tcp.port == 443"),
+            Some(true)
+        );
+
+        assert_eq!(
+            execute(
+                &ctx,
+                r#"(http.host == "a" or http.ua == "b") and tcp.port == 443"#
+            ),
+            Some(false)
+        );
+
+        ctx.set_field_value("http.host", "a").unwrap();
+
+        assert_eq!(
+            execute(&ctx, r#"http.host == "a" or http.ua == "b""#),
+            Some(true)
+        );
+        assert_eq!(
+            execute(&ctx, r#"http.host == "a" and http.ua == "b""#),
+            None
+        );
+    }
+
     #[test]
     fn ensure_send_and_sync() {
         fn is_send<T: Send>() {}
@@ -85,5 +730,8 @@ mod tests {
 
         is_send::<Filter>();
         is_sync::<Filter>();
+
+        is_send::<ThreeValuedFilter>();
+        is_sync::<ThreeValuedFilter>();
     }
 }
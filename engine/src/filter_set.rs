@@ -0,0 +1,428 @@
+use crate::{
+    ast::{
+        forward_function_panics, unshare_expr, CombinedExpr, CombiningOp, Expr, FieldExpr,
+        FilterAst, NodeId, SimpleExpr, UnaryOp,
+    },
+    execution_context::ExecutionContext,
+    filter::{CompileError, CompiledExpr, ExecutionScope, SchemeMismatchError},
+    regex_budget::RegexBudget,
+    scheme::Scheme,
+};
+use failure::Fail;
+use fnv::FnvHashMap;
+use std::collections::BTreeMap;
+
+// Filters in a set are compiled into trees of logical operators over shared
+// predicates (field expressions), so that identical predicates are compiled
+// once and executed at most once per context. Predicates are not indexed in
+// any other way, so each distinct one is still executed on its own.
+enum SetExpr {
+    Predicate(usize),
+    Not(Box<SetExpr>),
+    Combining {
+        op: CombiningOp,
+        items: Box<[SetExpr]>,
+    },
+}
+
+struct SetExecution<'a, 's> {
+    predicates: &'a [CompiledExpr<'s>],
+    results: Box<[Option<bool>]>,
+    ctx: &'a ExecutionContext<'s>,
+    scope: ExecutionScope,
+}
+
+impl<'a, 's> SetExecution<'a, 's> {
+    fn execute(&mut self, expr: &SetExpr) -> bool {
+        match expr {
+            SetExpr::Predicate(index) => {
+                if let Some(value) = self.results[*index] {
+                    return value;
+                }
+                let value = self.predicates[*index].execute_in(self.ctx, &mut self.scope);
+                self.results[*index] = Some(value);
+                value
+            }
+            SetExpr::Not(arg) => !self.execute(arg),
+            SetExpr::Combining { op, items } => match op {
+                CombiningOp::And => items.iter().all(|item| self.execute(item)),
+                CombiningOp::Or => items.iter().any(|item| self.execute(item)),
+                CombiningOp::Xor => items
+                    .iter()
+                    .fold(false, |acc, item| acc ^ self.execute(item)),
+            },
+        }
+    }
+}
+
+/// An error that occurs if a filter can't be added to a [`FilterSet`].
+#[derive(Debug, PartialEq, Fail)]
+pub enum AddFilterError {
+    /// The filter was parsed with a different scheme than the set's.
+    #[fail(display = "{}", _0)]
+    SchemeMismatch(#[cause] SchemeMismatchError),
+
+    /// The filter can't be compiled.
+    #[fail(display = "{}", _0)]
+    Compile(#[cause] CompileError),
+}
+
+impl From<SchemeMismatchError> for AddFilterError {
+    fn from(err: SchemeMismatchError) -> Self {
+        AddFilterError::SchemeMismatch(err)
+    }
+}
+
+impl From<CompileError> for AddFilterError {
+    fn from(err: CompileError) -> Self {
+        AddFilterError::Compile(err)
+    }
+}
+
+/// Difference in matching filters of two [`FilterSet`]s on a single context.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Divergence {
+    /// Position of the context in the evaluated sequence.
+    pub context: usize,
+    /// Indices of filters that match only with the new set.
+    pub added: Vec<usize>,
+    /// Indices of filters that match only with the old set.
+    pub removed: Vec<usize>,
+}
+
+/// Result of comparing verdicts of two [`FilterSet`]s with
+/// [`FilterSet::compare`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DivergenceReport {
+    /// Number of evaluated contexts.
+    pub evaluated: usize,
+    /// Contexts that got different verdicts, in evaluation order.
+    pub divergences: Vec<Divergence>,
+}
+
+impl DivergenceReport {
+    /// Checks whether both sets produced the same verdicts on all contexts.
+    pub fn is_empty(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Returns indices of filters whose verdict changed on at least one
+    /// context, along with the number of such contexts, sorted by index.
+    pub fn changed_filters(&self) -> Vec<(usize, usize)> {
+        let mut counts = BTreeMap::new();
+        for divergence in &self.divergences {
+            for &index in divergence.added.iter().chain(&divergence.removed) {
+                *counts.entry(index).or_insert(0) += 1;
+            }
+        }
+        counts.into_iter().collect()
+    }
+}
+
+fn sorted_difference(lhs: &[usize], rhs: &[usize]) -> Vec<usize> {
+    lhs.iter()
+        .filter(|index| rhs.binary_search(index).is_err())
+        .cloned()
+        .collect()
+}
+
+/// A set of filters compiled together to be matched against the same
+/// contexts.
+///
+/// Identical predicates on fields that are shared between filters, like
+/// `port == 443`, are compiled only once and evaluated at most once per
+/// execution. Other predicates are evaluated one by one, as if each
+/// [`Filter`](::Filter) was executed separately, so the cost still grows
+/// with the number of distinct predicates in the set.
+pub struct FilterSet<'s> {
+    scheme: &'s Scheme,
+    predicate_indices: FnvHashMap<NodeId, Vec<(FieldExpr<'s>, usize)>>,
+    predicates: Vec<CompiledExpr<'s>>,
+    filters: Vec<SetExpr>,
+    regex_budget: Option<RegexBudget>,
+}
+
+impl<'s> FilterSet<'s> {
+    /// Creates an empty set for filters parsed with a given scheme.
+    pub fn new(scheme: &'s Scheme) -> Self {
+        FilterSet {
+            scheme,
+            predicate_indices: FnvHashMap::default(),
+            predicates: Vec::new(),
+            filters: Vec::new(),
+            regex_budget: None,
+        }
+    }
+
+    /// Compiles a [`FilterAst`] into the set and returns its index.
+    ///
+    /// The set is left intact if the filter can't be compiled.
+    pub fn add(&mut self, ast: FilterAst<'s>) -> Result<usize, AddFilterError> {
+        if self.scheme != ast.scheme() {
+            return Err(SchemeMismatchError.into());
+        }
+        ast.check_compilable()?;
+        let filter = self.add_expr(ast.into_expression());
+        self.filters.push(filter);
+        Ok(self.filters.len() - 1)
+    }
+
+    /// Returns the number of filters in the set.
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Checks whether the set has no filters.
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Sets a limit on the amount of regex matching done by each execution
+    /// of the set, which is shared by all its filters, or removes it with
+    /// `None`.
+    pub fn set_regex_budget(&mut self, budget: Option<RegexBudget>) {
+        self.regex_budget = budget;
+    }
+
+    /// Executes all filters against a provided context with values and
+    /// returns indices of the matching ones in ascending order.
+    pub fn execute(&self, ctx: &ExecutionContext<'s>) -> Result<Vec<usize>, SchemeMismatchError> {
+        if self.scheme != ctx.scheme() {
+            return Err(SchemeMismatchError);
+        }
+
+        let mut execution = SetExecution {
+            predicates: &self.predicates,
+            results: vec![None; self.predicates.len()].into(),
+            ctx,
+            scope: ExecutionScope::new(self.regex_budget),
+        };
+
+        Ok(forward_function_panics(|| {
+            self.filters
+                .iter()
+                .enumerate()
+                .filter(|(_, filter)| execution.execute(filter))
+                .map(|(index, _)| index)
+                .collect()
+        }))
+    }
+
+    /// Evaluates this (old) and another (new) version of a set over the same
+    /// contexts and reports where their verdicts diverge.
+    ///
+    /// Filters are identified by their indices, so both versions should add
+    /// the same rules in the same order, and only append new ones.
+    pub fn compare<'a, I>(
+        &self,
+        new: &FilterSet<'s>,
+        contexts: I,
+    ) -> Result<DivergenceReport, SchemeMismatchError>
+    where
+        's: 'a,
+        I: IntoIterator<Item = &'a ExecutionContext<'s>>,
+    {
+        let mut report = DivergenceReport::default();
+
+        for (context, ctx) in contexts.into_iter().enumerate() {
+            let old_matches = self.execute(ctx)?;
+            let new_matches = new.execute(ctx)?;
+
+            if old_matches != new_matches {
+                report.divergences.push(Divergence {
+                    context,
+                    added: sorted_difference(&new_matches, &old_matches),
+                    removed: sorted_difference(&old_matches, &new_matches),
+                });
+            }
+
+            report.evaluated += 1;
+        }
+
+        Ok(report)
+    }
+
+    fn add_expr(&mut self, expr: CombinedExpr<'s>) -> SetExpr {
+        match expr {
+            CombinedExpr::Simple(expr) => self.add_simple_expr(expr),
+            CombinedExpr::Combining { op, items } => SetExpr::Combining {
+                op,
+                items: items.into_iter().map(|item| self.add_expr(item)).collect(),
+            },
+        }
+    }
+
+    fn add_simple_expr(&mut self, expr: SimpleExpr<'s>) -> SetExpr {
+        match expr {
+            SimpleExpr::Field(expr) => self.add_predicate(expr),
+            SimpleExpr::Parenthesized(expr) => self.add_expr(unshare_expr(expr)),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => SetExpr::Not(Box::new(self.add_simple_expr(*arg))),
+        }
+    }
+
+    fn add_predicate(&mut self, mut expr: FieldExpr<'s>) -> SetExpr {
+        // Negated operators share the predicate with their positive forms.
+        if expr.is_negated() {
+            expr.set_negated(false);
+            return SetExpr::Not(Box::new(self.add_predicate(expr)));
+        }
+
+        // Identical predicates share a node ID, which is used to find them
+        // quickly, but IDs can collide, so candidates are compared in full.
+        let bucket = self.predicate_indices.entry(expr.node_id()).or_default();
+
+        let index = match bucket.iter().find(|(other, _)| *other == expr) {
+            Some(&(_, index)) => index,
+            None => {
+                let index = self.predicates.len();
+                self.predicates.push(expr.clone().compile());
+                bucket.push((expr, index));
+                index
+            }
+        };
+
+        SetExpr::Predicate(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let scheme = Scheme! {
+            http.host: Bytes,
+            tcp.port: Int,
+            ssl: Bool,
+        };
+
+        let mut set = FilterSet::new(&scheme);
+
+        assert!(set.is_empty());
+
+        for filter in &[
+            "tcp.port == 443 and ssl",
+            r#"tcp.port == 443 or http.host == "example.org""#,
+            "not ssl",
+            "tcp.port == 0x1bb",
+            "ssl xor (tcp.port == 80)",
+        ] {
+            set.add(scheme.parse(filter).unwrap()).unwrap();
+        }
+
+        assert_eq!(set.len(), 5);
+
+        // `tcp.port == 443`, `ssl`, `http.host == "example.org"`, `tcp.port == 80`
+        assert_eq!(set.predicates.len(), 4);
+
+        let mut ctx = ExecutionContext::new(&scheme);
+        ctx.set_field_value("http.host", "example.com").unwrap();
+        ctx.set_field_value("tcp.port", 443).unwrap();
+        ctx.set_field_value("ssl", true).unwrap();
+
+        assert_eq!(set.execute(&ctx), Ok(vec![0, 1, 3, 4]));
+
+        ctx.set_field_value("tcp.port", 80).unwrap();
+        ctx.set_field_value("ssl", false).unwrap();
+
+        assert_eq!(set.execute(&ctx), Ok(vec![2, 4]));
+    }
+
+    #[test]
+    fn test_compare() {
+        let scheme = Scheme! { tcp.port: Int };
+
+        let set = |filters: &[&str]| {
+            let mut set = FilterSet::new(&scheme);
+            for filter in filters {
+                set.add(scheme.parse(filter).unwrap()).unwrap();
+            }
+            set
+        };
+
+        let old = set(&["tcp.port == 80", "tcp.port > 1000"]);
+        let new = set(&[
+            "tcp.port in { 80 443 }",
+            "tcp.port > 1000",
+            "tcp.port == 22",
+        ]);
+
+        let contexts = [22, 80, 443, 8080]
+            .iter()
+            .map(|&port| {
+                let mut ctx = ExecutionContext::new(&scheme);
+                ctx.set_field_value("tcp.port", port).unwrap();
+                ctx
+            })
+            .collect::<Vec<_>>();
+
+        let report = old.compare(&new, &contexts).unwrap();
+
+        assert_eq!(report.evaluated, 4);
+        assert_eq!(
+            report.divergences,
+            [
+                Divergence {
+                    context: 0,
+                    added: vec![2],
+                    removed: vec![],
+                },
+                Divergence {
+                    context: 2,
+                    added: vec![0],
+                    removed: vec![],
+                },
+            ]
+        );
+        assert_eq!(report.changed_filters(), [(0, 1), (2, 1)]);
+
+        assert!(old.compare(&old, &contexts).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scheme_mismatch() {
+        let scheme1 = Scheme! { foo: Int };
+        let scheme2 = Scheme! { foo: Int };
+
+        let mut set = FilterSet::new(&scheme1);
+
+        assert_eq!(
+            set.add(scheme2.parse("foo == 42").unwrap()),
+            Err(AddFilterError::SchemeMismatch(SchemeMismatchError))
+        );
+
+        set.add(scheme1.parse("foo == 42").unwrap()).unwrap();
+
+        let ctx = ExecutionContext::new(&scheme2);
+
+        assert_eq!(set.execute(&ctx), Err(SchemeMismatchError));
+    }
+
+    #[test]
+    fn test_unbound_placeholder() {
+        let scheme = Scheme! { tcp.port: Int };
+
+        let mut set = FilterSet::new(&scheme);
+
+        assert_eq!(
+            set.add(scheme.parse("tcp.port == ${port:Int}").unwrap()),
+            Err(AddFilterError::Compile(CompileError::UnboundPlaceholder(
+                "port".to_owned()
+            )))
+        );
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn ensure_send_and_sync() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<FilterSet<'_>>();
+        is_sync::<FilterSet<'_>>();
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{
+    ast::{
+        CombinedExpr, CombiningOp, FieldExpr, FieldOp, FunctionCallArgExpr, IntOp, LhsFieldExpr,
+        OrderingOp, SimpleExpr, UnaryOp,
+    },
+    rhs_types::{Bytes, ExplicitIpRange, IntLiteral, MacAddr, StrLiteral},
+    types::{GetType, RhsValue, RhsValues},
+};
+use serde::{Serialize, Serializer};
+use std::{
+    fmt::{self, Display, Formatter},
+    net::IpAddr,
+    ops::RangeInclusive,
+    time::Duration,
+};
+
+// Bumped whenever the encoding below changes, which is a breaking change.
+const VERSION: u8 = 1;
+
+/// A stable 128-bit hash of a filter returned by
+/// [`FilterAst::fingerprint`](::FilterAst::fingerprint).
+///
+/// It's displayed and serialized as 32 hexadecimal digits.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Fingerprint(u128);
+
+impl Fingerprint {
+    /// Returns the numeric value of the fingerprint.
+    pub fn as_u128(self) -> u128 {
+        self.0
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+// 128-bit FNV-1a, which is simple enough to be reimplemented by consumers
+// in other languages and, unlike the default hasher, is fixed forever.
+fn fnv1a_128(data: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    data.iter().fold(OFFSET_BASIS, |hash, &b| {
+        (hash ^ u128::from(b)).wrapping_mul(PRIME)
+    })
+}
+
+// Writes a binary encoding of the AST in which equivalent spellings of the
+// same filter coincide.
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn tag(&mut self, tag: u8) {
+        self.0.push(tag);
+    }
+
+    fn len(&mut self, len: usize) {
+        self.0.extend_from_slice(&(len as u64).to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn int(&mut self, int: i32) {
+        self.0.extend_from_slice(&int.to_le_bytes());
+    }
+
+    fn duration(&mut self, duration: Duration) {
+        self.0.extend_from_slice(&duration.as_nanos().to_le_bytes());
+    }
+
+    fn ip(&mut self, addr: IpAddr) {
+        match addr {
+            IpAddr::V4(addr) => {
+                self.tag(4);
+                self.0.extend_from_slice(&addr.octets());
+            }
+            IpAddr::V6(addr) => {
+                self.tag(6);
+                self.0.extend_from_slice(&addr.octets());
+            }
+        }
+    }
+
+    fn mac(&mut self, addr: MacAddr) {
+        self.0.extend_from_slice(&addr.0);
+    }
+
+    fn ordering_op(&mut self, op: OrderingOp) {
+        self.tag(match op {
+            OrderingOp::Equal => 1,
+            OrderingOp::NotEqual => 2,
+            OrderingOp::GreaterThanEqual => 3,
+            OrderingOp::LessThanEqual => 4,
+            OrderingOp::GreaterThan => 5,
+            OrderingOp::LessThan => 6,
+        });
+    }
+
+    fn int_op(&mut self, op: IntOp) {
+        self.tag(match op {
+            IntOp::BitwiseAnd => 1,
+            IntOp::BitwiseOr => 2,
+            IntOp::BitwiseXor => 3,
+        });
+    }
+
+    fn combined(&mut self, expr: &CombinedExpr<'_>) {
+        match expr {
+            CombinedExpr::Simple(expr) => self.simple(expr),
+            CombinedExpr::Combining { op, items } => {
+                let mut operands = Vec::new();
+                flatten(*op, items, &mut operands);
+
+                self.tag(0x01);
+                self.tag(match op {
+                    CombiningOp::And => 1,
+                    CombiningOp::Or => 2,
+                    CombiningOp::Xor => 3,
+                });
+                self.len(operands.len());
+                for operand in operands {
+                    self.combined(operand);
+                }
+            }
+        }
+    }
+
+    fn simple(&mut self, expr: &SimpleExpr<'_>) {
+        match expr {
+            SimpleExpr::Field(expr) => self.field_expr(expr),
+            SimpleExpr::Parenthesized(expr) => self.combined(expr),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => {
+                self.tag(0x02);
+                self.simple(arg);
+            }
+        }
+    }
+
+    fn lhs(&mut self, lhs: &LhsFieldExpr<'_>) {
+        match lhs {
+            LhsFieldExpr::Field(field) => {
+                self.tag(0x10);
+                self.bytes(field.name().as_bytes());
+            }
+            LhsFieldExpr::Slice(slice) => {
+                self.tag(0x11);
+                self.bytes(slice.field().name().as_bytes());
+                self.len(slice.start());
+                match slice.end() {
+                    Some(end) => {
+                        self.tag(1);
+                        self.len(end);
+                    }
+                    None => self.tag(0),
+                }
+            }
+            LhsFieldExpr::FunctionCallExpr(call) => {
+                self.tag(0x12);
+                self.bytes(call.name().as_bytes());
+                let skipped = call.skipped_params();
+                self.len(call.args().len() + skipped.len());
+                let mut index = 0;
+                for arg in call.args() {
+                    while skipped.contains(&index) {
+                        self.tag(0x15);
+                        index += 1;
+                    }
+                    index += 1;
+                    match arg {
+                        FunctionCallArgExpr::LhsFieldExpr(lhs) => {
+                            self.tag(0x13);
+                            self.lhs(lhs);
+                        }
+                        FunctionCallArgExpr::Literal(value) => {
+                            self.tag(0x14);
+                            self.value(value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn value(&mut self, value: &RhsValue) {
+        match value {
+            RhsValue::Ip(addr) => {
+                self.tag(0x30);
+                self.ip(*addr);
+            }
+            RhsValue::Bytes(bytes) => self.bytes_value(bytes),
+            RhsValue::Str(s) => self.str_value(s),
+            RhsValue::Int(int) => {
+                self.tag(0x32);
+                self.int(int.value());
+            }
+            RhsValue::Bool(b) => match *b {},
+            RhsValue::Duration(duration) => {
+                self.tag(0x33);
+                self.duration(*duration);
+            }
+            RhsValue::Mac(addr) => {
+                self.tag(0x34);
+                self.mac(*addr);
+            }
+        }
+    }
+
+    // Byte strings are the same regardless of the literal format.
+    fn bytes_value(&mut self, bytes: &Bytes) {
+        self.tag(0x31);
+        self.bytes(bytes);
+    }
+
+    fn str_value(&mut self, s: &StrLiteral) {
+        self.tag(0x35);
+        self.bytes(s.as_bytes());
+    }
+
+    // Sets are encoded as sorted lists of encodings of unique items.
+    fn set<T>(&mut self, items: &[T], mut encode: impl FnMut(&mut Encoder, &T)) {
+        let mut items = items
+            .iter()
+            .map(|item| {
+                let mut encoder = Encoder::default();
+                encode(&mut encoder, item);
+                encoder.0
+            })
+            .collect::<Vec<_>>();
+        items.sort();
+        items.dedup();
+
+        self.len(items.len());
+        for item in items {
+            self.0.extend(item);
+        }
+    }
+
+    fn values(&mut self, values: &RhsValues) {
+        match values {
+            RhsValues::Ip(ranges) => self.set(ranges, |this, range| {
+                // Networks and explicit ranges covering the same addresses
+                // are the same.
+                let (start, end) = match ExplicitIpRange::from(range.clone()) {
+                    ExplicitIpRange::V4(range) => {
+                        (IpAddr::V4(*range.start()), IpAddr::V4(*range.end()))
+                    }
+                    ExplicitIpRange::V6(range) => {
+                        (IpAddr::V6(*range.start()), IpAddr::V6(*range.end()))
+                    }
+                };
+                this.tag(0x40);
+                this.ip(start);
+                this.ip(end);
+            }),
+            RhsValues::Bytes(values) => self.set(values, Encoder::bytes_value),
+            RhsValues::Str(values) => self.set(values, Encoder::str_value),
+            RhsValues::Int(ranges) => self.set(&merge_int_ranges(ranges), |this, &(start, end)| {
+                this.tag(0x41);
+                this.int(start);
+                this.int(end);
+            }),
+            RhsValues::Bool(values) => self.set(values, |_, b| match *b {}),
+            RhsValues::Duration(ranges) => self.set(ranges, |this, range| {
+                this.tag(0x42);
+                this.duration(*range.start());
+                this.duration(*range.end());
+            }),
+            RhsValues::Mac(prefixes) => self.set(prefixes, |this, prefix| {
+                let range = RangeInclusive::<MacAddr>::from(*prefix);
+                this.tag(0x43);
+                this.mac(*range.start());
+                this.mac(*range.end());
+            }),
+        }
+    }
+
+    fn field_expr(&mut self, expr: &FieldExpr<'_>) {
+        // Negated operators are encoded the same way as a `not` applied to
+        // the non-negated ones.
+        if expr.is_negated() {
+            self.tag(0x02);
+        }
+
+        self.tag(0x03);
+        self.lhs(expr.lhs());
+
+        match expr.op() {
+            FieldOp::IsTrue => self.tag(0x20),
+            FieldOp::Ordering { op, rhs } => {
+                self.tag(0x21);
+                self.ordering_op(*op);
+                self.value(rhs);
+            }
+            FieldOp::FieldOrdering { op, rhs_field } => {
+                self.tag(0x22);
+                self.ordering_op(*op);
+                self.bytes(rhs_field.name().as_bytes());
+            }
+            FieldOp::PlaceholderOrdering { op, placeholder } => {
+                self.tag(0x2c);
+                self.ordering_op(*op);
+                self.bytes(placeholder.name().as_bytes());
+                self.tag(placeholder.get_type() as u8);
+            }
+            FieldOp::Int { op, rhs } => {
+                self.tag(0x23);
+                self.int_op(*op);
+                self.int(rhs.value());
+            }
+            FieldOp::IntOrdering {
+                op,
+                rhs,
+                ordering,
+                value,
+            } => {
+                self.tag(0x24);
+                self.int_op(*op);
+                self.int(rhs.value());
+                self.ordering_op(*ordering);
+                self.int(value.value());
+            }
+            FieldOp::Contains(bytes) => {
+                self.tag(0x25);
+                self.bytes(bytes);
+            }
+            FieldOp::ContainsAny(values) => {
+                self.tag(0x26);
+                self.set(values, Encoder::bytes_value);
+            }
+            FieldOp::StartsWith(bytes) => {
+                self.tag(0x27);
+                self.bytes(bytes);
+            }
+            FieldOp::EndsWith(bytes) => {
+                self.tag(0x28);
+                self.bytes(bytes);
+            }
+            FieldOp::Matches(regex) => {
+                self.tag(if regex.is_unicode() { 0x2d } else { 0x29 });
+                self.bytes(regex.as_str().as_bytes());
+            }
+            FieldOp::CaseInsensitiveEqual(bytes) => {
+                self.tag(0x2a);
+                self.bytes(bytes);
+            }
+            FieldOp::OneOf(values) => {
+                self.tag(0x2b);
+                self.values(values);
+            }
+            FieldOp::InList(list) => {
+                self.tag(0x2e);
+                self.bytes(list.name().as_bytes());
+            }
+        }
+    }
+}
+
+// Merges overlapping and adjacent ranges, so that `{ 1..2 3 }` and
+// `{ 1..3 }` are the same.
+fn merge_int_ranges(ranges: &[RangeInclusive<IntLiteral>]) -> Vec<(i32, i32)> {
+    let mut ranges = ranges
+        .iter()
+        .map(|range| (range.start().value(), range.end().value()))
+        .collect::<Vec<_>>();
+    ranges.sort();
+
+    let mut merged: Vec<(i32, i32)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if i64::from(start) <= i64::from(last.1) + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// Collects operands of nested operators of the same kind, which don't change
+// the result, like in `a and (b and c)`.
+fn flatten<'a, 's>(
+    op: CombiningOp,
+    items: &'a [CombinedExpr<'s>],
+    operands: &mut Vec<&'a CombinedExpr<'s>>,
+) {
+    for item in items {
+        let mut operand = item;
+        while let CombinedExpr::Simple(SimpleExpr::Parenthesized(expr)) = operand {
+            operand = expr;
+        }
+        match operand {
+            CombinedExpr::Combining {
+                op: item_op,
+                items: item_items,
+            } if *item_op == op => flatten(op, item_items, operands),
+            _ => operands.push(item),
+        }
+    }
+}
+
+pub(crate) fn fingerprint(expr: &CombinedExpr<'_>) -> Fingerprint {
+    let mut encoder = Encoder::default();
+    encoder.tag(VERSION);
+    encoder.combined(expr);
+    Fingerprint(fnv1a_128(&encoder.0))
+}
+
+#[test]
+fn test_fnv1a_128() {
+    assert_eq!(fnv1a_128(b""), 0x6c62272e07bb014262b821756295c58d);
+    assert_eq!(fnv1a_128(b"a"), 0xd228cb696f1a8caf78912b704e4a8964);
+}
+
+#[test]
+fn test_fingerprint() {
+    let scheme = &Scheme! {
+        http.host: Bytes,
+        ip.src: Ip,
+        tcp.port: Int,
+        ssl: Bool,
+        eth.src: Mac,
+    };
+
+    let fingerprint = |filter: &str| scheme.parse(filter).unwrap().fingerprint();
+
+    // The value must never change, or fingerprints stored by users would
+    // become stale.
+    let golden = fingerprint("tcp.port in { 443 80 }");
+    assert_eq!(golden.as_u128(), 0x7168878083dc689688fecda011f33274);
+    assert_eq!(golden.to_string(), "7168878083dc689688fecda011f33274");
+    assert_eq!(
+        serde_json::to_value(golden).unwrap(),
+        "7168878083dc689688fecda011f33274"
+    );
+
+    for &(a, b) in &[
+        ("tcp.port in { 443 80 }", "tcp.port in {80 0x1bb 0x50}"),
+        (
+            r#"http.host == "ab" && (ssl || tcp.port eq 80)"#,
+            r#"(http.host == 61:62) and (((ssl) or tcp.port == 0x50))"#,
+        ),
+        ("ssl and (ssl and not ssl)", "ssl and ssl and !ssl"),
+        (
+            r#"http.host contains any { "a" "b" "a" }"#,
+            r#"http.host contains any { "b" "a" }"#,
+        ),
+        (
+            "ip.src in { 10.0.0.0/31 }",
+            "ip.src in { 10.0.0.0..10.0.0.1 }",
+        ),
+        ("tcp.port in { 80..81 83 82 }", "tcp.port in { 80..83 }"),
+        ("tcp.port not in { 80 }", "not (tcp.port in { 80 })"),
+        (r#"http.host !~ "a""#, r#"not http.host matches "a""#),
+    ] {
+        assert_eq!(fingerprint(a), fingerprint(b), "{} vs {}", a, b);
+    }
+
+    for &(a, b) in &[
+        ("tcp.port == 80", "tcp.port in { 80 }"),
+        ("tcp.port == 80", "tcp.port != 80"),
+        ("ssl and tcp.port == 80", "tcp.port == 80 and ssl"),
+        ("ssl and ssl or ssl", "ssl and (ssl or ssl)"),
+        ("ssl", "not ssl"),
+        (r#"http.host == "ab""#, r#"http.host contains "ab""#),
+        (
+            r#"http.host contains any { "ab" }"#,
+            r#"http.host contains any { "a" "b" }"#,
+        ),
+    ] {
+        assert_ne!(fingerprint(a), fingerprint(b), "{} vs {}", a, b);
+    }
+}
@@ -0,0 +1,832 @@
+use crate::{
+    ast::{
+        CombinedExpr, CombiningOp, FieldExpr, FieldOp, FunctionCallArgExpr, IntOp, LhsFieldExpr,
+        OrderingOp, SimpleExpr, UnaryOp,
+    },
+    rhs_types::{Bytes, BytesFormat, ExplicitIpRange, IpRange, Regex, RegexFormat},
+    types::{GetType, RhsValue, RhsValues, Type},
+};
+use cidr::Cidr;
+use std::{
+    fmt::{Display, Write},
+    ops::RangeInclusive,
+    str,
+    time::Duration,
+};
+
+/// Spelling of operators that have both a word and a symbol form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatorStyle {
+    /// Words like `and`, `not`, `eq` or `matches`.
+    Words,
+    /// Symbols like `&&`, `!`, `==` or `~`.
+    Symbols,
+}
+
+/// Options of [`FilterAst::format`](::FilterAst::format).
+///
+/// The defaults produce the canonical form returned by
+/// [`FilterAst::to_canonical_string`](::FilterAst::to_canonical_string).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    logical_op_style: OperatorStyle,
+    comparison_op_style: OperatorStyle,
+    set_spacing: bool,
+    max_width: Option<usize>,
+    indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            logical_op_style: OperatorStyle::Words,
+            comparison_op_style: OperatorStyle::Symbols,
+            set_spacing: true,
+            max_width: None,
+            indent: 4,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Sets the spelling of `and`, `or`, `xor` and `not`.
+    ///
+    /// Defaults to [`OperatorStyle::Words`].
+    pub fn logical_op_style(mut self, style: OperatorStyle) -> Self {
+        self.logical_op_style = style;
+        self
+    }
+
+    /// Sets the spelling of comparison, bitwise and `matches` operators.
+    ///
+    /// Defaults to [`OperatorStyle::Symbols`].
+    pub fn comparison_op_style(mut self, style: OperatorStyle) -> Self {
+        self.comparison_op_style = style;
+        self
+    }
+
+    /// Sets whether sets are padded with spaces inside braces, like
+    /// `{ 80 443 }`, or not, like `{80 443}`.
+    ///
+    /// Defaults to `true`.
+    pub fn set_spacing(mut self, set_spacing: bool) -> Self {
+        self.set_spacing = set_spacing;
+        self
+    }
+
+    /// Sets the line width to wrap long expressions at, or `None` to always
+    /// produce a single line.
+    ///
+    /// Logical operators that don't fit are split into one operand per line,
+    /// each prefixed with the operator, and parentheses and sets that don't
+    /// fit are split into one item per line. Single comparisons are never
+    /// split, so lines with them can still exceed the width.
+    ///
+    /// Defaults to `None`.
+    pub fn max_width(mut self, max_width: Option<usize>) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Sets the number of spaces to indent wrapped lines with.
+    ///
+    /// Defaults to 4.
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+}
+
+struct Printer<'o> {
+    options: &'o FormatOptions,
+    // Same as in options, except for nested printers that check whether an
+    // expression fits on a single line.
+    max_width: Option<usize>,
+    out: String,
+}
+
+impl<'o> Printer<'o> {
+    fn write(&mut self, s: impl Display) {
+        // Writing to a `String` never fails.
+        write!(self.out, "{}", s).unwrap();
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.extend((0..indent).map(|_| ' '));
+    }
+
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |pos| pos + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    // Writes the output of the callback if wrapping is disabled or if it
+    // fits on the current line, and returns whether it did.
+    fn write_single_line(&mut self, write: impl FnOnce(&mut Printer<'o>)) -> bool {
+        let max_width = match self.max_width {
+            Some(max_width) => max_width,
+            None => {
+                write(self);
+                return true;
+            }
+        };
+
+        let mut single_line = Printer {
+            options: self.options,
+            max_width: None,
+            out: String::new(),
+        };
+        write(&mut single_line);
+
+        if self.column() + single_line.out.chars().count() <= max_width {
+            self.out.push_str(&single_line.out);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pick(&self, style: OperatorStyle, word: &'static str, symbol: &'static str) -> &'static str {
+        match style {
+            OperatorStyle::Words => word,
+            OperatorStyle::Symbols => symbol,
+        }
+    }
+
+    fn write_combining_op(&mut self, op: CombiningOp) {
+        let style = self.options.logical_op_style;
+        self.write(match op {
+            CombiningOp::And => self.pick(style, "and", "&&"),
+            CombiningOp::Or => self.pick(style, "or", "||"),
+            CombiningOp::Xor => self.pick(style, "xor", "^^"),
+        });
+    }
+
+    fn write_ordering_op(&mut self, op: OrderingOp) {
+        let style = self.options.comparison_op_style;
+        self.write(match op {
+            OrderingOp::Equal => self.pick(style, "eq", "=="),
+            OrderingOp::NotEqual => self.pick(style, "ne", "!="),
+            OrderingOp::GreaterThanEqual => self.pick(style, "ge", ">="),
+            OrderingOp::LessThanEqual => self.pick(style, "le", "<="),
+            OrderingOp::GreaterThan => self.pick(style, "gt", ">"),
+            OrderingOp::LessThan => self.pick(style, "lt", "<"),
+        });
+    }
+
+    fn write_int_op(&mut self, op: IntOp) {
+        let style = self.options.comparison_op_style;
+        self.write(match op {
+            IntOp::BitwiseAnd => self.pick(style, "bitwise_and", "&"),
+            IntOp::BitwiseOr => self.pick(style, "bitwise_or", "|"),
+            IntOp::BitwiseXor => self.pick(style, "bitwise_xor", "^"),
+        });
+    }
+
+    fn write_combined(&mut self, expr: &CombinedExpr<'_>, indent: usize) {
+        match expr {
+            CombinedExpr::Simple(expr) => self.write_simple(expr, indent),
+            CombinedExpr::Combining { op, items } => {
+                let fits =
+                    self.write_single_line(|this| this.write_combining(*op, items, indent, false));
+                if !fits {
+                    self.write_combining(*op, items, indent, true);
+                }
+            }
+        }
+    }
+
+    fn write_combining(
+        &mut self,
+        op: CombiningOp,
+        items: &[CombinedExpr<'_>],
+        indent: usize,
+        wrap: bool,
+    ) {
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                if wrap {
+                    self.newline(indent);
+                } else {
+                    self.write(" ");
+                }
+                self.write_combining_op(op);
+                self.write(" ");
+            }
+            match item {
+                // Nested operators are either of higher precedence, or were
+                // built programmatically and need parentheses to keep the
+                // structure when parsed back.
+                CombinedExpr::Combining { op: item_op, .. } if *item_op <= op => {
+                    self.write_parenthesized(item, indent)
+                }
+                CombinedExpr::Combining { .. } => {
+                    self.write_combined(item, indent + self.options.indent)
+                }
+                CombinedExpr::Simple(item) => self.write_simple(item, indent),
+            }
+        }
+    }
+
+    fn write_parenthesized(&mut self, expr: &CombinedExpr<'_>, indent: usize) {
+        let fits = self.write_single_line(|this| {
+            this.write("(");
+            this.write_combined(expr, indent);
+            this.write(")");
+        });
+        if !fits {
+            let inner_indent = indent + self.options.indent;
+            self.write("(");
+            self.newline(inner_indent);
+            self.write_combined(expr, inner_indent);
+            self.newline(indent);
+            self.write(")");
+        }
+    }
+
+    fn write_simple(&mut self, expr: &SimpleExpr<'_>, indent: usize) {
+        match expr {
+            SimpleExpr::Field(expr) => self.write_field_expr(expr, indent),
+            SimpleExpr::Parenthesized(expr) => self.write_parenthesized(expr, indent),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => {
+                let op = self.pick(self.options.logical_op_style, "not ", "!");
+                self.write(op);
+                self.write_simple(arg, indent);
+            }
+        }
+    }
+
+    fn write_lhs(&mut self, lhs: &LhsFieldExpr<'_>) {
+        match lhs {
+            LhsFieldExpr::Field(field) => self.write(field.name()),
+            LhsFieldExpr::Slice(slice) => {
+                self.write(format_args!("{}[{}..", slice.field().name(), slice.start()));
+                if let Some(end) = slice.end() {
+                    self.write(end);
+                }
+                self.write("]");
+            }
+            LhsFieldExpr::FunctionCallExpr(call) => {
+                self.write(call.name());
+                self.write("(");
+                for (i, (arg, name)) in call.args().iter().zip(call.arg_names()).enumerate() {
+                    if i != 0 {
+                        self.write(", ");
+                    }
+                    if let Some(name) = name {
+                        self.write(name);
+                        self.write(": ");
+                    }
+                    match arg {
+                        FunctionCallArgExpr::LhsFieldExpr(lhs) => self.write_lhs(lhs),
+                        FunctionCallArgExpr::Literal(value) => self.write_value(value),
+                    }
+                }
+                self.write(")");
+            }
+        }
+    }
+
+    fn write_value(&mut self, value: &RhsValue) {
+        match value {
+            RhsValue::Ip(addr) => self.write(addr),
+            RhsValue::Bytes(bytes) => write_bytes(&mut self.out, bytes),
+            RhsValue::Str(s) => write_bytes(&mut self.out, s.as_bytes_literal()),
+            RhsValue::Int(int) => self.write(int),
+            RhsValue::Bool(b) => match *b {},
+            RhsValue::Duration(duration) => write_duration(&mut self.out, *duration),
+            RhsValue::Mac(mac) => self.write(mac),
+        }
+    }
+
+    fn write_set(&mut self, items: Vec<String>, indent: usize) {
+        let fits = self.write_single_line(|this| {
+            let padding = if this.options.set_spacing && !items.is_empty() {
+                " "
+            } else {
+                ""
+            };
+            this.write("{");
+            this.write(padding);
+            this.write(items.join(" "));
+            this.write(padding);
+            this.write("}");
+        });
+        if !fits {
+            self.write("{");
+            for item in items {
+                self.newline(indent + self.options.indent);
+                self.write(item);
+            }
+            self.newline(indent);
+            self.write("}");
+        }
+    }
+
+    // Regexes matched against `Str` are always in Unicode mode, and ones
+    // matched against `Bytes` are marked with a suffix, so that they are
+    // lexed back the same regardless of the scheme settings.
+    fn write_regex(&mut self, expr: &FieldExpr<'_>, regex: &Regex) {
+        write_regex(&mut self.out, regex);
+        if regex.is_unicode() && expr.lhs().get_type() != Type::Str {
+            self.write("u");
+        }
+    }
+
+    fn write_field_expr(&mut self, expr: &FieldExpr<'_>, indent: usize) {
+        self.write_lhs(expr.lhs());
+
+        if expr.is_negated() {
+            if let FieldOp::Matches(regex) = expr.op() {
+                let op = self.pick(self.options.comparison_op_style, " not matches ", " !~ ");
+                self.write(op);
+                self.write_regex(expr, regex);
+                return;
+            }
+            self.write(" not");
+        }
+
+        match expr.op() {
+            FieldOp::IsTrue => {}
+            FieldOp::Ordering { op, rhs } => {
+                self.write(" ");
+                self.write_ordering_op(*op);
+                self.write(" ");
+                self.write_value(rhs);
+            }
+            FieldOp::FieldOrdering { op, rhs_field } => {
+                self.write(" ");
+                self.write_ordering_op(*op);
+                self.write(" ");
+                self.write(rhs_field.name());
+            }
+            FieldOp::PlaceholderOrdering { op, placeholder } => {
+                self.write(" ");
+                self.write_ordering_op(*op);
+                self.write(" ");
+                self.write(placeholder);
+            }
+            FieldOp::Int { op, rhs } => {
+                self.write(" ");
+                self.write_int_op(*op);
+                self.write(format_args!(" {}", rhs));
+            }
+            FieldOp::IntOrdering {
+                op,
+                rhs,
+                ordering,
+                value,
+            } => {
+                self.write(" ");
+                self.write_int_op(*op);
+                self.write(format_args!(" {} ", rhs));
+                self.write_ordering_op(*ordering);
+                self.write(format_args!(" {}", value));
+            }
+            FieldOp::Contains(bytes) => {
+                self.write(" contains ");
+                write_bytes(&mut self.out, bytes);
+            }
+            FieldOp::ContainsAny(values) => {
+                self.write(" contains any ");
+                self.write_set(values.iter().map(bytes_to_string).collect(), indent);
+            }
+            FieldOp::StartsWith(bytes) => {
+                self.write(" startswith ");
+                write_bytes(&mut self.out, bytes);
+            }
+            FieldOp::EndsWith(bytes) => {
+                self.write(" endswith ");
+                write_bytes(&mut self.out, bytes);
+            }
+            FieldOp::Matches(regex) => {
+                let op = self.pick(self.options.comparison_op_style, " matches ", " ~ ");
+                self.write(op);
+                self.write_regex(expr, regex);
+            }
+            FieldOp::CaseInsensitiveEqual(bytes) => {
+                self.write(" ieq ");
+                write_bytes(&mut self.out, bytes);
+            }
+            FieldOp::OneOf(values) => {
+                self.write(" in ");
+                self.write_set(values_to_strings(values), indent);
+            }
+            FieldOp::InList(list) => {
+                self.write(" in $");
+                self.write(list.name());
+            }
+        }
+    }
+}
+
+fn bytes_to_string(bytes: &Bytes) -> String {
+    let mut out = String::new();
+    write_bytes(&mut out, bytes);
+    out
+}
+
+// Writes a raw string unless it contains its own terminator, and returns
+// whether it was written.
+fn write_raw_string(out: &mut String, s: &str, hashes: u8) -> bool {
+    let hashes = "#".repeat(hashes.into());
+    if s.contains(&format!("\"{}", hashes)) {
+        return false;
+    }
+    write!(out, "r{}\"{}\"{}", hashes, s, hashes).unwrap();
+    true
+}
+
+// Writes a literal that is lexed back into the same bytes and format.
+//
+// Raw strings that can't be represented as such are written quoted.
+fn write_bytes(out: &mut String, bytes: &Bytes) {
+    match bytes.format() {
+        BytesFormat::RawString(hashes) => {
+            let written = match str::from_utf8(bytes) {
+                Ok(s) => write_raw_string(out, s, hashes),
+                Err(_) => false,
+            };
+            if !written {
+                write_bytes(out, &Bytes::new(&bytes[..], BytesFormat::Quoted));
+            }
+        }
+        BytesFormat::Quoted => {
+            out.push('"');
+            match str::from_utf8(bytes) {
+                Ok(s) => {
+                    for c in s.chars() {
+                        match c {
+                            '"' | '\\' => {
+                                out.push('\\');
+                                out.push(c);
+                            }
+                            c if c.is_ascii_control() => {
+                                write!(out, "\\x{:02x}", c as u32).unwrap()
+                            }
+                            c if c.is_control() => write!(out, "\\u{{{:x}}}", c as u32).unwrap(),
+                            c => out.push(c),
+                        }
+                    }
+                }
+                Err(_) => {
+                    for &b in bytes.iter() {
+                        match b {
+                            b'"' | b'\\' => {
+                                out.push('\\');
+                                out.push(b as char);
+                            }
+                            b' '..=b'~' => out.push(b as char),
+                            _ => write!(out, "\\x{:02x}", b).unwrap(),
+                        }
+                    }
+                }
+            }
+            out.push('"');
+        }
+        BytesFormat::Raw => {
+            for (i, b) in bytes.iter().enumerate() {
+                if i != 0 {
+                    out.push(':');
+                }
+                write!(out, "{:02X}", b).unwrap();
+            }
+        }
+    }
+}
+
+// Mirrors the lexer, which unescapes `\"` outside of character classes only.
+fn write_regex(out: &mut String, regex: &Regex) {
+    if let RegexFormat::RawString(hashes) = regex.format() {
+        if write_raw_string(out, regex.as_str(), hashes) {
+            return;
+        }
+    }
+    out.push('"');
+    let mut in_char_class = false;
+    let mut iter = regex.as_str().chars();
+    while let Some(c) = iter.next() {
+        match c {
+            '\\' => {
+                out.push('\\');
+                if let Some(c) = iter.next() {
+                    out.push(c);
+                }
+            }
+            '"' if !in_char_class => out.push_str("\\\""),
+            '[' if !in_char_class => {
+                in_char_class = true;
+                out.push('[');
+            }
+            ']' if in_char_class => {
+                in_char_class = false;
+                out.push(']');
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Writes the duration as components with the largest units first, like
+// `1h30m`.
+fn write_duration(out: &mut String, duration: Duration) {
+    const UNITS: &[(&str, u128)] = &[
+        ("d", 24 * 60 * 60 * 1_000_000_000),
+        ("h", 60 * 60 * 1_000_000_000),
+        ("m", 60 * 1_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ];
+
+    let mut nanos = duration.as_nanos();
+    if nanos == 0 {
+        out.push_str("0s");
+        return;
+    }
+    for &(unit, multiplier) in UNITS {
+        if nanos >= multiplier {
+            write!(out, "{}{}", nanos / multiplier, unit).unwrap();
+            nanos %= multiplier;
+        }
+    }
+}
+
+fn range_to_string<T: PartialEq>(
+    range: &RangeInclusive<T>,
+    mut write: impl FnMut(&mut String, &T),
+) -> String {
+    let mut out = String::new();
+    write(&mut out, range.start());
+    if range.end() != range.start() {
+        out.push_str("..");
+        write(&mut out, range.end());
+    }
+    out
+}
+
+fn ip_range_to_string(range: &IpRange) -> String {
+    match range {
+        IpRange::Explicit(ExplicitIpRange::V4(range)) => {
+            format!("{}..{}", range.start(), range.end())
+        }
+        IpRange::Explicit(ExplicitIpRange::V6(range)) => {
+            format!("{}..{}", range.start(), range.end())
+        }
+        IpRange::Cidr(cidr) if cidr.first_address() == cidr.last_address() => {
+            cidr.first_address().to_string()
+        }
+        IpRange::Cidr(cidr) => format!("{}/{}", cidr.first_address(), cidr.network_length()),
+    }
+}
+
+pub(crate) fn values_to_strings(values: &RhsValues) -> Vec<String> {
+    match values {
+        RhsValues::Ip(ranges) => ranges.iter().map(ip_range_to_string).collect(),
+        RhsValues::Bytes(values) => values.iter().map(bytes_to_string).collect(),
+        RhsValues::Str(values) => values
+            .iter()
+            .map(|s| bytes_to_string(s.as_bytes_literal()))
+            .collect(),
+        RhsValues::Int(ranges) => ranges
+            .iter()
+            .map(|range| range_to_string(range, |out, int| write!(out, "{}", int).unwrap()))
+            .collect(),
+        RhsValues::Bool(values) => values.iter().map(|b| match *b {}).collect(),
+        RhsValues::Duration(ranges) => ranges
+            .iter()
+            .map(|range| range_to_string(range, |out, duration| write_duration(out, *duration)))
+            .collect(),
+        RhsValues::Mac(prefixes) => prefixes.iter().map(ToString::to_string).collect(),
+    }
+}
+
+pub(crate) fn format_expr(expr: &CombinedExpr<'_>, options: &FormatOptions) -> String {
+    let mut printer = Printer {
+        options,
+        max_width: options.max_width,
+        out: String::new(),
+    };
+    printer.write_combined(expr, 0);
+    printer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scheme;
+
+    fn scheme() -> Scheme {
+        Scheme! {
+            http.host: Bytes,
+            http.ua: Bytes,
+            ip.src: Ip,
+            ip.dst: Ip,
+            tcp.port: Int,
+            tcp.flags: Int,
+            ssl: Bool,
+            rtt: Duration,
+            eth.src: Mac,
+        }
+    }
+
+    fn assert_round_trip(scheme: &Scheme, filter: &str, options: &FormatOptions) -> String {
+        let ast = scheme.parse(filter).unwrap();
+        let formatted = ast.format(options);
+        assert_eq!(scheme.parse(&formatted), Ok(ast), "{}", formatted);
+        formatted
+    }
+
+    #[test]
+    fn test_canonical() {
+        let scheme = &scheme();
+
+        for &(filter, expected) in &[
+            (
+                r#"http.host  eq "example.org" && tcp.port in {80 0x1bb 8000..8080}"#,
+                r#"http.host == "example.org" and tcp.port in { 80 0x1bb 8000..8080 }"#,
+            ),
+            (
+                "(ssl or ssl) && !ssl ^^ tcp.flags bitwise_and 0x12 ge 0x12",
+                "(ssl or ssl) and not ssl xor tcp.flags & 0x12 >= 0x12",
+            ),
+            (
+                "tcp.flags & 0b0001_0010 != 0 and tcp.port == 0x1F",
+                "tcp.flags & 0b00010010 != 0 and tcp.port == 0x1F",
+            ),
+            (
+                r#"http.ua matches "\"a["]\\d" or http.ua[0..4] ieq "MOZI""#,
+                r#"http.ua ~ "\"a["]\\d" or http.ua[0..4] ieq "MOZI""#,
+            ),
+            (
+                r#"http.host contains any {"a" 62:63} or http.host endswith "\x00\u{85}é""#,
+                r#"http.host contains any { "a" 62:63 } or http.host endswith "\x00\u{85}é""#,
+            ),
+            (
+                r#"http.host startswith "\xff\"" and http.ua[..2] == "ab" or http.ua[3..] contains "x""#,
+                r#"http.host startswith "\xff\"" and http.ua[0..2] == "ab" or http.ua[3..] contains "x""#,
+            ),
+            (
+                "ip.src in { 10.0.0.0/8 192.168.0.1 } and ip.src != ip.dst",
+                "ip.src in { 10.0.0.0/8 192.168.0.1 } and ip.src != ip.dst",
+            ),
+            (
+                "rtt in { 90m 1500ms..1d } and eth.src in { aa:bb:cc:00:00:00/24 }",
+                "rtt in { 1h30m 1s500ms..1d } and eth.src in { aa:bb:cc:00:00:00/24 }",
+            ),
+            ("rtt > 0s", "rtt > 0s"),
+            (
+                r###"http.ua matches r#"\d"+"# and http.host == r"a\b""###,
+                r###"http.ua ~ r#"\d"+"# and http.host == r"a\b""###,
+            ),
+            (
+                r#"tcp.port not in {80} and http.ua not matches "a" or not http.host not  contains "b""#,
+                r#"tcp.port not in { 80 } and http.ua !~ "a" or not http.host not contains "b""#,
+            ),
+        ] {
+            let ast = scheme.parse(filter).unwrap();
+            assert_eq!(ast.to_canonical_string(), expected);
+            assert_eq!(ast.to_string(), expected);
+            assert_round_trip(scheme, filter, &FormatOptions::default());
+        }
+
+        assert_round_trip(
+            scheme,
+            "ip.src in { 10.0.0.1..10.0.0.5 ::1..::5 } or ip.src == ::1",
+            &FormatOptions::default(),
+        );
+    }
+
+    #[test]
+    fn test_preserves_structure() {
+        let scheme = &scheme();
+
+        let mut ast = scheme.parse("ssl and tcp.port == 80").unwrap();
+        let inner = ast.expression().clone();
+        *ast.expression_mut() = CombinedExpr::Combining {
+            op: CombiningOp::And,
+            items: vec![inner.clone(), inner],
+        };
+
+        let formatted = ast.to_canonical_string();
+        assert_eq!(
+            formatted,
+            "(ssl and tcp.port == 80) and (ssl and tcp.port == 80)"
+        );
+        assert_eq!(
+            scheme.parse(&formatted).unwrap().to_canonical_string(),
+            formatted
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let scheme = &scheme();
+        let filter = r#"not ssl and (tcp.port in {80 443} or http.ua matches "bot")"#;
+
+        assert_eq!(
+            assert_round_trip(
+                scheme,
+                filter,
+                &FormatOptions::default()
+                    .logical_op_style(OperatorStyle::Symbols)
+                    .comparison_op_style(OperatorStyle::Words)
+                    .set_spacing(false)
+            ),
+            r#"!ssl && (tcp.port in {80 443} || http.ua matches "bot")"#
+        );
+
+        assert_eq!(
+            assert_round_trip(
+                scheme,
+                "tcp.flags & 1 or tcp.flags ^ 2 == 2",
+                &FormatOptions::default().comparison_op_style(OperatorStyle::Words)
+            ),
+            "tcp.flags bitwise_and 1 or tcp.flags bitwise_xor 2 eq 2"
+        );
+
+        assert_eq!(
+            assert_round_trip(
+                scheme,
+                r#"http.ua !~ "bot" and http.ua not ieq "a""#,
+                &FormatOptions::default().comparison_op_style(OperatorStyle::Words)
+            ),
+            r#"http.ua not matches "bot" and http.ua not ieq "a""#
+        );
+    }
+
+    #[test]
+    fn test_wrapping() {
+        use indoc::indoc;
+
+        let scheme = &scheme();
+        let filter = r#"
+            http.host == "example.org" and tcp.port in {80 443 8080 8443 9000 9443}
+            or ssl and (http.ua contains "bot" or http.ua contains "crawler")
+        "#;
+
+        assert_eq!(
+            assert_round_trip(
+                scheme,
+                filter,
+                &FormatOptions::default().max_width(Some(80))
+            ),
+            indoc!(
+                r#"
+                http.host == "example.org" and tcp.port in { 80 443 8080 8443 9000 9443 }
+                or ssl and (http.ua contains "bot" or http.ua contains "crawler")"#
+            )
+        );
+
+        assert_eq!(
+            assert_round_trip(
+                scheme,
+                filter,
+                &FormatOptions::default().max_width(Some(40)).indent(2)
+            ),
+            indoc!(
+                r#"
+                http.host == "example.org"
+                  and tcp.port in {
+                    80
+                    443
+                    8080
+                    8443
+                    9000
+                    9443
+                  }
+                or ssl
+                  and (
+                    http.ua contains "bot"
+                    or http.ua contains "crawler"
+                  )"#
+            )
+        );
+
+        // Single comparisons are never split.
+        assert_eq!(
+            assert_round_trip(scheme, "ssl", &FormatOptions::default().max_width(Some(1))),
+            "ssl"
+        );
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        // raw strings containing their terminator fall back to quoted ones
+        for &(data, hashes, expected) in &[
+            (&b"a\\b"[..], 0, r#"r"a\b""#),
+            (&b"a\"b"[..], 1, r##"r#"a"b"#"##),
+            (&b"a\"b"[..], 0, r#""a\"b""#),
+            (&b"\xff"[..], 0, r#""\xff""#),
+        ] {
+            let bytes = Bytes::new(data, BytesFormat::RawString(hashes));
+            assert_eq!(bytes_to_string(&bytes), expected);
+        }
+    }
+}
@@ -1,45 +1,239 @@
-use crate::types::{LhsValue, Type};
-use std::fmt;
+use crate::{
+    ast::FunctionCallArgExpr,
+    rhs_types::Bytes,
+    scheme::Scheme,
+    types::{GetType, LhsValue, RhsValue, Type, TypeMismatchError},
+};
+use serde::{Deserialize, Serialize};
+use std::{any::Any, fmt, str, sync::Arc};
 
 /// An iterator over function arguments as [`LhsValue`]s.
 pub type FunctionArgs<'i, 'a> = &'i mut dyn Iterator<Item = LhsValue<'a>>;
 
+/// State of a function implementation prepared for a single call, see
+/// [`FunctionImpl::with_state`].
+pub type FunctionState = Arc<dyn Any + Send + Sync>;
+
 type FunctionPtr = for<'a> fn(FunctionArgs<'_, 'a>) -> LhsValue<'a>;
 
+type FunctionPreparePtr = fn(&[Option<LhsValue<'_>>]) -> FunctionState;
+
+type FunctionStatefulPtr =
+    for<'a> fn(&(dyn Any + Send + Sync), FunctionArgs<'_, 'a>) -> LhsValue<'a>;
+
+#[derive(Clone, Copy)]
+enum FunctionImplKind {
+    Stateless(FunctionPtr),
+    Stateful {
+        prepare: FunctionPreparePtr,
+        execute: FunctionStatefulPtr,
+    },
+}
+
 /// Wrapper around a function pointer providing the runtime implemetation.
 #[derive(Clone)]
-pub struct FunctionImpl(FunctionPtr);
+pub struct FunctionImpl(FunctionImplKind);
 
 impl FunctionImpl {
     /// Creates a new wrapper around a function pointer.
     pub fn new(func: FunctionPtr) -> Self {
-        Self(func)
+        Self(FunctionImplKind::Stateless(func))
+    }
+
+    /// Creates a new wrapper around a function pointer that additionally
+    /// gets a state, like a compiled regular expression, prepared by
+    /// `prepare` once for each call when the filter is compiled.
+    ///
+    /// `prepare` receives values of the arguments in order of the
+    /// parameters, with `None` for arguments that are not literals.
+    pub fn with_state(prepare: FunctionPreparePtr, func: FunctionStatefulPtr) -> Self {
+        Self(FunctionImplKind::Stateful {
+            prepare,
+            execute: func,
+        })
     }
 
     /// Calls the wrapped function pointer.
+    ///
+    /// The state of functions created with
+    /// [`with_state`](FunctionImpl::with_state) is prepared on each call.
     pub fn execute<'a>(&self, args: impl IntoIterator<Item = LhsValue<'a>>) -> LhsValue<'a> {
-        (self.0)(&mut args.into_iter())
+        match self.0 {
+            FunctionImplKind::Stateless(func) => func(&mut args.into_iter()),
+            FunctionImplKind::Stateful { prepare, execute } => {
+                let args = args.into_iter().collect::<Vec<_>>();
+                let state = prepare(
+                    &args
+                        .iter()
+                        .map(|arg| Some(arg.as_ref()))
+                        .collect::<Vec<_>>(),
+                );
+                execute(&*state, &mut args.into_iter())
+            }
+        }
+    }
+
+    // Prepares the state for a call from values of its literal arguments, if
+    // the implementation has any.
+    pub(crate) fn prepare(&self, literals: &[Option<LhsValue<'_>>]) -> Option<FunctionState> {
+        match self.0 {
+            FunctionImplKind::Stateless(_) => None,
+            FunctionImplKind::Stateful { prepare, .. } => Some(prepare(literals)),
+        }
+    }
+
+    // Calls the wrapped function pointer with the state prepared for the
+    // call, or prepares it on the fly if it's missing.
+    pub(crate) fn execute_prepared<'a>(
+        &self,
+        state: Option<&FunctionState>,
+        args: impl IntoIterator<Item = LhsValue<'a>>,
+    ) -> LhsValue<'a> {
+        match (self.0, state) {
+            (FunctionImplKind::Stateful { execute, .. }, Some(state)) => {
+                execute(&**state, &mut args.into_iter())
+            }
+            _ => self.execute(args),
+        }
     }
 }
 
 impl fmt::Debug for FunctionImpl {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt.debug_tuple("FunctionImpl")
-            .field(&(self.0 as *const ()))
-            .finish()
+        let mut tuple = fmt.debug_tuple("FunctionImpl");
+        match self.0 {
+            FunctionImplKind::Stateless(func) => tuple.field(&(func as *const ())),
+            FunctionImplKind::Stateful { prepare, execute } => tuple
+                .field(&(prepare as *const ()))
+                .field(&(execute as *const ())),
+        };
+        tuple.finish()
     }
 }
 
 impl PartialEq for FunctionImpl {
     fn eq(&self, other: &FunctionImpl) -> bool {
-        self.0 as *const () == other.0 as *const ()
+        match (self.0, other.0) {
+            (FunctionImplKind::Stateless(lhs), FunctionImplKind::Stateless(rhs)) => {
+                lhs as *const () == rhs as *const ()
+            }
+            (
+                FunctionImplKind::Stateful { prepare, execute },
+                FunctionImplKind::Stateful {
+                    prepare: other_prepare,
+                    execute: other_execute,
+                },
+            ) => {
+                prepare as *const () == other_prepare as *const ()
+                    && execute as *const () == other_execute as *const ()
+            }
+            _ => false,
+        }
     }
 }
 
 impl Eq for FunctionImpl {}
 
+type FunctionLiteralCheckFn = dyn Fn(&RhsValue) -> Result<(), String> + Send + Sync;
+
+/// Wrapper around a closure validating values of a literal argument when a
+/// function call is parsed.
+///
+/// The closure returns a description of the problem if the value is
+/// invalid, which is reported as a parse error.
+#[derive(Clone)]
+pub struct FunctionLiteralCheck(Arc<FunctionLiteralCheckFn>);
+
+impl FunctionLiteralCheck {
+    /// Creates a new wrapper around a closure.
+    pub fn new(func: impl Fn(&RhsValue) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(func))
+    }
+
+    /// Calls the wrapped closure.
+    pub fn check(&self, value: &RhsValue) -> Result<(), String> {
+        (self.0)(value)
+    }
+
+    fn as_ptr(&self) -> *const () {
+        &*self.0 as *const FunctionLiteralCheckFn as *const ()
+    }
+}
+
+impl fmt::Debug for FunctionLiteralCheck {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_tuple("FunctionLiteralCheck")
+            .field(&self.as_ptr())
+            .finish()
+    }
+}
+
+impl PartialEq for FunctionLiteralCheck {
+    fn eq(&self, other: &FunctionLiteralCheck) -> bool {
+        self.as_ptr() == other.as_ptr()
+    }
+}
+
+impl Eq for FunctionLiteralCheck {}
+
+type FunctionDefaultArgs<'a, 's> = [Option<&'a FunctionCallArgExpr<'s>>];
+
+type FunctionDefaultFn =
+    dyn for<'a, 's> Fn(&'s Scheme, &FunctionDefaultArgs<'a, 's>) -> LhsValue<'static> + Send + Sync;
+
+/// Wrapper around a closure computing the default value of an optional
+/// argument when a function call is parsed.
+///
+/// The closure receives the scheme and the arguments of the call, so that
+/// defaults can depend on either of them. Arguments are indexed by position
+/// of their parameters, with `None` for optional arguments that were
+/// omitted, including ones skipped by passing later arguments by name.
+#[derive(Clone)]
+pub struct FunctionDefaultImpl(Arc<FunctionDefaultFn>);
+
+impl FunctionDefaultImpl {
+    /// Creates a new wrapper around a closure.
+    pub fn new(
+        func: impl for<'s> Fn(&'s Scheme, &[Option<&FunctionCallArgExpr<'s>>]) -> LhsValue<'static>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self(Arc::new(func))
+    }
+
+    /// Calls the wrapped closure.
+    pub fn execute<'s>(
+        &self,
+        scheme: &'s Scheme,
+        args: &[Option<&FunctionCallArgExpr<'s>>],
+    ) -> LhsValue<'static> {
+        (self.0)(scheme, args)
+    }
+
+    fn as_ptr(&self) -> *const () {
+        &*self.0 as *const FunctionDefaultFn as *const ()
+    }
+}
+
+impl fmt::Debug for FunctionDefaultImpl {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_tuple("FunctionDefaultImpl")
+            .field(&self.as_ptr())
+            .finish()
+    }
+}
+
+impl PartialEq for FunctionDefaultImpl {
+    fn eq(&self, other: &FunctionDefaultImpl) -> bool {
+        self.as_ptr() == other.as_ptr()
+    }
+}
+
+impl Eq for FunctionDefaultImpl {}
+
 /// Defines what kind of argument a function expects.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum FunctionArgKind {
     /// Allow only literal as argument.
     Literal,
@@ -48,21 +242,84 @@ pub enum FunctionArgKind {
 }
 
 /// Defines a mandatory function argument.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct FunctionParam {
     /// How the argument can be specified when calling a function.
     pub arg_kind: FunctionArgKind,
     /// The type of its associated value.
     pub val_type: Type,
+    /// Validates values of literal arguments when a call is parsed.
+    ///
+    /// It's not serialized, so literals are not validated with deserialized
+    /// schemes.
+    #[serde(skip)]
+    pub literal_check: Option<FunctionLiteralCheck>,
 }
 
 /// Defines an optional function argument.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct FunctionOptParam {
+    /// Name by which the argument can be passed as `name: value`, if any.
+    pub name: Option<String>,
     /// How the argument can be specified when calling a function.
     pub arg_kind: FunctionArgKind,
     /// The default value if the argument is missing.
+    ///
+    /// Also defines the type of the argument.
     pub default_value: LhsValue<'static>,
+    /// Computes the default value when a call is parsed instead of using
+    /// `default_value`.
+    ///
+    /// The computed value must have the same type as `default_value`.
+    pub default_fn: Option<FunctionDefaultImpl>,
+}
+
+impl FunctionOptParam {
+    /// Creates an optional parameter that can only be passed by position and
+    /// has a static default value.
+    pub fn new(arg_kind: FunctionArgKind, default_value: impl Into<LhsValue<'static>>) -> Self {
+        FunctionOptParam {
+            name: None,
+            arg_kind,
+            default_value: default_value.into(),
+            default_fn: None,
+        }
+    }
+
+    /// Sets the name by which the argument can be passed.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets how the default value is computed when a call is parsed.
+    pub fn with_default_fn(mut self, default_fn: FunctionDefaultImpl) -> Self {
+        self.default_fn = Some(default_fn);
+        self
+    }
+
+    // Computes the default value of an omitted argument, failing if it has
+    // another type than `default_value`.
+    pub(crate) fn resolve_default<'s>(
+        &self,
+        scheme: &'s Scheme,
+        args: &[Option<&FunctionCallArgExpr<'s>>],
+    ) -> Result<LhsValue<'static>, TypeMismatchError> {
+        match &self.default_fn {
+            Some(default_fn) => {
+                let value = default_fn.execute(scheme, args);
+                let expected = self.default_value.get_type();
+                if value.get_type() != expected {
+                    return Err(TypeMismatchError {
+                        expected,
+                        actual: value.get_type(),
+                    });
+                }
+                Ok(value)
+            }
+            None => Ok(self.default_value.clone()),
+        }
+    }
 }
 
 /// Defines a function.
@@ -77,3 +334,124 @@ pub struct Function {
     /// Actual implementation that will be called at runtime.
     pub implementation: FunctionImpl,
 }
+
+impl Function {
+    // Checks whether calls with the same arguments are accepted by both
+    // functions, ignoring defaults of optional parameters.
+    pub(crate) fn has_same_params(&self, other: &Function) -> bool {
+        self.params == other.params
+            && self.opt_params.len() == other.opt_params.len()
+            && self
+                .opt_params
+                .iter()
+                .zip(&other.opt_params)
+                .all(|(param, other)| {
+                    param.arg_kind == other.arg_kind
+                        && param.default_value.get_type() == other.default_value.get_type()
+                })
+    }
+
+    /// Formats parameters and the return type as
+    /// `(field Bytes, literal Int count = 10) -> Bytes`.
+    pub(crate) fn signature(&self) -> String {
+        let kind = |arg_kind: &FunctionArgKind| match arg_kind {
+            FunctionArgKind::Literal => "literal",
+            FunctionArgKind::Field => "field",
+        };
+
+        let mut params = Vec::with_capacity(self.params.len() + self.opt_params.len());
+
+        for param in &self.params {
+            params.push(format!("{} {:?}", kind(&param.arg_kind), param.val_type));
+        }
+
+        for param in &self.opt_params {
+            let default_value = match &param.default_value {
+                LhsValue::Bytes(bytes) => match str::from_utf8(bytes) {
+                    Ok(s) => format!("{:?}", s),
+                    Err(_) => format!("{:?}", Bytes::from(bytes.to_vec())),
+                },
+                value => format!("{:?}", value),
+            };
+            let name = match &param.name {
+                Some(name) => format!(" {}", name),
+                None => String::new(),
+            };
+            params.push(format!(
+                "{} {:?}{} = {}",
+                kind(&param.arg_kind),
+                param.default_value.get_type(),
+                name,
+                default_value
+            ));
+        }
+
+        format!("({}) -> {:?}", params.join(", "), self.return_type)
+    }
+}
+
+/// The type of a parameter or the return value of a [`GenericFunction`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GenericType {
+    /// A fixed type.
+    Concrete(Type),
+    /// The type variable, which is substituted with each of
+    /// [`GenericFunction::types`].
+    Var,
+}
+
+/// Defines a mandatory argument of a [`GenericFunction`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GenericFunctionParam {
+    /// How the argument can be specified when calling a function.
+    pub arg_kind: FunctionArgKind,
+    /// The type of its associated value.
+    pub val_type: GenericType,
+}
+
+/// Defines a function that accepts arguments of several types, with the
+/// return type derived from them, e.g. `(field T, literal T) -> T`.
+///
+/// It's registered with
+/// [`Scheme::add_generic_function`](::Scheme::add_generic_function) as an
+/// overload for each type the variable can take, so calls are checked and
+/// get their return type from the types of arguments actually passed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GenericFunction {
+    /// List of mandatory arguments.
+    pub params: Vec<GenericFunctionParam>,
+    /// List of optional arguments that can be specified after mandatory ones.
+    pub opt_params: Vec<FunctionOptParam>,
+    /// Function return type.
+    pub return_type: GenericType,
+    /// Types the type variable can take.
+    pub types: Vec<Type>,
+    /// Actual implementation that will be called at runtime.
+    pub implementation: FunctionImpl,
+}
+
+impl GenericFunction {
+    /// Returns the function with the type variable substituted with a given
+    /// type.
+    pub fn instantiate(&self, ty: Type) -> Function {
+        let resolve = |generic: GenericType| match generic {
+            GenericType::Concrete(ty) => ty,
+            GenericType::Var => ty,
+        };
+
+        Function {
+            params: self
+                .params
+                .iter()
+                .map(|param| FunctionParam {
+                    arg_kind: param.arg_kind.clone(),
+                    val_type: resolve(param.val_type),
+                    literal_check: None,
+                })
+                .collect(),
+            opt_params: self.opt_params.clone(),
+            return_type: resolve(self.return_type),
+            implementation: self.implementation.clone(),
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use crate::rhs_types::{IntFormat, IntFormatting, IntLiteral, IntRadix};
+use serde::ser::{
+    Error, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct,
+    SerializeStructVariant, SerializeTuple, SerializeTupleStruct, SerializeTupleVariant,
+    Serializer,
+};
+use std::fmt::{self, Display, Formatter};
+
+// Integer literals are serialized as nested newtype structs around their
+// value. Most serializers treat newtype structs as their contents, so
+// literals are serialized as plain numbers, while [`IntFormattingSerializer`]
+// reads the original formatting back from the names of those structs:
+//
+// - the outermost one is named `INT_LITERAL_TOKEN`;
+// - the next one is named after the radix and case of the literal;
+// - then there's one named after each decimal digit of its width.
+pub(crate) const INT_LITERAL_TOKEN: &str = "$wirefilter::private::IntLiteral";
+
+const RADIX_TOKENS: [(&str, IntRadix, bool); 8] = [
+    ("$wirefilter::private::Decimal", IntRadix::Decimal, false),
+    (
+        "$wirefilter::private::DecimalUpper",
+        IntRadix::Decimal,
+        true,
+    ),
+    (
+        "$wirefilter::private::Hexadecimal",
+        IntRadix::Hexadecimal,
+        false,
+    ),
+    (
+        "$wirefilter::private::HexadecimalUpper",
+        IntRadix::Hexadecimal,
+        true,
+    ),
+    ("$wirefilter::private::Octal", IntRadix::Octal, false),
+    ("$wirefilter::private::OctalUpper", IntRadix::Octal, true),
+    ("$wirefilter::private::Binary", IntRadix::Binary, false),
+    ("$wirefilter::private::BinaryUpper", IntRadix::Binary, true),
+];
+
+const DIGIT_TOKENS: [&str; 10] = [
+    "$wirefilter::private::0",
+    "$wirefilter::private::1",
+    "$wirefilter::private::2",
+    "$wirefilter::private::3",
+    "$wirefilter::private::4",
+    "$wirefilter::private::5",
+    "$wirefilter::private::6",
+    "$wirefilter::private::7",
+    "$wirefilter::private::8",
+    "$wirefilter::private::9",
+];
+
+/// Contents of the newtype struct an [`IntLiteral`] is serialized as.
+pub(crate) struct IntLiteralValue(pub IntLiteral);
+
+impl Serialize for IntLiteralValue {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        let IntFormat {
+            radix,
+            width,
+            uppercase,
+        } = self.0.format();
+        let &(token, ..) = RADIX_TOKENS
+            .iter()
+            .find(|&&(_, r, u)| r == radix && u == uppercase)
+            .expect("all radixes are listed");
+        let digits = width.to_string();
+        ser.serialize_newtype_struct(
+            token,
+            &Width {
+                digits: digits.as_bytes(),
+                value: self.0.value(),
+            },
+        )
+    }
+}
+
+// Remaining digits of the width of a literal, around its value.
+struct Width<'a> {
+    digits: &'a [u8],
+    value: i32,
+}
+
+impl<'a> Serialize for Width<'a> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        match self.digits.split_first() {
+            Some((digit, digits)) => ser.serialize_newtype_struct(
+                DIGIT_TOKENS[usize::from(digit - b'0')],
+                &Width {
+                    digits,
+                    value: self.value,
+                },
+            ),
+            None => ser.serialize_i32(self.value),
+        }
+    }
+}
+
+// Reads an integer literal back from the contents of its newtype struct.
+#[derive(Default)]
+struct LiteralReader {
+    radix: Option<(IntRadix, bool)>,
+    width: usize,
+    value: Option<i32>,
+}
+
+impl LiteralReader {
+    fn read<T: ?Sized + Serialize>(value: &T) -> Option<IntLiteral> {
+        let mut reader = LiteralReader::default();
+        value.serialize(&mut reader).ok()?;
+        let (radix, uppercase) = reader.radix?;
+        let format = IntFormat {
+            radix,
+            width: reader.width,
+            uppercase,
+        };
+        Some(IntLiteral::new(reader.value?, format))
+    }
+}
+
+// Anything but the structs an integer literal is serialized as.
+#[derive(Debug)]
+struct UnexpectedValue;
+
+impl Display for UnexpectedValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("unexpected value in an integer literal")
+    }
+}
+
+impl std::error::Error for UnexpectedValue {}
+
+impl Error for UnexpectedValue {
+    fn custom<T: Display>(_msg: T) -> Self {
+        UnexpectedValue
+    }
+}
+
+macro_rules! reject {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+            Err(UnexpectedValue)
+        })*
+    };
+}
+
+impl<'a> Serializer for &'a mut LiteralReader {
+    type Ok = ();
+    type Error = UnexpectedValue;
+    type SerializeSeq = Impossible<(), UnexpectedValue>;
+    type SerializeTuple = Impossible<(), UnexpectedValue>;
+    type SerializeTupleStruct = Impossible<(), UnexpectedValue>;
+    type SerializeTupleVariant = Impossible<(), UnexpectedValue>;
+    type SerializeMap = Impossible<(), UnexpectedValue>;
+    type SerializeStruct = Impossible<(), UnexpectedValue>;
+    type SerializeStructVariant = Impossible<(), UnexpectedValue>;
+
+    reject! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i64(v: i64);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u64(v: u64);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(name: &'static str);
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str);
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), UnexpectedValue> {
+        self.value = Some(v);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), UnexpectedValue> {
+        if self.radix.is_none() {
+            let &(_, radix, uppercase) = RADIX_TOKENS
+                .iter()
+                .find(|(token, ..)| *token == name)
+                .ok_or(UnexpectedValue)?;
+            self.radix = Some((radix, uppercase));
+        } else {
+            let digit = DIGIT_TOKENS
+                .iter()
+                .position(|token| *token == name)
+                .ok_or(UnexpectedValue)?;
+            self.width = self
+                .width
+                .checked_mul(10)
+                .and_then(|width| width.checked_add(digit))
+                .ok_or(UnexpectedValue)?;
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<(), UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, UnexpectedValue> {
+        Err(UnexpectedValue)
+    }
+}
+
+// An integer literal formatted in the given radix, without leading zeros.
+struct Normalized(IntLiteral, IntRadix);
+
+impl Display for Normalized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.fmt_with(
+            f,
+            IntFormat {
+                radix: self.1,
+                ..IntFormat::default()
+            },
+        )
+    }
+}
+
+/// A serializer that represents integer literals found anywhere in the
+/// serialized value according to the given [`IntFormatting`], and forwards
+/// everything else to the wrapped serializer.
+pub(crate) struct IntFormattingSerializer<S> {
+    inner: S,
+    formatting: IntFormatting,
+}
+
+impl<S> IntFormattingSerializer<S> {
+    pub(crate) fn new(inner: S, formatting: IntFormatting) -> Self {
+        IntFormattingSerializer { inner, formatting }
+    }
+}
+
+// A nested value to be serialized with the same formatting.
+struct Formatted<'a, T: ?Sized> {
+    value: &'a T,
+    formatting: IntFormatting,
+}
+
+impl<'a, T: ?Sized + Serialize> Serialize for Formatted<'a, T> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        self.value
+            .serialize(IntFormattingSerializer::new(ser, self.formatting))
+    }
+}
+
+// Wraps a compound serializer, so that its elements are formatted too.
+pub(crate) struct Compound<S> {
+    inner: S,
+    formatting: IntFormatting,
+}
+
+impl<S> Compound<S> {
+    fn new(inner: S, formatting: IntFormatting) -> Self {
+        Compound { inner, formatting }
+    }
+
+    fn wrap<'a, T: ?Sized>(&self, value: &'a T) -> Formatted<'a, T> {
+        Formatted {
+            value,
+            formatting: self.formatting,
+        }
+    }
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(fn $method(self, $($arg: $ty),*) -> Result<Self::Ok, Self::Error> {
+            self.inner.$method($($arg),*)
+        })*
+    };
+}
+
+impl<S: Serializer> Serializer for IntFormattingSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S::SerializeSeq>;
+    type SerializeTuple = Compound<S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S::SerializeTupleVariant>;
+    type SerializeMap = Compound<S::SerializeMap>;
+    type SerializeStruct = Compound<S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S::SerializeStructVariant>;
+
+    forward! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i64(v: i64);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u64(v: u64);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(name: &'static str);
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str);
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let formatting = self.formatting;
+        self.inner.serialize_some(&Formatted { value, formatting })
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        if name == INT_LITERAL_TOKEN {
+            let literal = LiteralReader::read(value)
+                .ok_or_else(|| S::Error::custom("malformed integer literal"))?;
+            return match self.formatting {
+                IntFormatting::Numeric => self.inner.serialize_i32(literal.value()),
+                IntFormatting::Preserve => self.inner.collect_str(&literal),
+                IntFormatting::Normalize(radix) => {
+                    self.inner.collect_str(&Normalized(literal, radix))
+                }
+            };
+        }
+        let formatting = self.formatting;
+        self.inner
+            .serialize_newtype_struct(name, &Formatted { value, formatting })
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let formatting = self.formatting;
+        self.inner
+            .serialize_newtype_variant(name, index, variant, &Formatted { value, formatting })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_seq(len)?,
+            self.formatting,
+        ))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_tuple(len)?,
+            self.formatting,
+        ))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_tuple_struct(name, len)?,
+            self.formatting,
+        ))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(Compound::new(
+            self.inner
+                .serialize_tuple_variant(name, index, variant, len)?,
+            self.formatting,
+        ))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_map(len)?,
+            self.formatting,
+        ))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_struct(name, len)?,
+            self.formatting,
+        ))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(Compound::new(
+            self.inner
+                .serialize_struct_variant(name, index, variant, len)?,
+            self.formatting,
+        ))
+    }
+
+    fn collect_str<T: ?Sized + Display>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.collect_str(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+macro_rules! impl_compound {
+    ($($trait:ident { $($method:ident($($arg:ident: $ty:ty),*);)* })*) => {
+        $(impl<S: $trait> $trait for Compound<S> {
+            type Ok = S::Ok;
+            type Error = S::Error;
+
+            $(fn $method<T: ?Sized + Serialize>(
+                &mut self,
+                $($arg: $ty,)*
+                value: &T,
+            ) -> Result<(), S::Error> {
+                let value = self.wrap(value);
+                self.inner.$method($($arg,)* &value)
+            })*
+
+            fn end(self) -> Result<S::Ok, S::Error> {
+                self.inner.end()
+            }
+        })*
+    };
+}
+
+impl_compound! {
+    SerializeSeq { serialize_element(); }
+    SerializeTuple { serialize_element(); }
+    SerializeTupleStruct { serialize_field(); }
+    SerializeTupleVariant { serialize_field(); }
+    SerializeStruct { serialize_field(key: &'static str); }
+    SerializeStructVariant { serialize_field(key: &'static str); }
+}
+
+impl<S: SerializeMap> SerializeMap for Compound<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), S::Error> {
+        let key = self.wrap(key);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
@@ -0,0 +1,190 @@
+use crate::rhs_types::{aggregate_ip_ranges, IpRange};
+use cidr::{Cidr, IpCidr};
+use std::net::IpAddr;
+
+// A node of a path-compressed binary trie.
+//
+// Since aggregated networks don't overlap, each of them is a leaf, and
+// branches only need to store the bit that distinguishes their children,
+// so a trie of `n` networks has at most `2n - 1` nodes.
+enum Node {
+    Branch { bit: u8, children: [u32; 2] },
+    Leaf { prefix: u128, len: u8 },
+}
+
+// Mask of the given number of highest bits.
+fn prefix_mask(len: u8) -> u128 {
+    if len == 0 {
+        0
+    } else {
+        u128::max_value() << (128 - u32::from(len))
+    }
+}
+
+// A set of disjoint networks of the same family, with addresses aligned to
+// the highest bits of `u128`.
+struct Trie {
+    nodes: Vec<Node>,
+}
+
+impl Trie {
+    // Builds a trie from networks sorted by address.
+    fn new(networks: &[(u128, u8)]) -> Self {
+        let mut trie = Trie {
+            nodes: Vec::with_capacity(networks.len() * 2),
+        };
+        if !networks.is_empty() {
+            trie.insert(networks);
+        }
+        trie
+    }
+
+    fn insert(&mut self, networks: &[(u128, u8)]) -> u32 {
+        let index = self.nodes.len() as u32;
+
+        if let [(prefix, len)] = *networks {
+            self.nodes.push(Node::Leaf { prefix, len });
+            return index;
+        }
+
+        // All networks share the bits before the first one in which the
+        // lowest and the highest addresses differ, and are split by it.
+        let (first, _) = networks[0];
+        let (last, last_len) = networks[networks.len() - 1];
+        let last = last | !prefix_mask(last_len);
+        let bit = (first ^ last).leading_zeros() as u8;
+        let split = networks
+            .iter()
+            .position(|&(prefix, _)| (prefix << bit) >> 127 == 1)
+            .unwrap();
+
+        self.nodes.push(Node::Branch {
+            bit,
+            children: [0, 0],
+        });
+        let left = self.insert(&networks[..split]);
+        let right = self.insert(&networks[split..]);
+        self.nodes[index as usize] = Node::Branch {
+            bit,
+            children: [left, right],
+        };
+
+        index
+    }
+
+    fn contains(&self, addr: u128) -> bool {
+        let mut node = match self.nodes.first() {
+            Some(node) => node,
+            None => return false,
+        };
+
+        loop {
+            match *node {
+                Node::Branch { bit, children } => {
+                    node = &self.nodes[children[((addr << bit) >> 127) as usize] as usize];
+                }
+                Node::Leaf { prefix, len } => return (addr ^ prefix) & prefix_mask(len) == 0,
+            }
+        }
+    }
+}
+
+/// A set of IP ranges stored as a prefix trie of aggregated networks.
+///
+/// Lookups take time proportional to the number of distinct prefix bits
+/// rather than to the size of the set, which matters for lists with tens of
+/// thousands of prefixes.
+pub(crate) struct IpPrefixTrie {
+    v4: Trie,
+    v6: Trie,
+}
+
+impl IpPrefixTrie {
+    pub(crate) fn new<I: IntoIterator<Item = IpRange>>(ranges: I) -> Self {
+        let mut v4 = Vec::new();
+        let mut v6 = Vec::new();
+
+        for cidr in aggregate_ip_ranges(ranges) {
+            match cidr {
+                IpCidr::V4(cidr) => v4.push((
+                    u128::from(u32::from(cidr.first_address())) << 96,
+                    cidr.network_length(),
+                )),
+                IpCidr::V6(cidr) => {
+                    v6.push((u128::from(cidr.first_address()), cidr.network_length()))
+                }
+            }
+        }
+
+        IpPrefixTrie {
+            v4: Trie::new(&v4),
+            v6: Trie::new(&v6),
+        }
+    }
+
+    pub(crate) fn contains(&self, addr: &IpAddr) -> bool {
+        match *addr {
+            IpAddr::V4(addr) => self.v4.contains(u128::from(u32::from(addr)) << 96),
+            IpAddr::V6(addr) => self.v6.contains(u128::from(addr)),
+        }
+    }
+}
+
+#[test]
+fn test_ip_prefix_trie() {
+    use crate::{
+        lex::{LexState, LexWith},
+        types::{RhsValues, Type},
+    };
+
+    let trie = |input| match RhsValues::lex_with(input, (Type::Ip, &LexState::default()))
+        .unwrap()
+        .0
+    {
+        RhsValues::Ip(ranges) => IpPrefixTrie::new(ranges),
+        _ => unreachable!(),
+    };
+    let addr = |addr: &str| addr.parse::<IpAddr>().unwrap();
+
+    let set = trie(
+        "{ 10.0.0.0/8 10.1.0.0/16 192.168.0.1..192.168.0.6 192.168.1.0/24 192.168.2.0/24 \
+         203.0.113.7 2001:db8::/32 ::1 }",
+    );
+
+    for included in &[
+        "10.0.0.0",
+        "10.255.255.255",
+        "192.168.0.1",
+        "192.168.0.6",
+        "192.168.1.0",
+        "192.168.2.255",
+        "203.0.113.7",
+        "2001:db8::1",
+        "::1",
+    ] {
+        assert!(set.contains(&addr(included)), "{}", included);
+    }
+
+    for excluded in &[
+        "9.255.255.255",
+        "11.0.0.0",
+        "192.168.0.0",
+        "192.168.0.7",
+        "192.168.3.0",
+        "203.0.113.6",
+        "203.0.113.8",
+        "2001:db9::",
+        "::2",
+        "::",
+    ] {
+        assert!(!set.contains(&addr(excluded)), "{}", excluded);
+    }
+
+    let empty = trie("{ }");
+    assert!(!empty.contains(&addr("10.0.0.1")));
+    assert!(!empty.contains(&addr("::1")));
+
+    let all = trie("{ 0.0.0.0/0 }");
+    assert!(all.contains(&addr("255.255.255.255")));
+    assert!(!all.contains(&addr("::")));
+}
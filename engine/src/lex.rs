@@ -1,11 +1,11 @@
 use crate::{
-    rhs_types::RegexError,
-    scheme::{UnknownFieldError, UnknownFunctionError},
+    rhs_types::{IntLexOptions, MacParseError, RegexError},
+    scheme::{UnknownConstantError, UnknownFieldError, UnknownFunctionError, UnknownListError},
     types::{Type, TypeMismatchError},
 };
 use cidr::NetworkParseError;
 use failure::Fail;
-use std::num::ParseIntError;
+use std::{cell::RefCell, num::ParseIntError};
 
 #[derive(Debug, PartialEq, Fail)]
 pub enum LexErrorKind {
@@ -28,12 +28,27 @@ pub enum LexErrorKind {
     #[fail(display = "{}", _0)]
     ParseRegex(#[cause] RegexError),
 
-    #[fail(display = "expected \", xHH or OOO after \\")]
+    #[fail(display = "{}", _0)]
+    ParseMac(#[cause] MacParseError),
+
+    #[fail(display = "expected \", xHH, OOO or u{{HHHHHH}} after \\")]
     InvalidCharacterEscape,
 
+    #[fail(display = "invalid unicode code point")]
+    InvalidUnicodeEscape,
+
+    #[fail(display = "string is not valid UTF-8")]
+    InvalidUtf8,
+
     #[fail(display = "could not find an ending quote")]
     MissingEndingQuote,
 
+    #[fail(display = "too many # symbols in a raw string")]
+    TooManyHashes,
+
+    #[fail(display = "{} is already defined", _0)]
+    DuplicateDefinition(String),
+
     #[fail(display = "expected {} {}s, but found {}", expected, name, actual)]
     CountMismatch {
         name: &'static str,
@@ -47,15 +62,66 @@ pub enum LexErrorKind {
     #[fail(display = "{}", _0)]
     UnknownFunction(#[cause] UnknownFunctionError),
 
+    #[fail(display = "{}", _0)]
+    UnknownList(#[cause] UnknownListError),
+
+    #[fail(display = "{}", _0)]
+    UnknownConstant(#[cause] UnknownConstantError),
+
     #[fail(display = "cannot use this operation type {:?}", lhs_type)]
     UnsupportedOp { lhs_type: Type },
 
+    #[fail(display = "only `in` and operators on bytes can be negated")]
+    UnsupportedNegation,
+
+    #[fail(display = "placeholders are only supported in comparisons")]
+    UnsupportedPlaceholder,
+
+    #[fail(display = "sets of type {:?} can't have exclusions", lhs_type)]
+    UnsupportedExclusion { lhs_type: Type },
+
+    #[fail(display = "{}", _0)]
+    TypeMismatch(#[cause] TypeMismatchError),
+
     #[fail(display = "incompatible range bounds")]
     IncompatibleRangeBounds,
 
+    #[fail(display = "duration is too large")]
+    DurationOverflow,
+
+    #[fail(display = "comparison of a Bool with an integer was converted to a boolean check")]
+    BoolIntComparison,
+
+    #[fail(display = "value overlaps another one in the same set")]
+    OverlappingSetValues,
+
+    #[fail(
+        display = "integer literal is out of range and was saturated to {}",
+        value
+    )]
+    IntOverflow { value: i32 },
+
+    #[fail(display = "nesting depth exceeds the limit of {}", limit)]
+    NestingTooDeep { limit: usize },
+
+    #[fail(
+        display = "expansions of definitions exceed the limit of {} expressions",
+        limit
+    )]
+    ExpansionTooLarge { limit: usize },
+
     #[fail(display = "unrecognised input")]
     EOF,
 
+    #[fail(display = "call of function {} matches several of its signatures", _0)]
+    AmbiguousFunctionCall(String),
+
+    #[fail(display = "unknown argument name {}", _0)]
+    UnknownArgumentName(String),
+
+    #[fail(display = "argument #{} is passed more than once", _0)]
+    DuplicateArgument(usize),
+
     #[fail(display = "invalid number of arguments")]
     InvalidArgumentsCount {
         expected_min: usize,
@@ -68,6 +134,9 @@ pub enum LexErrorKind {
         #[cause]
         mismatch: TypeMismatchError,
     },
+
+    #[fail(display = "invalid value of argument #{}: {}", index, message)]
+    InvalidArgumentValue { index: usize, message: String },
 }
 
 pub type LexError<'i> = (LexErrorKind, &'i str);
@@ -88,6 +157,38 @@ impl<'i, T: Lex<'i>, E> LexWith<'i, E> for T {
     }
 }
 
+/// State of lexing values of a single filter, like options of the scheme
+/// it's parsed with and warnings reported so far.
+#[derive(Default)]
+pub(crate) struct LexState<'i> {
+    int_options: IntLexOptions,
+    warnings: RefCell<Vec<(LexErrorKind, &'i str)>>,
+}
+
+impl<'i> LexState<'i> {
+    pub fn new(int_options: IntLexOptions) -> Self {
+        LexState {
+            int_options,
+            warnings: RefCell::default(),
+        }
+    }
+
+    /// Options of lexing integer literals.
+    pub fn int_options(&self) -> IntLexOptions {
+        self.int_options
+    }
+
+    /// Reports a warning about the given span of the input.
+    pub fn warn(&self, kind: LexErrorKind, span: &'i str) {
+        self.warnings.borrow_mut().push((kind, span));
+    }
+
+    /// Returns all warnings reported so far.
+    pub fn into_warnings(self) -> Vec<(LexErrorKind, &'i str)> {
+        self.warnings.into_inner()
+    }
+}
+
 pub fn expect<'i>(input: &'i str, s: &'static str) -> Result<&'i str, LexError<'i>> {
     if input.starts_with(s) {
         Ok(&input[s.len()..])
@@ -112,6 +213,9 @@ pub fn skip_space(input: &str) -> &str {
 ///
 /// It works by recursively processing variants one by one, while passing
 /// around intermediate state (partial declaration and lexer bodies).
+///
+/// Each variant can be preceded by attributes (including doc comments),
+/// which are passed through to the declaration.
 macro_rules! lex_enum {
     // Branch for handling `SomeType => VariantName`.
     //
@@ -120,12 +224,12 @@ macro_rules! lex_enum {
     // On the parser side, tries to parse `SomeType` and wraps into the variant
     // on success.
     (@decl $preamble:tt $name:ident $input:ident { $($decl:tt)* } { $($expr:tt)* } {
-        $ty:ty => $item:ident,
+        $(# $vattrs:tt)* $ty:ty => $item:ident,
         $($rest:tt)*
     }) => {
         lex_enum!(@decl $preamble $name $input {
             $($decl)*
-            $item($ty),
+            $(# $vattrs)* $item($ty),
         } {
             $($expr)*
             if let Ok((res, $input)) = $crate::lex::Lex::lex($input) {
@@ -142,12 +246,12 @@ macro_rules! lex_enum {
     // On the parser side, tries to parse either of the given string values,
     // and returns the variant if any of them succeeded.
     (@decl $preamble:tt $name:ident $input:ident { $($decl:tt)* } { $($expr:tt)* } {
-        $($s:tt)|+ => $item:ident $(= $value:expr)*,
+        $(# $vattrs:tt)* $($s:literal)|+ => $item:ident $(= $value:expr)*,
         $($rest:tt)*
     }) => {
         lex_enum!(@decl $preamble $name $input {
             $($decl)*
-            $item $(= $value)*,
+            $(# $vattrs)* $item $(= $value)*,
         } {
             $($expr)*
             $(if let Ok($input) = $crate::lex::expect($input, $s) {
@@ -227,6 +331,26 @@ pub fn take(input: &str, expected: usize) -> LexResult<'_, &str> {
     Ok((span(input, rest), rest))
 }
 
+// Lexes a raw string like `r"a\b"` or `r#"a"b"#`, where escapes are not
+// processed, and returns its contents along with the number of `#` symbols.
+pub fn lex_raw_string(input: &str) -> LexResult<'_, (&str, u8)> {
+    let input = expect(input, "r")?;
+    let rest = input.trim_start_matches('#');
+    let hashes = span(input, rest);
+    if hashes.len() > usize::from(u8::max_value()) {
+        return Err((LexErrorKind::TooManyHashes, hashes));
+    }
+    let rest = expect(rest, "\"")?;
+    let terminator = format!("\"{}", hashes);
+    match rest.find(&terminator) {
+        Some(end) => Ok((
+            (&rest[..end], hashes.len() as u8),
+            &rest[end + terminator.len()..],
+        )),
+        None => Err((LexErrorKind::MissingEndingQuote, rest)),
+    }
+}
+
 pub fn complete<T>(res: LexResult<'_, T>) -> Result<T, LexError<'_>> {
     let (res, input) = res?;
     if input.is_empty() {
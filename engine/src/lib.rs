@@ -28,7 +28,7 @@
 //!     println!("Parsed filter representation: {:?}", ast);
 //!
 //!     // Compile the AST into an executable filter.
-//!     let filter = ast.compile();
+//!     let filter = ast.compile()?;
 //!
 //!     // Set runtime field values to test the filter against.
 //!     let mut ctx = ExecutionContext::new(&scheme);
@@ -62,22 +62,84 @@ mod lex;
 mod scheme;
 
 mod ast;
+mod ast_json;
+mod bindings;
+mod complete;
+mod cost;
+mod equivalence;
 mod execution_context;
 mod filter;
+mod filter_set;
+mod fingerprint;
+mod format;
 mod functions;
 mod heap_searcher;
+mod int_formatting;
+mod ip_trie;
+mod lint;
+mod list_matcher;
+#[cfg(feature = "profiling")]
+mod profile;
 mod range_set;
+mod regex_budget;
+mod regex_sets;
 mod rhs_types;
+mod shared_exprs;
+mod simplify;
+mod spans;
+mod sql;
 mod strict_partial_ord;
+mod trace;
 mod types;
+mod value_set;
+
+#[cfg(feature = "stdlib")]
+pub mod stdlib;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub use self::{
-    ast::FilterAst,
-    execution_context::ExecutionContext,
-    filter::{Filter, SchemeMismatchError},
+    ast::{
+        visitor, CombinedExpr, CombiningOp, FieldExpr, FieldOp, FilterAst, FunctionCallArgExpr,
+        FunctionCallExpr, IntOp, LhsFieldExpr, NodeId, OrderingOp, SimpleExpr, SliceExpr, UnaryOp,
+    },
+    ast_json::AstJsonError,
+    bindings::{BindError, Bindings, Placeholder},
+    complete::{Completion, CompletionKind, Completions},
+    cost::{CostModel, DefaultCostModel},
+    equivalence::EquivalenceError,
+    execution_context::{ExecutionContext, ExecutionContextPool, JsonValueError},
+    filter::{CompileError, ExecutionError, Filter, SchemeMismatchError, ThreeValuedFilter},
+    filter_set::{AddFilterError, Divergence, DivergenceReport, FilterSet},
+    fingerprint::Fingerprint,
+    format::{FormatOptions, OperatorStyle},
     functions::{
-        Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionOptParam, FunctionParam,
+        Function, FunctionArgKind, FunctionArgs, FunctionDefaultImpl, FunctionImpl,
+        FunctionLiteralCheck, FunctionOptParam, FunctionParam, FunctionState, GenericFunction,
+        GenericFunctionParam, GenericType,
+    },
+    lint::{Lint, RegexReplacement},
+    list_matcher::{BloomMatcher, HashSetMatcher, ListMatcher},
+    regex_budget::{RegexBudget, RegexBudgetAction},
+    rhs_types::{
+        aggregate_ip_ranges, Bytes, BytesFormat, ExplicitIpRange, IntFormat, IntFormatting,
+        IntLiteral, IntOverflow, IntRadix, IpRange, MacAddr, MacPrefix, Regex, RegexFormat,
+        StrLiteral, UninhabitedBool,
+    },
+    scheme::{
+        ConstantRedefinitionError, Field, FieldRedefinitionError, List, ListRedefinitionError,
+        Metadata, ParseError, ParseWarning, Scheme, SchemeIncompatibility, UnknownConstantError,
+        UnknownFieldError, UnknownFunctionError, UnknownListError,
+    },
+    spans::ExprSpans,
+    sql::{SqlDialect, SqlError},
+    trace::Trace,
+    types::{
+        GetType, LhsValue, RhsValue, RhsValues, StrConversionError, Type, TypeMismatchError,
+        TypedValue,
     },
-    scheme::{FieldRedefinitionError, ParseError, Scheme, UnknownFieldError},
-    types::{GetType, LhsValue, Type, TypeMismatchError},
 };
+
+#[cfg(feature = "profiling")]
+pub use self::profile::NodeProfile;
@@ -0,0 +1,681 @@
+use crate::{
+    ast::{
+        visitor::{walk_expr, Visitor, VisitorMut},
+        CombinedExpr, CombiningOp, FieldExpr, FieldOp, NodeId, OrderingOp, SimpleExpr,
+    },
+    equivalence::{constant_outcome, implies},
+    fingerprint::fingerprint,
+    rhs_types::Bytes,
+    scheme::Scheme,
+    spans::ExprSpans,
+    types::{GetType, RhsValue, Type},
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    slice,
+};
+
+/// A cheaper operation that is equivalent to a regular expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RegexReplacement {
+    /// `== "literal"` for `^literal$`.
+    Equal(Bytes),
+    /// `contains "literal"` for an unanchored `literal`.
+    Contains(Bytes),
+    /// `startswith "literal"` for `^literal`.
+    StartsWith(Bytes),
+    /// `endswith "literal"` for `literal$`.
+    EndsWith(Bytes),
+}
+
+impl Display for RegexReplacement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexReplacement::Equal(bytes) => write!(f, "== {:?}", bytes),
+            RegexReplacement::Contains(bytes) => write!(f, "contains {:?}", bytes),
+            RegexReplacement::StartsWith(bytes) => write!(f, "startswith {:?}", bytes),
+            RegexReplacement::EndsWith(bytes) => write!(f, "endswith {:?}", bytes),
+        }
+    }
+}
+
+/// A warning about a suboptimal or suspicious part of a filter reported by
+/// [`FilterAst::lint`](::FilterAst::lint).
+///
+/// Lints refer to comparisons by their [`NodeId`]s, which can be mapped to
+/// the source with [`Lint::spans`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Lint {
+    /// `matches` is used with a regular expression that is a plain literal,
+    /// possibly anchored, and can be replaced with a cheaper operation.
+    SimpleRegex {
+        /// The comparison.
+        node_id: NodeId,
+        /// Source of the regular expression.
+        regex: String,
+        /// Suggested replacement.
+        replacement: RegexReplacement,
+        /// Whether the regular expression is used with a negated operator,
+        /// in which case the replacement has to be negated too.
+        negated: bool,
+    },
+
+    /// A comparison has the same outcome for all values, like
+    /// `tcp.port >= -2147483648`.
+    ConstantComparison {
+        /// The comparison.
+        node_id: NodeId,
+        /// The outcome.
+        result: bool,
+    },
+
+    /// An operand of a logical operator is repeated, like the second `ssl`
+    /// in `ssl and tcp.port == 443 and ssl`.
+    DuplicateClause {
+        /// Comparisons of the repeated operand.
+        node_ids: Vec<NodeId>,
+    },
+
+    /// Operands of `and` can't match together, like in
+    /// `tcp.port == 80 and ssl and tcp.port == 443`.
+    Contradiction {
+        /// Comparisons of a minimal set of operands that contradict each
+        /// other.
+        node_ids: Vec<NodeId>,
+    },
+
+    /// Operands of `or` match all values together, like in
+    /// `tcp.port != 80 or ssl or tcp.port != 443`.
+    Tautology {
+        /// Comparisons of a minimal set of operands that cover all values.
+        node_ids: Vec<NodeId>,
+    },
+
+    /// An operand of `or` can only match when one of the preceding operands
+    /// does, so it's never evaluated to a match, like `tcp.port == 443` in
+    /// `tcp.port in { 80 443 } or tcp.port == 443`.
+    UnreachableBranch {
+        /// Comparisons of the unreachable operand.
+        node_ids: Vec<NodeId>,
+    },
+}
+
+impl Lint {
+    /// Checks whether [`FilterAst::fix_lints`](::FilterAst::fix_lints) can
+    /// fix this lint automatically.
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            Lint::SimpleRegex { .. } => true,
+            Lint::ConstantComparison { .. }
+            | Lint::Contradiction { .. }
+            | Lint::Tautology { .. }
+            | Lint::DuplicateClause { .. }
+            | Lint::UnreachableBranch { .. } => false,
+        }
+    }
+
+    /// Returns a stable identifier of the kind of the lint, like
+    /// `simple-regex`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::SimpleRegex { .. } => "simple-regex",
+            Lint::ConstantComparison { .. } => "constant-comparison",
+            Lint::Contradiction { .. } => "contradiction",
+            Lint::Tautology { .. } => "tautology",
+            Lint::DuplicateClause { .. } => "duplicate-clause",
+            Lint::UnreachableBranch { .. } => "unreachable-branch",
+        }
+    }
+
+    /// Returns identifiers of the comparisons the lint refers to.
+    pub fn node_ids(&self) -> &[NodeId] {
+        match self {
+            Lint::SimpleRegex { node_id, .. } | Lint::ConstantComparison { node_id, .. } => {
+                slice::from_ref(node_id)
+            }
+            Lint::Contradiction { node_ids }
+            | Lint::Tautology { node_ids }
+            | Lint::DuplicateClause { node_ids }
+            | Lint::UnreachableBranch { node_ids } => node_ids,
+        }
+    }
+
+    /// Returns sorted ranges of byte offsets of the comparisons the lint
+    /// refers to in the source of a filter parsed with
+    /// [`Scheme::parse_with_spans`](::Scheme::parse_with_spans).
+    ///
+    /// Identical comparisons elsewhere in the filter are included too.
+    pub fn spans(&self, spans: &ExprSpans) -> Vec<Range<usize>> {
+        let mut ranges = self
+            .node_ids()
+            .iter()
+            .flat_map(|&node_id| spans.get(node_id).iter().cloned())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| (range.start, range.end));
+        ranges.dedup();
+        ranges
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::SimpleRegex {
+                regex,
+                replacement,
+                negated,
+                ..
+            } => {
+                write!(f, "regular expression {:?} can be replaced with `", regex)?;
+                match (negated, replacement) {
+                    (false, replacement) => write!(f, "{}", replacement)?,
+                    (true, RegexReplacement::Equal(bytes)) => write!(f, "!= {:?}", bytes)?,
+                    (true, replacement) => write!(f, "not {}", replacement)?,
+                }
+                f.write_str("`")
+            }
+            Lint::ConstantComparison { result, .. } => {
+                write!(f, "comparison is always {}", result)
+            }
+            Lint::Contradiction { .. } => f.write_str("clauses contradict each other"),
+            Lint::Tautology { .. } => f.write_str("clauses together match everything"),
+            Lint::DuplicateClause { .. } => f.write_str("clause is repeated"),
+            Lint::UnreachableBranch { .. } => {
+                f.write_str("clause never matches unless a preceding alternative does")
+            }
+        }
+    }
+}
+
+// Returns the literal matched by a regex if it consists only of literal
+// characters and, optionally, `^` and `$` anchors.
+fn simple_regex(regex: &str) -> Option<RegexReplacement> {
+    let (anchored_start, regex) = match regex.strip_prefix('^') {
+        Some(regex) => (true, regex),
+        None => (false, regex),
+    };
+
+    let mut anchored_end = false;
+    let mut literal = String::new();
+    let mut iter = regex.chars();
+
+    while let Some(c) = iter.next() {
+        match c {
+            // Only escaped metacharacters are literals, other escapes denote
+            // classes or assertions.
+            '\\' => match iter.next()? {
+                c @ '\\'
+                | c @ '.'
+                | c @ '+'
+                | c @ '*'
+                | c @ '?'
+                | c @ '('
+                | c @ ')'
+                | c @ '|'
+                | c @ '['
+                | c @ ']'
+                | c @ '{'
+                | c @ '}'
+                | c @ '^'
+                | c @ '$' => literal.push(c),
+                _ => return None,
+            },
+            '$' if iter.as_str().is_empty() => anchored_end = true,
+            '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => {
+                return None;
+            }
+            c => literal.push(c),
+        }
+    }
+
+    let literal = Bytes::from(literal);
+
+    Some(match (anchored_start, anchored_end) {
+        (true, true) => RegexReplacement::Equal(literal),
+        (true, false) => RegexReplacement::StartsWith(literal),
+        (false, true) => RegexReplacement::EndsWith(literal),
+        (false, false) => RegexReplacement::Contains(literal),
+    })
+}
+
+fn simple_regex_lint(expr: &FieldExpr<'_>) -> Option<Lint> {
+    match expr.op() {
+        FieldOp::Matches(regex) => Some(Lint::SimpleRegex {
+            node_id: expr.node_id(),
+            regex: regex.as_str().to_owned(),
+            replacement: simple_regex(regex.as_str())?,
+            negated: expr.is_negated(),
+        }),
+        _ => None,
+    }
+}
+
+// Returns the outcome of an expression if it's known to be the same for all
+// values.
+fn constant_result<'s>(expr: &CombinedExpr<'s>, scheme: &'s Scheme) -> Option<bool> {
+    constant_outcome(expr, scheme).unwrap_or(None)
+}
+
+// Returns a minimal subset of operands of `and` or `or` that together have
+// the given constant outcome, if the operator has it while none of its
+// operands has it alone.
+fn constant_operands<'a, 's>(
+    op: CombiningOp,
+    items: &'a [CombinedExpr<'s>],
+    result: bool,
+    scheme: &'s Scheme,
+) -> Option<Vec<&'a CombinedExpr<'s>>> {
+    let combine = |items: &[&CombinedExpr<'s>]| CombinedExpr::Combining {
+        op,
+        items: items.iter().map(|&item| item.clone()).collect(),
+    };
+
+    // Operands with a constant outcome are reported on their own.
+    if items
+        .iter()
+        .any(|item| constant_result(item, scheme) == Some(result))
+    {
+        return None;
+    }
+
+    let mut operands = items.iter().collect::<Vec<_>>();
+    if constant_result(&combine(&operands), scheme) != Some(result) {
+        return None;
+    }
+
+    let mut index = 0;
+    while index < operands.len() {
+        let mut rest = operands.clone();
+        rest.remove(index);
+        if constant_result(&combine(&rest), scheme) == Some(result) {
+            operands = rest;
+        } else {
+            index += 1;
+        }
+    }
+
+    Some(operands)
+}
+
+fn node_ids(expr: &CombinedExpr<'_>) -> Vec<NodeId> {
+    struct NodeIdCollector(Vec<NodeId>);
+
+    impl<'s> Visitor<'s> for NodeIdCollector {
+        fn visit_field_expr(&mut self, expr: &FieldExpr<'s>) {
+            self.0.push(expr.node_id());
+        }
+    }
+
+    let mut collector = NodeIdCollector(Vec::new());
+    collector.visit_expr(expr);
+    collector.0
+}
+
+struct Linter<'s> {
+    scheme: &'s Scheme,
+    lints: Vec<Lint>,
+}
+
+impl<'s> Linter<'s> {
+    fn lint_operands(&mut self, op: CombiningOp, items: &[CombinedExpr<'s>]) {
+        let constant = match op {
+            CombiningOp::And => constant_operands(op, items, false, self.scheme).map(|operands| {
+                Lint::Contradiction {
+                    node_ids: operands.into_iter().flat_map(node_ids).collect(),
+                }
+            }),
+            CombiningOp::Or => {
+                constant_operands(op, items, true, self.scheme).map(|operands| Lint::Tautology {
+                    node_ids: operands.into_iter().flat_map(node_ids).collect(),
+                })
+            }
+            CombiningOp::Xor => None,
+        };
+        self.lints.extend(constant);
+
+        let fingerprints = items.iter().map(fingerprint).collect::<Vec<_>>();
+
+        for (index, item) in items.iter().enumerate() {
+            if fingerprints[..index].contains(&fingerprints[index]) {
+                self.lints.push(Lint::DuplicateClause {
+                    node_ids: node_ids(item),
+                });
+            } else if op == CombiningOp::Or
+                && items[..index]
+                    .iter()
+                    .any(|other| implies(item, other, self.scheme, false).unwrap_or(false))
+            {
+                self.lints.push(Lint::UnreachableBranch {
+                    node_ids: node_ids(item),
+                });
+            }
+        }
+    }
+}
+
+impl<'s> Visitor<'s> for Linter<'s> {
+    fn visit_expr(&mut self, expr: &CombinedExpr<'s>) {
+        if let CombinedExpr::Combining { op, items } = expr {
+            self.lint_operands(*op, items);
+        }
+        walk_expr(self, expr);
+    }
+
+    fn visit_field_expr(&mut self, expr: &FieldExpr<'s>) {
+        self.lints.extend(simple_regex_lint(expr));
+        if let Some(result) = constant_result(
+            &CombinedExpr::Simple(SimpleExpr::Field(expr.clone())),
+            self.scheme,
+        ) {
+            self.lints.push(Lint::ConstantComparison {
+                node_id: expr.node_id(),
+                result,
+            });
+        }
+    }
+}
+
+struct Fixer;
+
+impl<'s> VisitorMut<'s> for Fixer {
+    fn visit_field_expr(&mut self, expr: &mut FieldExpr<'s>) {
+        if let Some(Lint::SimpleRegex {
+            replacement,
+            negated,
+            ..
+        }) = simple_regex_lint(expr)
+        {
+            *expr.op_mut() = match replacement {
+                // Ordering operators can't be negated, so the negation
+                // is moved into the operator itself.
+                RegexReplacement::Equal(bytes) => {
+                    expr.set_negated(false);
+                    // Literals are built from the regex source, so they
+                    // are valid UTF-8.
+                    let rhs = if expr.lhs().get_type() == Type::Str {
+                        RhsValue::Str(String::from_utf8(bytes.to_vec()).unwrap().into())
+                    } else {
+                        RhsValue::Bytes(bytes)
+                    };
+                    FieldOp::Ordering {
+                        op: if negated {
+                            OrderingOp::NotEqual
+                        } else {
+                            OrderingOp::Equal
+                        },
+                        rhs,
+                    }
+                }
+                RegexReplacement::Contains(bytes) => FieldOp::Contains(bytes),
+                RegexReplacement::StartsWith(bytes) => FieldOp::StartsWith(bytes),
+                RegexReplacement::EndsWith(bytes) => FieldOp::EndsWith(bytes),
+            };
+        }
+    }
+}
+
+pub(crate) fn lint<'s>(expr: &CombinedExpr<'s>, scheme: &'s Scheme) -> Vec<Lint> {
+    let mut linter = Linter {
+        scheme,
+        lints: Vec::new(),
+    };
+    linter.visit_expr(expr);
+    linter.lints
+}
+
+pub(crate) fn fix_lints<'s>(expr: &mut CombinedExpr<'s>, scheme: &'s Scheme) -> Vec<Lint> {
+    let lints = lint(expr, scheme);
+    Fixer.visit_expr(expr);
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_regex() {
+        let literal = |s: &str| Bytes::from(s.to_owned());
+
+        assert_eq!(
+            simple_regex("^abc$"),
+            Some(RegexReplacement::Equal(literal("abc")))
+        );
+        assert_eq!(
+            simple_regex(r"^example\.org"),
+            Some(RegexReplacement::StartsWith(literal("example.org")))
+        );
+        assert_eq!(
+            simple_regex(r"\.php$"),
+            Some(RegexReplacement::EndsWith(literal(".php")))
+        );
+        assert_eq!(
+            simple_regex("/admin/"),
+            Some(RegexReplacement::Contains(literal("/admin/")))
+        );
+        assert_eq!(simple_regex("^a.c$"), None);
+        assert_eq!(simple_regex(r"\d+"), None);
+        assert_eq!(simple_regex("a|b"), None);
+        assert_eq!(simple_regex("(?i)abc"), None);
+        assert_eq!(simple_regex("a$b"), None);
+    }
+
+    #[test]
+    fn test_lint() {
+        let scheme = Scheme! { http.host: Bytes, http.path: Bytes };
+
+        let mut ast = scheme
+            .parse(
+                r#"http.host matches "^example\.org$" or http.path ~ "^/api/" or http.path ~ "/wp-" or http.host ~ "^a+$""#,
+            )
+            .unwrap();
+
+        let lints = ast.lint();
+
+        assert_eq!(
+            lints
+                .iter()
+                .map(|lint| (lint.to_string(), lint.is_fixable()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    r#"regular expression "^example\\.org$" can be replaced with `== "example.org"`"#
+                        .to_owned(),
+                    true
+                ),
+                (
+                    r#"regular expression "^/api/" can be replaced with `startswith "/api/"`"#
+                        .to_owned(),
+                    true
+                ),
+                (
+                    r#"regular expression "/wp-" can be replaced with `contains "/wp-"`"#
+                        .to_owned(),
+                    true
+                ),
+            ]
+        );
+
+        assert_eq!(ast.fix_lints(), lints);
+
+        assert_eq!(
+            ast,
+            scheme
+                .parse(
+                    r#"http.host == "example.org" or http.path startswith "/api/" or http.path contains "/wp-" or http.host ~ "^a+$""#
+                )
+                .unwrap()
+        );
+
+        assert_eq!(ast.lint(), []);
+
+        let mut ast = scheme
+            .parse(r#"http.host !~ "^example\.org$" and http.path not matches "^/api/""#)
+            .unwrap();
+
+        assert_eq!(
+            ast.lint()
+                .iter()
+                .map(|lint| lint.to_string())
+                .collect::<Vec<_>>(),
+            [
+                r#"regular expression "^example\\.org$" can be replaced with `!= "example.org"`"#,
+                r#"regular expression "^/api/" can be replaced with `not startswith "/api/"`"#,
+            ]
+        );
+
+        ast.fix_lints();
+
+        assert_eq!(
+            ast,
+            scheme
+                .parse(r#"http.host != "example.org" and http.path not startswith "/api/""#)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lint_mistakes() {
+        let scheme = Scheme! { tcp.port: Int, ssl: Bool };
+
+        let source = "tcp.port <= 2147483647 and (tcp.port in { 80 443 } or tcp.port == 443) \
+                      and ssl and tcp.port > 2147483647 and ssl";
+        let (ast, spans) = scheme.parse_with_spans(source).unwrap();
+
+        let lints = ast.lint();
+
+        assert_eq!(
+            lints
+                .iter()
+                .map(|lint| {
+                    (
+                        lint.code(),
+                        lint.to_string(),
+                        lint.spans(&spans)
+                            .into_iter()
+                            .map(|span| &source[span])
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "duplicate-clause",
+                    "clause is repeated".to_owned(),
+                    vec!["ssl", "ssl"]
+                ),
+                (
+                    "constant-comparison",
+                    "comparison is always true".to_owned(),
+                    vec!["tcp.port <= 2147483647"]
+                ),
+                (
+                    "unreachable-branch",
+                    "clause never matches unless a preceding alternative does".to_owned(),
+                    vec!["tcp.port == 443"]
+                ),
+                (
+                    "constant-comparison",
+                    "comparison is always false".to_owned(),
+                    vec!["tcp.port > 2147483647"]
+                ),
+            ]
+        );
+
+        assert!(lints.iter().all(|lint| !lint.is_fixable()));
+        let mut fixed = ast.clone();
+        assert_eq!(fixed.fix_lints(), lints);
+        assert_eq!(fixed, ast);
+
+        // operands of `and` that imply each other aren't unreachable
+        assert_eq!(
+            scheme
+                .parse("tcp.port in { 80 443 } and tcp.port == 443")
+                .unwrap()
+                .lint(),
+            []
+        );
+    }
+
+    #[test]
+    fn test_lint_contradictions() {
+        let scheme = Scheme! { tcp.port: Int, ssl: Bool, http.host: Bytes };
+
+        let lint = |source| {
+            let (ast, spans) = scheme.parse_with_spans(source).unwrap();
+            ast.lint()
+                .iter()
+                .map(|lint| {
+                    (
+                        lint.code(),
+                        lint.spans(&spans)
+                            .into_iter()
+                            .map(|span| &source[span])
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            lint(r#"tcp.port == 80 and http.host == "a" and tcp.port == 443"#),
+            [("contradiction", vec!["tcp.port == 80", "tcp.port == 443"])]
+        );
+
+        assert_eq!(
+            lint("ssl and (tcp.port < 1024 or tcp.port > 80 or http.host contains \"a\")"),
+            [("tautology", vec!["tcp.port < 1024", "tcp.port > 80"])]
+        );
+
+        // only the innermost contradiction is reported
+        assert_eq!(
+            lint("ssl and (tcp.port in { 1..10 } and tcp.port in { 20..30 })"),
+            [(
+                "contradiction",
+                vec!["tcp.port in { 1..10 }", "tcp.port in { 20..30 }"]
+            )]
+        );
+
+        assert_eq!(lint("tcp.port > 80 and tcp.port < 443"), []);
+    }
+
+    #[test]
+    fn test_overlapping_set_values() {
+        let scheme = Scheme! { ip.src: Ip, tcp.port: Int };
+
+        let (_, warnings) = scheme
+            .parse_with_warnings(
+                "let nets = ip.src in { 10.0.0.0/8 192.168.0.0/16 10.1.0.0/16 }; \
+                 nets or (nets and tcp.port in { 1..1024 80 2000 })",
+            )
+            .unwrap();
+
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| (warning.code(), warning.token(), warning.message()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "overlapping-set-values",
+                    "10.1.0.0/16",
+                    "value overlaps another one in the same set".to_owned()
+                ),
+                (
+                    "overlapping-set-values",
+                    "80",
+                    "value overlaps another one in the same set".to_owned()
+                ),
+            ]
+        );
+
+        assert_eq!(
+            scheme
+                .parse_with_warnings("ip.src in { 10.0.0.0/8 11.0.0.0/8 ::/0 }")
+                .unwrap()
+                .1,
+            []
+        );
+    }
+}
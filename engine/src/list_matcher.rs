@@ -0,0 +1,220 @@
+use fnv::{FnvBuildHasher, FnvHasher};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, f64::consts::LN_2, hash::Hasher, iter::FromIterator, sync::Arc};
+
+/// A matcher of exact byte values for named lists of `Bytes` and `Str`
+/// values.
+///
+/// Matchers are set on an [`ExecutionContext`](crate::ExecutionContext)
+/// with [`set_list_matcher`](crate::ExecutionContext::set_list_matcher)
+/// and shared between contexts, so large lists are loaded only once.
+pub trait ListMatcher: Send + Sync {
+    /// Checks whether the value is in the list.
+    fn contains(&self, value: &[u8]) -> bool;
+}
+
+/// A [`ListMatcher`] backed by a hash set of values.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HashSetMatcher {
+    values: HashSet<Box<[u8]>, FnvBuildHasher>,
+}
+
+impl HashSetMatcher {
+    /// Creates an empty matcher.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a value to the list.
+    pub fn insert(&mut self, value: &[u8]) {
+        self.values.insert(value.into());
+    }
+
+    /// Number of distinct values in the list.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Checks whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<T: AsRef<[u8]>> Extend<T> for HashSetMatcher {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            self.insert(value.as_ref());
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for HashSetMatcher {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut matcher = HashSetMatcher::new();
+        matcher.extend(values);
+        matcher
+    }
+}
+
+impl ListMatcher for HashSetMatcher {
+    fn contains(&self, value: &[u8]) -> bool {
+        self.values.contains(value)
+    }
+}
+
+/// A probabilistic [`ListMatcher`] backed by a Bloom filter.
+///
+/// It takes a fraction of the memory of a [`HashSetMatcher`], but can report
+/// values that aren't in the list as present, with the false positive rate
+/// it was created with. Values that are in the list are always reported.
+/// When false positives are unacceptable, the matcher can be chained to an
+/// exact verifier with [`BloomMatcher::with_verifier`], which is consulted
+/// only for values that pass the filter, like a [`HashSetMatcher`] loaded
+/// lazily or a lookup in an external store.
+///
+/// The filter can be serialized to distribute a precomputed blob. Hashes
+/// don't depend on the platform, but the verifier isn't serialized.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BloomMatcher {
+    num_hashes: u32,
+    bits: Vec<u64>,
+    #[serde(skip)]
+    verifier: Option<Arc<dyn ListMatcher>>,
+}
+
+impl BloomMatcher {
+    /// Creates an empty filter sized for the expected number of values and
+    /// the given false positive rate, which must be between 0 and 1.
+    pub fn new(expected_values: usize, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+
+        let expected_values = expected_values.max(1) as f64;
+        let num_bits = (-expected_values * false_positive_rate.ln() / (LN_2 * LN_2)).ceil();
+        let num_words = ((num_bits / 64.0).ceil() as usize).max(1);
+        let num_hashes = ((num_words * 64) as f64 / expected_values * LN_2).round();
+
+        BloomMatcher {
+            num_hashes: (num_hashes as u32).clamp(1, 32),
+            bits: vec![0; num_words],
+            verifier: None,
+        }
+    }
+
+    /// Creates a filter with all of the given values and the given false
+    /// positive rate, which must be between 0 and 1.
+    pub fn from_values<I>(values: I, false_positive_rate: f64) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let values = values.into_iter().collect::<Vec<_>>();
+        let mut matcher = BloomMatcher::new(values.len(), false_positive_rate);
+        for value in &values {
+            matcher.insert(value.as_ref());
+        }
+        matcher
+    }
+
+    /// Chains the filter to a matcher that confirms values passing it.
+    pub fn with_verifier(mut self, verifier: Arc<dyn ListMatcher>) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Adds a value to the filter.
+    pub fn insert(&mut self, value: &[u8]) {
+        for index in self.bit_indices(value) {
+            self.bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    // Positions of the bits of a value, derived from two hashes.
+    fn bit_indices(&self, value: &[u8]) -> impl Iterator<Item = usize> {
+        let mut hasher = FnvHasher::default();
+        hasher.write(value);
+        let first = mix(hasher.finish());
+        let second = mix(first) | 1;
+        let num_bits = self.bits.len() as u64 * 64;
+
+        (0..u64::from(self.num_hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % num_bits) as usize)
+    }
+}
+
+// Finalizer of SplitMix64, which spreads FNV hashes over all bits and
+// derives an independent second hash.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl ListMatcher for BloomMatcher {
+    fn contains(&self, value: &[u8]) -> bool {
+        // Deserialized filters might be empty.
+        if self.bits.is_empty() {
+            return false;
+        }
+
+        self.bit_indices(value)
+            .all(|index| self.bits[index / 64] & (1 << (index % 64)) != 0)
+            && self
+                .verifier
+                .as_ref()
+                .is_none_or(|verifier| verifier.contains(value))
+    }
+}
+
+#[test]
+fn test_hash_set_matcher() {
+    let matcher: HashSetMatcher = ["evil.example", "bad.example", "evil.example"]
+        .iter()
+        .collect();
+
+    assert_eq!(matcher.len(), 2);
+    assert!(matcher.contains(b"evil.example"));
+    assert!(!matcher.contains(b"example.org"));
+
+    let json = serde_json::to_string(&matcher).unwrap();
+    let copy: HashSetMatcher = serde_json::from_str(&json).unwrap();
+    assert_eq!(copy, matcher);
+}
+
+#[test]
+fn test_bloom_matcher() {
+    let values = (0..1000)
+        .map(|i| format!("host{}.example", i))
+        .collect::<Vec<_>>();
+    let matcher = BloomMatcher::from_values(&values, 0.01);
+
+    // no false negatives
+    assert!(values
+        .iter()
+        .all(|value| matcher.contains(value.as_bytes())));
+
+    let false_positives = (0..10000)
+        .filter(|i| matcher.contains(format!("other{}.example", i).as_bytes()))
+        .count();
+    assert!(false_positives < 300, "{} false positives", false_positives);
+
+    // precomputed filters keep their contents
+    let json = serde_json::to_string(&matcher).unwrap();
+    let copy: BloomMatcher = serde_json::from_str(&json).unwrap();
+    assert!(values.iter().all(|value| copy.contains(value.as_bytes())));
+
+    // a verifier removes false positives
+    let verifier: HashSetMatcher = values.iter().collect();
+    let verified = copy.with_verifier(Arc::new(verifier));
+    assert!(values
+        .iter()
+        .all(|value| verified.contains(value.as_bytes())));
+    assert!((0..10000).all(|i| !verified.contains(format!("other{}.example", i).as_bytes())));
+
+    let empty: BloomMatcher = serde_json::from_str(r#"{ "num_hashes": 1, "bits": [] }"#).unwrap();
+    assert!(!empty.contains(b"host0.example"));
+}
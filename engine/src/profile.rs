@@ -0,0 +1,189 @@
+use crate::{
+    ast::{CombinedExpr, Expr, FieldExpr, NodeId, SimpleExpr},
+    filter::{CompileContext, CompiledExpr},
+    format::{format_expr, FormatOptions},
+    spans::ExprSpans,
+};
+use indexmap::IndexMap;
+use std::{
+    cmp::Reverse,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+struct NodeCounters {
+    node_id: NodeId,
+    expression: String,
+    hits: AtomicU64,
+    nanos: AtomicU64,
+}
+
+/// Evaluation statistics of a comparison in a filter compiled with
+/// [`FilterAst::compile_with_profile`](::FilterAst::compile_with_profile).
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeProfile {
+    /// Identifier of the comparison.
+    pub node_id: NodeId,
+    /// The comparison in the canonical form.
+    pub expression: String,
+    /// Ranges of byte offsets of the comparison in the source.
+    pub spans: Vec<Range<usize>>,
+    /// Number of evaluations.
+    pub hits: u64,
+    /// Total time spent on evaluations.
+    pub elapsed: Duration,
+}
+
+/// Counters of comparisons of a filter being compiled with profiling, keyed
+/// by their node IDs.
+#[derive(Default)]
+pub(crate) struct ProfiledNodes(IndexMap<NodeId, Arc<NodeCounters>>);
+
+/// Counters of all comparisons of a filter, shared with its compiled
+/// expressions.
+pub(crate) struct Profiler {
+    nodes: Vec<Arc<NodeCounters>>,
+    spans: ExprSpans,
+}
+
+impl Profiler {
+    pub(crate) fn new(nodes: ProfiledNodes, spans: ExprSpans) -> Self {
+        Profiler {
+            nodes: nodes.0.into_iter().map(|(_, node)| node).collect(),
+            spans,
+        }
+    }
+
+    pub(crate) fn report(&self) -> Vec<NodeProfile> {
+        let mut report = self
+            .nodes
+            .iter()
+            .map(|node| NodeProfile {
+                node_id: node.node_id,
+                expression: node.expression.clone(),
+                spans: self.spans.get(node.node_id).to_vec(),
+                hits: node.hits.load(Ordering::Relaxed),
+                elapsed: Duration::from_nanos(node.nanos.load(Ordering::Relaxed)),
+            })
+            .collect::<Vec<_>>();
+        report.sort_by_key(|node| Reverse(node.elapsed));
+        report
+    }
+
+    pub(crate) fn reset(&self) {
+        for node in &self.nodes {
+            node.hits.store(0, Ordering::Relaxed);
+            node.nanos.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Compiles a comparison, adding counters if the filter is being compiled
+/// with profiling.
+pub(crate) fn compile_instrumented<'s>(
+    expr: FieldExpr<'s>,
+    cx: &mut CompileContext<'s>,
+) -> CompiledExpr<'s> {
+    let counters = cx.profiled_nodes.as_mut().map(|nodes| {
+        let node_id = expr.node_id();
+        Arc::clone(nodes.0.entry(node_id).or_insert_with(|| {
+            Arc::new(NodeCounters {
+                node_id,
+                expression: format_expr(
+                    &CombinedExpr::Simple(SimpleExpr::Field(expr.clone())),
+                    &FormatOptions::default(),
+                ),
+                hits: AtomicU64::new(0),
+                nanos: AtomicU64::new(0),
+            })
+        }))
+    });
+
+    let compiled = expr.compile_in(cx);
+    match counters {
+        Some(counters) => CompiledExpr::new(move |ctx, scope| {
+            let start = Instant::now();
+            let result = compiled.execute_in(ctx, scope);
+            counters.hits.fetch_add(1, Ordering::Relaxed);
+            counters
+                .nanos
+                .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            result
+        }),
+        None => compiled,
+    }
+}
+
+#[test]
+fn test_profile() {
+    use crate::execution_context::ExecutionContext;
+
+    let scheme = Scheme! { http.host: Bytes, tcp.port: Int, ssl: Bool };
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("http.host", "example.org").unwrap();
+    ctx.set_field_value("tcp.port", 80).unwrap();
+    ctx.set_field_value("ssl", false).unwrap();
+
+    let source = "tcp.port == 80 and (ssl or http.host contains \"example\" or tcp.port == 80)";
+    let (ast, spans) = scheme.parse_with_spans(source).unwrap();
+    let port_id = scheme.parse("tcp.port == 80").unwrap().node_ids()[0];
+    assert_eq!(spans.get(port_id), [0..14, 59..73]);
+
+    let filter = ast.clone().compile_with_profile(spans).unwrap();
+    for _ in 0..3 {
+        assert_eq!(filter.execute(&ctx), Ok(true));
+    }
+
+    let mut report = filter.profile().unwrap();
+    report.sort_by_key(|node| node.spans.first().map(|span| span.start));
+    let summary = report
+        .iter()
+        .map(|node| {
+            (
+                node.expression.as_str(),
+                node.spans
+                    .iter()
+                    .map(|span| &source[span.clone()])
+                    .collect(),
+                node.hits,
+            )
+        })
+        .collect::<Vec<(_, Vec<_>, _)>>();
+    assert_eq!(
+        summary,
+        [
+            (
+                "tcp.port == 80",
+                vec!["tcp.port == 80", "tcp.port == 80"],
+                3
+            ),
+            ("ssl", vec!["ssl"], 3),
+            (
+                "http.host contains \"example\"",
+                vec!["http.host contains \"example\""],
+                3
+            ),
+        ]
+    );
+
+    filter.reset_profile();
+    assert!(filter.profile().unwrap().iter().all(|node| node.hits == 0));
+
+    assert_eq!(ast.compile().unwrap().profile(), None);
+
+    // ASTs that weren't parsed with spans can be profiled as well
+    let filter = scheme
+        .parse("let https = ssl; https")
+        .unwrap()
+        .compile_with_profile(ExprSpans::default())
+        .unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(false));
+    let report = filter.profile().unwrap();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].hits, 1);
+    assert!(report[0].spans.is_empty());
+}
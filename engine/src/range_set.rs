@@ -54,3 +54,142 @@ impl<T> RangeSet<T> {
             .is_ok()
     }
 }
+
+/// Bounds of inclusive ranges that can be split around excluded values.
+pub(crate) trait RangeBound: Ord + Copy {
+    fn checked_pred(self) -> Option<Self>;
+    fn checked_succ(self) -> Option<Self>;
+}
+
+macro_rules! impl_range_bound {
+    ($($ty:ty),*) => {
+        $(impl RangeBound for $ty {
+            fn checked_pred(self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+
+            fn checked_succ(self) -> Option<Self> {
+                self.checked_add(1)
+            }
+        })*
+    };
+}
+
+impl_range_bound!(i32, u128);
+
+/// Removes excluded values from a list of ranges, splitting ranges that
+/// contain them, and returns the remaining ranges sorted by start.
+pub(crate) fn subtract_ranges<T: RangeBound>(
+    ranges: Vec<RangeInclusive<T>>,
+    excluded: Vec<RangeInclusive<T>>,
+) -> Vec<RangeInclusive<T>> {
+    let ranges = RangeSet::from(ranges).ranges;
+    let excluded = RangeSet::from(excluded).ranges;
+
+    let mut remaining = Vec::with_capacity(ranges.len());
+
+    for range in ranges {
+        let (start, end) = range.into_inner();
+
+        // Skip exclusions that end before the range.
+        let first = match excluded.binary_search_by(|range| {
+            if *range.end() < start {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(index) | Err(index) => index,
+        };
+
+        let mut next = Some(start);
+
+        for exclusion in &excluded[first..] {
+            let current = match next {
+                Some(current) if current <= end => current,
+                _ => break,
+            };
+            if *exclusion.start() > end {
+                break;
+            }
+            if let Some(pred) = exclusion.start().checked_pred() {
+                if current <= pred {
+                    remaining.push(current..=pred);
+                }
+            }
+            next = exclusion.end().checked_succ();
+        }
+
+        match next {
+            Some(current) if current <= end => remaining.push(current..=end),
+            _ => {}
+        }
+    }
+
+    remaining
+}
+
+/// Returns sorted indices of ranges that overlap other ones. Of each pair of
+/// overlapping ranges, the one that comes later in the list is reported.
+pub(crate) fn find_overlapping_ranges<T: Ord + Copy>(ranges: &[RangeInclusive<T>]) -> Vec<usize> {
+    let mut order = (0..ranges.len()).collect::<Vec<_>>();
+    order.sort_unstable_by_key(|&index| (*ranges[index].start(), index));
+
+    let mut overlapping = Vec::new();
+    // The range that reaches the furthest among the ones seen so far.
+    let mut furthest: Option<usize> = None;
+
+    for index in order {
+        let range = &ranges[index];
+        match furthest {
+            Some(other) if range.start() <= ranges[other].end() => {
+                overlapping.push(index.max(other));
+                if range.end() > ranges[other].end() {
+                    furthest = Some(index);
+                }
+            }
+            _ => furthest = Some(index),
+        }
+    }
+
+    overlapping.sort_unstable();
+    overlapping.dedup();
+    overlapping
+}
+
+#[test]
+fn test_subtract_ranges() {
+    assert_eq!(
+        subtract_ranges(vec![0..=100, 200..=300], vec![10..=19, 15..=29, 90..=210]),
+        [0..=9, 30..=89, 211..=300]
+    );
+
+    assert_eq!(subtract_ranges(vec![0..=10], vec![0..=10]), []);
+
+    assert_eq!(
+        subtract_ranges(vec![i32::min_value()..=i32::max_value()], vec![0..=0]),
+        [i32::min_value()..=-1, 1..=i32::max_value()]
+    );
+
+    assert_eq!(
+        subtract_ranges(
+            vec![i32::min_value()..=i32::max_value()],
+            vec![i32::min_value()..=-1, i32::max_value()..=i32::max_value()]
+        ),
+        [0..=i32::max_value() - 1]
+    );
+
+    assert_eq!(subtract_ranges(vec![5..=7], vec![]), [5..=7]);
+}
+
+#[test]
+fn test_find_overlapping_ranges() {
+    assert_eq!(
+        find_overlapping_ranges(&[0..=10, 20..=30, 5..=6, 31..=40, 25..=35]),
+        [2, 4]
+    );
+
+    assert_eq!(find_overlapping_ranges(&[3..=3, 1..=2, 3..=3]), [2]);
+
+    assert!(find_overlapping_ranges::<i32>(&[]).is_empty());
+}
@@ -0,0 +1,164 @@
+/// Result of a `matches` comparison once the regex budget of an execution
+/// is exhausted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RegexBudgetAction {
+    /// The regex is considered to match, which makes blocking filters fail
+    /// closed.
+    Match,
+    /// The regex is considered not to match, which makes blocking filters
+    /// fail open.
+    NoMatch,
+}
+
+/// A limit on the amount of regex matching done by a single filter
+/// execution.
+///
+/// Regexes are compiled to finite automata without backtracking, so matching
+/// takes time linear in the input, and the budget is measured in bytes of
+/// field values passed to `matches` operators. Once a comparison would
+/// exceed the budget, it and all following ones in the same execution
+/// evaluate to the configured [`RegexBudgetAction`] without matching.
+///
+/// Only `matches` comparisons are metered. There's no interface for custom
+/// regex matchers, and functions, including the ones in [`stdlib`](::stdlib)
+/// that take regexes, and [list matchers](::ListMatcher) are expected to
+/// bound their own work.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RegexBudget {
+    /// Maximum total length of inputs matched by regexes.
+    pub max_bytes: usize,
+    /// Result of comparisons that exceed the budget.
+    pub on_exhausted: RegexBudgetAction,
+}
+
+/// Budget state of a single filter execution.
+pub(crate) struct ScopeBudget {
+    budget: RegexBudget,
+    remaining: usize,
+    exhausted: bool,
+}
+
+impl ScopeBudget {
+    pub(crate) fn new(budget: RegexBudget) -> Self {
+        ScopeBudget {
+            budget,
+            remaining: budget.max_bytes,
+            exhausted: false,
+        }
+    }
+
+    /// Spends the budget on matching an input of the given length, or
+    /// returns the result of the comparison if it doesn't fit.
+    ///
+    /// Once a comparison has exceeded the budget, all following ones in the
+    /// same execution are short-circuited, including ones with empty inputs.
+    pub(crate) fn spend(&mut self, len: usize) -> Option<bool> {
+        if self.exhausted || len > self.remaining {
+            self.exhausted = true;
+            Some(self.budget.on_exhausted == RegexBudgetAction::Match)
+        } else {
+            self.remaining -= len;
+            None
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_regex_budget() {
+    use crate::execution_context::ExecutionContext;
+
+    let scheme = Scheme! { http.host: Bytes, http.path: Bytes };
+    let mut filter = scheme
+        .parse(r#"http.path matches "^/a" or http.host matches "\.org$""#)
+        .unwrap()
+        .compile()
+        .unwrap();
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("http.path", "/b").unwrap();
+    ctx.set_field_value("http.host", "example.org").unwrap();
+
+    assert_eq!(filter.execute(&ctx), Ok(true));
+
+    // Only the path fits into the budget.
+    filter.set_regex_budget(Some(RegexBudget {
+        max_bytes: 10,
+        on_exhausted: RegexBudgetAction::NoMatch,
+    }));
+    assert_eq!(filter.execute(&ctx), Ok(false));
+
+    // The budget is reset for each execution.
+    ctx.set_field_value("http.path", "/a").unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(true));
+
+    ctx.set_field_value("http.path", "/b").unwrap();
+    filter.set_regex_budget(Some(RegexBudget {
+        max_bytes: 10,
+        on_exhausted: RegexBudgetAction::Match,
+    }));
+    ctx.set_field_value("http.host", "example.com").unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(true));
+
+    filter.set_regex_budget(None);
+    assert_eq!(filter.execute(&ctx), Ok(false));
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_regex_budget_exhausted() {
+    use crate::execution_context::ExecutionContext;
+
+    let scheme = Scheme! { http.host: Bytes, http.path: Bytes };
+    let mut filter = scheme
+        .parse(r#"http.host matches "\.org$" or http.path matches "^$""#)
+        .unwrap()
+        .compile()
+        .unwrap();
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("http.host", "example.com").unwrap();
+    ctx.set_field_value("http.path", "").unwrap();
+
+    assert_eq!(filter.execute(&ctx), Ok(true));
+
+    // The empty path would fit into the remaining budget, but the budget is
+    // already exhausted by the host.
+    filter.set_regex_budget(Some(RegexBudget {
+        max_bytes: 5,
+        on_exhausted: RegexBudgetAction::NoMatch,
+    }));
+    assert_eq!(filter.execute(&ctx), Ok(false));
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_regex_budget_execution_paths() {
+    use crate::{execution_context::ExecutionContext, filter_set::FilterSet};
+
+    let scheme = Scheme! { http.host: Bytes };
+    let budget = Some(RegexBudget {
+        max_bytes: 5,
+        on_exhausted: RegexBudgetAction::NoMatch,
+    });
+    let filter = || scheme.parse(r#"http.host matches "\.org$""#).unwrap();
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("http.host", "example.org").unwrap();
+
+    let mut traced = filter().compile_with_trace().unwrap();
+    assert!(traced.execute_with_trace(&ctx).unwrap().unwrap().result());
+    traced.set_regex_budget(budget);
+    assert!(!traced.execute_with_trace(&ctx).unwrap().unwrap().result());
+
+    let mut three_valued = filter().compile_three_valued().unwrap();
+    assert_eq!(three_valued.execute(&ctx), Ok(Some(true)));
+    three_valued.set_regex_budget(budget);
+    assert_eq!(three_valued.execute(&ctx), Ok(Some(false)));
+
+    let mut set = FilterSet::new(&scheme);
+    set.add(filter()).unwrap();
+    assert_eq!(set.execute(&ctx), Ok(vec![0]));
+    set.set_regex_budget(budget);
+    assert_eq!(set.execute(&ctx), Ok(vec![]));
+}
@@ -0,0 +1,174 @@
+use crate::{
+    ast::{visitor::Visitor, CombinedExpr, FieldExpr, FieldOp, LhsFieldExpr},
+    rhs_types::{Regex, RegexSet},
+    scheme::Field,
+};
+use fnv::FnvHashMap;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+// Minimum number of distinct regexes matched against the same field for
+// them to be compiled into a set.
+const MIN_SET_SIZE: usize = 4;
+
+static NEXT_SET_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Results of sets matched during a single filter execution, keyed by set
+/// ID.
+pub(crate) type RegexSetResults = FnvHashMap<usize, Box<[bool]>>;
+
+/// A set of regexes matched against the same field, shared between `matches`
+/// nodes of a filter.
+///
+/// The set is matched at most once per filter execution, and nodes look up
+/// their results by index.
+pub(crate) struct SharedRegexSet {
+    id: usize,
+    set: RegexSet,
+}
+
+impl SharedRegexSet {
+    pub(crate) fn is_match(
+        &self,
+        index: usize,
+        text: &[u8],
+        results: &mut RegexSetResults,
+    ) -> bool {
+        results
+            .entry(self.id)
+            .or_insert_with(|| self.set.matches(text))[index]
+    }
+}
+
+#[derive(Default)]
+struct RegexCollector(FnvHashMap<usize, Vec<Regex>>);
+
+impl<'s> Visitor<'s> for RegexCollector {
+    fn visit_field_expr(&mut self, expr: &FieldExpr<'s>) {
+        if let (LhsFieldExpr::Field(field), FieldOp::Matches(regex)) = (expr.lhs(), expr.op()) {
+            // Sets are compiled without Unicode support.
+            if regex.is_unicode() {
+                return;
+            }
+            let regexes = self.0.entry(field.index()).or_default();
+            if !regexes.contains(regex) {
+                regexes.push(regex.clone());
+            }
+        }
+    }
+}
+
+/// Sets of a filter being compiled, along with the index of each regex in its
+/// set, keyed by field index and regex source.
+///
+/// Many regexes matched against the same field are put into a set, so that
+/// they're matched in a single pass over its value.
+#[derive(Default)]
+pub(crate) struct RegexSets(FnvHashMap<(usize, String), (Arc<SharedRegexSet>, usize)>);
+
+impl RegexSets {
+    pub(crate) fn new(expr: &CombinedExpr<'_>) -> Self {
+        let mut collector = RegexCollector::default();
+        collector.visit_expr(expr);
+
+        let mut sets = FnvHashMap::default();
+
+        for (field, regexes) in collector.0 {
+            if regexes.len() < MIN_SET_SIZE {
+                continue;
+            }
+
+            // Individual regexes are used if the set exceeds size limits.
+            let set = match RegexSet::new(&regexes) {
+                Ok(set) => Arc::new(SharedRegexSet {
+                    id: NEXT_SET_ID.fetch_add(1, Ordering::Relaxed),
+                    set,
+                }),
+                Err(_) => continue,
+            };
+
+            for (index, regex) in regexes.iter().enumerate() {
+                sets.insert(
+                    (field, regex.as_str().to_owned()),
+                    (Arc::clone(&set), index),
+                );
+            }
+        }
+
+        RegexSets(sets)
+    }
+
+    /// Returns the set that contains a regex matched against a field, along
+    /// with its index.
+    pub(crate) fn find(
+        &self,
+        field: Field<'_>,
+        regex: &Regex,
+    ) -> Option<(Arc<SharedRegexSet>, usize)> {
+        if regex.is_unicode() {
+            return None;
+        }
+        self.0
+            .get(&(field.index(), regex.as_str().to_owned()))
+            .cloned()
+    }
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_regex_sets() {
+    use crate::execution_context::ExecutionContext;
+
+    let scheme = &Scheme! { http.host: Bytes, http.path: Bytes };
+
+    let ast = scheme
+        .parse(
+            r#"http.path ~ "^/a" or http.path ~ "^/b" or http.path ~ "^/c"
+                or (http.path ~ "^/d" and http.host ~ "^example\.")
+                or not http.path ~ "^/a" and http.path ~ "z$""#,
+        )
+        .unwrap();
+
+    let mut collector = RegexCollector::default();
+    collector.visit_expr(ast.expression());
+
+    let host = scheme.get_field_index("http.host").unwrap().index();
+    let path = scheme.get_field_index("http.path").unwrap().index();
+
+    assert_eq!(collector.0[&host].len(), 1);
+    assert_eq!(
+        collector.0[&path]
+            .iter()
+            .map(Regex::as_str)
+            .collect::<Vec<_>>(),
+        ["^/a", "^/b", "^/c", "^/d", "z$"]
+    );
+
+    let sets = RegexSets::new(ast.expression());
+    assert_eq!(sets.0.len(), 5);
+    assert!(sets
+        .find(
+            scheme.get_field_index("http.host").unwrap(),
+            &collector.0[&host][0]
+        )
+        .is_none());
+
+    let filter = ast.compile().unwrap();
+
+    let mut ctx = ExecutionContext::new(scheme);
+
+    for &(host, path, result) in &[
+        ("example.org", "/a", true),
+        ("example.org", "/d", true),
+        ("other.org", "/d", false),
+        ("other.org", "/xyz", true),
+        ("other.org", "/abz", true),
+        ("other.org", "/x", false),
+    ] {
+        ctx.set_field_value("http.host", host).unwrap();
+        ctx.set_field_value("http.path", path).unwrap();
+        assert_eq!(filter.execute(&ctx), Ok(result), "{} {}", host, path);
+    }
+}
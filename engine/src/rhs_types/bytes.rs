@@ -1,8 +1,8 @@
 use crate::{
-    lex::{expect, take, Lex, LexErrorKind, LexResult},
+    lex::{expect, lex_raw_string, take, take_while, Lex, LexErrorKind, LexResult},
     strict_partial_ord::StrictPartialOrd,
 };
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use std::{
     borrow::Borrow,
     fmt::{self, Debug, Formatter},
@@ -11,16 +11,43 @@ use std::{
     str,
 };
 
-#[derive(PartialEq, Eq, Clone, Serialize)]
-#[serde(untagged)]
-pub enum Bytes {
-    Str(Box<str>),
-    Raw(Box<[u8]>),
+/// Format a [`Bytes`] literal was written in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum BytesFormat {
+    /// A quoted string like `"abc"`, possibly with escapes like `"\xff"`.
+    Quoted,
+    /// A sequence of bytes like `61:62:63`.
+    Raw,
+    /// A raw string like `r"a\b"` or `r#"a"b"#` without escapes, along with
+    /// the number of `#` symbols around it.
+    RawString(u8),
+}
+
+/// A byte string literal.
+#[derive(PartialEq, Eq, Clone)]
+pub struct Bytes {
+    data: Box<[u8]>,
+    format: BytesFormat,
+}
+
+impl Bytes {
+    /// Creates a literal with the given format.
+    pub fn new(data: impl Into<Box<[u8]>>, format: BytesFormat) -> Self {
+        Bytes {
+            data: data.into(),
+            format,
+        }
+    }
+
+    /// Format the literal was written in.
+    pub fn format(&self) -> BytesFormat {
+        self.format
+    }
 }
 
 // We need custom `Hash` consistent with `Borrow` invariants.
 // We can get away with `Eq` invariant though because we do want
-// `Bytes == Bytes` to check enum tags but `Bytes == &[u8]` to ignore them, and
+// `Bytes == Bytes` to check formats but `Bytes == &[u8]` to ignore them, and
 // consistency of the latter is all that matters for `Borrow` consumers.
 #[allow(clippy::derive_hash_xor_eq)]
 impl Hash for Bytes {
@@ -31,31 +58,48 @@ impl Hash for Bytes {
 
 impl From<Vec<u8>> for Bytes {
     fn from(src: Vec<u8>) -> Self {
-        Bytes::Raw(src.into_boxed_slice())
+        Bytes::new(src, BytesFormat::Raw)
     }
 }
 
 impl From<String> for Bytes {
     fn from(src: String) -> Self {
-        Bytes::Str(src.into_boxed_str())
+        Bytes::new(src.into_bytes(), BytesFormat::Quoted)
     }
 }
 
 impl From<Bytes> for Box<[u8]> {
     fn from(bytes: Bytes) -> Self {
-        match bytes {
-            Bytes::Str(s) => s.into_boxed_bytes(),
-            Bytes::Raw(b) => b,
-        }
+        bytes.data
     }
 }
 
 impl Debug for Bytes {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            Bytes::Str(s) => s.fmt(f),
-            Bytes::Raw(b) => {
-                for (i, b) in b.iter().cloned().enumerate() {
+        match self.format {
+            BytesFormat::Quoted => match str::from_utf8(&self.data) {
+                Ok(s) => s.fmt(f),
+                Err(_) => {
+                    f.write_str("\"")?;
+                    for &b in self.data.iter() {
+                        match b {
+                            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+                            b' '..=b'~' => write!(f, "{}", b as char)?,
+                            _ => write!(f, "\\x{:02x}", b)?,
+                        }
+                    }
+                    f.write_str("\"")
+                }
+            },
+            BytesFormat::RawString(hashes) => match str::from_utf8(&self.data) {
+                Ok(s) => {
+                    let hashes = "#".repeat(hashes.into());
+                    write!(f, "r{}\"{}\"{}", hashes, s, hashes)
+                }
+                Err(_) => Bytes::new(&self.data[..], BytesFormat::Quoted).fmt(f),
+            },
+            BytesFormat::Raw => {
+                for (i, b) in self.data.iter().cloned().enumerate() {
                     if i != 0 {
                         write!(f, ":")?;
                     }
@@ -67,14 +111,22 @@ impl Debug for Bytes {
     }
 }
 
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        match (self.format, str::from_utf8(&self.data)) {
+            (BytesFormat::Quoted, Ok(s)) | (BytesFormat::RawString(_), Ok(s)) => {
+                ser.serialize_str(s)
+            }
+            _ => self.data.serialize(ser),
+        }
+    }
+}
+
 impl Deref for Bytes {
     type Target = [u8];
 
     fn deref(&self) -> &[u8] {
-        match self {
-            Bytes::Str(s) => s.as_bytes(),
-            Bytes::Raw(b) => b,
-        }
+        &self.data
     }
 }
 
@@ -100,6 +152,22 @@ fn oct_byte(input: &str) -> LexResult<'_, u8> {
     fixed_byte(input, 3, 8)
 }
 
+// Lexes the `{...}` part of a `\u{...}` escape.
+fn unicode_char(input: &str) -> LexResult<'_, char> {
+    let input = expect(input, "{")?;
+    let (digits, rest) = take_while(input, "hex digit", |c| c.is_digit(16))?;
+    let rest = expect(rest, "}")?;
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| (c, rest))
+        .ok_or_else(|| (LexErrorKind::InvalidUnicodeEscape, digits))
+}
+
+fn push_char(res: &mut Vec<u8>, c: char) {
+    res.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
 lex_enum!(ByteSeparator {
     ":" => Colon,
     "-" => Dash,
@@ -108,9 +176,15 @@ lex_enum!(ByteSeparator {
 
 impl<'i> Lex<'i> for Bytes {
     fn lex(mut input: &str) -> LexResult<'_, Self> {
-        if let Ok(input) = expect(input, "\"") {
+        if input.starts_with('r') {
+            let ((s, hashes), rest) = lex_raw_string(input)?;
+            Ok((
+                Bytes::new(s.as_bytes(), BytesFormat::RawString(hashes)),
+                rest,
+            ))
+        } else if let Ok(input) = expect(input, "\"") {
             let full_input = input;
-            let mut res = String::new();
+            let mut res = Vec::new();
             let mut iter = input.chars();
             loop {
                 match iter
@@ -124,17 +198,22 @@ impl<'i> Lex<'i> for Bytes {
                             .next()
                             .ok_or_else(|| (LexErrorKind::MissingEndingQuote, full_input))?;
 
-                        res.push(match c {
-                            '"' | '\\' => c,
+                        match c {
+                            '"' | '\\' => res.push(c as u8),
                             'x' => {
                                 let (b, input) = hex_byte(iter.as_str())?;
                                 iter = input.chars();
-                                b as char
+                                res.push(b);
                             }
                             '0'..='7' => {
                                 let (b, input) = oct_byte(input)?;
                                 iter = input.chars();
-                                b as char
+                                res.push(b);
+                            }
+                            'u' => {
+                                let (c, input) = unicode_char(iter.as_str())?;
+                                iter = input.chars();
+                                push_char(&mut res, c);
                             }
                             _ => {
                                 return Err((
@@ -142,10 +221,10 @@ impl<'i> Lex<'i> for Bytes {
                                     &input[..c.len_utf8()],
                                 ));
                             }
-                        });
+                        }
                     }
-                    '"' => return Ok((res.into(), iter.as_str())),
-                    c => res.push(c),
+                    '"' => return Ok((Bytes::new(res, BytesFormat::Quoted), iter.as_str())),
+                    c => push_char(&mut res, c),
                 };
             }
         } else {
@@ -179,6 +258,34 @@ fn test() {
         Bytes::from("s\\t\"r\n\0t".to_owned())
     );
 
+    assert_ok!(
+        Bytes::lex(r#""\xff\x00\377""#),
+        Bytes::new(vec![0xFF, 0x00, 0xFF], BytesFormat::Quoted)
+    );
+
+    assert_ok!(
+        Bytes::lex(r#""\u{41}\u{1F600}\u{e9}""#),
+        Bytes::from("A😀é".to_owned())
+    );
+
+    assert_err!(
+        Bytes::lex(r#""\u{D800}""#),
+        LexErrorKind::InvalidUnicodeEscape,
+        "D800"
+    );
+
+    assert_err!(
+        Bytes::lex(r#""\u{1234567}""#),
+        LexErrorKind::InvalidUnicodeEscape,
+        "1234567"
+    );
+
+    assert_err!(
+        Bytes::lex(r#""\u41""#),
+        LexErrorKind::ExpectedLiteral("{"),
+        "41\""
+    );
+
     assert_err!(
         Bytes::lex("01:4x;"),
         LexErrorKind::ParseInt {
@@ -190,6 +297,29 @@ fn test() {
 
     assert_ok!(Bytes::lex("01;"), Bytes::from(vec![0x01]), ";");
 
+    assert_ok!(
+        Bytes::lex(r#"r"a\b\x00";"#),
+        Bytes::new(&b"a\\b\\x00"[..], BytesFormat::RawString(0)),
+        ";"
+    );
+
+    assert_ok!(
+        Bytes::lex(r###"r##"a"#b"##"###),
+        Bytes::new(&b"a\"#b"[..], BytesFormat::RawString(2))
+    );
+
+    assert_err!(
+        Bytes::lex(r###"r#"a"b"###),
+        LexErrorKind::MissingEndingQuote,
+        "a\"b"
+    );
+
+    assert_err!(
+        Bytes::lex("r'a'"),
+        LexErrorKind::ExpectedLiteral("\""),
+        "'a'"
+    );
+
     assert_ok!(Bytes::lex("01:2f-34"), Bytes::from(vec![0x01, 0x2F, 0x34]));
 
     assert_err!(Bytes::lex("\"1"), LexErrorKind::MissingEndingQuote, "1");
@@ -233,3 +363,29 @@ fn test() {
         "3😢"
     );
 }
+
+#[test]
+fn test_format() {
+    let quoted = Bytes::lex(r#""a\xffb""#).unwrap().0;
+    assert_eq!(quoted.format(), BytesFormat::Quoted);
+    assert_eq!(format!("{:?}", quoted), r#""a\xffb""#);
+    assert_json!(quoted, [0x61, 0xff, 0x62]);
+
+    let quoted = Bytes::lex(r#""\x61b""#).unwrap().0;
+    assert_eq!(format!("{:?}", quoted), r#""ab""#);
+    assert_json!(quoted, "ab");
+
+    let raw = Bytes::lex("61:62").unwrap().0;
+    assert_eq!(raw.format(), BytesFormat::Raw);
+    assert_eq!(format!("{:?}", raw), "61:62");
+    assert_json!(raw, [0x61, 0x62]);
+
+    let raw_string = Bytes::lex(r##"r#"a"\b"#"##).unwrap().0;
+    assert_eq!(raw_string.format(), BytesFormat::RawString(1));
+    assert_eq!(format!("{:?}", raw_string), r##"r#"a"\b"#"##);
+    assert_json!(raw_string, r#"a"\b"#);
+
+    // formats are distinguished, but not when compared to plain bytes
+    assert_ne!(quoted, raw);
+    assert_eq!(&quoted as &[u8], &raw as &[u8]);
+}
@@ -0,0 +1,119 @@
+use crate::{
+    lex::{expect, span, take_while, Lex, LexErrorKind, LexResult},
+    strict_partial_ord::StrictPartialOrd,
+};
+use std::{ops::RangeInclusive, time::Duration};
+
+// Longer suffixes must go first so that `ms` is not lexed as `m` + `s`.
+const UNITS: &[(&str, u64)] = &[
+    ("ns", 1),
+    ("us", 1_000),
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("m", 60 * 1_000_000_000),
+    ("h", 60 * 60 * 1_000_000_000),
+    ("d", 24 * 60 * 60 * 1_000_000_000),
+];
+
+fn lex_component(input: &str) -> LexResult<'_, u64> {
+    let (digits, rest) = take_while(input, "digit", |c| c.is_ascii_digit())?;
+    let value = digits
+        .parse::<u64>()
+        .map_err(|err| (LexErrorKind::ParseInt { err, radix: 10 }, digits))?;
+    let (unit, rest) = match UNITS.iter().find(|(unit, _)| rest.starts_with(unit)) {
+        Some(&(unit, multiplier)) => (multiplier, &rest[unit.len()..]),
+        None => return Err((LexErrorKind::ExpectedName("duration unit"), rest)),
+    };
+    match value.checked_mul(unit) {
+        Some(nanos) => Ok((nanos, rest)),
+        None => Err((LexErrorKind::DurationOverflow, span(input, rest))),
+    }
+}
+
+impl<'i> Lex<'i> for Duration {
+    fn lex(input: &str) -> LexResult<'_, Self> {
+        let initial_input = input;
+        let (mut nanos, mut input) = lex_component(input)?;
+        while input.starts_with(|c: char| c.is_ascii_digit()) {
+            let (component, rest) = lex_component(input)?;
+            input = rest;
+            nanos = nanos
+                .checked_add(component)
+                .ok_or_else(|| (LexErrorKind::DurationOverflow, span(initial_input, input)))?;
+        }
+        Ok((Duration::from_nanos(nanos), input))
+    }
+}
+
+impl<'i> Lex<'i> for RangeInclusive<Duration> {
+    fn lex(input: &str) -> LexResult<'_, Self> {
+        let initial_input = input;
+        let (first, input) = Duration::lex(input)?;
+        let (last, input) = if let Ok(input) = expect(input, "..") {
+            Duration::lex(input)?
+        } else {
+            (first, input)
+        };
+        if last < first {
+            return Err((
+                LexErrorKind::IncompatibleRangeBounds,
+                span(initial_input, input),
+            ));
+        }
+        Ok((first..=last, input))
+    }
+}
+
+impl StrictPartialOrd for Duration {}
+
+#[test]
+fn test() {
+    use std::str::FromStr;
+
+    assert_ok!(Duration::lex("5s"), Duration::from_secs(5));
+    assert_ok!(Duration::lex("200ms;"), Duration::from_millis(200), ";");
+    assert_ok!(Duration::lex("1h30m"), Duration::from_secs(90 * 60));
+    assert_ok!(
+        Duration::lex("1d2h3m4s5ms6us7ns"),
+        Duration::new(93_784, 5_006_007)
+    );
+    assert_ok!(Duration::lex("0ns"), Duration::from_nanos(0));
+    assert_ok!(Duration::lex("10s 5m"), Duration::from_secs(10), " 5m");
+    assert_err!(
+        Duration::lex("10"),
+        LexErrorKind::ExpectedName("duration unit"),
+        ""
+    );
+    assert_err!(
+        Duration::lex("10x"),
+        LexErrorKind::ExpectedName("duration unit"),
+        "x"
+    );
+    assert_err!(Duration::lex("s"), LexErrorKind::ExpectedName("digit"), "s");
+    assert_err!(
+        Duration::lex("99999999999999999999s"),
+        LexErrorKind::ParseInt {
+            err: u64::from_str("99999999999999999999").unwrap_err(),
+            radix: 10
+        },
+        "99999999999999999999"
+    );
+    assert_err!(
+        Duration::lex("999999999999d"),
+        LexErrorKind::DurationOverflow,
+        "999999999999d"
+    );
+    assert_ok!(
+        RangeInclusive::lex("1s..2m"),
+        Duration::from_secs(1)..=Duration::from_secs(120)
+    );
+    assert_ok!(
+        RangeInclusive::lex("5ms"),
+        Duration::from_millis(5)..=Duration::from_millis(5)
+    );
+    assert_err!(
+        <RangeInclusive<Duration>>::lex("1m..1s"),
+        LexErrorKind::IncompatibleRangeBounds,
+        "1m..1s"
+    );
+}
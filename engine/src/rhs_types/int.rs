@@ -1,28 +1,284 @@
 use crate::{
-    lex::{expect, span, take_while, Lex, LexErrorKind, LexResult},
+    int_formatting::{IntLiteralValue, INT_LITERAL_TOKEN},
+    lex::{expect, span, take_while, Lex, LexErrorKind, LexResult, LexState, LexWith},
+    range_set::{find_overlapping_ranges, subtract_ranges},
     strict_partial_ord::StrictPartialOrd,
 };
-use std::ops::RangeInclusive;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug, Display, Formatter},
+    ops::RangeInclusive,
+};
+
+/// Radix of an integer literal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntRadix {
+    /// A decimal literal like `42`.
+    #[default]
+    Decimal,
+    /// A hexadecimal literal like `0x2a`.
+    Hexadecimal,
+    /// An octal literal like `052`.
+    Octal,
+    /// A binary literal like `0b101010`.
+    Binary,
+}
+
+/// Original formatting of an integer literal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IntFormat {
+    /// Radix the literal was written in.
+    pub radix: IntRadix,
+    /// Minimal number of digits (excluding any prefix and `_` separators) to
+    /// preserve leading zeros with.
+    pub width: usize,
+    /// Whether hexadecimal digits were written in upper case, like `0x1F`.
+    pub uppercase: bool,
+}
+
+/// Defines how integer literals are represented when serializing an AST.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntFormatting {
+    /// Plain numbers (the default).
+    #[default]
+    Numeric,
+    /// Strings with the radix, leading zeros and case of hexadecimal digits
+    /// as originally written.
+    Preserve,
+    /// Strings normalized to the given radix.
+    Normalize(IntRadix),
+}
+
+/// Defines what happens to integer literals that don't fit into an `Int`,
+/// like `0xffffffffffffffff`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IntOverflow {
+    /// Such literals are rejected (the default).
+    #[default]
+    Error,
+    /// Such literals are clamped to the closest value that fits, and a
+    /// warning is reported.
+    Saturate,
+}
+
+/// Options of lexing integer literals, set by the scheme being parsed with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IntLexOptions {
+    /// Whether literals with leading zeros are decimal rather than octal.
+    pub decimal_leading_zeros: bool,
+    /// What happens to literals that don't fit into an `Int`.
+    pub overflow: IntOverflow,
+}
+
+/// An integer literal along with its original formatting.
+///
+/// Literals are equal if they have the same value, regardless of how they
+/// were written. Use [`IntLiteral::same_format`] to compare the formatting.
+#[derive(Clone, Copy)]
+pub struct IntLiteral {
+    value: i32,
+    format: IntFormat,
+}
+
+impl IntLiteral {
+    /// Creates a literal with the given formatting.
+    pub fn new(value: i32, format: IntFormat) -> Self {
+        IntLiteral { value, format }
+    }
+
+    /// Checks whether two literals are written the same way, in addition to
+    /// having the same value.
+    pub fn same_format(&self, other: &IntLiteral) -> bool {
+        self.value == other.value && self.format == other.format
+    }
+
+    /// Numeric value of the literal.
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    /// Original formatting of the literal.
+    pub fn format(&self) -> IntFormat {
+        self.format
+    }
+
+    pub(crate) fn fmt_with(&self, f: &mut Formatter<'_>, format: IntFormat) -> fmt::Result {
+        let IntFormat {
+            radix,
+            width,
+            uppercase,
+        } = format;
+        // Widen to avoid overflow on `abs()` of `i32::MIN`.
+        let value = i64::from(self.value);
+        if value < 0 {
+            f.write_str("-")?;
+        }
+        let value = value.abs();
+        match radix {
+            IntRadix::Decimal => write!(f, "{:01$}", value, width),
+            IntRadix::Hexadecimal if uppercase => write!(f, "0x{:01$X}", value, width),
+            IntRadix::Hexadecimal => write!(f, "0x{:01$x}", value, width),
+            IntRadix::Octal => write!(f, "0{:01$o}", value, width),
+            IntRadix::Binary => write!(f, "0b{:01$b}", value, width),
+        }
+    }
+}
+
+/// Returns indices of integer ranges that overlap other ones, like `80` in
+/// `{ 1..1024 80 }`.
+pub(crate) fn find_overlapping_int_ranges(ranges: &[RangeInclusive<IntLiteral>]) -> Vec<usize> {
+    find_overlapping_ranges(
+        &ranges
+            .iter()
+            .map(|range| range.start().value()..=range.end().value())
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Removes excluded integers from a list of ranges, like in
+/// `{ 1..1024 except 22 80 }`.
+///
+/// Bounds of the remaining ranges keep the formatting of the first range.
+pub(crate) fn exclude_int_ranges(
+    ranges: Vec<RangeInclusive<IntLiteral>>,
+    excluded: Vec<RangeInclusive<IntLiteral>>,
+) -> Vec<RangeInclusive<IntLiteral>> {
+    let format = ranges
+        .first()
+        .map_or_else(IntFormat::default, |range| range.start().format());
+    let values = |ranges: &[RangeInclusive<IntLiteral>]| {
+        ranges
+            .iter()
+            .map(|range| range.start().value()..=range.end().value())
+            .collect()
+    };
+
+    subtract_ranges(values(&ranges), values(&excluded))
+        .into_iter()
+        .map(|range| {
+            IntLiteral::new(*range.start(), format)..=IntLiteral::new(*range.end(), format)
+        })
+        .collect()
+}
+
+impl From<i32> for IntLiteral {
+    fn from(value: i32) -> Self {
+        IntLiteral::new(value, IntFormat::default())
+    }
+}
+
+impl PartialEq for IntLiteral {
+    fn eq(&self, other: &IntLiteral) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for IntLiteral {}
+
+impl Debug for IntLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.value, f)
+    }
+}
+
+impl Display for IntLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, self.format)
+    }
+}
+
+impl Serialize for IntLiteral {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_newtype_struct(INT_LITERAL_TOKEN, &IntLiteralValue(*self))
+    }
+}
 
 fn lex_digits(input: &str) -> LexResult<'_, &str> {
-    // Lex any supported digits (up to radix 16) for better error locations.
-    take_while(input, "digit", |c| c.is_digit(16))
+    // Lex any supported digits (up to radix 16) for better error locations,
+    // along with `_` separators after the first digit, like in `1_000_000`.
+    match input.chars().next() {
+        Some(c) if c.is_ascii_hexdigit() => {
+            take_while(input, "digit", |c| c.is_ascii_hexdigit() || c == '_')
+        }
+        _ => Err((LexErrorKind::ExpectedName("digit"), input)),
+    }
+}
+
+// Number of digits, excluding `_` separators.
+fn digit_count(digits: &str) -> usize {
+    digits.chars().filter(|&c| c != '_').count()
 }
 
-fn parse_number<'i>((input, rest): (&'i str, &'i str), radix: u32) -> LexResult<'_, i32> {
-    match i32::from_str_radix(input, radix) {
+fn parse_number<'i>(
+    (input, rest): (&'i str, &'i str),
+    radix: u32,
+    state: &LexState<'i>,
+) -> LexResult<'i, i32> {
+    let digits = input.replace('_', "");
+    match i32::from_str_radix(&digits, radix) {
         Ok(res) => Ok((res, rest)),
-        Err(err) => Err((LexErrorKind::ParseInt { err, radix }, input)),
+        Err(err) => {
+            // Digits valid for the radix can only fail to parse by overflowing.
+            let unsigned = digits.trim_start_matches('-');
+            let overflow = unsigned.chars().all(|c| c.is_digit(radix));
+            match state.int_options().overflow {
+                IntOverflow::Saturate if overflow => {
+                    let value = if unsigned.len() < digits.len() {
+                        i32::min_value()
+                    } else {
+                        i32::max_value()
+                    };
+                    state.warn(LexErrorKind::IntOverflow { value }, input);
+                    Ok((value, rest))
+                }
+                _ => Err((LexErrorKind::ParseInt { err, radix }, input)),
+            }
+        }
     }
 }
 
-impl<'i> Lex<'i> for i32 {
-    fn lex(input: &str) -> LexResult<'_, Self> {
+impl<'i, 'a> LexWith<'i, &'a LexState<'i>> for IntLiteral {
+    fn lex_with(input: &'i str, state: &'a LexState<'i>) -> LexResult<'i, Self> {
         if let Ok(input) = expect(input, "0x") {
-            parse_number(lex_digits(input)?, 16)
+            let (digits, rest) = lex_digits(input)?;
+            let (value, rest) = parse_number((digits, rest), 16, state)?;
+            let format = IntFormat {
+                radix: IntRadix::Hexadecimal,
+                width: digit_count(digits),
+                uppercase: digits.chars().any(|c| c.is_ascii_uppercase()),
+            };
+            Ok((IntLiteral::new(value, format), rest))
+        } else if let Ok(input) = expect(input, "0b") {
+            let (digits, rest) = lex_digits(input)?;
+            let (value, rest) = parse_number((digits, rest), 2, state)?;
+            let format = IntFormat {
+                radix: IntRadix::Binary,
+                width: digit_count(digits),
+                ..IntFormat::default()
+            };
+            Ok((IntLiteral::new(value, format), rest))
         } else if input.starts_with('0') {
             // not using `expect` because we want to include `0` too
-            parse_number(lex_digits(input)?, 8)
+            let (digits, rest) = lex_digits(input)?;
+            let decimal = state.int_options().decimal_leading_zeros;
+            let radix = if decimal { 10 } else { 8 };
+            let (value, rest) = parse_number((digits, rest), radix, state)?;
+            let format = match digit_count(digits) {
+                // a lone `0` is indistinguishable from a decimal zero
+                1 => IntFormat::default(),
+                width if decimal => IntFormat {
+                    radix: IntRadix::Decimal,
+                    width,
+                    ..IntFormat::default()
+                },
+                width => IntFormat {
+                    radix: IntRadix::Octal,
+                    width: width - 1,
+                    ..IntFormat::default()
+                },
+            };
+            Ok((IntLiteral::new(value, format), rest))
         } else {
             let without_neg = match expect(input, "-") {
                 Ok(input) => input,
@@ -31,21 +287,29 @@ impl<'i> Lex<'i> for i32 {
 
             let (_, rest) = lex_digits(without_neg)?;
 
-            parse_number((span(input, rest), rest), 10)
+            let (value, rest) = parse_number((span(input, rest), rest), 10, state)?;
+            Ok((value.into(), rest))
         }
     }
 }
 
-impl<'i> Lex<'i> for RangeInclusive<i32> {
+impl<'i> Lex<'i> for i32 {
     fn lex(input: &str) -> LexResult<'_, Self> {
+        let (literal, rest) = IntLiteral::lex_with(input, &LexState::default())?;
+        Ok((literal.value, rest))
+    }
+}
+
+impl<'i, 'a> LexWith<'i, &'a LexState<'i>> for RangeInclusive<IntLiteral> {
+    fn lex_with(input: &'i str, state: &'a LexState<'i>) -> LexResult<'i, Self> {
         let initial_input = input;
-        let (first, input) = i32::lex(input)?;
+        let (first, input) = IntLiteral::lex_with(input, state)?;
         let (last, input) = if let Ok(input) = expect(input, "..") {
-            i32::lex(input)?
+            IntLiteral::lex_with(input, state)?
         } else {
             (first, input)
         };
-        if last < first {
+        if last.value < first.value {
             return Err((
                 LexErrorKind::IncompatibleRangeBounds,
                 span(initial_input, input),
@@ -57,10 +321,42 @@ impl<'i> Lex<'i> for RangeInclusive<i32> {
 
 impl StrictPartialOrd for i32 {}
 
+impl PartialEq<IntLiteral> for i32 {
+    fn eq(&self, other: &IntLiteral) -> bool {
+        *self == other.value
+    }
+}
+
+impl PartialOrd<IntLiteral> for i32 {
+    fn partial_cmp(&self, other: &IntLiteral) -> Option<Ordering> {
+        self.partial_cmp(&other.value)
+    }
+}
+
+impl StrictPartialOrd<IntLiteral> for i32 {}
+
 #[test]
 fn test() {
     use std::str::FromStr;
 
+    fn lex_with_options(input: &str, options: IntLexOptions) -> LexResult<'_, i32> {
+        let (literal, rest) = IntLiteral::lex_with(input, &LexState::new(options))?;
+        Ok((literal.value(), rest))
+    }
+
+    fn lex_range(input: &str) -> LexResult<'_, RangeInclusive<IntLiteral>> {
+        RangeInclusive::lex_with(input, &LexState::default())
+    }
+
+    let decimal_leading_zeros = IntLexOptions {
+        decimal_leading_zeros: true,
+        ..Default::default()
+    };
+    let saturate = IntLexOptions {
+        overflow: IntOverflow::Saturate,
+        ..Default::default()
+    };
+
     assert_ok!(i32::lex("0"), 0i32, "");
     assert_ok!(i32::lex("0-"), 0i32, "-");
     assert_ok!(i32::lex("0x1f5+"), 501i32, "+");
@@ -68,6 +364,26 @@ fn test() {
     assert_ok!(i32::lex("78!"), 78i32, "!");
     assert_ok!(i32::lex("0xefg"), 239i32, "g");
     assert_ok!(i32::lex("-12-"), -12i32, "-");
+    assert_ok!(i32::lex("0b1010 "), 10i32, " ");
+    assert_ok!(i32::lex("1_000_000;"), 1_000_000i32, ";");
+    assert_ok!(i32::lex("-1_000"), -1000i32, "");
+    assert_ok!(i32::lex("0x7fff_ffff_"), i32::max_value(), "");
+    assert_ok!(i32::lex("0b1111_0000"), 240i32, "");
+    assert_err!(i32::lex("_1"), LexErrorKind::ExpectedName("digit"), "_1");
+    assert_err!(
+        i32::lex("0b102"),
+        LexErrorKind::ParseInt {
+            err: i32::from_str_radix("102", 2).unwrap_err(),
+            radix: 2
+        },
+        "102"
+    );
+    assert_ok!(
+        lex_with_options("0123;", decimal_leading_zeros),
+        123i32,
+        ";"
+    );
+    assert_ok!(lex_with_options("0x10", decimal_leading_zeros), 16i32, "");
     assert_err!(
         i32::lex("-2147483649!"),
         LexErrorKind::ParseInt {
@@ -92,13 +408,166 @@ fn test() {
         },
         "10fe"
     );
-    assert_ok!(RangeInclusive::lex("78!"), 78i32..=78i32, "!");
-    assert_ok!(RangeInclusive::lex("0..10"), 0i32..=10i32);
-    assert_ok!(RangeInclusive::lex("0123..0xefg"), 83i32..=239i32, "g");
-    assert_ok!(RangeInclusive::lex("-20..-10"), -20i32..=-10i32);
+    assert_ok!(
+        lex_with_options("0xffff_ffff_ffff_ffff;", saturate),
+        i32::max_value(),
+        ";"
+    );
+    assert_ok!(
+        lex_with_options("-2147483649", saturate),
+        i32::min_value(),
+        ""
+    );
+    assert_err!(
+        lex_with_options("10fex", saturate),
+        LexErrorKind::ParseInt {
+            err: i32::from_str("10fe").unwrap_err(),
+            radix: 10
+        },
+        "10fe"
+    );
+    assert_ok!(
+        lex_range("78!"),
+        IntLiteral::from(78)..=IntLiteral::from(78),
+        "!"
+    );
+    assert_ok!(
+        lex_range("0..10"),
+        IntLiteral::from(0)..=IntLiteral::from(10)
+    );
+    let range = assert_ok!(
+        lex_range("0123..0xefg"),
+        IntLiteral::from(83)..=IntLiteral::from(239),
+        "g"
+    );
+    assert!(range.start().same_format(&IntLiteral::new(
+        83,
+        IntFormat {
+            radix: IntRadix::Octal,
+            width: 3,
+            ..IntFormat::default()
+        }
+    )));
+    assert!(range.end().same_format(&IntLiteral::new(
+        239,
+        IntFormat {
+            radix: IntRadix::Hexadecimal,
+            width: 2,
+            ..IntFormat::default()
+        }
+    )));
+    assert_ok!(
+        lex_range("-20..-10"),
+        IntLiteral::from(-20)..=IntLiteral::from(-10)
+    );
     assert_err!(
-        <RangeInclusive<i32>>::lex("10..0"),
+        lex_range("10..0"),
         LexErrorKind::IncompatibleRangeBounds,
         "10..0"
     );
 }
+
+#[test]
+fn test_format() {
+    use crate::int_formatting::IntFormattingSerializer;
+
+    fn preserved_with_options(input: &str, options: IntLexOptions) -> String {
+        let state = LexState::new(options);
+        IntLiteral::lex_with(input, &state).unwrap().0.to_string()
+    }
+
+    fn preserved(input: &str) -> String {
+        preserved_with_options(input, IntLexOptions::default())
+    }
+
+    fn lex(input: &str) -> IntLiteral {
+        IntLiteral::lex_with(input, &LexState::default()).unwrap().0
+    }
+
+    let decimal_leading_zeros = IntLexOptions {
+        decimal_leading_zeros: true,
+        ..Default::default()
+    };
+
+    assert_eq!(preserved("0"), "0");
+    assert_eq!(preserved("42"), "42");
+    assert_eq!(preserved("-42"), "-42");
+    assert_eq!(preserved("0x2a"), "0x2a");
+    assert_eq!(preserved("0x2A"), "0x2A");
+    assert_eq!(preserved("0x1F_FF"), "0x1FFF");
+    assert_eq!(preserved("0x002a"), "0x002a");
+    assert_eq!(preserved("052"), "052");
+    assert_eq!(preserved("00052"), "00052");
+    assert_eq!(preserved("0b101010"), "0b101010");
+    assert_eq!(preserved("0b0000_1111"), "0b00001111");
+    assert_eq!(preserved("1_000_000"), "1000000");
+    assert_eq!(
+        preserved_with_options("0080", decimal_leading_zeros),
+        "0080"
+    );
+
+    let serialized = |literal: IntLiteral, formatting| {
+        let mut out = Vec::new();
+        literal
+            .serialize(IntFormattingSerializer::new(
+                &mut serde_json::Serializer::new(&mut out),
+                formatting,
+            ))
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let literal = lex("0x002a");
+
+    assert_eq!(serde_json::to_string(&literal).unwrap(), "42");
+    assert_eq!(serialized(literal, IntFormatting::Preserve), r#""0x002a""#);
+    assert_eq!(
+        serialized(literal, IntFormatting::Normalize(IntRadix::Decimal)),
+        r#""42""#
+    );
+    assert_eq!(
+        serialized(literal, IntFormatting::Normalize(IntRadix::Octal)),
+        r#""052""#
+    );
+
+    // equality ignores formatting
+    assert_eq!(literal, IntLiteral::from(42));
+    assert!(!literal.same_format(&IntLiteral::from(42)));
+    assert!(literal.same_format(&lex("0x002a")));
+
+    let literal = lex("0x1F");
+
+    assert_eq!(serialized(literal, IntFormatting::Preserve), r#""0x1F""#);
+    assert_eq!(
+        serialized(literal, IntFormatting::Normalize(IntRadix::Hexadecimal)),
+        r#""0x1f""#
+    );
+    assert_eq!(
+        serialized(literal, IntFormatting::Normalize(IntRadix::Binary)),
+        r#""0b11111""#
+    );
+
+    let literal = lex("0x00000000002a");
+
+    assert_eq!(serde_json::to_string(&literal).unwrap(), "42");
+    assert_eq!(
+        serialized(literal, IntFormatting::Preserve),
+        r#""0x00000000002a""#
+    );
+
+    // anything else serialized under the same name is rejected
+    struct Impostor;
+
+    impl Serialize for Impostor {
+        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+            ser.serialize_newtype_struct(INT_LITERAL_TOKEN, "0x2a")
+        }
+    }
+
+    assert!(Impostor
+        .serialize(IntFormattingSerializer::new(
+            &mut serde_json::Serializer::new(Vec::new()),
+            IntFormatting::Preserve,
+        ))
+        .is_err());
+}
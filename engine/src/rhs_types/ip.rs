@@ -1,5 +1,6 @@
 use crate::{
     lex::{take_while, Lex, LexError, LexErrorKind, LexResult},
+    range_set::{find_overlapping_ranges, subtract_ranges},
     strict_partial_ord::StrictPartialOrd,
 };
 use cidr::{Cidr, IpCidr, Ipv4Cidr, Ipv6Cidr, NetworkParseError};
@@ -34,17 +35,23 @@ impl<'i> Lex<'i> for IpAddr {
     }
 }
 
+/// An inclusive range of IP addresses of the same family.
 #[derive(PartialEq, Eq, Clone, Serialize, Debug)]
 #[serde(untagged)]
 pub enum ExplicitIpRange {
+    /// A range of IPv4 addresses.
     V4(RangeInclusive<Ipv4Addr>),
+    /// A range of IPv6 addresses.
     V6(RangeInclusive<Ipv6Addr>),
 }
 
+/// An IP range literal.
 #[derive(PartialEq, Eq, Clone, Serialize, Debug)]
 #[serde(untagged)]
 pub enum IpRange {
+    /// A single address or a range like `10.0.0.1..10.0.0.5`.
     Explicit(ExplicitIpRange),
+    /// A network in CIDR notation like `10.0.0.0/8`.
     Cidr(IpCidr),
 }
 
@@ -123,6 +130,236 @@ impl From<IpRange> for ExplicitIpRange {
     }
 }
 
+// Merges overlapping and adjacent ranges of addresses of the same family
+// represented as integers.
+fn merge_ranges(mut ranges: Vec<RangeInclusive<u128>>) -> Vec<RangeInclusive<u128>> {
+    ranges.sort_unstable_by_key(|range| *range.start());
+    ranges.dedup_by(|b, a| {
+        if *b.start() <= a.end().saturating_add(1) {
+            if b.end() > a.end() {
+                *a = *a.start()..=*b.end();
+            }
+            true
+        } else {
+            false
+        }
+    });
+    ranges
+}
+
+// Represents a range as a network if it's one, or as an explicit range
+// otherwise.
+fn to_ip_range(range: RangeInclusive<u128>, bits: u32, to_addr: fn(u128) -> IpAddr) -> IpRange {
+    let (start, end) = range.into_inner();
+    let mask = end - start;
+
+    if mask & mask.wrapping_add(1) == 0 && start & mask == 0 {
+        let len = bits - (128 - mask.leading_zeros());
+        IpRange::Cidr(IpCidr::new(to_addr(start), len as u8).unwrap())
+    } else {
+        IpRange::Explicit(match (to_addr(start), to_addr(end)) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => ExplicitIpRange::V4(start..=end),
+            (IpAddr::V6(start), IpAddr::V6(end)) => ExplicitIpRange::V6(start..=end),
+            _ => unreachable!(),
+        })
+    }
+}
+
+/// Converts a list of IP ranges into a canonical minimal form.
+///
+/// Overlapping and adjacent ranges are merged, and the result is sorted
+/// with IPv4 ranges first. Ranges that are networks are represented in CIDR
+/// notation.
+pub(crate) fn canonicalize_ip_ranges(ranges: Vec<IpRange>) -> Vec<IpRange> {
+    let (v4, v6) = split_ip_ranges(ranges);
+    from_int_ranges(merge_ranges(v4), merge_ranges(v6))
+}
+
+/// Removes excluded addresses from a list of IP ranges, like in
+/// `{ 10.0.0.0/8 except 10.1.2.0/24 }`, and returns the remaining ranges in
+/// the canonical form.
+pub(crate) fn exclude_ip_ranges(ranges: Vec<IpRange>, excluded: Vec<IpRange>) -> Vec<IpRange> {
+    let (v4, v6) = split_ip_ranges(ranges);
+    let (excluded_v4, excluded_v6) = split_ip_ranges(excluded);
+    from_int_ranges(
+        merge_ranges(subtract_ranges(v4, excluded_v4)),
+        merge_ranges(subtract_ranges(v6, excluded_v6)),
+    )
+}
+
+/// Returns indices of IP ranges that overlap other ones, like
+/// `10.1.0.0/16` in `{ 10.0.0.0/8 10.1.0.0/16 }`.
+pub(crate) fn find_overlapping_ip_ranges(ranges: &[IpRange]) -> Vec<usize> {
+    // IPv4 addresses are ordered before all IPv6 ones, so that ranges of
+    // different families never overlap.
+    let bounds = ranges
+        .iter()
+        .map(|range| match range.clone().into() {
+            ExplicitIpRange::V4(range) => {
+                (false, u128::from(u32::from(*range.start())))
+                    ..=(false, u128::from(u32::from(*range.end())))
+            }
+            ExplicitIpRange::V6(range) => {
+                (true, u128::from(*range.start()))..=(true, u128::from(*range.end()))
+            }
+        })
+        .collect::<Vec<_>>();
+    find_overlapping_ranges(&bounds)
+}
+
+// Splits IP ranges into IPv4 and IPv6 ranges represented as integers.
+fn split_ip_ranges(ranges: Vec<IpRange>) -> (Vec<RangeInclusive<u128>>, Vec<RangeInclusive<u128>>) {
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+
+    for range in ranges {
+        match range.into() {
+            ExplicitIpRange::V4(range) => {
+                v4.push(u128::from(u32::from(*range.start()))..=u128::from(u32::from(*range.end())))
+            }
+            ExplicitIpRange::V6(range) => {
+                v6.push(u128::from(*range.start())..=u128::from(*range.end()))
+            }
+        }
+    }
+
+    (v4, v6)
+}
+
+// Converts sorted and merged IPv4 and IPv6 ranges represented as integers
+// back into IP ranges.
+fn from_int_ranges(v4: Vec<RangeInclusive<u128>>, v6: Vec<RangeInclusive<u128>>) -> Vec<IpRange> {
+    v4.into_iter()
+        .map(|range| to_ip_range(range, 32, |addr| Ipv4Addr::from(addr as u32).into()))
+        .chain(
+            v6.into_iter()
+                .map(|range| to_ip_range(range, 128, |addr| Ipv6Addr::from(addr).into())),
+        )
+        .collect()
+}
+
+// Mask of the given number of lowest bits.
+fn host_mask(host_bits: u32) -> u128 {
+    if host_bits == 0 {
+        0
+    } else {
+        u128::max_value() >> (128 - host_bits)
+    }
+}
+
+// Splits a range of addresses represented as integers into the minimal list
+// of networks covering it.
+fn to_cidrs(
+    range: RangeInclusive<u128>,
+    bits: u32,
+    to_addr: fn(u128) -> IpAddr,
+    cidrs: &mut Vec<IpCidr>,
+) {
+    let (mut start, end) = range.into_inner();
+
+    loop {
+        // Largest network aligned at `start` that doesn't go past `end`.
+        let mut host_bits = start.trailing_zeros().min(bits);
+        while host_mask(host_bits) > end - start {
+            host_bits -= 1;
+        }
+
+        cidrs.push(IpCidr::new(to_addr(start), (bits - host_bits) as u8).unwrap());
+
+        let last = start + host_mask(host_bits);
+        if last == end {
+            break;
+        }
+        start = last + 1;
+    }
+}
+
+/// Aggregates a list of IP ranges into the minimal list of networks that
+/// covers the same addresses.
+///
+/// Duplicate, contained, overlapping and adjacent ranges are merged, so lists
+/// with many prefixes, like ones imported from geolocation databases, can be
+/// stored compactly. The result is sorted, with IPv4 networks first.
+pub fn aggregate_ip_ranges<I: IntoIterator<Item = IpRange>>(ranges: I) -> Vec<IpCidr> {
+    let mut cidrs = Vec::new();
+
+    for range in canonicalize_ip_ranges(ranges.into_iter().collect()) {
+        match range {
+            IpRange::Cidr(cidr) => cidrs.push(cidr),
+            IpRange::Explicit(ExplicitIpRange::V4(range)) => to_cidrs(
+                u128::from(u32::from(*range.start()))..=u128::from(u32::from(*range.end())),
+                32,
+                |addr| Ipv4Addr::from(addr as u32).into(),
+                &mut cidrs,
+            ),
+            IpRange::Explicit(ExplicitIpRange::V6(range)) => to_cidrs(
+                u128::from(*range.start())..=u128::from(*range.end()),
+                128,
+                |addr| Ipv6Addr::from(addr).into(),
+                &mut cidrs,
+            ),
+        }
+    }
+
+    cidrs
+}
+
+// Range of IPv4-mapped IPv6 addresses, `::ffff:0:0/96`.
+const IPV4_MAPPED: RangeInclusive<u128> = 0xffff_0000_0000..=0xffff_ffff_ffff;
+
+/// Converts an IPv4-mapped IPv6 address like `::ffff:10.0.0.1` to the IPv4
+/// address it maps, and returns any other address as is.
+pub(crate) fn unmap_ipv4(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) if IPV4_MAPPED.contains(&u128::from(v6)) => {
+            IpAddr::V4(Ipv4Addr::from(u128::from(v6) as u32))
+        }
+        _ => addr,
+    }
+}
+
+/// Replaces parts of IPv6 ranges that cover IPv4-mapped addresses with the
+/// corresponding IPv4 ranges.
+pub(crate) fn unmap_ipv4_ranges(ranges: Vec<IpRange>) -> Vec<IpRange> {
+    let mut unmapped = Vec::with_capacity(ranges.len());
+
+    for range in ranges {
+        let (start, end) = match ExplicitIpRange::from(range.clone()) {
+            ExplicitIpRange::V6(range) => (u128::from(*range.start()), u128::from(*range.end())),
+            ExplicitIpRange::V4(_) => {
+                unmapped.push(range);
+                continue;
+            }
+        };
+
+        let (mapped_start, mapped_end) = IPV4_MAPPED.into_inner();
+
+        if end < mapped_start || start > mapped_end {
+            unmapped.push(range);
+            continue;
+        }
+
+        let v6 = |start, end| {
+            IpRange::Explicit(ExplicitIpRange::V6(
+                Ipv6Addr::from(start)..=Ipv6Addr::from(end),
+            ))
+        };
+
+        if start < mapped_start {
+            unmapped.push(v6(start, mapped_start - 1));
+        }
+        unmapped.push(IpRange::Explicit(ExplicitIpRange::V4(
+            Ipv4Addr::from(start.max(mapped_start) as u32)
+                ..=Ipv4Addr::from(end.min(mapped_end) as u32),
+        )));
+        if end > mapped_end {
+            unmapped.push(v6(mapped_end + 1, end));
+        }
+    }
+
+    unmapped
+}
+
 impl StrictPartialOrd for IpAddr {
     fn strict_partial_cmp(&self, other: &Self) -> Option<Ordering> {
         match (self, other) {
@@ -233,6 +470,186 @@ fn test_lex() {
     );
 }
 
+#[test]
+fn test_canonicalize_ip_ranges() {
+    use crate::{
+        lex::{LexState, LexWith},
+        types::{RhsValues, Type},
+    };
+
+    let lex = |input| match RhsValues::lex_with(input, (Type::Ip, &LexState::default()))
+        .unwrap()
+        .0
+    {
+        RhsValues::Ip(ranges) => canonicalize_ip_ranges(ranges),
+        _ => unreachable!(),
+    };
+
+    let explicit = |first: [u8; 4], last: [u8; 4]| {
+        IpRange::Explicit(ExplicitIpRange::V4(first.into()..=last.into()))
+    };
+
+    let cidr = |addr: IpAddr, len| IpRange::Cidr(IpCidr::new(addr, len).unwrap());
+
+    assert_eq!(
+        lex("{ ::1 10.0.1.0/24 10.0.0.0/24 10.0.0.5 ::/0 192.168.0.1..192.168.0.5 192.168.0.4..192.168.0.6 }"),
+        [
+            cidr([10, 0, 0, 0].into(), 23),
+            explicit([192, 168, 0, 1], [192, 168, 0, 6]),
+            cidr([0; 16].into(), 0),
+        ]
+    );
+
+    assert_eq!(
+        lex("{ 0.0.0.0/1 128.0.0.0/1 }"),
+        [cidr([0, 0, 0, 0].into(), 0)]
+    );
+
+    assert_eq!(
+        lex("{ 10.0.0.1 10.0.0.3 10.0.0.2 }"),
+        [explicit([10, 0, 0, 1], [10, 0, 0, 3])]
+    );
+
+    assert_eq!(
+        lex("{ 10.0.0.1 10.0.0.1 ::1 }"),
+        [
+            cidr([10, 0, 0, 1].into(), 32),
+            cidr([0, 0, 0, 0, 0, 0, 0, 1].into(), 128),
+        ]
+    );
+}
+
+#[test]
+fn test_exclude_ip_ranges() {
+    use crate::{
+        lex::{LexState, LexWith},
+        types::{RhsValues, Type},
+    };
+
+    let lex = |input| match RhsValues::lex_with(input, (Type::Ip, &LexState::default()))
+        .unwrap()
+        .0
+    {
+        RhsValues::Ip(ranges) => ranges,
+        _ => unreachable!(),
+    };
+
+    let explicit = |first: [u8; 4], last: [u8; 4]| {
+        IpRange::Explicit(ExplicitIpRange::V4(first.into()..=last.into()))
+    };
+
+    let cidr = |addr: IpAddr, len| IpRange::Cidr(IpCidr::new(addr, len).unwrap());
+
+    assert_eq!(
+        exclude_ip_ranges(
+            lex("{ 10.0.0.0/8 ::/0 192.168.0.0/24 }"),
+            lex("{ 10.1.2.0/24 ::/1 192.168.0.0/24 }")
+        ),
+        [
+            explicit([10, 0, 0, 0], [10, 1, 1, 255]),
+            explicit([10, 1, 3, 0], [10, 255, 255, 255]),
+            cidr([0x8000, 0, 0, 0, 0, 0, 0, 0].into(), 1),
+        ]
+    );
+
+    assert_eq!(
+        exclude_ip_ranges(lex("{ 10.0.0.0/24 }"), lex("{ 10.0.0.128/25 ::1 }")),
+        [cidr([10, 0, 0, 0].into(), 25)]
+    );
+}
+
+#[test]
+fn test_aggregate_ip_ranges() {
+    use crate::{
+        lex::{LexState, LexWith},
+        types::{RhsValues, Type},
+    };
+
+    let aggregate = |input| match RhsValues::lex_with(input, (Type::Ip, &LexState::default()))
+        .unwrap()
+        .0
+    {
+        RhsValues::Ip(ranges) => aggregate_ip_ranges(ranges),
+        _ => unreachable!(),
+    };
+
+    let cidr = |addr: IpAddr, len| IpCidr::new(addr, len).unwrap();
+
+    assert_eq!(
+        aggregate(
+            "{ 10.0.1.0/24 10.0.0.0/24 10.0.0.128/25 192.168.0.1..192.168.0.6 ::/1 8000::/1 }"
+        ),
+        [
+            cidr([10, 0, 0, 0].into(), 23),
+            cidr([192, 168, 0, 1].into(), 32),
+            cidr([192, 168, 0, 2].into(), 31),
+            cidr([192, 168, 0, 4].into(), 31),
+            cidr([192, 168, 0, 6].into(), 32),
+            cidr([0; 16].into(), 0),
+        ]
+    );
+
+    assert_eq!(
+        aggregate("{ ::1..ffff:ffff:ffff:ffff:ffff:ffff:ffff:fffe }").len(),
+        254
+    );
+
+    assert_eq!(aggregate("{ }"), []);
+}
+
+#[test]
+fn test_unmap_ipv4() {
+    use crate::{
+        lex::{LexState, LexWith},
+        types::{RhsValues, Type},
+    };
+
+    assert_eq!(
+        unmap_ipv4("::ffff:203.0.113.7".parse().unwrap()),
+        IpAddr::from([203, 0, 113, 7])
+    );
+    assert_eq!(
+        unmap_ipv4("::203.0.113.7".parse().unwrap()),
+        "::203.0.113.7".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        unmap_ipv4(IpAddr::from([10, 0, 0, 1])),
+        IpAddr::from([10, 0, 0, 1])
+    );
+
+    let lex = |input| match RhsValues::lex_with(input, (Type::Ip, &LexState::default()))
+        .unwrap()
+        .0
+    {
+        RhsValues::Ip(ranges) => canonicalize_ip_ranges(unmap_ipv4_ranges(ranges)),
+        _ => unreachable!(),
+    };
+
+    let cidr = |addr: IpAddr, len| IpRange::Cidr(IpCidr::new(addr, len).unwrap());
+
+    assert_eq!(
+        lex("{ ::ffff:203.0.113.0/120 ::1 10.0.0.0/8 }"),
+        [
+            cidr([10, 0, 0, 0].into(), 8),
+            cidr([203, 0, 113, 0].into(), 24),
+            cidr([0, 0, 0, 0, 0, 0, 0, 1].into(), 128),
+        ]
+    );
+
+    assert_eq!(
+        lex("{ ::/0 }"),
+        [
+            cidr([0, 0, 0, 0].into(), 0),
+            IpRange::Explicit(ExplicitIpRange::V6(
+                [0; 16].into()..=[0, 0, 0, 0, 0, 0xfffe, 0xffff, 0xffff].into()
+            )),
+            IpRange::Explicit(ExplicitIpRange::V6(
+                [0, 0, 0, 0, 1, 0, 0, 0].into()..=[0xffff; 8].into()
+            )),
+        ]
+    );
+}
+
 #[test]
 fn test_strict_partial_ord() {
     let ips = &[
@@ -0,0 +1,330 @@
+use crate::{
+    lex::{take_while, Lex, LexError, LexErrorKind, LexResult},
+    strict_partial_ord::StrictPartialOrd,
+};
+use failure::Fail;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    num::ParseIntError,
+    ops::RangeInclusive,
+};
+
+const MAC_LEN: usize = 6;
+const MAC_BITS: u8 = MAC_LEN as u8 * 8;
+
+/// An error that occurs when parsing a MAC address or a MAC prefix.
+#[derive(Debug, PartialEq, Fail)]
+pub enum MacParseError {
+    /// The address doesn't consist of six `:`-separated hex octets.
+    #[fail(display = "invalid MAC address")]
+    InvalidAddress,
+
+    /// The prefix length is not a number.
+    #[fail(display = "{}", _0)]
+    PrefixLengthParseError(#[cause] ParseIntError),
+
+    /// The prefix length is longer than 48 bits.
+    #[fail(display = "MAC prefix length {} is too long", _0)]
+    PrefixLengthTooLong(u8),
+
+    /// The address has bits set past the prefix length.
+    #[fail(display = "MAC prefix has bits set past its length")]
+    InvalidHostPart,
+}
+
+/// A 48-bit MAC address.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct MacAddr(pub [u8; MAC_LEN]);
+
+impl From<[u8; MAC_LEN]> for MacAddr {
+    fn from(octets: [u8; MAC_LEN]) -> Self {
+        MacAddr(octets)
+    }
+}
+
+impl MacAddr {
+    fn to_u64(self) -> u64 {
+        self.0
+            .iter()
+            .fold(0, |acc, &octet| (acc << 8) | u64::from(octet))
+    }
+
+    fn from_u64(value: u64) -> Self {
+        let mut octets = [0; MAC_LEN];
+        for (i, octet) in octets.iter_mut().rev().enumerate() {
+            *octet = (value >> (i * 8)) as u8;
+        }
+        MacAddr(octets)
+    }
+
+    fn parse(input: &str) -> Result<Self, MacParseError> {
+        fn parse_octets(input: &str, octets: &mut Vec<u8>) -> Result<(), MacParseError> {
+            if input.is_empty() {
+                return Ok(());
+            }
+            for octet in input.split(':') {
+                if octet.is_empty() || octet.len() > 2 {
+                    return Err(MacParseError::InvalidAddress);
+                }
+                octets.push(
+                    u8::from_str_radix(octet, 16).map_err(|_| MacParseError::InvalidAddress)?,
+                );
+            }
+            Ok(())
+        }
+
+        let mut head = Vec::with_capacity(MAC_LEN);
+        let mut tail = Vec::with_capacity(MAC_LEN);
+
+        // `::` zero-fills the missing octets, just like in IPv6 addresses.
+        let compressed = match input.find("::") {
+            Some(pos) => {
+                parse_octets(&input[..pos], &mut head)?;
+                parse_octets(&input[pos + 2..], &mut tail)?;
+                true
+            }
+            None => {
+                parse_octets(input, &mut head)?;
+                false
+            }
+        };
+
+        let len = head.len() + tail.len();
+        if len > MAC_LEN || (!compressed && len != MAC_LEN) {
+            return Err(MacParseError::InvalidAddress);
+        }
+
+        let mut octets = [0; MAC_LEN];
+        octets[..head.len()].copy_from_slice(&head);
+        octets[MAC_LEN - tail.len()..].copy_from_slice(&tail);
+        Ok(MacAddr(octets))
+    }
+}
+
+impl Display for MacAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, octet) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02x}", octet)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for MacAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Serialize for MacAddr {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MacAddr {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(de)?;
+        MacAddr::parse(&s).map_err(de::Error::custom)
+    }
+}
+
+fn match_addr_or_prefix(input: &str) -> LexResult<'_, &str> {
+    take_while(input, "MAC address character", |c| match c {
+        '0'..='9' | 'a'..='f' | 'A'..='F' | ':' | '/' => true,
+        _ => false,
+    })
+}
+
+fn parse_addr(input: &str) -> Result<MacAddr, LexError<'_>> {
+    MacAddr::parse(input).map_err(|err| (LexErrorKind::ParseMac(err), input))
+}
+
+impl<'i> Lex<'i> for MacAddr {
+    fn lex(input: &str) -> LexResult<'_, Self> {
+        let (chunk, rest) = match_addr_or_prefix(input)?;
+        parse_addr(chunk).map(|addr| (addr, rest))
+    }
+}
+
+impl StrictPartialOrd for MacAddr {}
+
+/// A MAC address prefix like `aa:bb:cc::/24`.
+///
+/// A plain address is treated as a prefix of full length.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct MacPrefix {
+    addr: MacAddr,
+    len: u8,
+}
+
+impl MacPrefix {
+    fn mask(self) -> u64 {
+        let host_bits = MAC_BITS - self.len;
+        ((1u64 << MAC_BITS) - 1) >> host_bits << host_bits
+    }
+
+    fn new(addr: MacAddr, len: u8) -> Result<Self, MacParseError> {
+        if len > MAC_BITS {
+            return Err(MacParseError::PrefixLengthTooLong(len));
+        }
+        let prefix = MacPrefix { addr, len };
+        if addr.to_u64() & !prefix.mask() != 0 {
+            return Err(MacParseError::InvalidHostPart);
+        }
+        Ok(prefix)
+    }
+}
+
+impl Display for MacPrefix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl Debug for MacPrefix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Serialize for MacPrefix {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+impl From<MacPrefix> for RangeInclusive<MacAddr> {
+    fn from(prefix: MacPrefix) -> Self {
+        let first = prefix.addr.to_u64();
+        let last = first | (!prefix.mask() & ((1u64 << MAC_BITS) - 1));
+        prefix.addr..=MacAddr::from_u64(last)
+    }
+}
+
+impl<'i> Lex<'i> for MacPrefix {
+    fn lex(input: &str) -> LexResult<'_, Self> {
+        let (chunk, rest) = match_addr_or_prefix(input)?;
+
+        let prefix = match chunk.find('/') {
+            Some(split_pos) => {
+                let addr = parse_addr(&chunk[..split_pos])?;
+                let len_str = &chunk[split_pos + 1..];
+                let len = len_str.parse::<u8>().map_err(|err| {
+                    (
+                        LexErrorKind::ParseMac(MacParseError::PrefixLengthParseError(err)),
+                        len_str,
+                    )
+                })?;
+                MacPrefix::new(addr, len).map_err(|err| (LexErrorKind::ParseMac(err), chunk))?
+            }
+            None => MacPrefix {
+                addr: parse_addr(chunk)?,
+                len: MAC_BITS,
+            },
+        };
+
+        Ok((prefix, rest))
+    }
+}
+
+#[test]
+fn test_lex() {
+    use std::str::FromStr;
+
+    fn prefix(octets: [u8; MAC_LEN], len: u8) -> MacPrefix {
+        MacPrefix::new(MacAddr(octets), len).unwrap()
+    }
+
+    assert_ok!(
+        MacAddr::lex("aa:bb:cc:dd:ee:ff;"),
+        MacAddr([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]),
+        ";"
+    );
+    assert_ok!(
+        MacAddr::lex("0:1:2:A:B:C"),
+        MacAddr([0x00, 0x01, 0x02, 0x0a, 0x0b, 0x0c])
+    );
+    assert_ok!(
+        MacAddr::lex("aa:bb:cc::"),
+        MacAddr([0xaa, 0xbb, 0xcc, 0, 0, 0])
+    );
+    assert_ok!(MacAddr::lex("::1"), MacAddr([0, 0, 0, 0, 0, 1]));
+    assert_err!(
+        MacAddr::lex("aa:bb:cc:dd:ee"),
+        LexErrorKind::ParseMac(MacParseError::InvalidAddress),
+        "aa:bb:cc:dd:ee"
+    );
+    assert_err!(
+        MacAddr::lex("aa:bb:cc:dd:ee:ff:00"),
+        LexErrorKind::ParseMac(MacParseError::InvalidAddress),
+        "aa:bb:cc:dd:ee:ff:00"
+    );
+    assert_err!(
+        MacAddr::lex("aaa:bb:cc:dd:ee:ff"),
+        LexErrorKind::ParseMac(MacParseError::InvalidAddress),
+        "aaa:bb:cc:dd:ee:ff"
+    );
+    assert_err!(
+        MacAddr::lex("xx"),
+        LexErrorKind::ExpectedName("MAC address character"),
+        "xx"
+    );
+
+    assert_ok!(
+        MacPrefix::lex("aa:bb:cc::/24 "),
+        prefix([0xaa, 0xbb, 0xcc, 0, 0, 0], 24),
+        " "
+    );
+    assert_ok!(
+        MacPrefix::lex("aa:bb:cc:dd:ee:ff"),
+        prefix([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff], 48)
+    );
+    assert_err!(
+        MacPrefix::lex("aa:bb:cc::/20"),
+        LexErrorKind::ParseMac(MacParseError::InvalidHostPart),
+        "aa:bb:cc::/20"
+    );
+    assert_err!(
+        MacPrefix::lex("aa:bb:cc::/49"),
+        LexErrorKind::ParseMac(MacParseError::PrefixLengthTooLong(49)),
+        "aa:bb:cc::/49"
+    );
+    assert_err!(
+        MacPrefix::lex("aa:bb:cc::/x"),
+        LexErrorKind::ParseMac(MacParseError::PrefixLengthParseError(
+            u8::from_str("").unwrap_err()
+        )),
+        ""
+    );
+    assert_err!(
+        MacPrefix::lex("aa::/2/4"),
+        LexErrorKind::ParseMac(MacParseError::PrefixLengthParseError(
+            u8::from_str("2/4").unwrap_err()
+        )),
+        "2/4"
+    );
+}
+
+#[test]
+fn test_prefix_range() {
+    let range: RangeInclusive<MacAddr> = MacPrefix::lex("aa:bb:cc::/24").unwrap().0.into();
+    assert_eq!(
+        range,
+        MacAddr([0xaa, 0xbb, 0xcc, 0, 0, 0])..=MacAddr([0xaa, 0xbb, 0xcc, 0xff, 0xff, 0xff])
+    );
+
+    let range: RangeInclusive<MacAddr> = MacPrefix::lex("::/0").unwrap().0.into();
+    assert_eq!(range, MacAddr([0; MAC_LEN])..=MacAddr([0xff; MAC_LEN]));
+
+    let range: RangeInclusive<MacAddr> = MacPrefix::lex("aa:bb:cc:dd:ee:ff").unwrap().0.into();
+    assert_eq!(
+        range,
+        MacAddr([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff])
+            ..=MacAddr([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff])
+    );
+}
@@ -1,12 +1,27 @@
 mod bool;
 mod bytes;
+mod duration;
 mod int;
 mod ip;
+mod mac;
 mod regex;
+mod str;
+
+pub(crate) use self::{
+    int::{exclude_int_ranges, find_overlapping_int_ranges, IntLexOptions},
+    ip::{
+        canonicalize_ip_ranges, exclude_ip_ranges, find_overlapping_ip_ranges, unmap_ipv4,
+        unmap_ipv4_ranges,
+    },
+    regex::RegexSet,
+};
 
 pub use self::{
     bool::UninhabitedBool,
-    bytes::Bytes,
-    ip::{ExplicitIpRange, IpRange},
-    regex::{Error as RegexError, Regex},
+    bytes::{Bytes, BytesFormat},
+    int::{IntFormat, IntFormatting, IntLiteral, IntOverflow, IntRadix},
+    ip::{aggregate_ip_ranges, ExplicitIpRange, IpRange},
+    mac::{MacAddr, MacParseError, MacPrefix},
+    regex::{Error as RegexError, Regex, RegexFormat},
+    str::StrLiteral,
 };
@@ -1,27 +1,84 @@
-use std::str::FromStr;
+use super::RegexFormat;
+use std::{ops::Range, str::FromStr};
 
 pub use regex::Error;
 
+/// A compiled regular expression literal.
 #[derive(Clone)]
-pub struct Regex(regex::bytes::Regex);
+pub struct Regex(regex::bytes::Regex, RegexFormat, bool);
 
 impl FromStr for Regex {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Error> {
-        ::regex::bytes::RegexBuilder::new(s)
-            .unicode(false)
-            .build()
-            .map(Regex)
+        Regex::new(s, false)
     }
 }
 
 impl Regex {
+    pub(crate) fn new(s: &str, unicode: bool) -> Result<Self, Error> {
+        ::regex::bytes::RegexBuilder::new(s)
+            .unicode(unicode)
+            .build()
+            .map(|regex| Regex(regex, RegexFormat::Quoted, unicode))
+    }
+
+    /// Checks whether the regex matches anywhere in the given bytes.
     pub fn is_match(&self, text: &[u8]) -> bool {
         self.0.is_match(text)
     }
 
+    /// Finds the first match in the given bytes and returns the position of
+    /// a capture group with the given index in it, where `0` is the whole
+    /// match.
+    pub fn find_group(&self, text: &[u8], index: usize) -> Option<Range<usize>> {
+        let group = self.0.captures(text)?.get(index)?;
+        Some(group.start()..group.end())
+    }
+
+    /// Finds the first match in the given bytes and returns the position of
+    /// a named capture group in it.
+    pub fn find_named_group(&self, text: &[u8], name: &str) -> Option<Range<usize>> {
+        let group = self.0.captures(text)?.name(name)?;
+        Some(group.start()..group.end())
+    }
+
+    /// Returns the original source of the regex.
     pub fn as_str(&self) -> &str {
         self.0.as_str()
     }
+
+    /// Returns the format the regex was written in.
+    pub fn format(&self) -> RegexFormat {
+        self.1
+    }
+
+    /// Returns whether the regex matches UTF-8 text with Unicode classes
+    /// and case folding, rather than arbitrary bytes.
+    pub fn is_unicode(&self) -> bool {
+        self.2
+    }
+
+    pub(crate) fn with_format(self, format: RegexFormat) -> Self {
+        Regex(self.0, format, self.2)
+    }
+}
+
+/// Several regular expressions compiled to be matched in a single pass.
+pub(crate) struct RegexSet(regex::bytes::RegexSet);
+
+impl RegexSet {
+    pub(crate) fn new<'a>(regexes: impl IntoIterator<Item = &'a Regex>) -> Result<Self, Error> {
+        ::regex::bytes::RegexSetBuilder::new(regexes.into_iter().map(Regex::as_str))
+            .unicode(false)
+            .build()
+            .map(RegexSet)
+    }
+
+    /// Returns whether each of the regexes matches anywhere in the given
+    /// bytes.
+    pub(crate) fn matches(&self, text: &[u8]) -> Box<[bool]> {
+        let matches = self.0.matches(text);
+        (0..self.0.len()).map(|i| matches.matched(i)).collect()
+    }
 }
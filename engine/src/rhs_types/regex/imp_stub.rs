@@ -1,5 +1,7 @@
+use super::RegexFormat;
 use failure::Fail;
 use std::fmt;
+use std::ops::Range;
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Fail)]
@@ -11,23 +13,73 @@ impl fmt::Display for Error {
     }
 }
 
+/// A compiled regular expression literal.
 #[derive(Clone)]
-pub struct Regex(String);
+pub struct Regex(String, RegexFormat, bool);
 
 impl FromStr for Regex {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Error> {
-        Ok(Regex(s.to_owned()))
+        Regex::new(s, false)
     }
 }
 
 impl Regex {
+    pub(crate) fn new(s: &str, unicode: bool) -> Result<Self, Error> {
+        Ok(Regex(s.to_owned(), RegexFormat::Quoted, unicode))
+    }
+
+    /// Checks whether the regex matches anywhere in the given bytes.
     pub fn is_match(&self, _text: &[u8]) -> bool {
         unimplemented!("Engine was built without regex support")
     }
 
+    /// Finds the first match in the given bytes and returns the position of
+    /// a capture group with the given index in it, where `0` is the whole
+    /// match.
+    pub fn find_group(&self, _text: &[u8], _index: usize) -> Option<Range<usize>> {
+        unimplemented!("Engine was built without regex support")
+    }
+
+    /// Finds the first match in the given bytes and returns the position of
+    /// a named capture group in it.
+    pub fn find_named_group(&self, _text: &[u8], _name: &str) -> Option<Range<usize>> {
+        unimplemented!("Engine was built without regex support")
+    }
+
+    /// Returns the original source of the regex.
     pub fn as_str(&self) -> &str {
         self.0.as_str()
     }
+
+    /// Returns the format the regex was written in.
+    pub fn format(&self) -> RegexFormat {
+        self.1
+    }
+
+    /// Returns whether the regex matches UTF-8 text with Unicode classes
+    /// and case folding, rather than arbitrary bytes.
+    pub fn is_unicode(&self) -> bool {
+        self.2
+    }
+
+    pub(crate) fn with_format(self, format: RegexFormat) -> Self {
+        Regex(self.0, format, self.2)
+    }
+}
+
+/// Several regular expressions compiled to be matched in a single pass.
+pub(crate) struct RegexSet;
+
+impl RegexSet {
+    pub(crate) fn new<'a>(_regexes: impl IntoIterator<Item = &'a Regex>) -> Result<Self, Error> {
+        Ok(RegexSet)
+    }
+
+    /// Returns whether each of the regexes matches anywhere in the given
+    /// bytes.
+    pub(crate) fn matches(&self, _text: &[u8]) -> Box<[bool]> {
+        unimplemented!("Engine was built without regex support")
+    }
 }
@@ -1,10 +1,7 @@
-use crate::lex::{expect, span, Lex, LexErrorKind, LexResult};
+use crate::lex::{expect, lex_raw_string, span, Lex, LexErrorKind, LexResult};
 use cfg_if::cfg_if;
-use serde::{Serialize, Serializer};
-use std::{
-    fmt::{self, Debug, Formatter},
-    str::FromStr,
-};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::fmt::{self, Debug, Formatter};
 
 cfg_if! {
     if #[cfg(feature = "regex")] {
@@ -16,9 +13,19 @@ cfg_if! {
     }
 }
 
+/// Format a [`Regex`] literal was written in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum RegexFormat {
+    /// A quoted string like `"a\\d"`, where `\"` is unescaped.
+    Quoted,
+    /// A raw string like `r"a\d"` or `r#"a"\d"#` without escapes, along
+    /// with the number of `#` symbols around it.
+    RawString(u8),
+}
+
 impl PartialEq for Regex {
     fn eq(&self, other: &Regex) -> bool {
-        self.as_str() == other.as_str()
+        self.as_str() == other.as_str() && self.is_unicode() == other.is_unicode()
     }
 }
 
@@ -32,10 +39,27 @@ impl Debug for Regex {
 
 impl<'i> Lex<'i> for Regex {
     fn lex(input: &str) -> LexResult<'_, Self> {
-        let input = expect(input, "\"")?;
-        let mut regex_buf = String::new();
-        let mut in_char_class = false;
-        let (regex_str, input) = {
+        Regex::lex_with_unicode(input, false)
+    }
+}
+
+impl Regex {
+    // Lexes a regex that matches UTF-8 text with Unicode classes and case
+    // folding if `unicode` is set or the regex has a `u` suffix, like
+    // `"(?i)straße"u`, or arbitrary bytes otherwise.
+    pub(crate) fn lex_with_unicode(input: &str, unicode: bool) -> LexResult<'_, Self> {
+        let (regex_buf, regex_str, format, input) = if input.starts_with('r') {
+            let ((regex_str, hashes), input) = lex_raw_string(input)?;
+            (
+                regex_str.to_owned(),
+                regex_str,
+                RegexFormat::RawString(hashes),
+                input,
+            )
+        } else {
+            let input = expect(input, "\"")?;
+            let mut regex_buf = String::new();
+            let mut in_char_class = false;
             let mut iter = input.chars();
             loop {
                 let before_char = iter.as_str();
@@ -52,7 +76,12 @@ impl<'i> Lex<'i> for Regex {
                         }
                     }
                     '"' if !in_char_class => {
-                        break (span(input, before_char), iter.as_str());
+                        break (
+                            regex_buf,
+                            span(input, before_char),
+                            RegexFormat::Quoted,
+                            iter.as_str(),
+                        );
                     }
                     '[' if !in_char_class => {
                         in_char_class = true;
@@ -68,21 +97,37 @@ impl<'i> Lex<'i> for Regex {
                 };
             }
         };
-        match Regex::from_str(&regex_buf) {
-            Ok(regex) => Ok((regex, input)),
+        let (unicode, input) = match expect(input, "u") {
+            Ok(input) => (true, input),
+            Err(_) => (unicode, input),
+        };
+        match Regex::new(&regex_buf, unicode) {
+            Ok(regex) => Ok((regex.with_format(format), input)),
             Err(err) => Err((LexErrorKind::ParseRegex(err), regex_str)),
         }
     }
 }
 
+// Unicode regexes are serialized along with the flag, like
+// `{"pattern": "\\w", "unicode": true}`, to tell them apart from byte ones,
+// which are serialized as plain strings.
 impl Serialize for Regex {
     fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
-        self.as_str().serialize(ser)
+        if self.is_unicode() {
+            let mut regex = ser.serialize_struct("Regex", 2)?;
+            regex.serialize_field("pattern", self.as_str())?;
+            regex.serialize_field("unicode", &true)?;
+            regex.end()
+        } else {
+            self.as_str().serialize(ser)
+        }
     }
 }
 
 #[test]
 fn test() {
+    use std::str::FromStr;
+
     let expr = assert_ok!(
         Regex::lex(r#""[a-z"\]]+\d{1,10}\"";"#),
         Regex::from_str(r#"[a-z"\]]+\d{1,10}""#).unwrap(),
@@ -91,9 +136,36 @@ fn test() {
 
     assert_json!(expr, r#"[a-z"\]]+\d{1,10}""#);
 
+    let expr = assert_ok!(
+        Regex::lex(r##"r#"[a-z"\]]+\d{1,10}""#;"##),
+        Regex::from_str(r#"[a-z"\]]+\d{1,10}""#).unwrap(),
+        ";"
+    );
+
+    assert_eq!(expr.format(), RegexFormat::RawString(1));
+
+    assert_json!(expr, r#"[a-z"\]]+\d{1,10}""#);
+
     assert_err!(
         Regex::lex(r#""abcd\"#),
         LexErrorKind::MissingEndingQuote,
         "abcd\\"
     );
+
+    let expr = assert_ok!(
+        Regex::lex(r#""(?i)straße"u and"#),
+        Regex::new("(?i)straße", true).unwrap(),
+        " and"
+    );
+    assert!(expr.is_unicode());
+
+    assert_json!(expr, { "pattern": "(?i)straße", "unicode": true });
+
+    let expr = assert_ok!(Regex::lex(r#"r"\w"u"#), Regex::new(r"\w", true).unwrap());
+    assert_eq!(expr.format(), RegexFormat::RawString(0));
+
+    assert_ne!(
+        Regex::lex(r#""\w""#).unwrap().0,
+        Regex::new(r"\w", true).unwrap()
+    );
 }
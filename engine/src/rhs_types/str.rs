@@ -0,0 +1,128 @@
+use crate::{
+    lex::{span, Lex, LexErrorKind, LexResult},
+    rhs_types::bytes::{Bytes, BytesFormat},
+    strict_partial_ord::StrictPartialOrd,
+};
+use serde::{Serialize, Serializer};
+use std::{
+    borrow::Borrow,
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    str,
+};
+
+/// A string literal that is guaranteed to be valid UTF-8.
+///
+/// It's written just like a [`Bytes`] literal, which is validated when
+/// lexed.
+#[derive(PartialEq, Eq, Clone)]
+pub struct StrLiteral(Bytes);
+
+impl StrLiteral {
+    /// Format the literal was written in.
+    pub fn format(&self) -> BytesFormat {
+        self.0.format()
+    }
+
+    pub(crate) fn as_bytes_literal(&self) -> &Bytes {
+        &self.0
+    }
+}
+
+// Consistent with `Borrow<str>`, see `Hash` of `Bytes`.
+#[allow(clippy::derive_hash_xor_eq)]
+impl Hash for StrLiteral {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        (self as &str).hash(h)
+    }
+}
+
+impl From<String> for StrLiteral {
+    fn from(src: String) -> Self {
+        StrLiteral(src.into())
+    }
+}
+
+impl From<StrLiteral> for Bytes {
+    fn from(s: StrLiteral) -> Self {
+        s.0
+    }
+}
+
+impl From<StrLiteral> for Box<str> {
+    fn from(s: StrLiteral) -> Self {
+        String::from_utf8(Box::<[u8]>::from(s.0).into_vec())
+            .unwrap()
+            .into_boxed_str()
+    }
+}
+
+impl Debug for StrLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Serialize for StrLiteral {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(self)
+    }
+}
+
+impl Deref for StrLiteral {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        // Validated when constructed.
+        unsafe { str::from_utf8_unchecked(&self.0) }
+    }
+}
+
+impl Borrow<str> for StrLiteral {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl<'i> Lex<'i> for StrLiteral {
+    fn lex(input: &str) -> LexResult<'_, Self> {
+        let (bytes, rest) = Bytes::lex(input)?;
+        match str::from_utf8(&bytes) {
+            Ok(_) => Ok((StrLiteral(bytes), rest)),
+            Err(_) => Err((LexErrorKind::InvalidUtf8, span(input, rest))),
+        }
+    }
+}
+
+impl StrictPartialOrd for str {}
+
+#[test]
+fn test() {
+    assert_ok!(
+        StrLiteral::lex(r#""stra\u{df}e";"#),
+        StrLiteral::from("straße".to_owned()),
+        ";"
+    );
+
+    assert_ok!(
+        StrLiteral::lex(r#"r"\d""#),
+        StrLiteral(Bytes::new(&b"\\d"[..], BytesFormat::RawString(0)))
+    );
+
+    let raw = assert_ok!(
+        StrLiteral::lex("c3:9f"),
+        StrLiteral(vec![0xC3, 0x9F].into())
+    );
+    assert_eq!(&raw as &str, "ß");
+    assert_eq!(format!("{:?}", raw), "C3:9F");
+    assert_json!(raw, "ß");
+
+    assert_err!(
+        StrLiteral::lex(r#""a\xffb";"#),
+        LexErrorKind::InvalidUtf8,
+        r#""a\xffb""#
+    );
+
+    assert_err!(StrLiteral::lex("c3;"), LexErrorKind::InvalidUtf8, "c3");
+}
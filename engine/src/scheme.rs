@@ -1,22 +1,35 @@
 use crate::{
-    ast::FilterAst,
-    functions::Function,
-    lex::{complete, expect, span, take_while, LexErrorKind, LexResult, LexWith},
-    types::{GetType, Type},
+    ast::{FilterAst, ParseState},
+    complete::{completions, Completions},
+    execution_context::lhs_value_from_json,
+    format::values_to_strings,
+    functions::{
+        Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionOptParam, FunctionParam,
+        GenericFunction,
+    },
+    lex::{complete, expect, span, take_while, LexErrorKind, LexResult, LexState, LexWith},
+    rhs_types::{IntLexOptions, IntOverflow},
+    spans::ExprSpans,
+    types::{GetType, LhsValue, RhsValues, Type},
 };
 use failure::Fail;
 use fnv::FnvBuildHasher;
 use indexmap::map::{Entry, IndexMap};
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
 use std::{
+    borrow::Cow,
     cmp::{max, min},
+    convert::TryFrom,
     error::Error,
     fmt::{self, Debug, Display, Formatter},
-    ptr,
+    ops::Range,
+    ptr, str,
 };
 
+/// A field registered in a [`Scheme`].
 #[derive(PartialEq, Eq, Clone, Copy)]
-pub(crate) struct Field<'s> {
+pub struct Field<'s> {
     scheme: &'s Scheme,
     index: usize,
 }
@@ -60,17 +73,24 @@ impl<'i, 's> LexWith<'i, &'s Scheme> for Field<'s> {
 }
 
 impl<'s> Field<'s> {
+    /// Name of the field.
     pub fn name(&self) -> &'s str {
         self.scheme.fields.get_index(self.index).unwrap().0
     }
 
-    pub fn index(&self) -> usize {
+    pub(crate) fn index(&self) -> usize {
         self.index
     }
 
+    /// Scheme the field belongs to.
     pub fn scheme(&self) -> &'s Scheme {
         self.scheme
     }
+
+    /// Metadata attached with [`Scheme::set_field_metadata`], if any.
+    pub fn metadata(&self) -> Option<&'s Metadata> {
+        self.scheme.metadata.get(self.name())
+    }
 }
 
 impl<'s> GetType for Field<'s> {
@@ -79,6 +99,90 @@ impl<'s> GetType for Field<'s> {
     }
 }
 
+/// A named list of values registered in a [`Scheme`], like
+/// `$malicious_domains`.
+///
+/// Filters refer to lists with `field in $name`, and their values are set on
+/// each [`ExecutionContext`](crate::ExecutionContext).
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct List<'s> {
+    scheme: &'s Scheme,
+    index: usize,
+}
+
+impl<'s> Serialize for List<'s> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        self.name().serialize(ser)
+    }
+}
+
+impl<'s> Debug for List<'s> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.name())
+    }
+}
+
+impl<'i, 's> LexWith<'i, &'s Scheme> for List<'s> {
+    fn lex_with(input: &'i str, scheme: &'s Scheme) -> LexResult<'i, Self> {
+        let initial_input = input;
+
+        let input = expect(input, "$")?;
+        let (name, input) = take_while(input, "identifier character", |c| {
+            c.is_ascii_alphanumeric() || c == '_'
+        })?;
+
+        let list = scheme
+            .get_list(name)
+            .map_err(|err| (LexErrorKind::UnknownList(err), span(initial_input, input)))?;
+
+        Ok((list, input))
+    }
+}
+
+impl<'s> List<'s> {
+    /// Name of the list, without the `$` prefix.
+    pub fn name(&self) -> &'s str {
+        self.scheme.lists.get_index(self.index).unwrap().0
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Scheme the list belongs to.
+    pub fn scheme(&self) -> &'s Scheme {
+        self.scheme
+    }
+}
+
+/// Type of the list elements.
+impl<'s> GetType for List<'s> {
+    fn get_type(&self) -> Type {
+        *self.scheme.lists.get_index(self.index).unwrap().1
+    }
+}
+
+/// Documentation of a field or a function registered in a [`Scheme`], for
+/// tools like editors and rule catalogs.
+///
+/// It doesn't affect parsing or execution of filters, but is preserved when
+/// the scheme is serialized.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Human-readable description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Example values, or example calls of a function, in filter syntax.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+    /// Deprecation notice, like the name of a replacement.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    /// Category used to group related items, like `"http"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
 /// An error that occurs if an unregistered field name was queried from a
 /// [`Scheme`](struct@Scheme).
 #[derive(Debug, PartialEq, Fail)]
@@ -91,6 +195,18 @@ pub struct UnknownFieldError;
 #[fail(display = "unknown function")]
 pub struct UnknownFunctionError;
 
+/// An error that occurs if an unregistered list name was queried from a
+/// [`Scheme`](struct@Scheme).
+#[derive(Debug, PartialEq, Fail)]
+#[fail(display = "unknown list")]
+pub struct UnknownListError;
+
+/// An error that occurs if an unregistered constant name was queried from a
+/// [`Scheme`](struct@Scheme).
+#[derive(Debug, PartialEq, Fail)]
+#[fail(display = "unknown constant")]
+pub struct UnknownConstantError;
+
 /// An error that occurs when previously defined field gets redefined.
 #[derive(Debug, PartialEq, Fail)]
 #[fail(display = "attempt to redefine field {}", _0)]
@@ -101,6 +217,16 @@ pub struct FieldRedefinitionError(String);
 #[fail(display = "attempt to redefine function {}", _0)]
 pub struct FunctionRedefinitionError(String);
 
+/// An error that occurs when previously defined list gets redefined.
+#[derive(Debug, PartialEq, Fail)]
+#[fail(display = "attempt to redefine list {}", _0)]
+pub struct ListRedefinitionError(String);
+
+/// An error that occurs when previously defined constant gets redefined.
+#[derive(Debug, PartialEq, Fail)]
+#[fail(display = "attempt to redefine constant {}", _0)]
+pub struct ConstantRedefinitionError(String);
+
 #[derive(Debug, PartialEq, Fail)]
 pub enum ItemRedefinitionError {
     #[fail(display = "{}", _0)]
@@ -108,11 +234,87 @@ pub enum ItemRedefinitionError {
 
     #[fail(display = "{}", _0)]
     Function(#[cause] FunctionRedefinitionError),
+
+    #[fail(display = "{}", _0)]
+    List(#[cause] ListRedefinitionError),
+
+    #[fail(display = "{}", _0)]
+    Constant(#[cause] ConstantRedefinitionError),
+}
+
+/// A difference between two schemes found by
+/// [`Scheme::check_compatibility`].
+#[derive(Debug, PartialEq, Eq, Clone, Fail)]
+pub enum SchemeIncompatibility {
+    /// A field was removed.
+    #[fail(display = "field {} is missing", _0)]
+    MissingField(String),
+
+    /// A field changed its type.
+    #[fail(
+        display = "field {} has type {:?} instead of {:?}",
+        name, actual, expected
+    )]
+    FieldTypeChanged {
+        /// Name of the field.
+        name: String,
+        /// Type in the original scheme.
+        expected: Type,
+        /// Type in the other scheme.
+        actual: Type,
+    },
+
+    /// A function was removed.
+    #[fail(display = "function {} is missing", _0)]
+    MissingFunction(String),
+
+    /// Parameters, default values or the return type of a function changed.
+    #[fail(
+        display = "function {} has signature {} instead of {}",
+        name, actual, expected
+    )]
+    FunctionSignatureChanged {
+        /// Name of the function.
+        name: String,
+        /// Signature in the original scheme, like
+        /// `(field Bytes, literal Int = 10) -> Bytes`.
+        expected: String,
+        /// Signature in the other scheme.
+        actual: String,
+    },
+
+    /// A list was removed.
+    #[fail(display = "list {} is missing", _0)]
+    MissingList(String),
+
+    /// A list changed the type of its elements.
+    #[fail(
+        display = "list {} has type {:?} instead of {:?}",
+        name, actual, expected
+    )]
+    ListTypeChanged {
+        /// Name of the list.
+        name: String,
+        /// Type in the original scheme.
+        expected: Type,
+        /// Type in the other scheme.
+        actual: Type,
+    },
+
+    /// A constant was removed.
+    #[fail(display = "constant {} is missing", _0)]
+    MissingConstant(String),
+
+    /// A constant changed its type or values.
+    #[fail(display = "constant {} has different values", _0)]
+    ConstantChanged(String),
 }
 
-/// An opaque filter parsing error associated with the original input.
+/// A filter parsing error associated with the original input.
 ///
-/// For now, you can just print it in a debug or a human-readable fashion.
+/// It can be printed in a debug or a human-readable fashion, rendered as a
+/// diagnostic with [`ParseError::render`], or inspected for integration with
+/// other tools.
 #[derive(Debug, PartialEq)]
 pub struct ParseError<'i> {
     kind: LexErrorKind,
@@ -120,13 +322,15 @@ pub struct ParseError<'i> {
     line_number: usize,
     span_start: usize,
     span_len: usize,
+    offset: usize,
 }
 
 impl<'i> Error for ParseError<'i> {}
 
 impl<'i> ParseError<'i> {
     pub(crate) fn new(mut input: &'i str, (kind, span): (LexErrorKind, &'i str)) -> Self {
-        let mut span_start = span.as_ptr() as usize - input.as_ptr() as usize;
+        let offset = span.as_ptr() as usize - input.as_ptr() as usize;
+        let mut span_start = offset;
 
         let (line_number, line_start) = input[..span_start]
             .match_indices('\n')
@@ -154,8 +358,112 @@ impl<'i> ParseError<'i> {
             line_number,
             span_start,
             span_len,
+            offset,
+        }
+    }
+
+    /// Returns a human-readable description of the error.
+    pub fn message(&self) -> String {
+        self.kind.to_string()
+    }
+
+    /// Returns the range of byte offsets of the offending token in the
+    /// original input.
+    ///
+    /// The range never spans multiple lines, and can be empty if the error
+    /// occurred at the end of the input.
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.span_len
+    }
+
+    /// Returns the offending token.
+    pub fn token(&self) -> &'i str {
+        &self.input[self.span_start..self.span_start + self.span_len]
+    }
+
+    /// Returns the 1-based line number of the offending token.
+    pub fn line(&self) -> usize {
+        self.line_number + 1
+    }
+
+    /// Returns the 1-based column of the offending token in bytes.
+    pub fn column(&self) -> usize {
+        self.span_start + 1
+    }
+
+    /// Renders a diagnostic with the error location in a source (like a file
+    /// name) and the offending line with the token underlined:
+    ///
+    /// ```text
+    /// error: expected digit
+    ///  --> rules.txt:2:8
+    ///   |
+    /// 2 | num == true or
+    ///   |        ^^^^^^^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        self.render_with_severity("error", source)
+    }
+
+    fn render_with_severity(&self, severity: &str, source: &str) -> String {
+        let line = self.line().to_string();
+        let gutter = " ".repeat(line.len());
+
+        format!(
+            "{}: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            severity,
+            self.kind,
+            gutter,
+            source,
+            line,
+            self.column(),
+            gutter,
+            line,
+            self.input,
+            gutter,
+            " ".repeat(self.span_start),
+            "^".repeat(max(1, self.span_len))
+        )
+    }
+}
+
+/// A non-fatal problem found while parsing a filter, like a construct that is
+/// accepted only in a lenient mode.
+#[derive(Debug, PartialEq)]
+pub struct ParseWarning<'i>(ParseError<'i>);
+
+impl<'i> ParseWarning<'i> {
+    /// Returns a human-readable description of the warning.
+    pub fn message(&self) -> String {
+        self.0.message()
+    }
+
+    /// Returns a stable identifier of the kind of the warning, like
+    /// `overlapping-set-values`.
+    pub fn code(&self) -> &'static str {
+        match self.0.kind {
+            LexErrorKind::BoolIntComparison => "bool-int-comparison",
+            LexErrorKind::OverlappingSetValues => "overlapping-set-values",
+            LexErrorKind::IntOverflow { .. } => "int-overflow",
+            _ => "warning",
         }
     }
+
+    /// Returns the range of byte offsets of the affected token in the
+    /// original input.
+    pub fn span(&self) -> Range<usize> {
+        self.0.span()
+    }
+
+    /// Returns the affected token.
+    pub fn token(&self) -> &'i str {
+        self.0.token()
+    }
+
+    /// Renders a diagnostic like [`ParseError::render`] does.
+    pub fn render(&self, source: &str) -> String {
+        self.0.render_with_severity("warning", source)
+    }
 }
 
 impl<'i> Display for ParseError<'i> {
@@ -188,12 +496,47 @@ impl<'i> Display for ParseError<'i> {
 /// This is necessary to provide typechecking for runtime values provided
 /// to the [execution context](::ExecutionContext) and also to aid parser
 /// in ambiguous contexts.
-#[derive(Default, Deserialize)]
-#[serde(transparent)]
+///
+/// Schemes can be serialized to share them with tools that only need to parse
+/// filters, like linters or editors. See [`Scheme::check_compatibility`] for
+/// checking whether a shared scheme is still up to date.
 pub struct Scheme {
     fields: IndexMap<String, Type, FnvBuildHasher>,
-    #[serde(skip)]
-    functions: IndexMap<String, Function, FnvBuildHasher>,
+    // Signatures of each function, in order of registration.
+    functions: IndexMap<String, Vec<Function>, FnvBuildHasher>,
+    // Element types of named lists.
+    lists: IndexMap<String, Type, FnvBuildHasher>,
+    // Named sets of values expanded at parse time.
+    constants: IndexMap<String, RhsValues, FnvBuildHasher>,
+    // Metadata of fields and functions, which share a namespace.
+    metadata: IndexMap<String, Metadata, FnvBuildHasher>,
+    max_nesting_depth: usize,
+    lenient_bool_comparisons: bool,
+    unicode_regexes: bool,
+    ipv4_mapped_equivalence: bool,
+    decimal_leading_zeros: bool,
+    int_overflow: IntOverflow,
+    // Names of deserialized functions, which have no implementations.
+    unavailable_functions: Vec<String>,
+}
+
+impl Default for Scheme {
+    fn default() -> Self {
+        Scheme {
+            fields: Default::default(),
+            functions: Default::default(),
+            lists: Default::default(),
+            constants: Default::default(),
+            metadata: Default::default(),
+            max_nesting_depth: Scheme::DEFAULT_MAX_NESTING_DEPTH,
+            lenient_bool_comparisons: false,
+            unicode_regexes: false,
+            ipv4_mapped_equivalence: false,
+            decimal_leading_zeros: false,
+            int_overflow: IntOverflow::Error,
+            unavailable_functions: Vec::new(),
+        }
+    }
 }
 
 impl PartialEq for Scheme {
@@ -205,6 +548,9 @@ impl PartialEq for Scheme {
 impl Eq for Scheme {}
 
 impl<'s> Scheme {
+    /// Default limit for [`max_nesting_depth`](Scheme::max_nesting_depth).
+    pub const DEFAULT_MAX_NESTING_DEPTH: usize = 128;
+
     /// Creates a new scheme.
     pub fn new() -> Self {
         Default::default()
@@ -214,7 +560,7 @@ impl<'s> Scheme {
     pub fn with_capacity(n: usize) -> Self {
         Scheme {
             fields: IndexMap::with_capacity_and_hasher(n, FnvBuildHasher::default()),
-            functions: Default::default(),
+            ..Default::default()
         }
     }
 
@@ -259,6 +605,76 @@ impl<'s> Scheme {
         }
     }
 
+    /// Returns a field with the given name.
+    pub fn get_field(&'s self, name: &str) -> Result<Field<'s>, UnknownFieldError> {
+        self.get_field_index(name)
+    }
+
+    /// Returns all registered fields, in order of registration.
+    pub fn iter_fields(&'s self) -> impl ExactSizeIterator<Item = Field<'s>> {
+        (0..self.fields.len()).map(move |index| Field {
+            scheme: self,
+            index,
+        })
+    }
+
+    /// Returns names of all registered functions, in order of registration.
+    pub fn function_names(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+
+    /// Returns names of all registered lists, without the `$` prefix, in
+    /// order of registration.
+    pub fn list_names(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.lists.keys().map(String::as_str)
+    }
+
+    /// Attaches metadata to a registered field, replacing any previous one.
+    pub fn set_field_metadata(
+        &mut self,
+        name: &str,
+        metadata: Metadata,
+    ) -> Result<(), UnknownFieldError> {
+        let (_, name, _) = self.fields.get_full(name).ok_or(UnknownFieldError)?;
+        self.metadata.insert(name.clone(), metadata);
+        Ok(())
+    }
+
+    /// Attaches metadata to a registered function, shared by all of its
+    /// overloads, replacing any previous one.
+    pub fn set_function_metadata(
+        &mut self,
+        name: &str,
+        metadata: Metadata,
+    ) -> Result<(), UnknownFunctionError> {
+        let (_, name, _) = self.functions.get_full(name).ok_or(UnknownFunctionError)?;
+        self.metadata.insert(name.clone(), metadata);
+        Ok(())
+    }
+
+    /// Returns metadata attached to a function with
+    /// [`Scheme::set_function_metadata`].
+    pub fn function_metadata(&self, name: &str) -> Result<Option<&Metadata>, UnknownFunctionError> {
+        if !self.functions.contains_key(name) {
+            return Err(UnknownFunctionError);
+        }
+        Ok(self.metadata.get(name))
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn fields(&self) -> impl ExactSizeIterator<Item = (&str, Type)> {
+        self.fields.iter().map(|(name, ty)| (name.as_str(), *ty))
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn functions(&self) -> impl Iterator<Item = (&str, &Function)> {
+        self.functions.iter().flat_map(|(name, overloads)| {
+            overloads
+                .iter()
+                .map(move |function| (name.as_str(), function))
+        })
+    }
+
     pub(crate) fn get_field_count(&self) -> usize {
         self.fields.len()
     }
@@ -277,12 +693,97 @@ impl<'s> Scheme {
                 FunctionRedefinitionError(entry.key().to_string()),
             )),
             Entry::Vacant(entry) => {
-                entry.insert(function);
+                entry.insert(vec![function]);
+                Ok(())
+            }
+        }
+    }
+
+    /// Registers another signature of a function, or the first one if the
+    /// function doesn't exist yet.
+    ///
+    /// Calls are resolved to the only signature that accepts types and kinds
+    /// of their arguments when filters are parsed, and calls that are
+    /// accepted by several signatures are rejected as ambiguous.
+    ///
+    /// Returns an error if a signature with the same parameters is already
+    /// registered.
+    pub fn add_function_overload(
+        &mut self,
+        name: String,
+        function: Function,
+    ) -> Result<(), ItemRedefinitionError> {
+        if self.fields.contains_key(&name) {
+            return Err(ItemRedefinitionError::Field(FieldRedefinitionError(name)));
+        };
+        match self.functions.entry(name) {
+            Entry::Occupied(mut entry) => {
+                if entry
+                    .get()
+                    .iter()
+                    .any(|overload| overload.has_same_params(&function))
+                {
+                    return Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+                        entry.key().to_string(),
+                    )));
+                }
+                entry.get_mut().push(function);
+                Ok(())
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(vec![function]);
                 Ok(())
             }
         }
     }
 
+    /// Registers several signatures of a function at once.
+    ///
+    /// Returns an error if any of them has the same parameters as an already
+    /// registered signature or as another one of them, in which case none of
+    /// them are registered.
+    pub fn add_function_overloads(
+        &mut self,
+        name: String,
+        functions: Vec<Function>,
+    ) -> Result<(), ItemRedefinitionError> {
+        if self.fields.contains_key(&name) {
+            return Err(ItemRedefinitionError::Field(FieldRedefinitionError(name)));
+        };
+        let registered = self.functions.get(&name).map_or(&[][..], Vec::as_slice);
+        for (index, function) in functions.iter().enumerate() {
+            if registered
+                .iter()
+                .chain(&functions[..index])
+                .any(|overload| overload.has_same_params(function))
+            {
+                return Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+                    name,
+                )));
+            }
+        }
+        if !functions.is_empty() {
+            self.functions.entry(name).or_default().extend(functions);
+        }
+        Ok(())
+    }
+
+    /// Registers an overload of a generic function for each type its type
+    /// variable can take.
+    ///
+    /// Returns an error if any of them has the same parameters as an already
+    /// registered signature, in which case the preceding ones stay registered.
+    pub fn add_generic_function(
+        &mut self,
+        name: String,
+        function: GenericFunction,
+    ) -> Result<(), ItemRedefinitionError> {
+        for &ty in &function.types {
+            self.add_function_overload(name.clone(), function.instantiate(ty))?;
+        }
+        Ok(())
+    }
+
     /// Registers a list of functions
     pub fn add_functions<I>(&mut self, functions: I) -> Result<(), ItemRedefinitionError>
     where
@@ -294,88 +795,791 @@ impl<'s> Scheme {
         Ok(())
     }
 
+    // Returns all signatures of a function.
+    pub(crate) fn get_function_overloads(
+        &'s self,
+        name: &str,
+    ) -> Result<&'s [Function], UnknownFunctionError> {
+        self.functions
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or(UnknownFunctionError)
+    }
+
+    #[cfg(test)]
     pub(crate) fn get_function(&'s self, name: &str) -> Result<&'s Function, UnknownFunctionError> {
-        self.functions.get(name).ok_or(UnknownFunctionError)
+        self.get_function_overloads(name)
+            .map(|overloads| &overloads[0])
     }
 
-    /// Parses a filter into an AST form.
-    pub fn parse<'i>(&'s self, input: &'i str) -> Result<FilterAst<'s>, ParseError<'i>> {
-        complete(FilterAst::lex_with(input.trim(), self)).map_err(|err| ParseError::new(input, err))
+    // Returns the name of a function registered in the scheme, which lives
+    // as long as the scheme, unlike the one in a parsed call.
+    pub(crate) fn get_function_name(&'s self, name: &str) -> &'s str {
+        match self.functions.get_full(name) {
+            Some((_, name, _)) => name,
+            None => panic!("function {} is not registered in the scheme", name),
+        }
     }
-}
 
-/// A convenience macro for constructing a [`Scheme`](struct@Scheme) with static
-/// contents.
-#[macro_export]
-macro_rules! Scheme {
-    ($($ns:ident $(. $field:ident)*: $ty:ident),* $(,)*) => {
-        $crate::Scheme::try_from_iter(
-            [$(
-                (
-                    concat!(stringify!($ns) $(, ".", stringify!($field))*),
-                    $crate::Type::$ty
-                )
-            ),*]
-            .iter()
-            .map(|&(k, v)| (k.to_owned(), v)),
-        )
-        // Treat duplciations in static schemes as a developer's mistake.
-        .unwrap_or_else(|err| panic!("{}", err))
-    };
-}
+    /// Registers a named list of values of the given type.
+    ///
+    /// Filters can check whether a field is in the list with
+    /// `field in $name`, which is type-checked when parsing, and values are
+    /// set separately on each [`ExecutionContext`](crate::ExecutionContext)
+    /// with [`set_list_values`](crate::ExecutionContext::set_list_values).
+    /// Lists have their own namespace, so they can share names with fields.
+    pub fn add_list(&mut self, name: String, ty: Type) -> Result<(), ItemRedefinitionError> {
+        match self.lists.entry(name) {
+            Entry::Occupied(entry) => Err(ItemRedefinitionError::List(ListRedefinitionError(
+                entry.key().to_string(),
+            ))),
+            Entry::Vacant(entry) => {
+                entry.insert(ty);
+                Ok(())
+            }
+        }
+    }
 
-#[test]
-fn test_parse_error() {
-    use indoc::indoc;
+    /// Returns a list with the given name, without the `$` prefix.
+    pub fn get_list(&'s self, name: &str) -> Result<List<'s>, UnknownListError> {
+        match self.lists.get_full(name) {
+            Some((index, ..)) => Ok(List {
+                scheme: self,
+                index,
+            }),
+            None => Err(UnknownListError),
+        }
+    }
 
-    let scheme = &Scheme! { num: Int };
+    pub(crate) fn get_list_count(&self) -> usize {
+        self.lists.len()
+    }
 
-    {
-        let err = scheme.parse("xyz").unwrap_err();
-        assert_eq!(
-            err,
-            ParseError {
-                kind: LexErrorKind::UnknownField(UnknownFieldError),
-                input: "xyz",
-                line_number: 0,
-                span_start: 0,
-                span_len: 3
+    /// Registers a named set of values, which filters can use in place of a
+    /// set literal, like `tcp.port in web_ports`.
+    ///
+    /// Unlike [lists](Scheme::add_list), constants are expanded into the
+    /// filter and type-checked when it's parsed, so they can't be changed
+    /// without parsing filters again. Constants have their own namespace.
+    pub fn add_constant(
+        &mut self,
+        name: String,
+        values: RhsValues,
+    ) -> Result<(), ItemRedefinitionError> {
+        match self.constants.entry(name) {
+            Entry::Occupied(entry) => Err(ItemRedefinitionError::Constant(
+                ConstantRedefinitionError(entry.key().to_string()),
+            )),
+            Entry::Vacant(entry) => {
+                entry.insert(values);
+                Ok(())
             }
-        );
-        assert_eq!(
-            err.to_string(),
-            indoc!(
-                r#"
-                Filter parsing error (1:1):
-                xyz
-                ^^^ unknown field
-                "#
-            )
-        );
+        }
     }
 
-    {
-        let err = scheme.parse("xyz\n").unwrap_err();
-        assert_eq!(
-            err,
-            ParseError {
-                kind: LexErrorKind::UnknownField(UnknownFieldError),
-                input: "xyz",
-                line_number: 0,
-                span_start: 0,
-                span_len: 3
-            }
-        );
-        assert_eq!(
-            err.to_string(),
-            indoc!(
-                r#"
-                Filter parsing error (1:1):
-                xyz
-                ^^^ unknown field
-                "#
-            )
-        );
+    /// Returns values of a constant with the given name.
+    pub fn get_constant(&self, name: &str) -> Result<&RhsValues, UnknownConstantError> {
+        self.constants.get(name).ok_or(UnknownConstantError)
+    }
+
+    /// Returns names of all registered constants, in order of registration.
+    pub fn constant_names(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.constants.keys().map(String::as_str)
+    }
+
+    /// Returns the maximum depth of nested parentheses, `not` operators and
+    /// function calls allowed in parsed filters.
+    pub fn max_nesting_depth(&self) -> usize {
+        self.max_nesting_depth
+    }
+
+    /// Sets the maximum depth of nested parentheses, `not` operators and
+    /// function calls allowed in parsed filters.
+    ///
+    /// Parsing, compilation and execution are recursive, so this protects
+    /// against stack overflows on adversarial filters.
+    pub fn set_max_nesting_depth(&mut self, depth: usize) {
+        self.max_nesting_depth = depth;
+    }
+
+    /// Returns whether comparisons of `Bool` fields with integers are
+    /// accepted.
+    pub fn lenient_bool_comparisons(&self) -> bool {
+        self.lenient_bool_comparisons
+    }
+
+    /// Sets whether comparisons like `bool_field == 1`, which are common in
+    /// generated and imported rulesets, are accepted.
+    ///
+    /// In lenient mode, `== 1` and `!= 0` are converted to a check of the
+    /// field, `== 0` and `!= 1` to its negation, and a warning is reported by
+    /// [`Scheme::parse_with_warnings`]. Otherwise such comparisons are
+    /// rejected, as are comparisons with other values in either mode.
+    pub fn set_lenient_bool_comparisons(&mut self, lenient: bool) {
+        self.lenient_bool_comparisons = lenient;
+    }
+
+    /// Returns whether regexes matched against `Bytes` are compiled in
+    /// Unicode mode.
+    pub fn unicode_regexes(&self) -> bool {
+        self.unicode_regexes
+    }
+
+    /// Sets whether regexes matched against `Bytes` are compiled in Unicode
+    /// mode, in which classes like `\w` and case folding with `(?i)` cover
+    /// all of Unicode, and `.` matches a whole UTF-8 encoded character.
+    ///
+    /// Individual regexes can enable Unicode mode with a `u` suffix, like
+    /// `"(?i)straße"u`, and regexes matched against `Str` always use it.
+    /// Compiled regexes are subject to the same size limits in either mode.
+    pub fn set_unicode_regexes(&mut self, unicode: bool) {
+        self.unicode_regexes = unicode;
+    }
+
+    /// Returns whether IPv4-mapped IPv6 addresses are treated as the IPv4
+    /// addresses they map.
+    pub fn ipv4_mapped_equivalence(&self) -> bool {
+        self.ipv4_mapped_equivalence
+    }
+
+    /// Sets whether IPv4-mapped IPv6 addresses, like `::ffff:203.0.113.7`
+    /// reported by dual-stack proxies, are treated as the IPv4 addresses they
+    /// map.
+    ///
+    /// When enabled, such addresses are converted to IPv4 both in `Ip` field
+    /// values set on an [`ExecutionContext`](crate::ExecutionContext) and in
+    /// filter literals, so `::ffff:203.0.113.7` is equal to `203.0.113.7` and
+    /// is contained in `203.0.113.0/24`, and IPv6 ranges covering mapped
+    /// addresses match the corresponding IPv4 ones.
+    pub fn set_ipv4_mapped_equivalence(&mut self, enabled: bool) {
+        self.ipv4_mapped_equivalence = enabled;
+    }
+
+    /// Returns whether integer literals with leading zeros are decimal.
+    pub fn decimal_leading_zeros(&self) -> bool {
+        self.decimal_leading_zeros
+    }
+
+    /// Sets whether integer literals with leading zeros, like `0080`, are
+    /// decimal rather than octal.
+    ///
+    /// By default a leading zero means octal, like in C, so `010` is `8`,
+    /// which is surprising for values like zero-padded port numbers. Octal
+    /// values can't be written when this is enabled, but hexadecimal (`0x`)
+    /// and binary (`0b`) ones still can, and leading zeros are preserved
+    /// when the filter is formatted.
+    pub fn set_decimal_leading_zeros(&mut self, enabled: bool) {
+        self.decimal_leading_zeros = enabled;
+    }
+
+    /// Returns what happens to integer literals that don't fit into an
+    /// `Int`.
+    pub fn int_overflow(&self) -> IntOverflow {
+        self.int_overflow
+    }
+
+    /// Sets what happens to integer literals that don't fit into an `Int`,
+    /// like `tcp.port == 0xffffffffffffffff`.
+    ///
+    /// By default they are rejected. With [`IntOverflow::Saturate`] they are
+    /// clamped to `i32::MAX` or `i32::MIN`, and a warning is reported by
+    /// [`Scheme::parse_with_warnings`]. Literals that aren't valid numbers
+    /// are rejected in either mode.
+    pub fn set_int_overflow(&mut self, overflow: IntOverflow) {
+        self.int_overflow = overflow;
+    }
+
+    /// Checks whether a function can be executed.
+    ///
+    /// Functions of deserialized schemes describe the arguments they accept,
+    /// so filters calling them can be parsed, but have no implementations.
+    /// [`FilterAst::compile`](::FilterAst::compile) fails with
+    /// [`CompileError::FunctionUnavailable`](::CompileError::FunctionUnavailable)
+    /// for filters calling them.
+    pub fn is_function_available(&self, name: &str) -> bool {
+        !self
+            .unavailable_functions
+            .iter()
+            .any(|unavailable| unavailable == name)
+    }
+
+    pub(crate) fn int_lex_options(&self) -> IntLexOptions {
+        IntLexOptions {
+            decimal_leading_zeros: self.decimal_leading_zeros,
+            overflow: self.int_overflow,
+        }
+    }
+
+    /// Returns tokens that can be inserted at the byte offset `cursor` of a
+    /// partially typed filter, for editor integrations.
+    ///
+    /// Completions include names of fields, functions and lists, and
+    /// operators, that keep the filter valid so far, like operators supported
+    /// by the type of the preceding field, or fields of the type expected by
+    /// a function argument. Literal values are not suggested. Only the input
+    /// before the cursor is taken into account, and there are no completions
+    /// if `cursor` is out of range or not on a `char` boundary of the input.
+    pub fn complete(&self, input: &str, cursor: usize) -> Completions {
+        completions(self, input, cursor)
+    }
+
+    /// Parses a filter into an AST form.
+    ///
+    /// A filter can start with definitions of named sub-expressions, like
+    /// `let is_internal = ip.src in { 10.0.0.0/8 }; is_internal and ssl`.
+    /// References to them are expanded into parenthesized expressions, and
+    /// each definition is evaluated at most once per execution of the
+    /// compiled filter. Expansions are limited to 65536 expressions in total.
+    pub fn parse<'i>(&'s self, input: &'i str) -> Result<FilterAst<'s>, ParseError<'i>> {
+        self.parse_with_warnings(input).map(|(ast, _)| ast)
+    }
+
+    /// Parses a filter into an AST form and reports any warnings.
+    pub fn parse_with_warnings<'i>(
+        &'s self,
+        input: &'i str,
+    ) -> Result<(FilterAst<'s>, Vec<ParseWarning<'i>>), ParseError<'i>> {
+        let state = ParseState::new(self);
+        let ast = self.parse_with_state(input, &state)?;
+        let (warnings, _) = state.finish();
+        let warnings = warnings
+            .into_iter()
+            .map(|warning| ParseWarning(ParseError::new(input, warning)))
+            .collect();
+        Ok((ast, warnings))
+    }
+
+    fn parse_with_state<'i>(
+        &'s self,
+        input: &'i str,
+        state: &ParseState<'i, 's>,
+    ) -> Result<FilterAst<'s>, ParseError<'i>> {
+        complete(FilterAst::lex_with_state(input.trim(), state))
+            .map_err(|err| ParseError::new(input, err))
+    }
+
+    /// Parses a filter into an AST form and records positions of its
+    /// comparisons, which are used to map [lints](Lint::spans) and profiles
+    /// of filters compiled with `FilterAst::compile_with_profile` to the
+    /// source.
+    pub fn parse_with_spans<'i>(
+        &'s self,
+        input: &'i str,
+    ) -> Result<(FilterAst<'s>, ExprSpans), ParseError<'i>> {
+        let state = ParseState::new(self).with_spans(input);
+        let ast = self.parse_with_state(input, &state)?;
+        let (_, spans) = state.finish();
+        Ok((ast, spans.unwrap_or_default()))
+    }
+
+    /// Checks whether filters that are valid for this scheme are parsed the
+    /// same way with another one, like a newer version of it, and returns all
+    /// differences that break this.
+    ///
+    /// Fields, functions, lists and constants that are only present in the
+    /// other scheme don't affect existing filters and aren't reported.
+    pub fn check_compatibility(&self, other: &Scheme) -> Vec<SchemeIncompatibility> {
+        let mut incompatibilities = Vec::new();
+
+        for (name, &expected) in &self.fields {
+            match other.fields.get(name) {
+                None => incompatibilities.push(SchemeIncompatibility::MissingField(name.clone())),
+                Some(&actual) if actual != expected => {
+                    incompatibilities.push(SchemeIncompatibility::FieldTypeChanged {
+                        name: name.clone(),
+                        expected,
+                        actual,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (name, overloads) in &self.functions {
+            match other.functions.get(name) {
+                None => {
+                    incompatibilities.push(SchemeIncompatibility::MissingFunction(name.clone()))
+                }
+                Some(other_overloads) => {
+                    let actual = other_overloads
+                        .iter()
+                        .map(Function::signature)
+                        .collect::<Vec<_>>();
+                    for function in overloads {
+                        let expected = function.signature();
+                        if !actual.contains(&expected) {
+                            incompatibilities.push(
+                                SchemeIncompatibility::FunctionSignatureChanged {
+                                    name: name.clone(),
+                                    expected,
+                                    actual: actual.join(" | "),
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for (name, &expected) in &self.lists {
+            match other.lists.get(name) {
+                None => incompatibilities.push(SchemeIncompatibility::MissingList(name.clone())),
+                Some(&actual) if actual != expected => {
+                    incompatibilities.push(SchemeIncompatibility::ListTypeChanged {
+                        name: name.clone(),
+                        expected,
+                        actual,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (name, expected) in &self.constants {
+            match other.constants.get(name) {
+                None => {
+                    incompatibilities.push(SchemeIncompatibility::MissingConstant(name.clone()))
+                }
+                Some(actual) if actual != expected => {
+                    incompatibilities.push(SchemeIncompatibility::ConstantChanged(name.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        incompatibilities
+    }
+
+    /// Produces a canonical textual dump of all registered fields and
+    /// functions.
+    ///
+    /// Items are sorted by name so that the output doesn't depend on the
+    /// registration order, which makes it suitable for golden-file snapshot
+    /// tests.
+    pub fn snapshot(&self) -> String {
+        let mut out = String::new();
+
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by_key(|&(name, _)| name);
+
+        out.push_str("fields:\n");
+        for (name, ty) in fields {
+            out.push_str(&format!("  {}: {:?}\n", name, ty));
+        }
+
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|&(name, _)| name);
+
+        out.push_str("functions:\n");
+        for (name, overloads) in functions {
+            for function in overloads {
+                out.push_str(&format!("  {}{}\n", name, function.signature()));
+            }
+        }
+
+        out
+    }
+}
+
+// Serialized form of a scheme:
+//
+// ```json
+// {
+//   "fields": { "http.host": "Bytes", "tcp.port": "Int" },
+//   "functions": {
+//     "echo": {
+//       "params": [{ "arg_kind": "Field", "val_type": "Bytes" }],
+//       "opt_params": [{ "name": "count", "arg_kind": "Literal", "val_type": "Int", "default_value": 10 }],
+//       "return_type": "Bytes"
+//     },
+//     "len": [
+//       { "params": [{ "arg_kind": "Field", "val_type": "Bytes" }], "return_type": "Int" },
+//       { "params": [{ "arg_kind": "Field", "val_type": "Ip" }], "return_type": "Int" }
+//     ]
+//   },
+//   "lists": { "malicious_domains": "Bytes" },
+//   "constants": {
+//     "web_ports": { "type": "Int", "values": ["80", "443", "8000..8080"] }
+//   },
+//   "metadata": {
+//     "http.host": { "description": "Host header", "examples": ["\"example.org\""], "category": "http" },
+//     "len": { "deprecated": "use `length` instead" }
+//   },
+//   "max_nesting_depth": 128,
+//   "lenient_bool_comparisons": false,
+//   "unicode_regexes": false,
+//   "ipv4_mapped_equivalence": false,
+//   "decimal_leading_zeros": false,
+//   "int_overflow": "Error"
+// }
+// ```
+//
+// A plain map of fields to types, which was the only supported form before,
+// is accepted too.
+#[derive(Serialize, Deserialize)]
+struct SchemeRepr {
+    fields: IndexMap<String, Type, FnvBuildHasher>,
+    #[serde(default)]
+    functions: IndexMap<String, FunctionReprs, FnvBuildHasher>,
+    #[serde(default)]
+    lists: IndexMap<String, Type, FnvBuildHasher>,
+    #[serde(default)]
+    constants: IndexMap<String, ConstantRepr, FnvBuildHasher>,
+    #[serde(default)]
+    metadata: IndexMap<String, Metadata, FnvBuildHasher>,
+    #[serde(default = "default_max_nesting_depth")]
+    max_nesting_depth: usize,
+    #[serde(default)]
+    lenient_bool_comparisons: bool,
+    #[serde(default)]
+    unicode_regexes: bool,
+    #[serde(default)]
+    ipv4_mapped_equivalence: bool,
+    #[serde(default)]
+    decimal_leading_zeros: bool,
+    #[serde(default)]
+    int_overflow: IntOverflow,
+}
+
+fn default_max_nesting_depth() -> usize {
+    Scheme::DEFAULT_MAX_NESTING_DEPTH
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SchemeReprOrFields {
+    Scheme(Box<SchemeRepr>),
+    Fields(IndexMap<String, Type, FnvBuildHasher>),
+}
+
+// Values of a constant are represented by literals in the filter syntax.
+#[derive(Serialize, Deserialize)]
+struct ConstantRepr {
+    #[serde(rename = "type")]
+    ty: Type,
+    values: Vec<String>,
+}
+
+impl<'a> From<&'a RhsValues> for ConstantRepr {
+    fn from(values: &'a RhsValues) -> Self {
+        ConstantRepr {
+            ty: values.get_type(),
+            values: values_to_strings(values),
+        }
+    }
+}
+
+impl ConstantRepr {
+    fn into_values<E: de::Error>(
+        self,
+        name: &str,
+        int_options: IntLexOptions,
+    ) -> Result<RhsValues, E> {
+        let source = format!("{{ {} }}", self.values.join(" "));
+        let state = LexState::new(int_options);
+        complete(RhsValues::lex_with(&source, (self.ty, &state))).map_err(|(err, _)| {
+            E::custom(format_args!("invalid values of constant {}: {}", name, err))
+        })
+    }
+}
+
+// A function with a single signature is represented by an object, and one
+// with overloads by an array of them.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FunctionReprs {
+    Function(FunctionRepr),
+    Overloads(Vec<FunctionRepr>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionRepr {
+    params: Vec<FunctionParam>,
+    #[serde(default)]
+    opt_params: Vec<FunctionOptParamRepr>,
+    return_type: Type,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionOptParamRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    arg_kind: FunctionArgKind,
+    val_type: Type,
+    default_value: Value,
+}
+
+// Same as `lhs_value_from_json`, except that non-UTF-8 byte strings are
+// represented as arrays.
+fn default_value_to_json(value: &LhsValue<'_>) -> Value {
+    match value {
+        LhsValue::Ip(addr) => json!(addr.to_string()),
+        LhsValue::Bytes(bytes) => match str::from_utf8(bytes) {
+            Ok(s) => json!(s),
+            Err(_) => json!(bytes),
+        },
+        LhsValue::Str(s) => json!(s),
+        LhsValue::Int(int) => json!(int),
+        LhsValue::Bool(b) => json!(b),
+        LhsValue::Duration(duration) => {
+            json!(u64::try_from(duration.as_nanos()).unwrap_or(u64::max_value()))
+        }
+        LhsValue::Mac(addr) => json!(addr.to_string()),
+    }
+}
+
+fn default_value_from_json(value: &Value, ty: Type) -> Option<LhsValue<'static>> {
+    if let (Type::Bytes, Some(bytes)) = (ty, value.as_array()) {
+        return bytes
+            .iter()
+            .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .map(LhsValue::from);
+    }
+    Some(match lhs_value_from_json(value, ty)? {
+        LhsValue::Bytes(bytes) => LhsValue::Bytes(Cow::Owned(bytes.into_owned())),
+        LhsValue::Str(s) => LhsValue::Str(Cow::Owned(s.into_owned())),
+        LhsValue::Ip(addr) => LhsValue::Ip(addr),
+        LhsValue::Int(int) => LhsValue::Int(int),
+        LhsValue::Bool(b) => LhsValue::Bool(b),
+        LhsValue::Duration(duration) => LhsValue::Duration(duration),
+        LhsValue::Mac(addr) => LhsValue::Mac(addr),
+    })
+}
+
+// Implementation of functions in deserialized schemes, which are only meant
+// for parsing filters. Filters calling them can't be compiled, and
+// simplification doesn't evaluate them, so it's never called.
+fn unavailable_function<'a>(_args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    panic!("functions of deserialized schemes can't be executed")
+}
+
+impl Serialize for Scheme {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        SchemeRepr {
+            fields: self.fields.clone(),
+            functions: self
+                .functions
+                .iter()
+                .map(|(name, overloads)| {
+                    let repr = match overloads.as_slice() {
+                        [function] => FunctionReprs::Function(FunctionRepr::from(function)),
+                        overloads => FunctionReprs::Overloads(
+                            overloads.iter().map(FunctionRepr::from).collect(),
+                        ),
+                    };
+                    (name.clone(), repr)
+                })
+                .collect(),
+            lists: self.lists.clone(),
+            constants: self
+                .constants
+                .iter()
+                .map(|(name, values)| (name.clone(), ConstantRepr::from(values)))
+                .collect(),
+            metadata: self.metadata.clone(),
+            max_nesting_depth: self.max_nesting_depth,
+            lenient_bool_comparisons: self.lenient_bool_comparisons,
+            unicode_regexes: self.unicode_regexes,
+            ipv4_mapped_equivalence: self.ipv4_mapped_equivalence,
+            decimal_leading_zeros: self.decimal_leading_zeros,
+            int_overflow: self.int_overflow,
+        }
+        .serialize(ser)
+    }
+}
+
+/// Functions of deserialized schemes accept the same arguments as the
+/// original ones, but aren't [available](Scheme::is_function_available) for
+/// execution, and computed default values are replaced with the static ones.
+impl<'de> Deserialize<'de> for Scheme {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let repr = match SchemeReprOrFields::deserialize(de)? {
+            SchemeReprOrFields::Scheme(repr) => *repr,
+            SchemeReprOrFields::Fields(fields) => SchemeRepr {
+                fields,
+                functions: Default::default(),
+                lists: Default::default(),
+                constants: Default::default(),
+                metadata: Default::default(),
+                max_nesting_depth: Scheme::DEFAULT_MAX_NESTING_DEPTH,
+                lenient_bool_comparisons: false,
+                unicode_regexes: false,
+                ipv4_mapped_equivalence: false,
+                decimal_leading_zeros: false,
+                int_overflow: IntOverflow::Error,
+            },
+        };
+
+        let mut scheme = Scheme {
+            fields: repr.fields,
+            lists: repr.lists,
+            max_nesting_depth: repr.max_nesting_depth,
+            lenient_bool_comparisons: repr.lenient_bool_comparisons,
+            unicode_regexes: repr.unicode_regexes,
+            ipv4_mapped_equivalence: repr.ipv4_mapped_equivalence,
+            decimal_leading_zeros: repr.decimal_leading_zeros,
+            int_overflow: repr.int_overflow,
+            ..Default::default()
+        };
+
+        for (name, reprs) in repr.functions {
+            let overloads = match reprs {
+                FunctionReprs::Function(function) => vec![function],
+                FunctionReprs::Overloads(overloads) => overloads,
+            };
+
+            for function in overloads {
+                scheme
+                    .add_function_overload(name.clone(), function.into_function(&name)?)
+                    .map_err(de::Error::custom)?;
+            }
+            scheme.unavailable_functions.push(name);
+        }
+
+        for (name, repr) in repr.constants {
+            let values = repr.into_values(&name, scheme.int_lex_options())?;
+            scheme.constants.insert(name, values);
+        }
+
+        for (name, metadata) in repr.metadata {
+            if !scheme.fields.contains_key(&name) && !scheme.functions.contains_key(&name) {
+                return Err(de::Error::custom(format_args!(
+                    "metadata of unknown field or function {}",
+                    name
+                )));
+            }
+            scheme.metadata.insert(name, metadata);
+        }
+
+        Ok(scheme)
+    }
+}
+
+impl<'a> From<&'a Function> for FunctionRepr {
+    fn from(function: &'a Function) -> Self {
+        FunctionRepr {
+            params: function.params.clone(),
+            opt_params: function
+                .opt_params
+                .iter()
+                .map(|param| FunctionOptParamRepr {
+                    name: param.name.clone(),
+                    arg_kind: param.arg_kind.clone(),
+                    val_type: param.default_value.get_type(),
+                    default_value: default_value_to_json(&param.default_value),
+                })
+                .collect(),
+            return_type: function.return_type,
+        }
+    }
+}
+
+impl FunctionRepr {
+    fn into_function<E: de::Error>(self, name: &str) -> Result<Function, E> {
+        let opt_params = self
+            .opt_params
+            .into_iter()
+            .map(|param| {
+                let default_value = default_value_from_json(&param.default_value, param.val_type)
+                    .ok_or_else(|| {
+                    E::custom(format!(
+                        "invalid default value of type {:?} for function {}",
+                        param.val_type, name
+                    ))
+                })?;
+                Ok(FunctionOptParam {
+                    name: param.name,
+                    arg_kind: param.arg_kind,
+                    default_value,
+                    default_fn: None,
+                })
+            })
+            .collect::<Result<_, E>>()?;
+
+        Ok(Function {
+            params: self.params,
+            opt_params,
+            return_type: self.return_type,
+            implementation: FunctionImpl::new(unavailable_function),
+        })
+    }
+}
+
+/// A convenience macro for constructing a [`Scheme`](struct@Scheme) with static
+/// contents.
+#[macro_export]
+macro_rules! Scheme {
+    ($($ns:ident $(. $field:ident)*: $ty:ident),* $(,)*) => {
+        $crate::Scheme::try_from_iter(
+            [$(
+                (
+                    concat!(stringify!($ns) $(, ".", stringify!($field))*),
+                    $crate::Type::$ty
+                )
+            ),*]
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v)),
+        )
+        // Treat duplciations in static schemes as a developer's mistake.
+        .unwrap_or_else(|err| panic!("{}", err))
+    };
+}
+
+#[test]
+fn test_parse_error() {
+    use indoc::indoc;
+
+    let scheme = &Scheme! { num: Int };
+
+    {
+        let err = scheme.parse("xyz").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                kind: LexErrorKind::UnknownField(UnknownFieldError),
+                input: "xyz",
+                line_number: 0,
+                span_start: 0,
+                span_len: 3,
+                offset: 0,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            indoc!(
+                r#"
+                Filter parsing error (1:1):
+                xyz
+                ^^^ unknown field
+                "#
+            )
+        );
+    }
+
+    {
+        let err = scheme.parse("xyz\n").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                kind: LexErrorKind::UnknownField(UnknownFieldError),
+                input: "xyz",
+                line_number: 0,
+                span_start: 0,
+                span_len: 3,
+                offset: 0,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            indoc!(
+                r#"
+                Filter parsing error (1:1):
+                xyz
+                ^^^ unknown field
+                "#
+            )
+        );
     }
 
     {
@@ -387,7 +1591,8 @@ fn test_parse_error() {
                 input: "    xyz",
                 line_number: 2,
                 span_start: 4,
-                span_len: 3
+                span_len: 3,
+                offset: 6,
             }
         );
         assert_eq!(
@@ -419,7 +1624,8 @@ fn test_parse_error() {
                 input: "num == true or",
                 line_number: 1,
                 span_start: 7,
-                span_len: 7
+                span_len: 7,
+                offset: 20,
             }
         );
         assert_eq!(
@@ -432,6 +1638,22 @@ fn test_parse_error() {
                 "#
             )
         );
+        assert_eq!(err.message(), "expected digit");
+        assert_eq!(err.span(), 20..27);
+        assert_eq!(err.token(), "true or");
+        assert_eq!((err.line(), err.column()), (2, 8));
+        assert_eq!(
+            err.render("rules.txt"),
+            indoc!(
+                r#"
+                error: expected digit
+                 --> rules.txt:2:8
+                  |
+                2 | num == true or
+                  |        ^^^^^^^
+                "#
+            )
+        );
     }
 }
 
@@ -495,3 +1717,671 @@ fn test_field_type_override() {
         ItemRedefinitionError::Field(FieldRedefinitionError("foo".into()))
     )
 }
+
+#[test]
+fn test_snapshot() {
+    use crate::functions::{
+        FunctionArgKind, FunctionArgs, FunctionImpl, FunctionOptParam, FunctionParam,
+    };
+    use crate::types::LhsValue;
+    use indoc::indoc;
+
+    fn echo<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let mut scheme = Scheme! {
+        num: Int,
+        str: Bytes,
+        ip: Ip,
+    };
+
+    scheme
+        .add_function(
+            "echo".into(),
+            Function {
+                params: vec![FunctionParam {
+                    arg_kind: FunctionArgKind::Field,
+                    val_type: Type::Bytes,
+                    literal_check: None,
+                }],
+                opt_params: vec![
+                    FunctionOptParam::new(FunctionArgKind::Literal, "abc"),
+                    FunctionOptParam::new(FunctionArgKind::Literal, LhsValue::Int(10)),
+                ],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::new(echo),
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        scheme.snapshot(),
+        indoc!(
+            r#"
+            fields:
+              ip: Ip
+              num: Int
+              str: Bytes
+            functions:
+              echo(field Bytes, literal Bytes = "abc", literal Int = 10) -> Bytes
+            "#
+        )
+    );
+
+    let ast = scheme.parse(r#"num > 3 && str == "abc""#).unwrap();
+
+    assert_eq!(
+        ast.snapshot(),
+        indoc!(
+            r#"
+            {
+              "op": "And",
+              "items": [
+                {
+                  "lhs": "num",
+                  "op": "GreaterThan",
+                  "rhs": 3
+                },
+                {
+                  "lhs": "str",
+                  "op": "Equal",
+                  "rhs": "abc"
+                }
+              ]
+            }"#
+        )
+    );
+}
+
+#[test]
+fn test_serde() {
+    fn echo<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let echo_function = |default_value: LhsValue<'static>| Function {
+        params: vec![FunctionParam {
+            arg_kind: FunctionArgKind::Field,
+            val_type: Type::Bytes,
+            literal_check: None,
+        }],
+        opt_params: vec![FunctionOptParam::new(
+            FunctionArgKind::Literal,
+            default_value,
+        )],
+        return_type: Type::Bytes,
+        implementation: FunctionImpl::new(echo),
+    };
+
+    let mut scheme = Scheme! { num: Int, str: Bytes };
+    scheme
+        .add_function("echo".into(), echo_function(b"\xff"[..].to_vec().into()))
+        .unwrap();
+    scheme.add_list("hosts".into(), Type::Bytes).unwrap();
+    scheme.set_lenient_bool_comparisons(true);
+
+    let value = serde_json::to_value(&scheme).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "fields": { "num": "Int", "str": "Bytes" },
+            "functions": {
+                "echo": {
+                    "params": [{ "arg_kind": "Field", "val_type": "Bytes" }],
+                    "opt_params": [{
+                        "arg_kind": "Literal",
+                        "val_type": "Bytes",
+                        "default_value": [255]
+                    }],
+                    "return_type": "Bytes"
+                }
+            },
+            "lists": { "hosts": "Bytes" },
+            "constants": {},
+            "metadata": {},
+            "max_nesting_depth": 128,
+            "lenient_bool_comparisons": true,
+            "unicode_regexes": false,
+            "ipv4_mapped_equivalence": false,
+            "decimal_leading_zeros": false,
+            "int_overflow": "Error"
+        })
+    );
+
+    let copy: Scheme = serde_json::from_value(value).unwrap();
+    assert_eq!(copy.snapshot(), scheme.snapshot());
+    assert!(copy.lenient_bool_comparisons());
+    assert!(copy.parse(r#"echo(str) == "abc" && num > 1"#).is_ok());
+    assert!(copy.parse("str in $hosts").is_ok());
+    assert_eq!(scheme.check_compatibility(&copy), []);
+
+    // plain maps of fields are still supported
+    let fields: Scheme = serde_json::from_str(r#"{ "num": "Int", "fields": "Bytes" }"#).unwrap();
+    assert_eq!(
+        fields.snapshot(),
+        "fields:\n  fields: Bytes\n  num: Int\nfunctions:\n"
+    );
+    assert_eq!(
+        fields.max_nesting_depth(),
+        Scheme::DEFAULT_MAX_NESTING_DEPTH
+    );
+
+    assert!(serde_json::from_value::<Scheme>(json!({
+        "fields": {},
+        "functions": {
+            "echo": {
+                "params": [],
+                "opt_params": [{ "arg_kind": "Literal", "val_type": "Int", "default_value": "abc" }],
+                "return_type": "Int"
+            }
+        }
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_metadata() {
+    fn id<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let mut scheme = Scheme! { http.host: Bytes, tcp.port: Int };
+    scheme
+        .add_function(
+            "lower".into(),
+            Function {
+                params: vec![FunctionParam {
+                    arg_kind: FunctionArgKind::Field,
+                    val_type: Type::Bytes,
+                    literal_check: None,
+                }],
+                opt_params: vec![],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::new(id),
+            },
+        )
+        .unwrap();
+
+    let host = Metadata {
+        description: Some("Host header".into()),
+        examples: vec!["\"example.org\"".into()],
+        deprecated: None,
+        category: Some("http".into()),
+    };
+    let lower = Metadata {
+        deprecated: Some("use `lowercase` instead".into()),
+        ..Default::default()
+    };
+    scheme
+        .set_field_metadata("http.host", host.clone())
+        .unwrap();
+    scheme
+        .set_function_metadata("lower", lower.clone())
+        .unwrap();
+    assert_eq!(
+        scheme.set_field_metadata("lower", Metadata::default()),
+        Err(UnknownFieldError)
+    );
+    assert_eq!(
+        scheme.set_function_metadata("http.host", Metadata::default()),
+        Err(UnknownFunctionError)
+    );
+
+    assert_eq!(
+        scheme
+            .iter_fields()
+            .map(|field| (field.name(), field.metadata()))
+            .collect::<Vec<_>>(),
+        [("http.host", Some(&host)), ("tcp.port", None)]
+    );
+    assert_eq!(scheme.function_names().collect::<Vec<_>>(), ["lower"]);
+    assert_eq!(scheme.function_metadata("lower"), Ok(Some(&lower)));
+    assert_eq!(scheme.function_metadata("upper"), Err(UnknownFunctionError));
+
+    let value = serde_json::to_value(&scheme).unwrap();
+    assert_eq!(
+        value["metadata"],
+        json!({
+            "http.host": {
+                "description": "Host header",
+                "examples": ["\"example.org\""],
+                "category": "http"
+            },
+            "lower": { "deprecated": "use `lowercase` instead" }
+        })
+    );
+
+    let copy: Scheme = serde_json::from_value(value).unwrap();
+    assert_eq!(copy.get_field("http.host").unwrap().metadata(), Some(&host));
+    assert_eq!(copy.function_metadata("lower"), Ok(Some(&lower)));
+
+    assert!(serde_json::from_value::<Scheme>(json!({
+        "fields": { "tcp.port": "Int" },
+        "metadata": { "http.host": { "description": "Host header" } }
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_constants() {
+    let mut scheme = Scheme! { tcp.port: Int };
+    scheme
+        .add_constant(
+            "web_ports".into(),
+            RhsValues::Int(vec![80.into()..=80.into(), 8000.into()..=8080.into()]),
+        )
+        .unwrap();
+    assert!(scheme
+        .add_constant("web_ports".into(), RhsValues::Int(vec![]))
+        .is_err());
+    assert_eq!(scheme.constant_names().collect::<Vec<_>>(), ["web_ports"]);
+
+    let value = serde_json::to_value(&scheme).unwrap();
+    assert_eq!(
+        value["constants"],
+        json!({ "web_ports": { "type": "Int", "values": ["80", "8000..8080"] } })
+    );
+
+    let copy: Scheme = serde_json::from_value(value).unwrap();
+    assert_eq!(
+        copy.get_constant("web_ports"),
+        scheme.get_constant("web_ports")
+    );
+    assert_eq!(scheme.check_compatibility(&copy), []);
+    assert!(copy.parse("tcp.port in web_ports").is_ok());
+
+    let mut changed = Scheme! { tcp.port: Int };
+    changed
+        .add_constant(
+            "web_ports".into(),
+            RhsValues::Int(vec![80.into()..=80.into()]),
+        )
+        .unwrap();
+    assert_eq!(
+        scheme.check_compatibility(&changed),
+        [SchemeIncompatibility::ConstantChanged("web_ports".into())]
+    );
+    assert_eq!(
+        scheme.check_compatibility(&Scheme! { tcp.port: Int }),
+        [SchemeIncompatibility::MissingConstant("web_ports".into())]
+    );
+
+    assert!(serde_json::from_value::<Scheme>(json!({
+        "fields": {},
+        "constants": { "ports": { "type": "Int", "values": ["http"] } }
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_decimal_leading_zeros() {
+    use crate::{
+        execution_context::ExecutionContext,
+        rhs_types::{IntFormat, IntLiteral, IntRadix},
+    };
+
+    let mut scheme = Scheme! { tcp.port: Int };
+    assert!(scheme.parse("tcp.port == 0080").is_err());
+
+    scheme.set_decimal_leading_zeros(true);
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("tcp.port", 80).unwrap();
+    let filter = scheme.parse("tcp.port == 0080").unwrap().compile().unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(true));
+
+    // constants are deserialized with the setting of their scheme
+    let copy: Scheme = serde_json::from_value(json!({
+        "fields": { "tcp.port": "Int" },
+        "constants": { "ports": { "type": "Int", "values": ["0080"] } },
+        "decimal_leading_zeros": true
+    }))
+    .unwrap();
+    assert!(copy.decimal_leading_zeros());
+    let port = IntLiteral::new(
+        80,
+        IntFormat {
+            radix: IntRadix::Decimal,
+            width: 4,
+            ..IntFormat::default()
+        },
+    );
+    assert_eq!(
+        copy.get_constant("ports"),
+        Ok(&RhsValues::Int(vec![port..=port]))
+    );
+}
+
+#[test]
+fn test_int_overflow() {
+    use crate::execution_context::ExecutionContext;
+
+    let mut scheme = Scheme! { tcp.port: Int };
+    assert!(scheme.parse("tcp.port != 0xffffffffffffffff").is_err());
+
+    scheme.set_int_overflow(IntOverflow::Saturate);
+    let filter = "tcp.port < 0xffffffffffffffff and tcp.port > -99999999999";
+    let (ast, warnings) = scheme.parse_with_warnings(filter).unwrap();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| (warning.code(), warning.token()))
+            .collect::<Vec<_>>(),
+        [
+            ("int-overflow", "ffffffffffffffff"),
+            ("int-overflow", "-99999999999")
+        ]
+    );
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("tcp.port", 80).unwrap();
+    assert_eq!(ast.compile().unwrap().execute(&ctx), Ok(true));
+
+    // invalid digits are still rejected
+    assert!(scheme.parse("tcp.port == 0b12").is_err());
+
+    let value = serde_json::to_value(&scheme).unwrap();
+    assert_eq!(value["int_overflow"], "Saturate");
+    let copy: Scheme = serde_json::from_value(value).unwrap();
+    assert_eq!(copy.int_overflow(), IntOverflow::Saturate);
+}
+
+#[test]
+fn test_deserialized_function() {
+    use crate::{execution_context::ExecutionContext, filter::CompileError};
+
+    let mut scheme: Scheme = serde_json::from_value(json!({
+        "fields": { "ssl": "Bool" },
+        "functions": {
+            "f": { "params": [], "return_type": "Bool" }
+        }
+    }))
+    .unwrap();
+    scheme
+        .add_function(
+            "g".into(),
+            Function {
+                params: vec![],
+                opt_params: vec![],
+                return_type: Type::Bool,
+                implementation: FunctionImpl::new(|_| LhsValue::Bool(true)),
+            },
+        )
+        .unwrap();
+
+    assert!(!scheme.is_function_available("f"));
+    assert!(scheme.is_function_available("g"));
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("ssl", false).unwrap();
+
+    let mut ast = scheme.parse("ssl or f()").unwrap();
+    assert_eq!(ast.simplify(), None);
+    assert_eq!(ast.specialize(&ctx), Ok(None));
+    assert_eq!(
+        ast.compile().err(),
+        Some(CompileError::FunctionUnavailable {
+            function: "f".into()
+        })
+    );
+
+    let filter = scheme.parse("ssl or g()").unwrap().compile().unwrap();
+    assert_eq!(filter.execute_catching(&ctx), Ok(true));
+}
+
+#[test]
+fn test_check_compatibility() {
+    fn id<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let function = |return_type| Function {
+        params: vec![FunctionParam {
+            arg_kind: FunctionArgKind::Field,
+            val_type: return_type,
+            literal_check: None,
+        }],
+        opt_params: vec![],
+        return_type,
+        implementation: FunctionImpl::new(id),
+    };
+
+    let mut old = Scheme! { a: Int, b: Bytes, c: Ip };
+    old.add_function("f".into(), function(Type::Int)).unwrap();
+    old.add_function("g".into(), function(Type::Int)).unwrap();
+    old.add_list("l".into(), Type::Ip).unwrap();
+    old.add_list("m".into(), Type::Bytes).unwrap();
+
+    let mut new = Scheme! { a: Int, b: Int, d: Bool };
+    new.add_function("f".into(), function(Type::Bytes)).unwrap();
+    new.add_function("h".into(), function(Type::Int)).unwrap();
+    new.add_list("l".into(), Type::Int).unwrap();
+
+    assert_eq!(old.check_compatibility(&old), []);
+    assert_eq!(
+        old.check_compatibility(&new),
+        [
+            SchemeIncompatibility::FieldTypeChanged {
+                name: "b".into(),
+                expected: Type::Bytes,
+                actual: Type::Int,
+            },
+            SchemeIncompatibility::MissingField("c".into()),
+            SchemeIncompatibility::FunctionSignatureChanged {
+                name: "f".into(),
+                expected: "(field Int) -> Int".into(),
+                actual: "(field Bytes) -> Bytes".into(),
+            },
+            SchemeIncompatibility::MissingFunction("g".into()),
+            SchemeIncompatibility::ListTypeChanged {
+                name: "l".into(),
+                expected: Type::Ip,
+                actual: Type::Int,
+            },
+            SchemeIncompatibility::MissingList("m".into()),
+        ]
+    );
+    assert_eq!(
+        old.check_compatibility(&new)[0].to_string(),
+        "field b has type Int instead of Bytes"
+    );
+
+    // only additions
+    assert_eq!(Scheme! { a: Int }.check_compatibility(&old), []);
+}
+
+#[test]
+fn test_function_overloads() {
+    fn id<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let function = |ty| Function {
+        params: vec![FunctionParam {
+            arg_kind: FunctionArgKind::Field,
+            val_type: ty,
+            literal_check: None,
+        }],
+        opt_params: vec![],
+        return_type: ty,
+        implementation: FunctionImpl::new(id),
+    };
+
+    let mut scheme = Scheme! { a: Int, b: Bytes };
+    scheme
+        .add_function_overload("f".into(), function(Type::Int))
+        .unwrap();
+    scheme
+        .add_function_overload("f".into(), function(Type::Bytes))
+        .unwrap();
+
+    assert_eq!(
+        scheme.add_function_overload("f".into(), function(Type::Int)),
+        Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+            "f".into()
+        )))
+    );
+    assert_eq!(
+        scheme.add_function("f".into(), function(Type::Ip)),
+        Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+            "f".into()
+        )))
+    );
+    assert_eq!(
+        scheme.add_function_overload("a".into(), function(Type::Ip)),
+        Err(ItemRedefinitionError::Field(FieldRedefinitionError(
+            "a".into()
+        )))
+    );
+
+    // either all overloads are registered or none
+    assert_eq!(
+        scheme.add_function_overloads("f".into(), vec![function(Type::Ip), function(Type::Int)]),
+        Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+            "f".into()
+        )))
+    );
+    assert_eq!(
+        scheme.add_function_overloads("g".into(), vec![function(Type::Ip), function(Type::Ip)]),
+        Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+            "g".into()
+        )))
+    );
+    assert_eq!(scheme.get_function_overloads("f").unwrap().len(), 2);
+    assert!(scheme.get_function_overloads("g").is_err());
+
+    assert!(scheme.parse(r#"f(a) == 1 and f(b) == "b""#).is_ok());
+    assert!(scheme.parse("f(b) == 1").is_err());
+
+    assert_eq!(
+        scheme.snapshot(),
+        "fields:\n  a: Int\n  b: Bytes\nfunctions:\n  f(field Int) -> Int\n  f(field Bytes) -> Bytes\n"
+    );
+
+    let value = serde_json::to_value(&scheme).unwrap();
+    assert_eq!(
+        value["functions"]["f"],
+        json!([
+            { "params": [{ "arg_kind": "Field", "val_type": "Int" }], "opt_params": [], "return_type": "Int" },
+            { "params": [{ "arg_kind": "Field", "val_type": "Bytes" }], "opt_params": [], "return_type": "Bytes" },
+        ])
+    );
+
+    let copy: Scheme = serde_json::from_value(value).unwrap();
+    assert_eq!(copy.snapshot(), scheme.snapshot());
+    assert_eq!(scheme.check_compatibility(&copy), []);
+
+    let mut old = Scheme! { a: Int, b: Bytes };
+    old.add_function("f".into(), function(Type::Ip)).unwrap();
+    assert_eq!(
+        old.check_compatibility(&scheme),
+        [SchemeIncompatibility::FunctionSignatureChanged {
+            name: "f".into(),
+            expected: "(field Ip) -> Ip".into(),
+            actual: "(field Int) -> Int | (field Bytes) -> Bytes".into(),
+        }]
+    );
+}
+
+#[test]
+fn test_generic_function() {
+    use crate::functions::{GenericFunctionParam, GenericType};
+
+    fn first<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    let function = GenericFunction {
+        params: vec![
+            GenericFunctionParam {
+                arg_kind: FunctionArgKind::Field,
+                val_type: GenericType::Var,
+            },
+            GenericFunctionParam {
+                arg_kind: FunctionArgKind::Literal,
+                val_type: GenericType::Var,
+            },
+        ],
+        opt_params: vec![],
+        return_type: GenericType::Var,
+        types: vec![Type::Int, Type::Bytes],
+        implementation: FunctionImpl::new(first),
+    };
+
+    assert_eq!(
+        function.instantiate(Type::Ip).signature(),
+        "(field Ip, literal Ip) -> Ip"
+    );
+
+    let mut scheme = Scheme! { a: Int, b: Bytes, c: Ip };
+    scheme
+        .add_generic_function("first".into(), function.clone())
+        .unwrap();
+
+    assert_eq!(
+        scheme.snapshot(),
+        "fields:\n  a: Int\n  b: Bytes\n  c: Ip\nfunctions:\n  first(field Int, literal Int) -> Int\n  first(field Bytes, literal Bytes) -> Bytes\n"
+    );
+
+    assert!(scheme.parse("first(a, 1) == 2").is_ok());
+    assert!(scheme.parse(r#"first(b, "x") == "y""#).is_ok());
+    assert!(scheme.parse(r#"first(a, "x") == 1"#).is_err());
+    assert!(scheme.parse(r#"first(b, "x") == 1"#).is_err());
+    assert!(scheme.parse("first(c, 10.0.0.1) == 10.0.0.1").is_err());
+
+    assert_eq!(
+        scheme.add_generic_function("first".into(), function),
+        Err(ItemRedefinitionError::Function(FunctionRedefinitionError(
+            "first".into()
+        )))
+    );
+}
+
+#[test]
+fn test_max_nesting_depth() {
+    let mut scheme = Scheme! { t: Bool };
+
+    let nested = |depth: usize| format!("{}t{}", "(".repeat(depth), ")".repeat(depth));
+
+    // adversarial input must fail cleanly instead of overflowing the stack
+    let input = nested(100_000);
+    let err = scheme.parse(&input).unwrap_err();
+    assert_eq!(
+        err.kind,
+        LexErrorKind::NestingTooDeep {
+            limit: Scheme::DEFAULT_MAX_NESTING_DEPTH
+        }
+    );
+    assert_eq!(err.span_start, Scheme::DEFAULT_MAX_NESTING_DEPTH);
+    assert_eq!(err.span_len, 1);
+
+    scheme.set_max_nesting_depth(3);
+
+    assert!(scheme.parse(&nested(3)).is_ok());
+    assert!(scheme.parse("not (not t)").is_ok());
+    assert!(scheme.parse("(t) and (t) and ((t))").is_ok());
+
+    assert_eq!(
+        scheme.parse(&nested(4)).unwrap_err().kind,
+        LexErrorKind::NestingTooDeep { limit: 3 }
+    );
+    assert_eq!(
+        scheme.parse("not (not !t)").unwrap_err().kind,
+        LexErrorKind::NestingTooDeep { limit: 3 }
+    );
+
+    // expansions of definitions count as one more level
+    assert!(scheme.parse("let a = ((t)); a").is_ok());
+    assert!(scheme.parse("let a = (t); let b = a; b").is_ok());
+    assert_eq!(
+        scheme.parse("let a = ((t)); (a)").unwrap_err().kind,
+        LexErrorKind::NestingTooDeep { limit: 3 }
+    );
+    assert_eq!(
+        scheme
+            .parse("let a = (t); let b = (a); b")
+            .unwrap_err()
+            .kind,
+        LexErrorKind::NestingTooDeep { limit: 3 }
+    );
+}
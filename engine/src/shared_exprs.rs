@@ -0,0 +1,133 @@
+use crate::ast::{
+    visitor::{walk_simple_expr, Visitor},
+    CombinedExpr, SimpleExpr,
+};
+use fnv::FnvHashMap;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+static NEXT_EXPR_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Results of shared expressions evaluated during a single filter execution,
+/// keyed by expression ID.
+pub(crate) type SharedExprResults = FnvHashMap<usize, bool>;
+
+fn expr_address(expr: &Arc<CombinedExpr<'_>>) -> usize {
+    &**expr as *const CombinedExpr<'_> as usize
+}
+
+// Counts occurrences of parenthesized expressions by their addresses, which
+// are the same only for expansions of the same `let` definition.
+#[derive(Default)]
+struct ExprCounter<'s>(FnvHashMap<usize, (Arc<CombinedExpr<'s>>, usize)>);
+
+impl<'s> Visitor<'s> for ExprCounter<'s> {
+    fn visit_simple_expr(&mut self, expr: &SimpleExpr<'s>) {
+        if let SimpleExpr::Parenthesized(expr) = expr {
+            self.0
+                .entry(expr_address(expr))
+                .or_insert_with(|| (Arc::clone(expr), 0))
+                .1 += 1;
+        }
+        walk_simple_expr(self, expr);
+    }
+}
+
+/// Expressions of a filter being compiled that are shared by several
+/// expansions of `let` definitions, along with their IDs, keyed by their
+/// addresses.
+///
+/// Shared expressions are evaluated at most once per filter execution.
+/// Parenthesized expressions written out several times are evaluated each
+/// time, as functions they call might not be pure. Expressions are retained
+/// so that their addresses can't be reused during compilation.
+#[derive(Default)]
+pub(crate) struct SharedExprs<'s>(FnvHashMap<usize, (Arc<CombinedExpr<'s>>, usize)>);
+
+impl<'s> SharedExprs<'s> {
+    pub(crate) fn new(expr: &CombinedExpr<'s>) -> Self {
+        let mut counter = ExprCounter::default();
+        counter.visit_expr(expr);
+
+        SharedExprs(
+            counter
+                .0
+                .into_iter()
+                .filter(|(_, (_, count))| *count > 1)
+                .map(|(address, (expr, _))| {
+                    (
+                        address,
+                        (expr, NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the ID of a parenthesized expression shared by several
+    /// expansions of a `let` definition.
+    pub(crate) fn find(&self, expr: &Arc<CombinedExpr<'s>>) -> Option<usize> {
+        self.0.get(&expr_address(expr)).map(|&(_, id)| id)
+    }
+}
+
+#[test]
+fn test_shared_exprs() {
+    use crate::{execution_context::ExecutionContext, functions::*, types::*};
+    use std::sync::atomic::AtomicUsize;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        args.next().unwrap()
+    }
+
+    let mut scheme = Scheme! { ssl: Bool, tcp.port: Int };
+    scheme
+        .add_function(
+            "count".into(),
+            Function {
+                params: vec![FunctionParam {
+                    arg_kind: FunctionArgKind::Field,
+                    val_type: Type::Int,
+                    literal_check: None,
+                }],
+                opt_params: vec![],
+                return_type: Type::Int,
+                implementation: FunctionImpl::new(count_function),
+            },
+        )
+        .unwrap();
+
+    let filter = scheme
+        .parse(
+            "let is_web = count(tcp.port) in { 80 443 }; \
+             (is_web and ssl) or (not is_web and not ssl) or (count(tcp.port) in {443 80})",
+        )
+        .unwrap()
+        .compile()
+        .unwrap();
+
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("ssl", false).unwrap();
+    ctx.set_field_value("tcp.port", 22).unwrap();
+
+    assert_eq!(filter.execute(&ctx), Ok(true));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    assert_eq!(filter.execute(&ctx), Ok(true));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    // hand-written expressions are never shared
+    CALLS.store(0, Ordering::SeqCst);
+    let filter = scheme
+        .parse("(count(tcp.port) == 80) or (count(tcp.port) == 80)")
+        .unwrap()
+        .compile()
+        .unwrap();
+    assert_eq!(filter.execute(&ctx), Ok(false));
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
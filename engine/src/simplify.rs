@@ -0,0 +1,362 @@
+use crate::{
+    ast::{
+        unshare_expr, CombinedExpr, CombiningOp, Expr, FieldExpr, FieldOp, FunctionCallArgExpr,
+        LhsFieldExpr, SimpleExpr, UnaryOp,
+    },
+    execution_context::ExecutionContext,
+    scheme::Scheme,
+};
+use std::{mem, sync::Arc};
+
+// Result of simplification of a subexpression.
+enum Folded<'s> {
+    Constant(bool),
+    Expr(CombinedExpr<'s>),
+}
+
+fn into_simple(expr: CombinedExpr<'_>) -> SimpleExpr<'_> {
+    match expr {
+        CombinedExpr::Simple(expr) => expr,
+        expr => SimpleExpr::Parenthesized(Arc::new(expr)),
+    }
+}
+
+fn negate(expr: CombinedExpr<'_>) -> CombinedExpr<'_> {
+    match into_simple(expr) {
+        SimpleExpr::Field(mut expr) if expr.is_negated() => {
+            expr.set_negated(false);
+            CombinedExpr::Simple(SimpleExpr::Field(expr))
+        }
+        SimpleExpr::Unary {
+            op: UnaryOp::Not,
+            arg,
+        } => match *arg {
+            SimpleExpr::Parenthesized(expr) => unshare_expr(expr),
+            arg => CombinedExpr::Simple(arg),
+        },
+        arg => CombinedExpr::Simple(SimpleExpr::Unary {
+            op: UnaryOp::Not,
+            arg: Box::new(arg),
+        }),
+    }
+}
+
+// Returns operands of an expression to be added to operands of the given
+// operator. All logical operators are associative, so nested operators of the
+// same kind are flattened, including ones in parentheses.
+fn flatten(expr: CombinedExpr<'_>, op: CombiningOp) -> Vec<CombinedExpr<'_>> {
+    match expr {
+        CombinedExpr::Combining { op: item_op, items } if item_op == op => items,
+        CombinedExpr::Simple(SimpleExpr::Parenthesized(expr)) => match *expr {
+            CombinedExpr::Combining { op: item_op, .. } if item_op == op => {
+                flatten(unshare_expr(expr), op)
+            }
+            _ => vec![CombinedExpr::Simple(SimpleExpr::Parenthesized(expr))],
+        },
+        expr => vec![expr],
+    }
+}
+
+fn as_one_of<'a, 's>(expr: &'a mut CombinedExpr<'s>) -> Option<&'a mut FieldExpr<'s>> {
+    match expr {
+        CombinedExpr::Simple(SimpleExpr::Field(expr)) => match expr.op() {
+            FieldOp::OneOf(_) if !expr.is_negated() => Some(expr),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Merges `x in { a }` into a preceding `x in { b }`, and returns the
+// expression back if that's not possible.
+fn merge_one_of<'s>(
+    prev: &mut CombinedExpr<'s>,
+    mut expr: CombinedExpr<'s>,
+) -> Option<CombinedExpr<'s>> {
+    match (as_one_of(prev), as_one_of(&mut expr)) {
+        (Some(prev), Some(next)) if prev.lhs() == next.lhs() => {
+            match (prev.op_mut(), mem::replace(next.op_mut(), FieldOp::IsTrue)) {
+                (FieldOp::OneOf(prev), FieldOp::OneOf(next)) => {
+                    prev.append(next).unwrap_or_else(|_| unreachable!());
+                }
+                _ => unreachable!(),
+            }
+            return None;
+        }
+        _ => {}
+    }
+
+    Some(expr)
+}
+
+struct Simplifier<'a, 's> {
+    // Comparisons are evaluated in this context if all fields they depend on
+    // have values in it, including comparisons on function calls that don't
+    // depend on any fields.
+    ctx: &'a ExecutionContext<'s>,
+}
+
+impl<'a, 's> Simplifier<'a, 's> {
+    // Checks whether the value only depends on fields with known values and
+    // can be computed, which isn't the case for unavailable functions.
+    fn is_known(&self, lhs: &LhsFieldExpr<'s>) -> bool {
+        match lhs {
+            LhsFieldExpr::Field(field) => self.ctx.has_field_value(*field),
+            LhsFieldExpr::Slice(slice) => self.ctx.has_field_value(slice.field()),
+            LhsFieldExpr::FunctionCallExpr(call) => {
+                self.ctx.scheme().is_function_available(call.name())
+                    && call.args().iter().all(|arg| match arg {
+                        FunctionCallArgExpr::LhsFieldExpr(lhs) => self.is_known(lhs),
+                        FunctionCallArgExpr::Literal(_) => true,
+                    })
+            }
+        }
+    }
+
+    fn simplify_expr(&self, expr: CombinedExpr<'s>) -> Folded<'s> {
+        match expr {
+            CombinedExpr::Simple(expr) => self.simplify_simple_expr(expr),
+            CombinedExpr::Combining { op, items } => {
+                let mut simplified = Vec::<CombinedExpr<'s>>::with_capacity(items.len());
+                let mut negated = false;
+
+                for item in items {
+                    let item = match self.simplify_expr(item) {
+                        Folded::Constant(value) => {
+                            match op {
+                                CombiningOp::And if !value => return Folded::Constant(false),
+                                CombiningOp::Or if value => return Folded::Constant(true),
+                                CombiningOp::Xor => negated ^= value,
+                                _ => {}
+                            }
+                            continue;
+                        }
+                        Folded::Expr(item) => item,
+                    };
+
+                    for item in flatten(item, op) {
+                        let item = match (op, simplified.last_mut()) {
+                            (CombiningOp::Or, Some(prev)) => merge_one_of(prev, item),
+                            _ => Some(item),
+                        };
+
+                        simplified.extend(item);
+                    }
+                }
+
+                let expr = match simplified.len() {
+                    0 => return Folded::Constant(op == CombiningOp::And || negated),
+                    1 => simplified.pop().unwrap(),
+                    _ => CombinedExpr::Combining {
+                        op,
+                        items: simplified,
+                    },
+                };
+
+                Folded::Expr(if negated { negate(expr) } else { expr })
+            }
+        }
+    }
+
+    fn simplify_simple_expr(&self, expr: SimpleExpr<'s>) -> Folded<'s> {
+        match expr {
+            SimpleExpr::Field(expr) => {
+                let is_known = self.is_known(expr.lhs())
+                    && match expr.op() {
+                        FieldOp::FieldOrdering { rhs_field, .. } => {
+                            self.ctx.has_field_value(*rhs_field)
+                        }
+                        FieldOp::PlaceholderOrdering { .. } | FieldOp::InList(_) => false,
+                        _ => true,
+                    };
+                if is_known {
+                    Folded::Constant(expr.compile().execute(self.ctx))
+                } else {
+                    Folded::Expr(CombinedExpr::Simple(SimpleExpr::Field(expr)))
+                }
+            }
+            SimpleExpr::Parenthesized(expr) => match self.simplify_expr(unshare_expr(expr)) {
+                Folded::Expr(expr) => Folded::Expr(CombinedExpr::Simple(into_simple(expr))),
+                constant => constant,
+            },
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => match self.simplify_simple_expr(*arg) {
+                Folded::Constant(value) => Folded::Constant(!value),
+                Folded::Expr(expr) => Folded::Expr(negate(expr)),
+            },
+        }
+    }
+}
+
+// Returns a simplified expression, or the value of the expression if it
+// turned out to be constant.
+pub(crate) fn simplify<'s>(
+    expr: CombinedExpr<'s>,
+    scheme: &'s Scheme,
+) -> Result<CombinedExpr<'s>, bool> {
+    specialize(expr, &ExecutionContext::new(scheme))
+}
+
+// Same as `simplify`, but also evaluates comparisons on fields that have
+// values in the context.
+pub(crate) fn specialize<'s>(
+    expr: CombinedExpr<'s>,
+    ctx: &ExecutionContext<'s>,
+) -> Result<CombinedExpr<'s>, bool> {
+    let simplifier = Simplifier { ctx };
+
+    match simplifier.simplify_expr(expr) {
+        Folded::Expr(expr) => Ok(expr),
+        Folded::Constant(value) => Err(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::FilterAst,
+        filter::SchemeMismatchError,
+        functions::{Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionParam},
+        types::{LhsValue, Type},
+    };
+    use std::net::IpAddr;
+
+    fn len_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        match args.next().unwrap() {
+            LhsValue::Bytes(bytes) => LhsValue::Int(bytes.len() as i32),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let mut scheme = Scheme! { tcp.port: Int, ssl: Bool };
+        scheme
+            .add_function(
+                "len".into(),
+                Function {
+                    params: vec![FunctionParam {
+                        arg_kind: FunctionArgKind::Literal,
+                        val_type: Type::Bytes,
+                        literal_check: None,
+                    }],
+                    opt_params: vec![],
+                    return_type: Type::Int,
+                    implementation: FunctionImpl::new(len_function),
+                },
+            )
+            .unwrap();
+
+        let simplify = |filter| {
+            let mut ast = scheme.parse(filter).unwrap();
+            assert_eq!(ast.simplify(), None, "{} is not constant", filter);
+            ast
+        };
+
+        // Serialized form doesn't depend on parentheses.
+        let assert_simplified = |ast: FilterAst<'_>, expected| {
+            assert_eq!(ast.snapshot(), scheme.parse(expected).unwrap().snapshot());
+        };
+
+        assert_simplified(
+            simplify(r#"len("abc") == 3 and tcp.port == 80"#),
+            "tcp.port == 80",
+        );
+        assert_simplified(simplify(r#"len("abc") == 4 or ssl"#), "ssl");
+        assert_simplified(
+            simplify(r#"tcp.port == 80 and (ssl or len("a") == 1)"#),
+            "tcp.port == 80",
+        );
+        assert_simplified(simplify(r#"ssl xor len("a") == 1"#), "not ssl");
+        assert_simplified(
+            simplify(r#"not (ssl xor tcp.port == 80 xor len("a") == 1)"#),
+            "ssl xor tcp.port == 80",
+        );
+        assert_simplified(simplify("not not ssl"), "ssl");
+        assert_simplified(simplify("not (not ssl)"), "ssl");
+        assert_simplified(
+            simplify("((ssl)) and (tcp.port == 80 or ssl)"),
+            "ssl and (tcp.port == 80 or ssl)",
+        );
+        assert_simplified(
+            simplify("ssl and (tcp.port == 80 and (tcp.port == 443))"),
+            "ssl and tcp.port == 80 and tcp.port == 443",
+        );
+        assert_simplified(
+            simplify(
+                "tcp.port in { 80 } or (tcp.port in { 443 8000..8080 } or ssl) or tcp.port in { 22 }",
+            ),
+            "tcp.port in { 80 443 8000..8080 } or ssl or tcp.port in { 22 }",
+        );
+        assert_simplified(
+            simplify("tcp.port in { 80 } and tcp.port in { 443 }"),
+            "tcp.port in { 80 } and tcp.port in { 443 }",
+        );
+        assert_simplified(
+            simplify("tcp.port not in { 80 } or tcp.port not in { 443 }"),
+            "tcp.port not in { 80 } or tcp.port not in { 443 }",
+        );
+        assert_simplified(simplify("not tcp.port not in { 80 }"), "tcp.port in { 80 }");
+
+        let mut ast = scheme.parse(r#"ssl and not len("") == 0"#).unwrap();
+        let original = ast.clone();
+        assert_eq!(ast.simplify(), Some(false));
+        assert_eq!(ast, original);
+
+        let mut ast = scheme.parse(r#"ssl or len("abc") > 2"#).unwrap();
+        assert_eq!(ast.simplify(), Some(true));
+    }
+
+    #[test]
+    fn test_specialize() {
+        let scheme = Scheme! {
+            tcp.port: Int,
+            ip.src: Ip,
+            http.host: Bytes,
+            http.path: Bytes,
+        };
+
+        let mut ctx = ExecutionContext::new(&scheme);
+        ctx.set_field_value("tcp.port", 443).unwrap();
+        ctx.set_field_value("ip.src", IpAddr::from([10, 0, 0, 1]))
+            .unwrap();
+
+        let specialize = |filter| {
+            let mut ast = scheme.parse(filter).unwrap();
+            assert_eq!(ast.specialize(&ctx), Ok(None), "{} is decided", filter);
+            ast.snapshot()
+        };
+
+        let parse = |filter| scheme.parse(filter).unwrap().snapshot();
+
+        assert_eq!(
+            specialize(r#"tcp.port in { 80 443 } and http.host == "example.org""#),
+            parse(r#"http.host == "example.org""#),
+        );
+        assert_eq!(
+            specialize(r#"(tcp.port == 80 or http.path == "/") and ip.src in { 10.0.0.0/8 }"#),
+            parse(r#"http.path == "/""#),
+        );
+        assert_eq!(
+            specialize(r#"http.host == "a" or (ip.src == 10.0.0.1 and http.path == "/")"#),
+            parse(r#"http.host == "a" or http.path == "/""#),
+        );
+
+        let mut ast = scheme
+            .parse(r#"tcp.port == 80 and http.host == "example.org""#)
+            .unwrap();
+        let original = ast.clone();
+        assert_eq!(ast.specialize(&ctx), Ok(Some(false)));
+        assert_eq!(ast, original);
+
+        let mut ast = scheme.parse("ip.src in { 10.0.0.0/8 }").unwrap();
+        assert_eq!(ast.specialize(&ctx), Ok(Some(true)));
+
+        let other = Scheme! { tcp.port: Int };
+        let mut ast = other.parse("tcp.port == 80").unwrap();
+        assert_eq!(ast.specialize(&ctx), Err(SchemeMismatchError));
+    }
+}
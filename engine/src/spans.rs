@@ -0,0 +1,32 @@
+use crate::ast::NodeId;
+use fnv::FnvHashMap;
+use std::ops::Range;
+
+/// Positions of comparisons in the source of a filter parsed with
+/// [`Scheme::parse_with_spans`](::Scheme::parse_with_spans), keyed by their
+/// [`NodeId`].
+///
+/// Identical comparisons share an identifier, so each of them can have
+/// several spans. Comparisons expanded from `let` definitions have spans in
+/// the definitions.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExprSpans(FnvHashMap<NodeId, Vec<Range<usize>>>);
+
+impl ExprSpans {
+    /// Returns ranges of byte offsets of a comparison in the source.
+    pub fn get(&self, node_id: NodeId) -> &[Range<usize>] {
+        self.0.get(&node_id).map_or(&[], |spans| spans)
+    }
+
+    /// Records the span of a comparison lexed from the input.
+    pub(crate) fn record(&mut self, input: &str, node_id: NodeId, span: &str) {
+        // Comparisons lexed from other strings can't be mapped.
+        let offset = (span.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+        if offset <= input.len() {
+            self.0
+                .entry(node_id)
+                .or_default()
+                .push(offset..offset + span.len());
+        }
+    }
+}
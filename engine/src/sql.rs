@@ -0,0 +1,696 @@
+use crate::{
+    ast::{
+        CombinedExpr, CombiningOp, FieldExpr, FieldOp, IntOp, LhsFieldExpr, OrderingOp, SimpleExpr,
+        UnaryOp,
+    },
+    rhs_types::{Bytes, ExplicitIpRange, IntLiteral, IpRange},
+    types::{GetType, RhsValue, RhsValues, Type},
+};
+use cidr::Cidr;
+use failure::Fail;
+use std::{
+    fmt::{Display, Write},
+    ops::RangeInclusive,
+    str,
+};
+
+/// SQL dialect to translate a filter into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDialect {
+    /// PostgreSQL.
+    Postgres,
+    /// ClickHouse.
+    ClickHouse,
+}
+
+/// An error that occurs if a filter can't be translated to SQL.
+#[derive(Debug, PartialEq, Fail)]
+pub enum SqlError {
+    /// Functions are implemented in Rust and have no SQL counterpart.
+    #[fail(display = "function {} can't be translated to SQL", _0)]
+    FunctionCall(String),
+
+    /// Values of the type have no SQL representation in the dialect.
+    #[fail(display = "values of type {:?} can't be translated to SQL", _0)]
+    UnsupportedType(Type),
+
+    /// Placeholders have to be bound first.
+    #[fail(display = "placeholder {} can't be translated to SQL", _0)]
+    Placeholder(String),
+
+    /// Values of named lists are only known at execution time.
+    #[fail(display = "list {} can't be translated to SQL", _0)]
+    List(String),
+}
+
+struct SqlWriter {
+    dialect: SqlDialect,
+    out: String,
+}
+
+impl SqlWriter {
+    fn write(&mut self, s: impl Display) {
+        // Writing to a `String` never fails.
+        write!(self.out, "{}", s).unwrap();
+    }
+
+    fn write_joined<T>(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+        separator: &str,
+        mut write_item: impl FnMut(&mut Self, T) -> Result<(), SqlError>,
+    ) -> Result<(), SqlError> {
+        for (i, item) in items.into_iter().enumerate() {
+            if i != 0 {
+                self.write(separator);
+            }
+            write_item(self, item)?;
+        }
+        Ok(())
+    }
+
+    fn write_identifier(&mut self, name: &str) {
+        let quote = match self.dialect {
+            SqlDialect::Postgres => '"',
+            SqlDialect::ClickHouse => '`',
+        };
+        self.out.push(quote);
+        for c in name.chars() {
+            if c == quote {
+                self.out.push(c);
+            }
+            self.out.push(c);
+        }
+        self.out.push(quote);
+    }
+
+    fn write_string(&mut self, s: &str) {
+        self.out.push('\'');
+        for c in s.chars() {
+            match c {
+                '\'' => self.out.push_str("''"),
+                // Backslash is an escape character in ClickHouse strings only.
+                '\\' if self.dialect == SqlDialect::ClickHouse => self.out.push_str("\\\\"),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('\'');
+    }
+
+    fn write_bytes(&mut self, bytes: &Bytes) {
+        match str::from_utf8(bytes) {
+            Ok(s) => self.write_string(s),
+            Err(_) => match self.dialect {
+                SqlDialect::Postgres => {
+                    self.write("'\\x");
+                    for b in bytes.iter() {
+                        self.write(format_args!("{:02x}", b));
+                    }
+                    self.write("'::bytea");
+                }
+                SqlDialect::ClickHouse => {
+                    self.write("'");
+                    for b in bytes.iter() {
+                        self.write(format_args!("\\x{:02x}", b));
+                    }
+                    self.write("'");
+                }
+            },
+        }
+    }
+
+    fn write_value(&mut self, value: &RhsValue) -> Result<(), SqlError> {
+        match value {
+            RhsValue::Ip(addr) => self.write_string(&addr.to_string()),
+            RhsValue::Bytes(bytes) => self.write_bytes(bytes),
+            RhsValue::Str(s) => self.write_string(s),
+            RhsValue::Int(int) => self.write(int.value()),
+            RhsValue::Bool(_) => unreachable!(),
+            value => return Err(SqlError::UnsupportedType(value.get_type())),
+        }
+        Ok(())
+    }
+
+    fn write_lhs(&mut self, lhs: &LhsFieldExpr<'_>) -> Result<(), SqlError> {
+        match lhs {
+            LhsFieldExpr::Field(field) => {
+                self.write_identifier(field.name());
+                Ok(())
+            }
+            LhsFieldExpr::Slice(slice) => {
+                // SQL strings are indexed from 1.
+                self.write("substr(");
+                self.write_identifier(slice.field().name());
+                self.write(format_args!(", {}", slice.start() + 1));
+                if let Some(end) = slice.end() {
+                    self.write(format_args!(", {}", end - slice.start()));
+                }
+                self.write(")");
+                Ok(())
+            }
+            LhsFieldExpr::FunctionCallExpr(call) => {
+                Err(SqlError::FunctionCall(call.name().to_owned()))
+            }
+        }
+    }
+
+    fn write_between(
+        &mut self,
+        lhs: &LhsFieldExpr<'_>,
+        start: impl Display,
+        end: impl Display,
+    ) -> Result<(), SqlError> {
+        self.write_lhs(lhs)?;
+        self.write(" BETWEEN ");
+        self.write(start);
+        self.write(" AND ");
+        self.write(end);
+        Ok(())
+    }
+
+    fn write_int_ranges(
+        &mut self,
+        lhs: &LhsFieldExpr<'_>,
+        ranges: &[RangeInclusive<IntLiteral>],
+    ) -> Result<(), SqlError> {
+        let (values, ranges): (Vec<_>, Vec<_>) = ranges
+            .iter()
+            .partition(|range| range.start().value() == range.end().value());
+
+        self.write("(");
+        if !values.is_empty() {
+            self.write_lhs(lhs)?;
+            self.write(" IN (");
+            self.write_joined(values, ", ", |this, range| {
+                this.write(range.start().value());
+                Ok(())
+            })?;
+            self.write(")");
+            if !ranges.is_empty() {
+                self.write(" OR ");
+            }
+        }
+        self.write_joined(ranges, " OR ", |this, range| {
+            this.write_between(lhs, range.start().value(), range.end().value())
+        })?;
+        self.write(")");
+        Ok(())
+    }
+
+    fn write_ip_ranges(
+        &mut self,
+        lhs: &LhsFieldExpr<'_>,
+        ranges: &[IpRange],
+    ) -> Result<(), SqlError> {
+        self.write("(");
+        self.write_joined(ranges, " OR ", |this, range| {
+            match range {
+                // Networks of a single address are compared for equality, as
+                // their string form has no prefix length.
+                IpRange::Cidr(cidr) if cidr.network_length() == cidr.family().len() => {
+                    this.write_lhs(lhs)?;
+                    this.write(" = ");
+                    this.write_string(&cidr.first_address().to_string());
+                }
+                IpRange::Cidr(cidr) => match this.dialect {
+                    SqlDialect::Postgres => {
+                        this.write_lhs(lhs)?;
+                        this.write(" <<= ");
+                        this.write_string(&cidr.to_string());
+                    }
+                    SqlDialect::ClickHouse => {
+                        this.write("isIPAddressInRange(toString(");
+                        this.write_lhs(lhs)?;
+                        this.write("), ");
+                        this.write_string(&cidr.to_string());
+                        this.write(")");
+                    }
+                },
+                IpRange::Explicit(range) => {
+                    let (start, end) = match range {
+                        ExplicitIpRange::V4(range) => {
+                            (range.start().to_string(), range.end().to_string())
+                        }
+                        ExplicitIpRange::V6(range) => {
+                            (range.start().to_string(), range.end().to_string())
+                        }
+                    };
+                    if start == end {
+                        this.write_lhs(lhs)?;
+                        this.write(" = ");
+                        this.write_string(&start);
+                    } else {
+                        this.write_lhs(lhs)?;
+                        this.write(" BETWEEN ");
+                        this.write_string(&start);
+                        this.write(" AND ");
+                        this.write_string(&end);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        self.write(")");
+        Ok(())
+    }
+
+    fn write_ordering_op(&mut self, op: OrderingOp) {
+        self.write(match op {
+            OrderingOp::Equal => " = ",
+            OrderingOp::NotEqual => " <> ",
+            OrderingOp::GreaterThanEqual => " >= ",
+            OrderingOp::LessThanEqual => " <= ",
+            OrderingOp::GreaterThan => " > ",
+            OrderingOp::LessThan => " < ",
+        });
+    }
+
+    fn write_int_op(
+        &mut self,
+        lhs: &LhsFieldExpr<'_>,
+        op: IntOp,
+        rhs: &IntLiteral,
+    ) -> Result<(), SqlError> {
+        match self.dialect {
+            SqlDialect::Postgres => {
+                self.write("(");
+                self.write_lhs(lhs)?;
+                self.write(match op {
+                    IntOp::BitwiseAnd => " & ",
+                    IntOp::BitwiseOr => " | ",
+                    IntOp::BitwiseXor => " # ",
+                });
+                self.write(format_args!("{})", rhs.value()));
+            }
+            SqlDialect::ClickHouse => {
+                self.write(match op {
+                    IntOp::BitwiseAnd => "bitAnd(",
+                    IntOp::BitwiseOr => "bitOr(",
+                    IntOp::BitwiseXor => "bitXor(",
+                });
+                self.write_lhs(lhs)?;
+                self.write(format_args!(", {})", rhs.value()));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_contains(&mut self, lhs: &LhsFieldExpr<'_>, bytes: &Bytes) -> Result<(), SqlError> {
+        self.write(match self.dialect {
+            SqlDialect::Postgres => "strpos(",
+            SqlDialect::ClickHouse => "position(",
+        });
+        self.write_lhs(lhs)?;
+        self.write(", ");
+        self.write_bytes(bytes);
+        self.write(") > 0");
+        Ok(())
+    }
+
+    // Writes a check for a prefix or, if `prefix` is false, a suffix.
+    fn write_affix(
+        &mut self,
+        lhs: &LhsFieldExpr<'_>,
+        bytes: &Bytes,
+        prefix: bool,
+    ) -> Result<(), SqlError> {
+        match self.dialect {
+            SqlDialect::Postgres => {
+                // PostgreSQL has no `ends_with`, so both are translated into
+                // `LIKE` with an escaped pattern.
+                let mut pattern = Vec::with_capacity(bytes.len() + 1);
+                if !prefix {
+                    pattern.push(b'%');
+                }
+                for &b in bytes.iter() {
+                    if b == b'\\' || b == b'%' || b == b'_' {
+                        pattern.push(b'\\');
+                    }
+                    pattern.push(b);
+                }
+                if prefix {
+                    pattern.push(b'%');
+                }
+                self.write_lhs(lhs)?;
+                self.write(" LIKE ");
+                self.write_bytes(&pattern.into());
+            }
+            SqlDialect::ClickHouse => {
+                self.write(if prefix { "startsWith(" } else { "endsWith(" });
+                self.write_lhs(lhs)?;
+                self.write(", ");
+                self.write_bytes(bytes);
+                self.write(")");
+            }
+        }
+        Ok(())
+    }
+
+    fn write_field_expr(&mut self, expr: &FieldExpr<'_>) -> Result<(), SqlError> {
+        if expr.is_negated() {
+            self.write("NOT (");
+            self.write_field_op(expr)?;
+            self.write(")");
+            Ok(())
+        } else {
+            self.write_field_op(expr)
+        }
+    }
+
+    fn write_field_op(&mut self, expr: &FieldExpr<'_>) -> Result<(), SqlError> {
+        let lhs = expr.lhs();
+
+        match expr.op() {
+            FieldOp::IsTrue => self.write_lhs(lhs)?,
+            FieldOp::Ordering { op, rhs } => {
+                self.write_lhs(lhs)?;
+                self.write_ordering_op(*op);
+                self.write_value(rhs)?;
+            }
+            FieldOp::FieldOrdering { op, rhs_field } => {
+                self.write_lhs(lhs)?;
+                self.write_ordering_op(*op);
+                self.write_identifier(rhs_field.name());
+            }
+            FieldOp::PlaceholderOrdering { placeholder, .. } => {
+                return Err(SqlError::Placeholder(placeholder.name().to_owned()));
+            }
+            FieldOp::InList(list) => {
+                return Err(SqlError::List(list.name().to_owned()));
+            }
+            FieldOp::Int { op, rhs } => {
+                self.write_int_op(lhs, *op, rhs)?;
+                self.write(" <> 0");
+            }
+            FieldOp::IntOrdering {
+                op,
+                rhs,
+                ordering,
+                value,
+            } => {
+                self.write_int_op(lhs, *op, rhs)?;
+                self.write_ordering_op(*ordering);
+                self.write(value.value());
+            }
+            FieldOp::CaseInsensitiveEqual(bytes) => {
+                // Both dialects fold ASCII letters in `lower`, though
+                // PostgreSQL folds some non-ASCII letters too.
+                self.write("lower(");
+                self.write_lhs(lhs)?;
+                self.write(") = ");
+                self.write_bytes(&bytes.to_ascii_lowercase().into());
+            }
+            FieldOp::Contains(bytes) => self.write_contains(lhs, bytes)?,
+            FieldOp::ContainsAny(values) => match self.dialect {
+                SqlDialect::Postgres => {
+                    self.write("(");
+                    self.write_joined(values, " OR ", |this, bytes| {
+                        this.write_contains(lhs, bytes)
+                    })?;
+                    self.write(")");
+                }
+                SqlDialect::ClickHouse => {
+                    self.write("multiSearchAny(");
+                    self.write_lhs(lhs)?;
+                    self.write(", [");
+                    self.write_joined(values, ", ", |this, bytes| {
+                        this.write_bytes(bytes);
+                        Ok(())
+                    })?;
+                    self.write("])");
+                }
+            },
+            FieldOp::StartsWith(bytes) => self.write_affix(lhs, bytes, true)?,
+            FieldOp::EndsWith(bytes) => self.write_affix(lhs, bytes, false)?,
+            FieldOp::Matches(regex) => match self.dialect {
+                SqlDialect::Postgres => {
+                    self.write_lhs(lhs)?;
+                    self.write(" ~ ");
+                    self.write_string(regex.as_str());
+                }
+                SqlDialect::ClickHouse => {
+                    self.write("match(");
+                    self.write_lhs(lhs)?;
+                    self.write(", ");
+                    self.write_string(regex.as_str());
+                    self.write(")");
+                }
+            },
+            FieldOp::OneOf(values) => match values {
+                RhsValues::Ip(ranges) => self.write_ip_ranges(lhs, ranges)?,
+                RhsValues::Int(ranges) => self.write_int_ranges(lhs, ranges)?,
+                RhsValues::Bytes(values) => {
+                    self.write_lhs(lhs)?;
+                    self.write(" IN (");
+                    self.write_joined(values, ", ", |this, bytes| {
+                        this.write_bytes(bytes);
+                        Ok(())
+                    })?;
+                    self.write(")");
+                }
+                RhsValues::Str(values) => {
+                    self.write_lhs(lhs)?;
+                    self.write(" IN (");
+                    self.write_joined(values, ", ", |this, s| {
+                        this.write_string(s);
+                        Ok(())
+                    })?;
+                    self.write(")");
+                }
+                RhsValues::Bool(_) => unreachable!(),
+                values => return Err(SqlError::UnsupportedType(values.get_type())),
+            },
+        }
+        Ok(())
+    }
+
+    fn write_simple_expr(&mut self, expr: &SimpleExpr<'_>) -> Result<(), SqlError> {
+        match expr {
+            SimpleExpr::Field(expr) => self.write_field_expr(expr),
+            SimpleExpr::Parenthesized(expr) => self.write_expr(expr),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => {
+                self.write("NOT (");
+                self.write_simple_expr(arg)?;
+                self.write(")");
+                Ok(())
+            }
+        }
+    }
+
+    fn write_expr(&mut self, expr: &CombinedExpr<'_>) -> Result<(), SqlError> {
+        match expr {
+            CombinedExpr::Simple(expr) => self.write_simple_expr(expr),
+            // `<>` is not associative in Postgres, so operands are combined
+            // in pairs, like `((a) <> (b)) <> (c)`.
+            CombinedExpr::Combining {
+                op: CombiningOp::Xor,
+                items,
+            } if self.dialect == SqlDialect::Postgres => {
+                for _ in 1..items.len() {
+                    self.write("(");
+                }
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        self.write(" <> ");
+                    }
+                    self.write("(");
+                    self.write_expr(item)?;
+                    self.write(")");
+                    if i != 0 {
+                        self.write(")");
+                    }
+                }
+                Ok(())
+            }
+            CombinedExpr::Combining { op, items } => {
+                let separator = match op {
+                    CombiningOp::And => " AND ",
+                    CombiningOp::Or => " OR ",
+                    CombiningOp::Xor => ", ",
+                };
+                if *op == CombiningOp::Xor {
+                    self.write("xor");
+                }
+                self.write("(");
+                self.write_joined(items, separator, |this, item| {
+                    // Parenthesize items for operators which bind tighter
+                    // than logical ones.
+                    this.write("(");
+                    this.write_expr(item)?;
+                    this.write(")");
+                    Ok(())
+                })?;
+                self.write(")");
+                Ok(())
+            }
+        }
+    }
+}
+
+pub(crate) fn to_sql(expr: &CombinedExpr<'_>, dialect: SqlDialect) -> Result<String, SqlError> {
+    let mut writer = SqlWriter {
+        dialect,
+        out: String::new(),
+    };
+    writer.write_expr(expr)?;
+    Ok(writer.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        functions::{Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionParam},
+        scheme::Scheme,
+        types::LhsValue,
+    };
+
+    fn echo_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        args.next().unwrap()
+    }
+
+    fn scheme() -> Scheme {
+        let mut scheme = Scheme! {
+            http.host: Bytes,
+            ip.src: Ip,
+            tcp.port: Int,
+            tcp.flags: Int,
+            ssl: Bool,
+            eth.src: Mac,
+        };
+        scheme
+            .add_function(
+                "echo".into(),
+                Function {
+                    params: vec![FunctionParam {
+                        arg_kind: FunctionArgKind::Field,
+                        val_type: Type::Bytes,
+                        literal_check: None,
+                    }],
+                    opt_params: vec![],
+                    return_type: Type::Bytes,
+                    implementation: FunctionImpl::new(echo_function),
+                },
+            )
+            .unwrap();
+        scheme
+    }
+
+    fn sql(scheme: &Scheme, filter: &str, dialect: SqlDialect) -> Result<String, SqlError> {
+        scheme.parse(filter).unwrap().to_sql(dialect)
+    }
+
+    #[test]
+    fn test_postgres() {
+        let scheme = &scheme();
+        let sql = |filter| sql(scheme, filter, SqlDialect::Postgres).unwrap();
+
+        assert_eq!(
+            sql(r#"http.host == "it's" and tcp.port != 80"#),
+            r#"(("http.host" = 'it''s') AND ("tcp.port" <> 80))"#
+        );
+        assert_eq!(
+            sql("not ssl or tcp.flags & 0x02"),
+            r#"((NOT ("ssl")) OR (("tcp.flags" & 2) <> 0))"#
+        );
+        assert_eq!(
+            sql("tcp.flags | 0x01 != 3 and tcp.flags ^ 1"),
+            r#"((("tcp.flags" | 1) <> 3) AND (("tcp.flags" # 1) <> 0))"#
+        );
+        assert_eq!(
+            sql(r#"http.host startswith "50%_" or http.host endswith ".org""#),
+            r#"(("http.host" LIKE '50\%\_%') OR ("http.host" LIKE '%.org'))"#
+        );
+        assert_eq!(
+            sql(r#"http.host contains any { "a" "b" }"#),
+            r#"(strpos("http.host", 'a') > 0 OR strpos("http.host", 'b') > 0)"#
+        );
+        assert_eq!(
+            sql(r#"http.host ieq "Example.COM""#),
+            r#"lower("http.host") = 'example.com'"#
+        );
+        assert_eq!(sql("tcp.port >= tcp.flags"), r#""tcp.port" >= "tcp.flags""#);
+        assert_eq!(sql("ssl xor ssl"), r#"(("ssl") <> ("ssl"))"#);
+        assert_eq!(
+            sql("ssl xor not ssl xor tcp.port == 80"),
+            r#"((("ssl") <> (NOT ("ssl"))) <> ("tcp.port" = 80))"#
+        );
+        assert_eq!(
+            sql("tcp.port in { 80 443 8000..8080 }"),
+            r#"("tcp.port" IN (80, 443) OR "tcp.port" BETWEEN 8000 AND 8080)"#
+        );
+        assert_eq!(
+            sql("ip.src in { 10.0.0.0/8 192.168.0.1..192.168.0.5 ::1 }"),
+            r#"("ip.src" <<= '10.0.0.0/8' OR "ip.src" BETWEEN '192.168.0.1' AND '192.168.0.5' OR "ip.src" = '::1')"#
+        );
+        assert_eq!(
+            sql(r#"http.host contains "a\\b" and http.host matches "^a.*$""#),
+            r#"((strpos("http.host", 'a\b') > 0) AND ("http.host" ~ '^a.*$'))"#
+        );
+        assert_eq!(
+            sql(r#"http.host in { "a" ff:fe }"#),
+            r#""http.host" IN ('a', '\xfffe'::bytea)"#
+        );
+        assert_eq!(
+            sql(r#"http.host[1..3] == "ab" or http.host[4..] contains "c""#),
+            r#"((substr("http.host", 2, 2) = 'ab') OR (strpos(substr("http.host", 5), 'c') > 0))"#
+        );
+        assert_eq!(
+            sql(r#"tcp.port not in { 80 443 } and http.host !~ "^a""#),
+            r#"((NOT (("tcp.port" IN (80, 443)))) AND (NOT ("http.host" ~ '^a')))"#
+        );
+    }
+
+    #[test]
+    fn test_clickhouse() {
+        let scheme = &scheme();
+        let sql = |filter| sql(scheme, filter, SqlDialect::ClickHouse).unwrap();
+
+        assert_eq!(
+            sql(r#"http.host == "a\\b" and tcp.flags & 2"#),
+            r#"((`http.host` = 'a\\b') AND (bitAnd(`tcp.flags`, 2) <> 0))"#
+        );
+        assert_eq!(
+            sql("tcp.flags & 0x12 == 0x12 or tcp.flags ^ 1 > 2"),
+            "((bitAnd(`tcp.flags`, 18) = 18) OR (bitXor(`tcp.flags`, 1) > 2))"
+        );
+        assert_eq!(sql("ssl ^^ ssl"), "xor((`ssl`), (`ssl`))");
+        assert_eq!(
+            sql("ip.src in { 10.0.0.0/8 192.168.0.1 }"),
+            "(isIPAddressInRange(toString(`ip.src`), '10.0.0.0/8') OR `ip.src` = '192.168.0.1')"
+        );
+        assert_eq!(
+            sql(r#"http.host contains ff and http.host ~ "a""#),
+            r#"((position(`http.host`, '\xff') > 0) AND (match(`http.host`, 'a')))"#
+        );
+        assert_eq!(
+            sql(r#"http.host startswith "a" and http.host endswith "b""#),
+            "((startsWith(`http.host`, 'a')) AND (endsWith(`http.host`, 'b')))"
+        );
+        assert_eq!(
+            sql(r#"http.host contains any { "a" "b" }"#),
+            "multiSearchAny(`http.host`, ['a', 'b'])"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let scheme = &scheme();
+
+        assert_eq!(
+            sql(scheme, r#"echo(http.host) == "a""#, SqlDialect::Postgres),
+            Err(SqlError::FunctionCall("echo".into()))
+        );
+        assert_eq!(
+            sql(
+                scheme,
+                "eth.src == aa:bb:cc:dd:ee:ff",
+                SqlDialect::ClickHouse
+            ),
+            Err(SqlError::UnsupportedType(Type::Mac))
+        );
+    }
+}
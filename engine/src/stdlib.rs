@@ -0,0 +1,476 @@
+//! A standard library of commonly used functions.
+//!
+//! All functions can be registered onto a [`Scheme`] at once with
+//! [`register`], or picked individually from [`functions`].
+//!
+//! | Function | Signature |
+//! |----------|-----------|
+//! | `lower` | `(field Bytes) -> Bytes` |
+//! | `lower` | `(field Str) -> Str` |
+//! | `upper` | `(field Bytes) -> Bytes` |
+//! | `upper` | `(field Str) -> Str` |
+//! | `len` | `(field Bytes) -> Int` |
+//! | `starts_with` | `(field Bytes, literal Bytes) -> Bool` |
+//! | `ends_with` | `(field Bytes, literal Bytes) -> Bool` |
+//! | `concat` | `(field Bytes, literal Bytes) -> Bytes` |
+//! | `url_decode` | `(field Bytes) -> Bytes` |
+//! | `to_string` | `(field Int) -> Bytes` |
+//! | `regex_capture` | `(field Bytes, literal Bytes, literal Int = 1) -> Bytes` |
+//! | `regex_capture_name` | `(field Bytes, literal Bytes, literal Bytes) -> Bytes` |
+//! | `any` | `(field Bool, field Bool = false, ...) -> Bool` |
+//! | `all` | `(field Bool, field Bool = true, ...) -> Bool` |
+//!
+//! `lower` and `upper` change case of ASCII letters in `Bytes`, and of all
+//! Unicode letters in `Str`.
+//!
+//! `any` and `all` accept up to [`MAX_BOOL_ARGS`] arguments.
+//!
+//! `regex_capture` and `regex_capture_name` match a regular expression given
+//! by the second argument and return a capture group by its index or name,
+//! or an empty string if there's no match. Invalid regular expressions are
+//! rejected when a filter is parsed, and valid ones are compiled once for
+//! each call when the filter is compiled.
+
+use crate::{
+    functions::{
+        Function, FunctionArgKind, FunctionArgs, FunctionImpl, FunctionLiteralCheck,
+        FunctionOptParam, FunctionParam, FunctionState,
+    },
+    rhs_types::Regex,
+    scheme::{ItemRedefinitionError, Scheme},
+    types::{LhsValue, RhsValue, Type},
+};
+use std::{any::Any, borrow::Cow, convert::TryFrom, ops::Range, str, sync::Arc};
+
+/// Maximum number of arguments accepted by `any` and `all`.
+pub const MAX_BOOL_ARGS: usize = 8;
+
+fn next_bytes<'a>(args: FunctionArgs<'_, 'a>) -> Cow<'a, [u8]> {
+    match args.next() {
+        Some(LhsValue::Bytes(bytes)) => bytes,
+        arg => panic!("Invalid argument: expected Bytes, got {:?}", arg),
+    }
+}
+
+fn next_str<'a>(args: FunctionArgs<'_, 'a>) -> Cow<'a, str> {
+    match args.next() {
+        Some(LhsValue::Str(s)) => s,
+        arg => panic!("Invalid argument: expected Str, got {:?}", arg),
+    }
+}
+
+fn next_int(args: FunctionArgs<'_, '_>) -> i32 {
+    match args.next() {
+        Some(LhsValue::Int(int)) => int,
+        arg => panic!("Invalid argument: expected Int, got {:?}", arg),
+    }
+}
+
+fn lower<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    LhsValue::Bytes(next_bytes(args).to_ascii_lowercase().into())
+}
+
+fn upper<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    LhsValue::Bytes(next_bytes(args).to_ascii_uppercase().into())
+}
+
+fn lower_str<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    LhsValue::Str(next_str(args).to_lowercase().into())
+}
+
+fn upper_str<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    LhsValue::Str(next_str(args).to_uppercase().into())
+}
+
+fn len<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    // Saturate rather than wrap for values that can't be represented.
+    let len = next_bytes(args).len();
+    LhsValue::Int(i32::try_from(len).unwrap_or(i32::MAX))
+}
+
+fn starts_with<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    let input = next_bytes(args);
+    let prefix = next_bytes(args);
+    LhsValue::Bool(input.starts_with(&prefix))
+}
+
+fn ends_with<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    let input = next_bytes(args);
+    let suffix = next_bytes(args);
+    LhsValue::Bool(input.ends_with(&suffix))
+}
+
+fn concat<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    let mut output = next_bytes(args).into_owned();
+    output.extend_from_slice(&next_bytes(args));
+    LhsValue::Bytes(output.into())
+}
+
+fn url_decode<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    fn hex_value(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let input = next_bytes(args);
+
+    if !input.contains(&b'%') {
+        return LhsValue::Bytes(input);
+    }
+
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() {
+            if let (Some(hi), Some(lo)) = (hex_value(input[i + 1]), hex_value(input[i + 2])) {
+                output.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        // Malformed escapes are passed through unchanged.
+        output.push(input[i]);
+        i += 1;
+    }
+    LhsValue::Bytes(output.into())
+}
+
+fn to_string<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    next_int(args).to_string().into()
+}
+
+fn parse_regex(pattern: &[u8]) -> Result<Regex, String> {
+    str::from_utf8(pattern)
+        .map_err(|err| err.to_string())
+        .and_then(|pattern| pattern.parse::<Regex>().map_err(|err| err.to_string()))
+}
+
+// Compiles the pattern passed as the second argument of a call. It's missing
+// only if the pattern wasn't validated, like when it's replaced after
+// parsing.
+fn prepare_regex(args: &[Option<LhsValue<'_>>]) -> FunctionState {
+    let regex = match args.get(1) {
+        Some(Some(LhsValue::Bytes(pattern))) => parse_regex(pattern).ok(),
+        _ => None,
+    };
+    Arc::new(regex)
+}
+
+// Matches the regex prepared for the call against the input and returns a
+// part of the input located by `find`, or an empty string.
+fn regex_capture_with<'a>(
+    state: &(dyn Any + Send + Sync),
+    input: Cow<'a, [u8]>,
+    find: impl FnOnce(&Regex, &[u8]) -> Option<Range<usize>>,
+) -> LhsValue<'a> {
+    let range = state
+        .downcast_ref::<Option<Regex>>()
+        .and_then(Option::as_ref)
+        .and_then(|regex| find(regex, &input));
+
+    LhsValue::Bytes(match (range, input) {
+        (Some(range), Cow::Borrowed(input)) => Cow::Borrowed(&input[range]),
+        (Some(range), Cow::Owned(input)) => Cow::Owned(input[range].to_vec()),
+        (None, _) => Cow::Borrowed(&[]),
+    })
+}
+
+fn regex_capture<'a>(state: &(dyn Any + Send + Sync), args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    let input = next_bytes(args);
+    let _pattern = next_bytes(args);
+    let index = next_int(args);
+    regex_capture_with(state, input, |regex, input| {
+        // Negative indices never match.
+        if index < 0 {
+            None
+        } else {
+            regex.find_group(input, index as usize)
+        }
+    })
+}
+
+fn regex_capture_name<'a>(
+    state: &(dyn Any + Send + Sync),
+    args: FunctionArgs<'_, 'a>,
+) -> LhsValue<'a> {
+    let input = next_bytes(args);
+    let _pattern = next_bytes(args);
+    let name = next_bytes(args);
+    regex_capture_with(state, input, |regex, input| {
+        regex.find_named_group(input, str::from_utf8(&name).ok()?)
+    })
+}
+
+fn any<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    // Consume all arguments regardless of the result.
+    LhsValue::Bool(args.fold(false, |acc, arg| acc | (arg == LhsValue::Bool(true))))
+}
+
+fn all<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+    LhsValue::Bool(args.fold(true, |acc, arg| acc & (arg == LhsValue::Bool(true))))
+}
+
+fn field(val_type: Type) -> FunctionParam {
+    FunctionParam {
+        arg_kind: FunctionArgKind::Field,
+        val_type,
+        literal_check: None,
+    }
+}
+
+fn literal(val_type: Type) -> FunctionParam {
+    FunctionParam {
+        arg_kind: FunctionArgKind::Literal,
+        val_type,
+        literal_check: None,
+    }
+}
+
+fn regex_literal() -> FunctionParam {
+    FunctionParam {
+        literal_check: Some(FunctionLiteralCheck::new(|value| match value {
+            RhsValue::Bytes(pattern) => parse_regex(pattern).map(|_| ()),
+            _ => Ok(()),
+        })),
+        ..literal(Type::Bytes)
+    }
+}
+
+fn function(
+    params: Vec<FunctionParam>,
+    return_type: Type,
+    implementation: for<'a> fn(FunctionArgs<'_, 'a>) -> LhsValue<'a>,
+) -> Function {
+    Function {
+        params,
+        opt_params: vec![],
+        return_type,
+        implementation: FunctionImpl::new(implementation),
+    }
+}
+
+fn variadic_bool(
+    default_value: bool,
+    implementation: for<'a> fn(FunctionArgs<'_, 'a>) -> LhsValue<'a>,
+) -> Function {
+    Function {
+        params: vec![field(Type::Bool)],
+        opt_params: (1..MAX_BOOL_ARGS)
+            .map(|_| FunctionOptParam::new(FunctionArgKind::Field, LhsValue::Bool(default_value)))
+            .collect(),
+        return_type: Type::Bool,
+        implementation: FunctionImpl::new(implementation),
+    }
+}
+
+/// Returns all standard functions along with their names.
+///
+/// Overloads of the same function are returned separately under the same
+/// name, and should be registered with
+/// [`Scheme::add_function_overload`].
+pub fn functions() -> Vec<(String, Function)> {
+    vec![
+        (
+            "lower",
+            function(vec![field(Type::Bytes)], Type::Bytes, lower),
+        ),
+        (
+            "lower",
+            function(vec![field(Type::Str)], Type::Str, lower_str),
+        ),
+        (
+            "upper",
+            function(vec![field(Type::Bytes)], Type::Bytes, upper),
+        ),
+        (
+            "upper",
+            function(vec![field(Type::Str)], Type::Str, upper_str),
+        ),
+        ("len", function(vec![field(Type::Bytes)], Type::Int, len)),
+        (
+            "starts_with",
+            function(
+                vec![field(Type::Bytes), literal(Type::Bytes)],
+                Type::Bool,
+                starts_with,
+            ),
+        ),
+        (
+            "ends_with",
+            function(
+                vec![field(Type::Bytes), literal(Type::Bytes)],
+                Type::Bool,
+                ends_with,
+            ),
+        ),
+        (
+            "concat",
+            function(
+                vec![field(Type::Bytes), literal(Type::Bytes)],
+                Type::Bytes,
+                concat,
+            ),
+        ),
+        (
+            "url_decode",
+            function(vec![field(Type::Bytes)], Type::Bytes, url_decode),
+        ),
+        (
+            "to_string",
+            function(vec![field(Type::Int)], Type::Bytes, to_string),
+        ),
+        (
+            "regex_capture",
+            Function {
+                params: vec![field(Type::Bytes), regex_literal()],
+                opt_params: vec![
+                    FunctionOptParam::new(FunctionArgKind::Literal, LhsValue::Int(1))
+                        .with_name("group"),
+                ],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::with_state(prepare_regex, regex_capture),
+            },
+        ),
+        (
+            "regex_capture_name",
+            Function {
+                params: vec![field(Type::Bytes), regex_literal(), literal(Type::Bytes)],
+                opt_params: vec![],
+                return_type: Type::Bytes,
+                implementation: FunctionImpl::with_state(prepare_regex, regex_capture_name),
+            },
+        ),
+        ("any", variadic_bool(false, any)),
+        ("all", variadic_bool(true, all)),
+    ]
+    .into_iter()
+    .map(|(name, function)| (name.to_owned(), function))
+    .collect()
+}
+
+/// Registers all standard functions onto a [`Scheme`].
+pub fn register(scheme: &mut Scheme) -> Result<(), ItemRedefinitionError> {
+    for (name, function) in functions() {
+        scheme.add_function_overload(name, function)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution_context::ExecutionContext;
+
+    fn scheme() -> Scheme {
+        let mut scheme = Scheme! {
+            http.host: Bytes,
+            http.path: Bytes,
+            http.user_agent: Str,
+            tcp.port: Int,
+            ssl: Bool,
+            tcp: Bool,
+        };
+        register(&mut scheme).unwrap();
+        scheme
+    }
+
+    fn matches(scheme: &Scheme, ctx: &ExecutionContext<'_>, filter: &str) -> bool {
+        scheme
+            .parse(filter)
+            .unwrap()
+            .compile()
+            .unwrap()
+            .execute(ctx)
+            .unwrap()
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_invalid_regex() {
+        let scheme = scheme();
+
+        for filter in &[
+            r#"regex_capture(http.host, "(") == """#,
+            r#"regex_capture_name(http.host, "[a-", "name") == """#,
+        ] {
+            let err = scheme.parse(filter).unwrap_err();
+            assert!(err.message().starts_with("invalid value of argument #1: "));
+        }
+    }
+
+    #[test]
+    fn test_register_twice() {
+        let mut scheme = scheme();
+        assert!(register(&mut scheme).is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        let scheme = scheme();
+        let mut ctx = ExecutionContext::new(&scheme);
+        ctx.set_field_value("http.host", "Example.COM").unwrap();
+        ctx.set_field_value("http.path", "/a%20b%2Fc%zz%4").unwrap();
+        ctx.set_field_value(
+            "http.user_agent",
+            LhsValue::from("Straße/ÉTÉ").into_str().unwrap(),
+        )
+        .unwrap();
+        ctx.set_field_value("tcp.port", 443).unwrap();
+        ctx.set_field_value("ssl", true).unwrap();
+        ctx.set_field_value("tcp", false).unwrap();
+
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"lower(http.host) == "example.com""#
+        ));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"upper(http.host) == "EXAMPLE.COM""#
+        ));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"lower(http.user_agent) == "straße/été""#
+        ));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"upper(http.user_agent) == "STRASSE/ÉTÉ""#
+        ));
+        assert!(matches(&scheme, &ctx, "len(http.host) == 11"));
+        assert!(matches(&scheme, &ctx, r#"starts_with(http.host, "Exa")"#));
+        assert!(!matches(&scheme, &ctx, r#"starts_with(http.host, "exa")"#));
+        assert!(matches(&scheme, &ctx, r#"ends_with(http.host, ".COM")"#));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"concat(http.host, ":443") == "Example.COM:443""#
+        ));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"url_decode(http.path) == "/a b/c%zz%4""#
+        ));
+        assert!(matches(&scheme, &ctx, r#"to_string(tcp.port) == "443""#));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"regex_capture(http.host, "^([a-z]+)\\.([a-z]+)$") == "" and regex_capture(http.host, "(?i)^[a-z]+\\.([a-z]+)$") == "COM""#
+        ));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"regex_capture(http.host, "[A-Z]+", 0) == "E" and regex_capture(http.host, "a", 2) == """#
+        ));
+        assert!(matches(
+            &scheme,
+            &ctx,
+            r#"regex_capture_name(http.path, "^/(?P<first>[^%]+)", "first") == "a" and regex_capture_name(http.path, "^/(?P<first>[^%]+)", "second") == """#
+        ));
+        assert!(matches(&scheme, &ctx, "any(tcp, ssl)"));
+        assert!(!matches(&scheme, &ctx, "any(tcp)"));
+        assert!(!matches(&scheme, &ctx, "all(tcp, ssl)"));
+        assert!(matches(&scheme, &ctx, "all(ssl, ssl)"));
+    }
+}
@@ -0,0 +1,550 @@
+//! Generators of random filters and execution contexts for property testing.
+//!
+//! Generators are deterministic and driven by a seed, so they can be plugged
+//! into any property-testing framework by generating the seed, and failures
+//! can be reproduced from it alone.
+//!
+//! ```
+//! use wirefilter::{testing::Generator, Scheme};
+//!
+//! let scheme = Scheme! { http.host: Bytes, tcp.port: Int };
+//!
+//! for seed in 0..100 {
+//!     let mut gen = Generator::new(seed);
+//!     let ast = gen.filter(&scheme);
+//!     let ctx = gen.context(&scheme);
+//!
+//!     // Printing and parsing back preserves the filter.
+//!     let printed = ast.to_canonical_string();
+//!     assert_eq!(scheme.parse(&printed).unwrap().to_canonical_string(), printed);
+//!
+//!     ast.compile().unwrap().execute(&ctx).unwrap();
+//! }
+//! ```
+//!
+//! Values are drawn from small domains shared between filters and contexts,
+//! so that generated comparisons hold for a fair share of contexts.
+
+use crate::{
+    ast::FilterAst,
+    execution_context::ExecutionContext,
+    functions::FunctionArgKind,
+    scheme::Scheme,
+    types::{LhsValue, Type},
+};
+use std::{net::IpAddr, time::Duration};
+
+const BYTES_ALPHABET: &[u8] = b"aAb/.";
+
+const INTS: &[i32] = &[0, 1, 7, 42, 80, 443, 1000];
+
+const IPS: &[&str] = &[
+    "10.0.0.0",
+    "10.0.0.1",
+    "10.0.0.2",
+    "10.0.0.5",
+    "127.0.0.1",
+    "192.168.0.1",
+    "::1",
+    "2001:db8::1",
+];
+
+const CIDRS: &[&str] = &[
+    "10.0.0.0/30",
+    "10.0.0.4/30",
+    "10.0.0.0/8",
+    "127.0.0.0/8",
+    "2001:db8::/32",
+];
+
+const DURATIONS: &[u64] = &[0, 1, 250, 1000, 1500];
+
+const MACS: &[[u8; 6]] = &[
+    [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x00],
+    [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x01],
+    [0x00, 0x1a, 0x2b, 0x00, 0x00, 0x02],
+    [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff],
+];
+
+const MAC_PREFIXES: &[&str] = &["00:1a:2b::/24", "00:1a:2b:3c::/32", "aa::/8"];
+
+const ORDERING_OPS: &[&str] = &["==", "!=", "<", "<=", ">", ">="];
+
+/// A generator of random filters and execution contexts.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    state: u64,
+    max_depth: usize,
+}
+
+impl Generator {
+    /// Creates a generator with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            // The state of xorshift must not be zero.
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            max_depth: 4,
+        }
+    }
+
+    /// Sets the maximum nesting depth of logical operators in generated
+    /// filters.
+    ///
+    /// Default is 4.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    // xorshift64*
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn chance(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    /// Generates the source of a random filter over fields and functions of
+    /// the scheme.
+    ///
+    /// Functions with arguments that can't be generated, like fields of a
+    /// type that isn't present in the scheme, are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheme has no fields.
+    pub fn filter_source(&mut self, scheme: &Scheme) -> String {
+        assert!(
+            scheme.fields().next().is_some(),
+            "can't generate filters over a scheme without fields"
+        );
+        let mut out = String::new();
+        self.write_expr(scheme, self.max_depth, &mut out);
+        out
+    }
+
+    /// Generates a random filter over fields and functions of the scheme.
+    ///
+    /// See [`Generator::filter_source`] for details.
+    pub fn filter<'s>(&mut self, scheme: &'s Scheme) -> FilterAst<'s> {
+        let source = self.filter_source(scheme);
+        match scheme.parse(&source) {
+            Ok(ast) => ast,
+            Err(err) => panic!("generated filter doesn't parse: {}", err),
+        }
+    }
+
+    /// Generates a random execution context with values for all fields of
+    /// the scheme.
+    pub fn context<'s>(&mut self, scheme: &'s Scheme) -> ExecutionContext<'s> {
+        let mut ctx = ExecutionContext::new(scheme);
+        for (name, ty) in scheme.fields() {
+            let value: LhsValue<'static> = match ty {
+                Type::Ip => self.pick(IPS).parse::<IpAddr>().unwrap().into(),
+                Type::Bytes => {
+                    let len = self.below(5);
+                    (0..len)
+                        .map(|_| self.pick(BYTES_ALPHABET))
+                        .collect::<Vec<u8>>()
+                        .into()
+                }
+                Type::Str => {
+                    let len = self.below(5);
+                    let bytes = (0..len)
+                        .map(|_| self.pick(BYTES_ALPHABET))
+                        .collect::<Vec<u8>>();
+                    LhsValue::Str(String::from_utf8(bytes).unwrap().into())
+                }
+                Type::Int => (self.pick(INTS)).into(),
+                Type::Bool => self.chance(2).into(),
+                Type::Duration => Duration::from_millis(self.pick(DURATIONS)).into(),
+                Type::Mac => LhsValue::Mac(crate::rhs_types::MacAddr(self.pick(MACS))),
+            };
+            ctx.set_field_value(name, value).unwrap();
+        }
+        ctx
+    }
+
+    fn write_expr(&mut self, scheme: &Scheme, depth: usize, out: &mut String) {
+        match self.below(if depth == 0 { 1 } else { 6 }) {
+            0..=2 => self.write_comparison(scheme, out),
+            3 => {
+                out.push_str(if self.chance(2) { "not " } else { "!" });
+                out.push('(');
+                self.write_expr(scheme, depth - 1, out);
+                out.push(')');
+            }
+            _ => {
+                let op = self.pick(&["and", "or", "xor", "&&", "||", "^^"]);
+                let count = 2 + self.below(2);
+                for i in 0..count {
+                    if i != 0 {
+                        out.push(' ');
+                        out.push_str(op);
+                        out.push(' ');
+                    }
+                    out.push('(');
+                    self.write_expr(scheme, depth - 1, out);
+                    out.push(')');
+                }
+            }
+        }
+    }
+
+    fn write_comparison(&mut self, scheme: &Scheme, out: &mut String) {
+        let ty = if self.chance(4) {
+            match self.write_function_call(scheme, out) {
+                Some(ty) => ty,
+                None => self.write_field(scheme, out),
+            }
+        } else {
+            self.write_field(scheme, out)
+        };
+
+        if ty == Type::Bool {
+            return;
+        }
+
+        out.push(' ');
+
+        let choice = self.below(10);
+
+        if choice < 4 {
+            let field = scheme
+                .fields()
+                .filter(|&(_, field_ty)| field_ty == ty)
+                .nth(self.below(scheme.fields().count()));
+            out.push_str(self.pick(ORDERING_OPS));
+            out.push(' ');
+            match field {
+                Some((name, _)) if self.chance(4) => out.push_str(name),
+                _ => self.write_literal(ty, out),
+            }
+        } else if choice < 6 {
+            if self.chance(4) {
+                out.push_str("not ");
+            }
+            out.push_str("in { ");
+            for _ in 0..1 + self.below(3) {
+                self.write_set_item(ty, out);
+                out.push(' ');
+            }
+            out.push('}');
+        } else {
+            match ty {
+                Type::Int => {
+                    out.push_str(self.pick(&["&", "|", "^"]));
+                    out.push(' ');
+                    self.write_literal(Type::Int, out);
+                    if self.chance(2) {
+                        out.push(' ');
+                        out.push_str(self.pick(ORDERING_OPS));
+                        out.push(' ');
+                        self.write_literal(Type::Int, out);
+                    }
+                }
+                Type::Bytes | Type::Str => {
+                    let op = self.below(6);
+                    // Regular expressions are negated with their own
+                    // operators below.
+                    if op < 5 && self.chance(4) {
+                        out.push_str("not ");
+                    }
+                    match op {
+                        0 => {
+                            out.push_str("contains ");
+                            self.write_literal(ty, out);
+                        }
+                        1 => {
+                            out.push_str("contains any { ");
+                            for _ in 0..1 + self.below(3) {
+                                self.write_literal(ty, out);
+                                out.push(' ');
+                            }
+                            out.push('}');
+                        }
+                        2 => {
+                            out.push_str("startswith ");
+                            self.write_literal(ty, out);
+                        }
+                        3 => {
+                            out.push_str("endswith ");
+                            self.write_literal(ty, out);
+                        }
+                        4 => {
+                            out.push_str("ieq ");
+                            self.write_literal(ty, out);
+                        }
+                        _ if cfg!(feature = "regex") => {
+                            out.push_str(self.pick(&["matches ", "~ ", "not matches ", "!~ "]));
+                            self.write_regex(out);
+                        }
+                        _ => {
+                            out.push_str("== ");
+                            self.write_literal(ty, out);
+                        }
+                    }
+                }
+                _ => {
+                    out.push_str(self.pick(ORDERING_OPS));
+                    out.push(' ');
+                    self.write_literal(ty, out);
+                }
+            }
+        }
+    }
+
+    // Writes a field or a slice of a `Bytes` field and returns its type.
+    fn write_field(&mut self, scheme: &Scheme, out: &mut String) -> Type {
+        let (name, ty) = scheme
+            .fields()
+            .nth(self.below(scheme.fields().count()))
+            .unwrap();
+        out.push_str(name);
+        if ty == Type::Bytes && self.chance(8) {
+            let start = self.below(3);
+            let end = start + self.below(3);
+            out.push_str(&format!("[{}..{}]", start, end));
+        }
+        ty
+    }
+
+    // Writes a call of a random function and returns its return type, or
+    // returns `None` if the function can't be called.
+    fn write_function_call(&mut self, scheme: &Scheme, out: &mut String) -> Option<Type> {
+        let count = scheme.functions().count();
+        if count == 0 {
+            return None;
+        }
+        let (name, function) = scheme.functions().nth(self.below(count)).unwrap();
+
+        let mut call = format!("{}(", name);
+        for (i, param) in function.params.iter().enumerate() {
+            if i != 0 {
+                call.push_str(", ");
+            }
+            match param.arg_kind {
+                FunctionArgKind::Field => {
+                    let fields = scheme
+                        .fields()
+                        .filter(|&(_, ty)| ty == param.val_type)
+                        .collect::<Vec<_>>();
+                    if fields.is_empty() {
+                        return None;
+                    }
+                    call.push_str(self.pick(&fields).0);
+                }
+                FunctionArgKind::Literal => {
+                    if param.val_type == Type::Bool {
+                        return None;
+                    }
+                    self.write_literal(param.val_type, &mut call);
+                }
+            }
+        }
+        call.push(')');
+
+        out.push_str(&call);
+        Some(function.return_type)
+    }
+
+    fn write_literal(&mut self, ty: Type, out: &mut String) {
+        match ty {
+            Type::Ip => out.push_str(self.pick(IPS)),
+            // The alphabet is ASCII, so it's valid UTF-8 too.
+            Type::Bytes | Type::Str => {
+                let len = 1 + self.below(3);
+                let bytes = (0..len)
+                    .map(|_| self.pick(BYTES_ALPHABET))
+                    .collect::<Vec<u8>>();
+                if self.chance(8) {
+                    let hex = bytes
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<Vec<_>>();
+                    out.push_str(&hex.join(":"));
+                } else {
+                    out.push('"');
+                    out.push_str(std::str::from_utf8(&bytes).unwrap());
+                    out.push('"');
+                }
+            }
+            Type::Int => {
+                let value = self.pick(INTS);
+                if self.chance(4) {
+                    out.push_str(&format!("{:#x}", value));
+                } else {
+                    out.push_str(&value.to_string());
+                }
+            }
+            Type::Bool => unreachable!("boolean literals don't exist"),
+            Type::Duration => {
+                let millis = self.pick(DURATIONS);
+                if millis.is_multiple_of(1000) && self.chance(2) {
+                    out.push_str(&format!("{}s", millis / 1000));
+                } else {
+                    out.push_str(&format!("{}ms", millis));
+                }
+            }
+            Type::Mac => {
+                let mac = self.pick(MACS);
+                let hex = mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>();
+                out.push_str(&hex.join(":"));
+            }
+        }
+    }
+
+    fn write_set_item(&mut self, ty: Type, out: &mut String) {
+        match ty {
+            Type::Ip if self.chance(2) => out.push_str(self.pick(CIDRS)),
+            Type::Int if self.chance(3) => {
+                let mut bounds = [self.pick(INTS), self.pick(INTS)];
+                bounds.sort();
+                out.push_str(&format!("{}..{}", bounds[0], bounds[1]));
+            }
+            Type::Duration if self.chance(3) => {
+                let mut bounds = [self.pick(DURATIONS), self.pick(DURATIONS)];
+                bounds.sort();
+                out.push_str(&format!("{}ms..{}ms", bounds[0], bounds[1]));
+            }
+            Type::Mac if self.chance(3) => out.push_str(self.pick(MAC_PREFIXES)),
+            _ => self.write_literal(ty, out),
+        }
+    }
+
+    fn write_regex(&mut self, out: &mut String) {
+        out.push('"');
+        if self.chance(3) {
+            out.push('^');
+        }
+        for _ in 0..1 + self.below(3) {
+            out.push_str(self.pick(&["a", "b", ".", "a*", "/", "[ab]"]));
+        }
+        if self.chance(3) {
+            out.push('$');
+        }
+        out.push('"');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        functions::{Function, FunctionArgs, FunctionImpl, FunctionParam},
+        scheme::Scheme,
+    };
+
+    fn len_function<'a>(args: FunctionArgs<'_, 'a>) -> LhsValue<'a> {
+        match args.next().unwrap() {
+            LhsValue::Bytes(bytes) => LhsValue::Int(bytes.len() as i32),
+            _ => unreachable!(),
+        }
+    }
+
+    fn scheme() -> Scheme {
+        let mut scheme = Scheme! {
+            ip.src: Ip,
+            ip.dst: Ip,
+            http.host: Bytes,
+            http.path: Bytes,
+            http.user_agent: Str,
+            tcp.port: Int,
+            tcp.flags: Int,
+            ssl: Bool,
+            latency: Duration,
+            eth.src: Mac,
+        };
+        scheme
+            .add_function(
+                "len".into(),
+                Function {
+                    params: vec![FunctionParam {
+                        arg_kind: FunctionArgKind::Field,
+                        val_type: Type::Bytes,
+                        literal_check: None,
+                    }],
+                    opt_params: vec![],
+                    return_type: Type::Int,
+                    implementation: FunctionImpl::new(len_function),
+                },
+            )
+            .unwrap();
+        scheme
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let scheme = scheme();
+        assert_eq!(
+            Generator::new(42).filter_source(&scheme),
+            Generator::new(42).filter_source(&scheme)
+        );
+        assert_ne!(
+            Generator::new(1).filter_source(&scheme),
+            Generator::new(2).filter_source(&scheme)
+        );
+    }
+
+    #[test]
+    fn test_print_parse_round_trip() {
+        let scheme = scheme();
+
+        for seed in 0..500 {
+            let ast = Generator::new(seed).filter(&scheme);
+            let printed = ast.to_canonical_string();
+            let parsed = scheme.parse(&printed).unwrap();
+
+            assert_eq!(parsed.to_canonical_string(), printed, "seed {}", seed);
+            assert_eq!(parsed.fingerprint(), ast.fingerprint(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_rewrites_preserve_results() {
+        let scheme = scheme();
+
+        for seed in 0..500 {
+            let mut gen = Generator::new(seed);
+            let ast = gen.filter(&scheme);
+            let contexts = (0..10).map(|_| gen.context(&scheme)).collect::<Vec<_>>();
+
+            let mut simplified = ast.clone();
+            let constant = simplified.simplify();
+
+            let mut reordered = ast.clone();
+            reordered.reorder_by_cost(&crate::cost::DefaultCostModel::new());
+
+            let filter = ast.compile().unwrap();
+            let simplified = simplified.compile().unwrap();
+            let reordered = reordered.compile().unwrap();
+
+            for ctx in &contexts {
+                let expected = filter.execute(ctx).unwrap();
+                let actual = match constant {
+                    Some(value) => value,
+                    None => simplified.execute(ctx).unwrap(),
+                };
+                assert_eq!(actual, expected, "simplified, seed {}", seed);
+                assert_eq!(
+                    reordered.execute(ctx).unwrap(),
+                    expected,
+                    "reordered, seed {}",
+                    seed
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::{
+    ast::{unshare_expr, CombinedExpr, CombiningOp, Expr, FieldExpr, NodeId, SimpleExpr, UnaryOp},
+    execution_context::ExecutionContext,
+    filter::{CompiledExpr, ExecutionScope},
+    types::LhsValue,
+};
+use serde_json::{json, Value};
+use std::{
+    fmt::Display,
+    str,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// OTLP JSON encodes 64-bit integers as strings.
+fn otel_int(value: impl Display) -> Value {
+    json!({ "intValue": value.to_string() })
+}
+
+fn otel_string(value: impl Display) -> Value {
+    json!({ "stringValue": value.to_string() })
+}
+
+// OTLP JSON encodes bytes in base64.
+fn otel_bytes(bytes: &[u8]) -> Value {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len() / 3 * 4 + 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            encoded.push(if i <= chunk.len() {
+                ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    json!({ "bytesValue": encoded })
+}
+
+// Converts an observed value into an OTLP `AnyValue`.
+fn otel_value(value: &LhsValue<'_>) -> Value {
+    match value {
+        LhsValue::Int(value) => otel_int(value),
+        LhsValue::Bool(value) => json!({ "boolValue": value }),
+        LhsValue::Duration(value) => otel_int(value.as_nanos()),
+        LhsValue::Ip(value) => otel_string(value),
+        LhsValue::Mac(value) => otel_string(value),
+        LhsValue::Str(value) => otel_string(value),
+        LhsValue::Bytes(value) => match str::from_utf8(value) {
+            Ok(value) => otel_string(value),
+            Err(_) => otel_bytes(value),
+        },
+    }
+}
+
+/// A node of a trace produced by
+/// [`Filter::execute_with_trace`](::Filter::execute_with_trace).
+///
+/// The trace mirrors the structure of the filter AST, except that
+/// parentheses are omitted.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Trace<'a, 's> {
+    /// An operation on a field or a function call.
+    Comparison {
+        /// The traced expression.
+        expr: &'a FieldExpr<'s>,
+        /// Observed value of the field or the result of the function call.
+        value: LhsValue<'a>,
+        /// Outcome of the operation.
+        result: bool,
+        /// Time spent on evaluation.
+        elapsed: Duration,
+    },
+
+    /// A negation.
+    Not {
+        /// Trace of the negated expression.
+        arg: Box<Trace<'a, 's>>,
+        /// Outcome of the negation.
+        result: bool,
+        /// Time spent on evaluation.
+        elapsed: Duration,
+    },
+
+    /// A logical operator applied to several expressions.
+    Combining {
+        /// The logical operator.
+        op: CombiningOp,
+        /// Traces of evaluated operands, in order.
+        items: Vec<Trace<'a, 's>>,
+        /// Number of trailing operands that weren't evaluated because the
+        /// result was already decided by the last evaluated one.
+        skipped: usize,
+        /// Outcome of the operator.
+        result: bool,
+        /// Time spent on evaluation.
+        elapsed: Duration,
+    },
+}
+
+impl<'a, 's> Trace<'a, 's> {
+    /// Returns the outcome of the traced expression.
+    pub fn result(&self) -> bool {
+        match self {
+            Trace::Comparison { result, .. }
+            | Trace::Not { result, .. }
+            | Trace::Combining { result, .. } => *result,
+        }
+    }
+
+    /// Returns the time spent on evaluation of the traced expression.
+    pub fn elapsed(&self) -> Duration {
+        match self {
+            Trace::Comparison { elapsed, .. }
+            | Trace::Not { elapsed, .. }
+            | Trace::Combining { elapsed, .. } => *elapsed,
+        }
+    }
+
+    /// Returns the [`NodeId`] of the traced comparison.
+    ///
+    /// Logical operators and negations don't have identifiers.
+    pub fn node_id(&self) -> Option<NodeId> {
+        match self {
+            Trace::Comparison { expr, .. } => Some(expr.node_id()),
+            Trace::Not { .. } | Trace::Combining { .. } => None,
+        }
+    }
+
+    /// Exports the trace as a list of OpenTelemetry span events in the OTLP
+    /// JSON encoding, one per evaluated node in depth-first order, which can
+    /// be attached to the span that covers filter execution.
+    ///
+    /// Start times of nodes are reconstructed from `start_time` of the
+    /// execution and durations of preceding nodes.
+    ///
+    /// Each event has the following attributes:
+    ///
+    /// * `wirefilter.result` - outcome of the node;
+    /// * `wirefilter.duration_ns` - time spent on evaluation;
+    /// * `wirefilter.depth` - depth of the node in the trace;
+    /// * `wirefilter.node_id`, `wirefilter.expression` and `wirefilter.value`
+    ///   for comparisons - the [`NodeId`], the JSON form of the expression
+    ///   and the observed value;
+    /// * `wirefilter.op` and `wirefilter.skipped` for logical operators.
+    ///
+    /// Observed values are integers for `Int` fields and for `Duration`
+    /// ones, in nanoseconds, booleans for `Bool` fields, bytes for `Bytes`
+    /// values that aren't valid UTF-8, and strings otherwise.
+    pub fn to_otel_events(&self, start_time: SystemTime) -> Vec<Value> {
+        let mut events = Vec::new();
+        self.push_otel_events(start_time, 0, &mut events);
+        events
+    }
+
+    fn push_otel_events(&self, start_time: SystemTime, depth: usize, events: &mut Vec<Value>) {
+        fn attribute(key: &str, value: Value) -> Value {
+            json!({ "key": format!("wirefilter.{}", key), "value": value })
+        }
+
+        let (name, mut attributes) = match self {
+            Trace::Comparison { expr, value, .. } => (
+                "comparison",
+                vec![
+                    attribute("node_id", otel_string(expr.node_id())),
+                    attribute(
+                        "expression",
+                        otel_string(serde_json::to_string(expr).unwrap()),
+                    ),
+                    attribute("value", otel_value(value)),
+                ],
+            ),
+            Trace::Not { .. } => ("not", vec![]),
+            Trace::Combining { op, skipped, .. } => (
+                "combining",
+                vec![
+                    attribute("op", otel_string(format!("{:?}", op))),
+                    attribute("skipped", otel_int(skipped)),
+                ],
+            ),
+        };
+
+        attributes.extend(vec![
+            attribute("result", json!({ "boolValue": self.result() })),
+            attribute("duration_ns", otel_int(self.elapsed().as_nanos())),
+            attribute("depth", otel_int(depth)),
+        ]);
+
+        let time = start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+
+        events.push(json!({
+            "timeUnixNano": time.to_string(),
+            "name": format!("wirefilter.{}", name),
+            "attributes": attributes,
+        }));
+
+        match self {
+            Trace::Comparison { .. } => {}
+            Trace::Not { arg, .. } => arg.push_otel_events(start_time, depth + 1, events),
+            Trace::Combining { items, .. } => {
+                let mut start_time = start_time;
+                for item in items {
+                    item.push_otel_events(start_time, depth + 1, events);
+                    start_time += item.elapsed();
+                }
+            }
+        }
+    }
+}
+
+// A copy of the AST with compiled leaves, retained by filters compiled with
+// `FilterAst::compile_with_trace`.
+pub(crate) enum TraceExpr<'s> {
+    Comparison {
+        expr: FieldExpr<'s>,
+        compiled: CompiledExpr<'s>,
+    },
+    Not(Box<TraceExpr<'s>>),
+    Combining {
+        op: CombiningOp,
+        items: Box<[TraceExpr<'s>]>,
+    },
+}
+
+impl<'s> TraceExpr<'s> {
+    pub(crate) fn new(expr: CombinedExpr<'s>) -> Self {
+        match expr {
+            CombinedExpr::Simple(expr) => Self::new_simple(expr),
+            CombinedExpr::Combining { op, items } => TraceExpr::Combining {
+                op,
+                items: items.into_iter().map(TraceExpr::new).collect(),
+            },
+        }
+    }
+
+    fn new_simple(expr: SimpleExpr<'s>) -> Self {
+        match expr {
+            SimpleExpr::Field(expr) => TraceExpr::Comparison {
+                compiled: expr.clone().compile(),
+                expr,
+            },
+            SimpleExpr::Parenthesized(expr) => Self::new(unshare_expr(expr)),
+            SimpleExpr::Unary {
+                op: UnaryOp::Not,
+                arg,
+            } => TraceExpr::Not(Box::new(Self::new_simple(*arg))),
+        }
+    }
+
+    pub(crate) fn execute<'a>(
+        &'a self,
+        ctx: &'a ExecutionContext<'s>,
+        scope: &mut ExecutionScope,
+    ) -> Trace<'a, 's> {
+        let start = Instant::now();
+        match self {
+            TraceExpr::Comparison { expr, compiled } => {
+                // Function calls are executed once more to record their
+                // results, but that's fine for a debugging aid.
+                let value = expr.lhs().execute(ctx);
+                let result = compiled.execute_in(ctx, scope);
+                Trace::Comparison {
+                    expr,
+                    value,
+                    result,
+                    elapsed: start.elapsed(),
+                }
+            }
+            TraceExpr::Not(arg) => {
+                let arg = arg.execute(ctx, scope);
+                Trace::Not {
+                    result: !arg.result(),
+                    arg: Box::new(arg),
+                    elapsed: start.elapsed(),
+                }
+            }
+            TraceExpr::Combining { op, items } => {
+                let mut traces = Vec::with_capacity(items.len());
+                let mut result = match op {
+                    CombiningOp::And => true,
+                    CombiningOp::Or | CombiningOp::Xor => false,
+                };
+
+                for item in items.iter() {
+                    let trace = item.execute(ctx, scope);
+                    let item_result = trace.result();
+                    traces.push(trace);
+
+                    match op {
+                        CombiningOp::And if !item_result => {
+                            result = false;
+                            break;
+                        }
+                        CombiningOp::Or if item_result => {
+                            result = true;
+                            break;
+                        }
+                        CombiningOp::Xor => result ^= item_result,
+                        _ => {}
+                    }
+                }
+
+                Trace::Combining {
+                    op: *op,
+                    skipped: items.len() - traces.len(),
+                    items: traces,
+                    result,
+                    elapsed: start.elapsed(),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_to_otel_events() {
+    let scheme = Scheme! { tcp.port: Int, ssl: Bool };
+    let mut ctx = ExecutionContext::new(&scheme);
+    ctx.set_field_value("tcp.port", 443).unwrap();
+    ctx.set_field_value("ssl", true).unwrap();
+
+    let filter = scheme
+        .parse("tcp.port == 443 and not ssl")
+        .unwrap()
+        .compile_with_trace()
+        .unwrap();
+    let trace = filter.execute_with_trace(&ctx).unwrap().unwrap();
+
+    let start_time = UNIX_EPOCH + Duration::from_secs(1);
+    let events = trace.to_otel_events(start_time);
+
+    let summary = events
+        .iter()
+        .map(|event| {
+            let attribute = |key: &str| {
+                event["attributes"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|attribute| attribute["key"] == format!("wirefilter.{}", key))
+                    .map(|attribute| attribute["value"].clone())
+            };
+            (
+                event["name"].as_str().unwrap().to_owned(),
+                attribute("result").unwrap(),
+                attribute("depth").unwrap(),
+                attribute("value"),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        summary,
+        [
+            (
+                "wirefilter.combining".to_owned(),
+                json!({ "boolValue": false }),
+                json!({ "intValue": "0" }),
+                None
+            ),
+            (
+                "wirefilter.comparison".to_owned(),
+                json!({ "boolValue": true }),
+                json!({ "intValue": "1" }),
+                Some(json!({ "intValue": "443" }))
+            ),
+            (
+                "wirefilter.not".to_owned(),
+                json!({ "boolValue": false }),
+                json!({ "intValue": "1" }),
+                None
+            ),
+            (
+                "wirefilter.comparison".to_owned(),
+                json!({ "boolValue": true }),
+                json!({ "intValue": "2" }),
+                Some(json!({ "boolValue": true }))
+            ),
+        ]
+    );
+
+    let comparison = &trace_items(&trace)[0];
+    assert_eq!(
+        comparison.node_id(),
+        Some(scheme.parse("tcp.port == 443").unwrap().node_ids()[0])
+    );
+    assert!(events[1]["attributes"]
+        .as_array()
+        .unwrap()
+        .contains(&json!({
+            "key": "wirefilter.node_id",
+            "value": { "stringValue": comparison.node_id().unwrap().to_string() }
+        })));
+    assert_eq!(trace.node_id(), None);
+
+    assert_eq!(events[0]["timeUnixNano"], "1000000000");
+    assert_eq!(
+        events[2]["timeUnixNano"],
+        (1_000_000_000 + trace_items(&trace)[0].elapsed().as_nanos()).to_string()
+    );
+
+    fn trace_items<'t, 'a, 's>(trace: &'t Trace<'a, 's>) -> &'t [Trace<'a, 's>] {
+        match trace {
+            Trace::Combining { items, .. } => items,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_otel_values() {
+    use crate::rhs_types::MacAddr;
+    use std::net::IpAddr;
+
+    assert_eq!(otel_value(&LhsValue::Int(-1)), json!({ "intValue": "-1" }));
+    assert_eq!(
+        otel_value(&LhsValue::Duration(Duration::from_millis(2))),
+        json!({ "intValue": "2000000" })
+    );
+    assert_eq!(
+        otel_value(&LhsValue::Ip(IpAddr::from([10, 0, 0, 1]))),
+        json!({ "stringValue": "10.0.0.1" })
+    );
+    assert_eq!(
+        otel_value(&LhsValue::Mac(MacAddr::from([0xaa, 0xbb, 0xcc, 0, 0, 1]))),
+        json!({ "stringValue": "aa:bb:cc:00:00:01" })
+    );
+    assert_eq!(
+        otel_value(&LhsValue::from("example.org")),
+        json!({ "stringValue": "example.org" })
+    );
+    assert_eq!(
+        otel_value(&LhsValue::from(&b"\xff\x00ab"[..])),
+        json!({ "bytesValue": "/wBhYg==" })
+    );
+    assert_eq!(
+        otel_value(&LhsValue::from(&b"\xffabc"[..])),
+        json!({ "bytesValue": "/2FiYw==" })
+    );
+    assert_eq!(
+        otel_value(&LhsValue::from(&b"\xffa"[..])),
+        json!({ "bytesValue": "/2E=" })
+    );
+}
@@ -1,6 +1,10 @@
 use crate::{
-    lex::{expect, skip_space, Lex, LexResult, LexWith},
-    rhs_types::{Bytes, IpRange, UninhabitedBool},
+    lex::{expect, skip_space, span, Lex, LexErrorKind, LexResult, LexState, LexWith},
+    rhs_types::{
+        exclude_int_ranges, exclude_ip_ranges, find_overlapping_int_ranges,
+        find_overlapping_ip_ranges, Bytes, IntLiteral, IpRange, MacAddr, MacPrefix, StrLiteral,
+        UninhabitedBool,
+    },
     strict_partial_ord::StrictPartialOrd,
 };
 use failure::Fail;
@@ -10,26 +14,67 @@ use std::{
     cmp::Ordering,
     convert::TryFrom,
     fmt::{self, Debug, Formatter},
+    mem,
     net::IpAddr,
     ops::RangeInclusive,
+    str,
+    time::Duration,
 };
 
-fn lex_rhs_values<'i, T: Lex<'i>>(input: &'i str) -> LexResult<'i, Vec<T>> {
+// Values of a set along with their spans, and values excluded from it after
+// `except` along with the keyword itself.
+type SetValues<'i, T> = (Vec<T>, Vec<&'i str>, Option<(Vec<T>, &'i str)>);
+
+fn lex_rhs_values<'i, T: for<'a> LexWith<'i, &'a LexState<'i>>>(
+    input: &'i str,
+    state: &LexState<'i>,
+) -> LexResult<'i, SetValues<'i, T>> {
     let mut input = expect(input, "{")?;
     let mut res = Vec::new();
+    let mut spans = Vec::new();
+    let mut except = None;
     loop {
         input = skip_space(input);
         if let Ok(rest) = expect(input, "}") {
             input = rest;
-            return Ok((res, input));
-        } else {
-            let (item, rest) = T::lex(input)?;
-            res.push(item);
-            input = rest;
+            let values = match except {
+                Some((included, keyword)) => (included, spans, Some((res, keyword))),
+                None => (res, spans, None),
+            };
+            return Ok((values, input));
+        }
+        match expect(input, "except") {
+            Ok(rest) if except.is_none() => {
+                except = Some((mem::take(&mut res), span(input, rest)));
+                input = rest;
+            }
+            _ => {
+                let (item, rest) = T::lex_with(input, state)?;
+                if except.is_none() {
+                    spans.push(span(input, rest));
+                }
+                res.push(item);
+                input = rest;
+            }
         }
     }
 }
 
+/// An error that occurs when a value can't be converted to a `Str`.
+#[derive(Debug, PartialEq, Fail)]
+pub enum StrConversionError {
+    /// The value is neither `Bytes` nor `Str`.
+    #[fail(display = "{}", _0)]
+    TypeMismatch(#[cause] TypeMismatchError),
+
+    /// The bytes are not valid UTF-8.
+    #[fail(display = "invalid UTF-8 after {} bytes", valid_up_to)]
+    InvalidUtf8 {
+        /// Length of the valid prefix.
+        valid_up_to: usize,
+    },
+}
+
 /// An error that occurs on a type mismatch.
 #[derive(Debug, PartialEq, Fail)]
 #[fail(
@@ -70,7 +115,7 @@ macro_rules! declare_types {
 
     ($($(# $attrs:tt)* $name:ident ( $(# $lhs_attrs:tt)* $lhs_ty:ty | $rhs_ty:ty | $multi_rhs_ty:ty ) , )*) => {
         /// Enumeration of supported types for field values.
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
         #[repr(C)]
         pub enum Type {
             $($(# $attrs)* $name,)*
@@ -88,6 +133,15 @@ macro_rules! declare_types {
             }
         }
 
+        impl<'i> Lex<'i> for Type {
+            fn lex(input: &str) -> LexResult<'_, Self> {
+                $(if let Ok(input) = expect(input, stringify!($name)) {
+                    return Ok((Type::$name, input));
+                })*
+                Err((LexErrorKind::ExpectedName("type"), input))
+            }
+        }
+
         declare_types! {
             /// An LHS value provided for filter execution.
             ///
@@ -107,6 +161,10 @@ macro_rules! declare_types {
             }
         })*
 
+        $(impl<'a> TypedValue for $lhs_ty {
+            const TYPE: Type = Type::$name;
+        })*
+
         $(impl<'a> TryFrom<LhsValue<'a>> for $lhs_ty {
             type Error = TypeMismatchError;
 
@@ -130,11 +188,14 @@ macro_rules! declare_types {
             }
         }
 
-        impl<'i> LexWith<'i, Type> for RhsValue {
-            fn lex_with(input: &str, ty: Type) -> LexResult<'_, Self> {
+        impl<'i, 'a> LexWith<'i, (Type, &'a LexState<'i>)> for RhsValue {
+            fn lex_with(
+                input: &'i str,
+                (ty, state): (Type, &'a LexState<'i>),
+            ) -> LexResult<'i, Self> {
                 Ok(match ty {
                     $(Type::$name => {
-                        let (value, input) = <$rhs_ty>::lex(input)?;
+                        let (value, input) = <$rhs_ty>::lex_with(input, state)?;
                         (RhsValue::$name(value), input)
                     })*
                 })
@@ -165,6 +226,10 @@ macro_rules! declare_types {
             ///
             /// This is used for `field in { ... }` operation that allows
             /// only same-typed values in a list.
+            ///
+            /// Sets of IPs and integers can be followed by `except` and values
+            /// to exclude, like `{ 10.0.0.0/8 except 10.1.2.0/24 }`, which are
+            /// subtracted from the ranges while parsing.
             #[derive(PartialEq, Eq, Clone, Serialize)]
             #[serde(untagged)]
             enum RhsValues {
@@ -172,20 +237,93 @@ macro_rules! declare_types {
             }
         }
 
-        impl<'i> LexWith<'i, Type> for RhsValues {
-            fn lex_with(input: &str, ty: Type) -> LexResult<'_, Self> {
+        impl<'i, 'a> LexWith<'i, (Type, &'a LexState<'i>)> for RhsValues {
+            fn lex_with(
+                input: &'i str,
+                (ty, state): (Type, &'a LexState<'i>),
+            ) -> LexResult<'i, Self> {
                 Ok(match ty {
                     $(Type::$name => {
-                        let (value, input) = lex_rhs_values(input)?;
-                        (RhsValues::$name(value), input)
+                        let ((values, spans, excluded), input) = lex_rhs_values(input, state)?;
+                        let values = RhsValues::$name(values);
+                        values.warn_overlaps(&spans, state);
+                        match excluded {
+                            Some((excluded, keyword)) => (
+                                values
+                                    .exclude(RhsValues::$name(excluded))
+                                    .map_err(|kind| (kind, keyword))?,
+                                input,
+                            ),
+                            None => (values, input),
+                        }
                     })*
                 })
             }
         }
+
+        impl RhsValues {
+            // Appends values from another list of the same type, or returns
+            // it back if types are different.
+            pub(crate) fn append(&mut self, other: RhsValues) -> Result<(), RhsValues> {
+                match (self, other) {
+                    $((RhsValues::$name(values), RhsValues::$name(other)) => {
+                        values.extend(other);
+                        Ok(())
+                    })*
+                    (_, other) => Err(other),
+                }
+            }
+        }
     };
 }
 
+impl RhsValues {
+    // Reports values of sets of IPs and integers that overlap other ones,
+    // which are likely mistakes, as parse warnings.
+    fn warn_overlaps<'i>(&self, spans: &[&'i str], state: &LexState<'i>) {
+        let overlapping = match self {
+            RhsValues::Ip(ranges) => find_overlapping_ip_ranges(ranges),
+            RhsValues::Int(ranges) => find_overlapping_int_ranges(ranges),
+            _ => return,
+        };
+        for index in overlapping {
+            state.warn(LexErrorKind::OverlappingSetValues, spans[index]);
+        }
+    }
+
+    // Removes values of another list of the same type, which is supported
+    // only for ranges of IPs and integers.
+    fn exclude(self, excluded: RhsValues) -> Result<RhsValues, LexErrorKind> {
+        match (self, excluded) {
+            (RhsValues::Ip(ranges), RhsValues::Ip(excluded)) => {
+                Ok(RhsValues::Ip(exclude_ip_ranges(ranges, excluded)))
+            }
+            (RhsValues::Int(ranges), RhsValues::Int(excluded)) => {
+                Ok(RhsValues::Int(exclude_int_ranges(ranges, excluded)))
+            }
+            (values, _) => Err(LexErrorKind::UnsupportedExclusion {
+                lhs_type: values.get_type(),
+            }),
+        }
+    }
+}
+
+/// A Rust type that is always converted into [`LhsValue`]s of the same
+/// [`Type`], so that the type is known without having a value.
+pub trait TypedValue {
+    /// Type of the converted values.
+    const TYPE: Type;
+}
+
 // special cases for simply passing owned and borrowed bytes
+impl<'a> TypedValue for &'a [u8] {
+    const TYPE: Type = Type::Bytes;
+}
+
+impl TypedValue for Vec<u8> {
+    const TYPE: Type = Type::Bytes;
+}
+
 impl<'a> From<&'a [u8]> for LhsValue<'a> {
     #[inline]
     fn from(b: &'a [u8]) -> Self {
@@ -201,6 +339,14 @@ impl From<Vec<u8>> for LhsValue<'static> {
 }
 
 // special cases for simply passing strings and string slices
+impl<'a> TypedValue for &'a str {
+    const TYPE: Type = Type::Bytes;
+}
+
+impl TypedValue for String {
+    const TYPE: Type = Type::Bytes;
+}
+
 impl<'a> From<&'a str> for LhsValue<'a> {
     #[inline]
     fn from(s: &'a str) -> Self {
@@ -220,8 +366,11 @@ impl<'a> From<&'a RhsValue> for LhsValue<'a> {
         match rhs_value {
             RhsValue::Ip(ip) => LhsValue::Ip(*ip),
             RhsValue::Bytes(bytes) => LhsValue::Bytes(Cow::Borrowed(bytes)),
-            RhsValue::Int(integer) => LhsValue::Int(*integer),
+            RhsValue::Int(integer) => LhsValue::Int(integer.value()),
+            RhsValue::Duration(duration) => LhsValue::Duration(*duration),
+            RhsValue::Mac(mac) => LhsValue::Mac(*mac),
             RhsValue::Bool(b) => match *b {},
+            RhsValue::Str(s) => LhsValue::Str(Cow::Borrowed(s)),
         }
     }
 }
@@ -235,10 +384,52 @@ impl<'a> LhsValue<'a> {
             LhsValue::Bytes(bytes) => LhsValue::Bytes(Cow::Borrowed(bytes)),
             LhsValue::Int(integer) => LhsValue::Int(*integer),
             LhsValue::Bool(b) => LhsValue::Bool(*b),
+            LhsValue::Duration(duration) => LhsValue::Duration(*duration),
+            LhsValue::Mac(mac) => LhsValue::Mac(*mac),
+            LhsValue::Str(s) => LhsValue::Str(Cow::Borrowed(s)),
+        }
+    }
+
+    /// Converts a `Bytes` value to a `Str` one if it's valid UTF-8, or
+    /// returns a `Str` value unchanged.
+    pub fn into_str(self) -> Result<Self, StrConversionError> {
+        let invalid_utf8 = |err: str::Utf8Error| StrConversionError::InvalidUtf8 {
+            valid_up_to: err.valid_up_to(),
+        };
+        match self {
+            LhsValue::Bytes(Cow::Borrowed(bytes)) => str::from_utf8(bytes)
+                .map(|s| LhsValue::Str(Cow::Borrowed(s)))
+                .map_err(invalid_utf8),
+            LhsValue::Bytes(Cow::Owned(bytes)) => String::from_utf8(bytes)
+                .map(|s| LhsValue::Str(Cow::Owned(s)))
+                .map_err(|err| invalid_utf8(err.utf8_error())),
+            LhsValue::Str(s) => Ok(LhsValue::Str(s)),
+            value => Err(StrConversionError::TypeMismatch(TypeMismatchError {
+                expected: Type::Str,
+                actual: value.get_type(),
+            })),
+        }
+    }
+}
+
+// Used for comparisons between fields.
+impl<'a> PartialOrd for LhsValue<'a> {
+    fn partial_cmp(&self, other: &LhsValue<'a>) -> Option<Ordering> {
+        match (self, other) {
+            (LhsValue::Ip(lhs), LhsValue::Ip(rhs)) => lhs.strict_partial_cmp(rhs),
+            (LhsValue::Bytes(lhs), LhsValue::Bytes(rhs)) => lhs[..].strict_partial_cmp(&rhs[..]),
+            (LhsValue::Int(lhs), LhsValue::Int(rhs)) => lhs.strict_partial_cmp(rhs),
+            (LhsValue::Bool(lhs), LhsValue::Bool(rhs)) => lhs.partial_cmp(rhs),
+            (LhsValue::Duration(lhs), LhsValue::Duration(rhs)) => lhs.strict_partial_cmp(rhs),
+            (LhsValue::Mac(lhs), LhsValue::Mac(rhs)) => lhs.strict_partial_cmp(rhs),
+            (LhsValue::Str(lhs), LhsValue::Str(rhs)) => lhs[..].strict_partial_cmp(&rhs[..]),
+            _ => None,
         }
     }
 }
 
+impl<'a> StrictPartialOrd for LhsValue<'a> {}
+
 declare_types!(
     /// An IPv4 or IPv6 field.
     ///
@@ -252,10 +443,29 @@ declare_types!(
     Bytes(#[serde(borrow)] Cow<'a, [u8]> | Bytes | Bytes),
 
     /// A 32-bit integer number.
-    Int(i32 | i32 | RangeInclusive<i32>),
+    Int(i32 | IntLiteral | RangeInclusive<IntLiteral>),
 
     /// A boolean.
     Bool(bool | UninhabitedBool | UninhabitedBool),
+
+    /// A time interval.
+    ///
+    /// Literals are written as a sequence of integer components with units,
+    /// e.g. `5s`, `200ms` or `1h30m`.
+    Duration(Duration | Duration | RangeInclusive<Duration>),
+
+    /// A 48-bit MAC address.
+    ///
+    /// Sets can contain prefixes like `aa:bb:cc::/24` to match whole OUIs.
+    Mac(MacAddr | MacAddr | MacPrefix),
+
+    /// A string field that is guaranteed to be valid UTF-8.
+    ///
+    /// Literals are written like bytes, but must be valid UTF-8. Unlike
+    /// bytes, strings are matched by regexes in Unicode mode and compared by
+    /// `ieq` with Unicode case mapping. Byte values can be converted with
+    /// [`LhsValue::into_str`].
+    Str(#[serde(borrow)] Cow<'a, str> | StrLiteral | StrLiteral),
 );
 
 #[test]
@@ -291,3 +501,24 @@ fn test_lhs_value_deserialize() {
     let b: LhsValue<'_> = serde_json::from_str("false").unwrap();
     assert_eq!(b, LhsValue::Bool(false));
 }
+
+#[test]
+fn test_into_str() {
+    assert_eq!(
+        LhsValue::from("straße").into_str(),
+        Ok(LhsValue::Str(Cow::Borrowed("straße")))
+    );
+
+    assert_eq!(
+        LhsValue::from(b"stra\xdfe".to_vec()).into_str(),
+        Err(StrConversionError::InvalidUtf8 { valid_up_to: 4 })
+    );
+
+    assert_eq!(
+        LhsValue::Int(1).into_str(),
+        Err(StrConversionError::TypeMismatch(TypeMismatchError {
+            expected: Type::Str,
+            actual: Type::Int,
+        }))
+    );
+}
@@ -0,0 +1,66 @@
+use crate::{
+    ip_trie::IpPrefixTrie,
+    list_matcher::ListMatcher,
+    range_set::RangeSet,
+    rhs_types::MacAddr,
+    types::{LhsValue, RhsValues},
+};
+use fnv::FnvBuildHasher;
+use indexmap::IndexSet;
+use std::{sync::Arc, time::Duration};
+
+/// A set of values of the same type prepared for lookups, which is used both
+/// for `in` with literal values and for named lists.
+pub(crate) enum ValueSet {
+    Ip(IpPrefixTrie),
+    Int(RangeSet<i32>),
+    Mac(RangeSet<MacAddr>),
+    Duration(RangeSet<Duration>),
+    Bytes(IndexSet<Box<[u8]>, FnvBuildHasher>),
+    Str(IndexSet<Box<str>, FnvBuildHasher>),
+    // Sets of booleans can't have any values.
+    Bool,
+    // A custom matcher of `Bytes` or `Str` values.
+    Matcher(Arc<dyn ListMatcher>),
+}
+
+impl ValueSet {
+    pub(crate) fn new(values: RhsValues) -> Self {
+        match values {
+            RhsValues::Ip(ranges) => ValueSet::Ip(IpPrefixTrie::new(ranges)),
+            RhsValues::Int(ranges) => ValueSet::Int(
+                ranges
+                    .iter()
+                    .map(|range| range.start().value()..=range.end().value())
+                    .collect(),
+            ),
+            RhsValues::Mac(prefixes) => {
+                ValueSet::Mac(prefixes.into_iter().map(Into::into).collect())
+            }
+            RhsValues::Duration(ranges) => ValueSet::Duration(ranges.into_iter().collect()),
+            RhsValues::Bytes(values) => {
+                ValueSet::Bytes(values.into_iter().map(Into::into).collect())
+            }
+            RhsValues::Str(values) => ValueSet::Str(values.into_iter().map(Into::into).collect()),
+            RhsValues::Bool(_) => ValueSet::Bool,
+        }
+    }
+
+    /// Checks whether a value of the same type is in the set.
+    pub(crate) fn contains(&self, value: &LhsValue<'_>) -> bool {
+        match (self, value) {
+            (ValueSet::Ip(ranges), LhsValue::Ip(addr)) => ranges.contains(addr),
+            (ValueSet::Int(ranges), LhsValue::Int(value)) => ranges.contains(value),
+            (ValueSet::Mac(ranges), LhsValue::Mac(addr)) => ranges.contains(addr),
+            (ValueSet::Duration(ranges), LhsValue::Duration(value)) => ranges.contains(value),
+            (ValueSet::Bytes(values), LhsValue::Bytes(value)) => values.contains(&value[..]),
+            (ValueSet::Str(values), LhsValue::Str(value)) => values.contains(&value[..]),
+            (ValueSet::Bool, LhsValue::Bool(_)) => false,
+            (ValueSet::Matcher(matcher), LhsValue::Bytes(value)) => matcher.contains(value),
+            (ValueSet::Matcher(matcher), LhsValue::Str(value)) => {
+                matcher.contains(value.as_bytes())
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -3,6 +3,7 @@
 
 #include <stdlib.h>
 #include <stdint.h>
+#include <stdbool.h>
 
 #ifdef __cplusplus
 extern "C" {
@@ -45,22 +46,82 @@ typedef union {
     } ok;
 } wirefilter_parsing_result_t;
 
+typedef union {
+    uint8_t success;
+    struct {
+        uint8_t _res1;
+        wirefilter_rust_allocated_str_t msg;
+    } err;
+    struct {
+        uint8_t _res2;
+        wirefilter_scheme_t *scheme;
+    } ok;
+} wirefilter_scheme_result_t;
+
+typedef union {
+    uint8_t success;
+    struct {
+        uint8_t _res1;
+        wirefilter_rust_allocated_str_t msg;
+    } err;
+    struct {
+        uint8_t _res2;
+        wirefilter_filter_t *filter;
+    } ok;
+} wirefilter_compiling_result_t;
+
+typedef union {
+    uint8_t success;
+    struct {
+        uint8_t _res1;
+        wirefilter_rust_allocated_str_t msg;
+    } err;
+    struct {
+        uint8_t _res2;
+        bool value;
+    } ok;
+} wirefilter_matching_result_t;
+
 typedef enum {
     WIREFILTER_TYPE_IP,
     WIREFILTER_TYPE_BYTES,
     WIREFILTER_TYPE_INT,
     WIREFILTER_TYPE_BOOL,
+    WIREFILTER_TYPE_DURATION,
+    WIREFILTER_TYPE_MAC,
+    WIREFILTER_TYPE_STR,
 } wirefilter_type_t;
 
 wirefilter_scheme_t *wirefilter_create_scheme();
 void wirefilter_free_scheme(wirefilter_scheme_t *scheme);
 
-void wirefilter_add_type_field_to_scheme(
+bool wirefilter_add_type_field_to_scheme(
     wirefilter_scheme_t *scheme,
     wirefilter_externally_allocated_str_t name,
     wirefilter_type_t type
 );
 
+bool wirefilter_add_function_to_scheme(
+    wirefilter_scheme_t *scheme,
+    wirefilter_externally_allocated_str_t name
+);
+
+bool wirefilter_add_standard_functions_to_scheme(wirefilter_scheme_t *scheme);
+
+// Functions of parsed schemes have no implementations: filters calling them
+// can be parsed, but `wirefilter_compile_filter` reports an error.
+wirefilter_scheme_result_t wirefilter_parse_scheme_from_json(
+    wirefilter_externally_allocated_str_t json
+);
+
+void wirefilter_free_scheme_result(wirefilter_scheme_result_t result);
+
+wirefilter_rust_allocated_str_t wirefilter_serialize_scheme_to_json(
+    const wirefilter_scheme_t *scheme
+);
+
+// Filters with placeholders like `${name:Int}` are rejected, as they can't
+// be bound through this API.
 wirefilter_parsing_result_t wirefilter_parse_filter(
     const wirefilter_scheme_t *scheme,
     wirefilter_externally_allocated_str_t input
@@ -68,7 +129,9 @@ wirefilter_parsing_result_t wirefilter_parse_filter(
 
 void wirefilter_free_parsing_result(wirefilter_parsing_result_t result);
 
-wirefilter_filter_t *wirefilter_compile_filter(wirefilter_filter_ast_t *ast);
+// Filters calling functions of parsed schemes can't be compiled.
+wirefilter_compiling_result_t wirefilter_compile_filter(wirefilter_filter_ast_t *ast);
+void wirefilter_free_compiling_result(wirefilter_compiling_result_t result);
 void wirefilter_free_compiled_filter(wirefilter_filter_t *filter);
 
 wirefilter_execution_context_t *wirefilter_create_execution_context(
@@ -78,41 +141,69 @@ void wirefilter_free_execution_context(
     wirefilter_execution_context_t *exec_ctx
 );
 
-void wirefilter_add_int_value_to_execution_context(
+bool wirefilter_add_int_value_to_execution_context(
     wirefilter_execution_context_t *exec_ctx,
     wirefilter_externally_allocated_str_t name,
     int32_t value
 );
 
-void wirefilter_add_bytes_value_to_execution_context(
+bool wirefilter_add_bytes_value_to_execution_context(
     wirefilter_execution_context_t *exec_ctx,
     wirefilter_externally_allocated_str_t name,
     wirefilter_externally_allocated_byte_arr_t value
 );
 
-void wirefilter_add_ipv6_value_to_execution_context(
+bool wirefilter_add_ipv6_value_to_execution_context(
     wirefilter_execution_context_t *exec_ctx,
     wirefilter_externally_allocated_str_t name,
     uint8_t value[16]
 );
 
-void wirefilter_add_ipv4_value_to_execution_context(
+bool wirefilter_add_ipv4_value_to_execution_context(
     wirefilter_execution_context_t *exec_ctx,
     wirefilter_externally_allocated_str_t name,
     uint8_t value[4]
 );
 
-void wirefilter_add_bool_value_to_execution_context(
+bool wirefilter_add_bool_value_to_execution_context(
     wirefilter_execution_context_t *exec_ctx,
     wirefilter_externally_allocated_str_t name,
     bool value
 );
 
+bool wirefilter_add_duration_value_to_execution_context(
+    wirefilter_execution_context_t *exec_ctx,
+    wirefilter_externally_allocated_str_t name,
+    uint64_t nanos
+);
+
+bool wirefilter_add_mac_value_to_execution_context(
+    wirefilter_execution_context_t *exec_ctx,
+    wirefilter_externally_allocated_str_t name,
+    uint8_t value[6]
+);
+
+bool wirefilter_add_str_value_to_execution_context(
+    wirefilter_execution_context_t *exec_ctx,
+    wirefilter_externally_allocated_str_t name,
+    wirefilter_externally_allocated_byte_arr_t value
+);
+
+// Returns false if the filter can't be executed, like when it calls a
+// panicking function or the context has another scheme. Use
+// `wirefilter_try_match` to get the error.
 bool wirefilter_match(
     const wirefilter_filter_t *filter,
     const wirefilter_execution_context_t *exec_ctx
 );
 
+wirefilter_matching_result_t wirefilter_try_match(
+    const wirefilter_filter_t *filter,
+    const wirefilter_execution_context_t *exec_ctx
+);
+
+void wirefilter_free_matching_result(wirefilter_matching_result_t result);
+
 bool wirefilter_filter_uses(
     const wirefilter_filter_ast_t *ast,
     wirefilter_externally_allocated_str_t field_name
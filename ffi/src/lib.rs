@@ -9,8 +9,12 @@ use std::{
     hash::Hasher,
     io::{self, Write},
     net::IpAddr,
+    time::Duration,
+};
+use wirefilter::{
+    stdlib, BindError, CompileError, ExecutionContext, Filter, FilterAst, LhsValue, MacAddr,
+    ParseError, Scheme, Type,
 };
-use wirefilter::{ExecutionContext, Filter, FilterAst, ParseError, Scheme, Type};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -41,6 +45,54 @@ impl<'s> ParsingResult<'s> {
     }
 }
 
+#[repr(u8)]
+pub enum SchemeResult {
+    Err(RustAllocatedString),
+    Ok(RustBox<Scheme>),
+}
+
+impl SchemeResult {
+    pub fn unwrap(self) -> RustBox<Scheme> {
+        match self {
+            SchemeResult::Err(err) => panic!("{}", &err as &str),
+            SchemeResult::Ok(scheme) => scheme,
+        }
+    }
+}
+
+#[repr(u8)]
+pub enum CompilingResult<'s> {
+    Err(RustAllocatedString),
+    Ok(RustBox<Filter<'s>>),
+}
+
+impl<'s> From<Filter<'s>> for CompilingResult<'s> {
+    fn from(filter: Filter<'s>) -> Self {
+        CompilingResult::Ok(filter.into())
+    }
+}
+
+impl<'s> From<CompileError> for CompilingResult<'s> {
+    fn from(err: CompileError) -> Self {
+        CompilingResult::Err(RustAllocatedString::from(err.to_string()))
+    }
+}
+
+impl<'s> CompilingResult<'s> {
+    pub fn unwrap(self) -> RustBox<Filter<'s>> {
+        match self {
+            CompilingResult::Err(err) => panic!("{}", &err as &str),
+            CompilingResult::Ok(filter) => filter,
+        }
+    }
+}
+
+#[repr(u8)]
+pub enum MatchingResult {
+    Err(RustAllocatedString),
+    Ok(bool),
+}
+
 #[no_mangle]
 pub extern "C" fn wirefilter_create_scheme() -> RustBox<Scheme> {
     Default::default()
@@ -56,8 +108,51 @@ pub extern "C" fn wirefilter_add_type_field_to_scheme(
     scheme: &mut Scheme,
     name: ExternallyAllocatedStr<'_>,
     ty: Type,
-) {
-    scheme.add_field(name.into_ref().to_owned(), ty).unwrap();
+) -> bool {
+    scheme.add_field(name.into_ref().to_owned(), ty).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_add_function_to_scheme(
+    scheme: &mut Scheme,
+    name: ExternallyAllocatedStr<'_>,
+) -> bool {
+    let name = name.into_ref();
+    let overloads = stdlib::functions()
+        .into_iter()
+        .filter(|(n, _)| n == name)
+        .map(|(_, function)| function)
+        .collect::<Vec<_>>();
+    !overloads.is_empty()
+        && scheme
+            .add_function_overloads(name.to_owned(), overloads)
+            .is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_add_standard_functions_to_scheme(scheme: &mut Scheme) -> bool {
+    stdlib::register(scheme).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_parse_scheme_from_json(
+    json: ExternallyAllocatedStr<'_>,
+) -> SchemeResult {
+    match serde_json::from_str::<Scheme>(json.into_ref()) {
+        Ok(scheme) => SchemeResult::Ok(scheme.into()),
+        Err(err) => SchemeResult::Err(err.to_string().into()),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_free_scheme_result(r: SchemeResult) {
+    drop(r);
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_serialize_scheme_to_json(scheme: &Scheme) -> RustAllocatedString {
+    // Scheme serialisation must never fail.
+    serde_json::to_string(scheme).unwrap().into()
 }
 
 #[no_mangle]
@@ -76,7 +171,16 @@ pub extern "C" fn wirefilter_parse_filter<'s, 'i>(
     input: ExternallyAllocatedStr<'i>,
 ) -> ParsingResult<'s> {
     match scheme.parse(input.into_ref()) {
-        Ok(filter) => ParsingResult::from(filter),
+        // Placeholders can't be bound through this API, and filters with
+        // unbound ones can't be compiled.
+        Ok(filter) => match filter.placeholders().first() {
+            Some(placeholder) => ParsingResult::Err(
+                BindError::Unbound(placeholder.name().to_owned())
+                    .to_string()
+                    .into(),
+            ),
+            None => ParsingResult::from(filter),
+        },
         Err(err) => ParsingResult::from(err),
     }
 }
@@ -143,15 +247,23 @@ pub extern "C" fn wirefilter_free_execution_context(exec_context: RustBox<Execut
     drop(exec_context);
 }
 
+fn set_field_value<'a, V: Into<LhsValue<'a>>>(
+    exec_context: &mut ExecutionContext<'a>,
+    name: ExternallyAllocatedStr<'_>,
+    value: V,
+) -> bool {
+    let name = name.into_ref();
+    exec_context.scheme().get_field(name).is_ok()
+        && exec_context.set_field_value(name, value).is_ok()
+}
+
 #[no_mangle]
 pub extern "C" fn wirefilter_add_int_value_to_execution_context<'a>(
     exec_context: &mut ExecutionContext<'a>,
     name: ExternallyAllocatedStr<'_>,
     value: i32,
-) {
-    exec_context
-        .set_field_value(name.into_ref(), value)
-        .unwrap();
+) -> bool {
+    set_field_value(exec_context, name, value)
 }
 
 #[no_mangle]
@@ -159,11 +271,9 @@ pub extern "C" fn wirefilter_add_bytes_value_to_execution_context<'a>(
     exec_context: &mut ExecutionContext<'a>,
     name: ExternallyAllocatedStr<'_>,
     value: ExternallyAllocatedByteArr<'a>,
-) {
+) -> bool {
     let slice: &[u8] = value.into_ref();
-    exec_context
-        .set_field_value(name.into_ref(), slice)
-        .unwrap();
+    set_field_value(exec_context, name, slice)
 }
 
 #[no_mangle]
@@ -171,10 +281,8 @@ pub extern "C" fn wirefilter_add_ipv6_value_to_execution_context(
     exec_context: &mut ExecutionContext<'_>,
     name: ExternallyAllocatedStr<'_>,
     value: &[u8; 16],
-) {
-    exec_context
-        .set_field_value(name.into_ref(), IpAddr::from(*value))
-        .unwrap();
+) -> bool {
+    set_field_value(exec_context, name, IpAddr::from(*value))
 }
 
 #[no_mangle]
@@ -182,10 +290,8 @@ pub extern "C" fn wirefilter_add_ipv4_value_to_execution_context(
     exec_context: &mut ExecutionContext<'_>,
     name: ExternallyAllocatedStr<'_>,
     value: &[u8; 4],
-) {
-    exec_context
-        .set_field_value(name.into_ref(), IpAddr::from(*value))
-        .unwrap();
+) -> bool {
+    set_field_value(exec_context, name, IpAddr::from(*value))
 }
 
 #[no_mangle]
@@ -193,18 +299,55 @@ pub extern "C" fn wirefilter_add_bool_value_to_execution_context(
     exec_context: &mut ExecutionContext<'_>,
     name: ExternallyAllocatedStr<'_>,
     value: bool,
-) {
-    exec_context
-        .set_field_value(name.into_ref(), value)
-        .unwrap();
+) -> bool {
+    set_field_value(exec_context, name, value)
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_add_duration_value_to_execution_context(
+    exec_context: &mut ExecutionContext<'_>,
+    name: ExternallyAllocatedStr<'_>,
+    nanos: u64,
+) -> bool {
+    set_field_value(exec_context, name, Duration::from_nanos(nanos))
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_add_mac_value_to_execution_context(
+    exec_context: &mut ExecutionContext<'_>,
+    name: ExternallyAllocatedStr<'_>,
+    value: &[u8; 6],
+) -> bool {
+    set_field_value(exec_context, name, MacAddr(*value))
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_add_str_value_to_execution_context<'a>(
+    exec_context: &mut ExecutionContext<'a>,
+    name: ExternallyAllocatedStr<'_>,
+    value: ExternallyAllocatedByteArr<'a>,
+) -> bool {
+    let slice: &[u8] = value.into_ref();
+    match LhsValue::from(slice).into_str() {
+        Ok(value) => set_field_value(exec_context, name, value),
+        Err(_) => false,
+    }
 }
 
 #[no_mangle]
 pub extern "C" fn wirefilter_compile_filter<'s>(
     filter_ast: RustBox<FilterAst<'s>>,
-) -> RustBox<Filter<'s>> {
+) -> CompilingResult<'s> {
     let filter_ast = filter_ast.into_real_box();
-    filter_ast.compile().into()
+    match filter_ast.compile() {
+        Ok(filter) => CompilingResult::from(filter),
+        Err(err) => CompilingResult::from(err),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_free_compiling_result(r: CompilingResult<'_>) {
+    drop(r);
 }
 
 #[no_mangle]
@@ -212,7 +355,25 @@ pub extern "C" fn wirefilter_match<'s>(
     filter: &Filter<'s>,
     exec_context: &ExecutionContext<'s>,
 ) -> bool {
-    filter.execute(exec_context).unwrap()
+    // Panics can't unwind across the FFI boundary, so any failure is treated
+    // as a mismatch. `wirefilter_try_match` reports the error instead.
+    filter.execute_catching(exec_context).unwrap_or(false)
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_try_match<'s>(
+    filter: &Filter<'s>,
+    exec_context: &ExecutionContext<'s>,
+) -> MatchingResult {
+    match filter.execute_catching(exec_context) {
+        Ok(value) => MatchingResult::Ok(value),
+        Err(err) => MatchingResult::Err(err.to_string().into()),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wirefilter_free_matching_result(r: MatchingResult) {
+    drop(r);
 }
 
 #[no_mangle]
@@ -274,6 +435,18 @@ mod ffi_test {
             Type::Int,
         );
 
+        wirefilter_add_type_field_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("latency"),
+            Type::Duration,
+        );
+
+        wirefilter_add_type_field_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("mac"),
+            Type::Mac,
+        );
+
         scheme
     }
 
@@ -316,6 +489,18 @@ mod ffi_test {
             1337,
         );
 
+        wirefilter_add_duration_value_to_execution_context(
+            &mut exec_context,
+            ExternallyAllocatedStr::from("latency"),
+            250_000_000,
+        );
+
+        wirefilter_add_mac_value_to_execution_context(
+            &mut exec_context,
+            ExternallyAllocatedStr::from("mac"),
+            &[0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e],
+        );
+
         exec_context
     }
 
@@ -329,7 +514,7 @@ mod ffi_test {
         exec_context: &ExecutionContext<'_>,
     ) -> bool {
         let filter = parse_filter(scheme, input).unwrap();
-        let filter = wirefilter_compile_filter(filter);
+        let filter = wirefilter_compile_filter(filter).unwrap();
 
         let result = wirefilter_match(&filter, exec_context);
 
@@ -375,6 +560,16 @@ mod ffi_test {
             }
         }
 
+        {
+            match parse_filter(&scheme, "num1 > ${min:Int}") {
+                ParsingResult::Ok(_) => panic!("Error expected"),
+                ParsingResult::Err(err) => {
+                    assert_eq!(&err as &str, "placeholder min is not bound");
+                    wirefilter_free_string(err);
+                }
+            }
+        }
+
         wirefilter_free_scheme(scheme);
     }
 
@@ -425,6 +620,18 @@ mod ffi_test {
                 &exec_context
             ));
 
+            assert!(match_filter(
+                "latency > 200ms && latency in { 0s..1s }",
+                &scheme,
+                &exec_context
+            ));
+
+            assert!(match_filter(
+                "mac in { 00:1a:2b::/24 } && mac != 00:1a:2b:3c:4d:5f",
+                &scheme,
+                &exec_context
+            ));
+
             wirefilter_free_execution_context(exec_context);
         }
 
@@ -514,4 +721,191 @@ mod ffi_test {
 
         wirefilter_free_scheme(scheme);
     }
+
+    #[test]
+    fn invalid_values() {
+        let mut scheme = create_scheme();
+
+        assert!(!wirefilter_add_type_field_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("num1"),
+            Type::Bytes,
+        ));
+
+        {
+            let mut exec_context = wirefilter_create_execution_context(&scheme);
+
+            assert!(wirefilter_add_int_value_to_execution_context(
+                &mut exec_context,
+                ExternallyAllocatedStr::from("num1"),
+                42,
+            ));
+
+            assert!(!wirefilter_add_int_value_to_execution_context(
+                &mut exec_context,
+                ExternallyAllocatedStr::from("str1"),
+                42,
+            ));
+
+            assert!(!wirefilter_add_int_value_to_execution_context(
+                &mut exec_context,
+                ExternallyAllocatedStr::from("unknown"),
+                42,
+            ));
+
+            wirefilter_free_execution_context(exec_context);
+        }
+
+        wirefilter_free_scheme(scheme);
+    }
+
+    #[test]
+    fn functions() {
+        let mut scheme = create_scheme();
+
+        assert!(wirefilter_add_function_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("lower"),
+        ));
+
+        assert!(!wirefilter_add_function_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("lower"),
+        ));
+
+        assert!(!wirefilter_add_function_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("unknown"),
+        ));
+
+        {
+            let exec_context = create_execution_context(&scheme);
+
+            assert!(match_filter(
+                r#"lower(str1) == "hey""#,
+                &scheme,
+                &exec_context
+            ));
+
+            wirefilter_free_execution_context(exec_context);
+        }
+
+        wirefilter_free_scheme(scheme);
+
+        let mut scheme = wirefilter_create_scheme();
+
+        assert!(wirefilter_add_standard_functions_to_scheme(&mut scheme));
+
+        assert!(!wirefilter_add_standard_functions_to_scheme(&mut scheme));
+
+        wirefilter_free_scheme(scheme);
+
+        // overloads of a function are either all registered or none is
+        let mut scheme = create_scheme();
+        let (name, function) = stdlib::functions()
+            .into_iter()
+            .find(|(name, function)| name == "upper" && function.params[0].val_type == Type::Str)
+            .unwrap();
+        scheme.add_function_overload(name, function).unwrap();
+
+        assert!(!wirefilter_add_function_to_scheme(
+            &mut scheme,
+            ExternallyAllocatedStr::from("upper"),
+        ));
+
+        assert!(scheme.parse(r#"upper(str1) == "HEY""#).is_err());
+
+        wirefilter_free_scheme(scheme);
+    }
+
+    #[test]
+    fn scheme_json() {
+        let scheme = create_scheme();
+
+        {
+            let json = wirefilter_serialize_scheme_to_json(&scheme);
+
+            let parsed =
+                wirefilter_parse_scheme_from_json(ExternallyAllocatedStr::from(&json as &str))
+                    .unwrap();
+
+            let parsed_json = wirefilter_serialize_scheme_to_json(&parsed);
+
+            assert_eq!(&parsed_json as &str, &json as &str);
+
+            wirefilter_free_string(parsed_json);
+
+            wirefilter_free_scheme(parsed);
+
+            wirefilter_free_string(json);
+        }
+
+        match wirefilter_parse_scheme_from_json(ExternallyAllocatedStr::from(r#"{"a":"Float"}"#)) {
+            SchemeResult::Ok(_) => panic!("Error expected"),
+            SchemeResult::Err(err) => wirefilter_free_string(err),
+        }
+
+        wirefilter_free_scheme(scheme);
+    }
+
+    #[test]
+    fn try_match() {
+        let scheme = create_scheme();
+        let other_scheme = create_scheme();
+
+        {
+            let exec_context = create_execution_context(&scheme);
+            let other_exec_context = create_execution_context(&other_scheme);
+
+            let filter = parse_filter(&scheme, "num1 == 42").unwrap();
+            let filter = wirefilter_compile_filter(filter).unwrap();
+
+            match wirefilter_try_match(&filter, &exec_context) {
+                MatchingResult::Ok(value) => assert!(value),
+                MatchingResult::Err(err) => panic!("{}", &err as &str),
+            }
+
+            match wirefilter_try_match(&filter, &other_exec_context) {
+                MatchingResult::Ok(_) => panic!("Error expected"),
+                MatchingResult::Err(err) => {
+                    assert_eq!(
+                        &err as &str,
+                        "execution context doesn't match the scheme with which filter was parsed"
+                    );
+                    wirefilter_free_string(err);
+                }
+            }
+
+            assert!(!wirefilter_match(&filter, &other_exec_context));
+
+            wirefilter_free_compiled_filter(filter);
+            wirefilter_free_execution_context(exec_context);
+            wirefilter_free_execution_context(other_exec_context);
+        }
+
+        wirefilter_free_scheme(scheme);
+        wirefilter_free_scheme(other_scheme);
+    }
+
+    #[test]
+    fn deserialized_functions() {
+        let scheme = wirefilter_parse_scheme_from_json(ExternallyAllocatedStr::from(
+            r#"{"fields":{},"functions":{"f":{"params":[],"return_type":"Bool"}}}"#,
+        ))
+        .unwrap();
+
+        {
+            let filter = parse_filter(&scheme, "f()").unwrap();
+
+            match wirefilter_compile_filter(filter) {
+                CompilingResult::Ok(_) => panic!("Error expected"),
+                CompilingResult::Err(err) => {
+                    assert_eq!(&err as &str, "function f can't be executed");
+                    wirefilter_free_string(err);
+                }
+            }
+        }
+
+        wirefilter_free_scheme(scheme);
+    }
 }
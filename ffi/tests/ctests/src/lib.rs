@@ -31,6 +31,8 @@ mod ffi_ctest {
     ffi_ctest!(
         create_scheme,
         add_fields_to_scheme,
+        add_functions_to_scheme,
+        scheme_json,
         parse_good_filter,
         parse_bad_filter,
         filter_uses_field,
@@ -40,5 +42,6 @@ mod ffi_ctest {
         create_execution_context,
         add_values_to_execution_context,
         match_filter,
+        try_match_filter,
     );
 }
@@ -51,6 +51,63 @@ void wirefilter_ffi_ctest_add_fields_to_scheme() {
     wirefilter_free_scheme(scheme);
 }
 
+void wirefilter_ffi_ctest_add_functions_to_scheme() {
+    wirefilter_scheme_t *scheme = wirefilter_create_scheme();
+    rust_assert(scheme != NULL, "could not create scheme");
+
+    initialize_scheme(scheme);
+
+    rust_assert(
+        wirefilter_add_function_to_scheme(scheme, wirefilter_string("lower")) == true,
+        "could not add function lower"
+    );
+
+    rust_assert(
+        wirefilter_add_function_to_scheme(scheme, wirefilter_string("unknown")) == false,
+        "should not add unknown function"
+    );
+
+    wirefilter_parsing_result_t result = wirefilter_parse_filter(
+        scheme,
+        wirefilter_string("lower(http.host) == \"www.cloudflare.com\"")
+    );
+    rust_assert(result.success == 1, "could not parse filter with function");
+
+    wirefilter_free_parsing_result(result);
+
+    wirefilter_free_scheme(scheme);
+}
+
+void wirefilter_ffi_ctest_scheme_json() {
+    wirefilter_scheme_t *scheme = wirefilter_create_scheme();
+    rust_assert(scheme != NULL, "could not create scheme");
+
+    initialize_scheme(scheme);
+
+    wirefilter_rust_allocated_str_t json = wirefilter_serialize_scheme_to_json(scheme);
+    rust_assert(json.data != NULL && json.length > 0, "could not serialize scheme to JSON");
+
+    wirefilter_externally_allocated_str_t input;
+    input.data = json.data;
+    input.length = json.length;
+
+    wirefilter_scheme_result_t result = wirefilter_parse_scheme_from_json(input);
+    rust_assert(result.success == 1, "could not parse scheme from JSON");
+    rust_assert(result.ok.scheme != NULL, "could not parse scheme from JSON");
+
+    wirefilter_free_scheme_result(result);
+
+    wirefilter_free_string(json);
+
+    result = wirefilter_parse_scheme_from_json(wirefilter_string("{\"a\":\"Float\"}"));
+    rust_assert(result.success == 0, "should not parse bad scheme");
+    rust_assert(result.err.msg.data && result.err.msg.length > 0, "missing error message");
+
+    wirefilter_free_scheme_result(result);
+
+    wirefilter_free_scheme(scheme);
+}
+
 void wirefilter_ffi_ctest_parse_good_filter() {
     wirefilter_scheme_t *scheme = wirefilter_create_scheme();
     rust_assert(scheme != NULL, "could not create scheme");
@@ -192,8 +249,11 @@ void wirefilter_ffi_ctest_compile_filter() {
     rust_assert(result.success == true, "could not parse good filter");
     rust_assert(result.ok.ast != NULL, "could not parse good filter");
 
-    wirefilter_filter_t *filter = wirefilter_compile_filter(result.ok.ast);
-    rust_assert(filter != NULL, "could not compile filter");
+    wirefilter_compiling_result_t compiled = wirefilter_compile_filter(result.ok.ast);
+    rust_assert(compiled.success == 1, "could not compile filter");
+    rust_assert(compiled.ok.filter != NULL, "could not compile filter");
+
+    wirefilter_filter_t *filter = compiled.ok.filter;
 
     wirefilter_free_compiled_filter(filter);
 
@@ -267,8 +327,11 @@ void wirefilter_ffi_ctest_match_filter() {
     rust_assert(result.success == true, "could not parse good filter");
     rust_assert(result.ok.ast != NULL, "could not parse good filter");
 
-    wirefilter_filter_t *filter = wirefilter_compile_filter(result.ok.ast);
-    rust_assert(filter != NULL, "could not compile filter");
+    wirefilter_compiling_result_t compiled = wirefilter_compile_filter(result.ok.ast);
+    rust_assert(compiled.success == 1, "could not compile filter");
+    rust_assert(compiled.ok.filter != NULL, "could not compile filter");
+
+    wirefilter_filter_t *filter = compiled.ok.filter;
 
     wirefilter_execution_context_t *exec_ctx = wirefilter_create_execution_context(scheme);
     rust_assert(exec_ctx != NULL, "could not create execution context");
@@ -309,3 +372,75 @@ void wirefilter_ffi_ctest_match_filter() {
 
     wirefilter_free_scheme(scheme);
 }
+
+void wirefilter_ffi_ctest_try_match_filter() {
+    wirefilter_scheme_t *scheme = wirefilter_create_scheme();
+    rust_assert(scheme != NULL, "could not create scheme");
+
+    initialize_scheme(scheme);
+
+    wirefilter_parsing_result_t result = wirefilter_parse_filter(
+        scheme,
+        wirefilter_string("tcp.port == 80")
+    );
+    rust_assert(result.success == true, "could not parse good filter");
+    rust_assert(result.ok.ast != NULL, "could not parse good filter");
+
+    wirefilter_compiling_result_t compiled = wirefilter_compile_filter(result.ok.ast);
+    rust_assert(compiled.success == 1, "could not compile filter");
+    rust_assert(compiled.ok.filter != NULL, "could not compile filter");
+
+    wirefilter_filter_t *filter = compiled.ok.filter;
+
+    wirefilter_execution_context_t *exec_ctx = wirefilter_create_execution_context(scheme);
+    rust_assert(exec_ctx != NULL, "could not create execution context");
+
+    rust_assert(
+        wirefilter_add_int_value_to_execution_context(
+            exec_ctx,
+            wirefilter_string("tcp.port"),
+            80
+        ) == true,
+        "could not set value of tcp.port"
+    );
+
+    rust_assert(
+        wirefilter_add_int_value_to_execution_context(
+            exec_ctx,
+            wirefilter_string("http.host"),
+            80
+        ) == false,
+        "should not set value of wrong type"
+    );
+
+    wirefilter_matching_result_t matching_result = wirefilter_try_match(filter, exec_ctx);
+    rust_assert(matching_result.success == 1, "could not match filter");
+    rust_assert(matching_result.ok.value == true, "filter should match");
+
+    wirefilter_free_matching_result(matching_result);
+
+    wirefilter_free_execution_context(exec_ctx);
+
+    wirefilter_scheme_t *other_scheme = wirefilter_create_scheme();
+    rust_assert(other_scheme != NULL, "could not create scheme");
+
+    exec_ctx = wirefilter_create_execution_context(other_scheme);
+    rust_assert(exec_ctx != NULL, "could not create execution context");
+
+    matching_result = wirefilter_try_match(filter, exec_ctx);
+    rust_assert(matching_result.success == 0, "should not match with context of other scheme");
+    rust_assert(
+        matching_result.err.msg.data && matching_result.err.msg.length > 0,
+        "missing error message"
+    );
+
+    wirefilter_free_matching_result(matching_result);
+
+    wirefilter_free_execution_context(exec_ctx);
+
+    wirefilter_free_scheme(other_scheme);
+
+    wirefilter_free_compiled_filter(filter);
+
+    wirefilter_free_scheme(scheme);
+}
@@ -0,0 +1,110 @@
+//! A language server for Wirefilter filters, communicating over stdio.
+//!
+//! It's started with the path to a serialized scheme, like
+//! `wirefilter-lsp scheme.json`, and treats each open document as a filter.
+
+mod server;
+
+use crate::server::Server;
+use serde_json::Value;
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    process,
+};
+use wirefilter::Scheme;
+
+fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// Reads a message framed with a `Content-Length` header, or returns `None` at
+// the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    const CONTENT_LENGTH: &str = "Content-Length:";
+
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(CONTENT_LENGTH) {
+            length = Some(
+                line[CONTENT_LENGTH.len()..]
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(invalid_data)?,
+            );
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(invalid_data)
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_string(message).map_err(invalid_data)?;
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn serve(
+    server: &mut Server<'_>,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    while let Some(message) = read_message(input)? {
+        for reply in server.handle(&message) {
+            write_message(output, &reply)?;
+        }
+        if server.exit_code().is_some() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn load_scheme(path: &str) -> Result<Scheme, String> {
+    let json = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    serde_json::from_str(&json).map_err(|err| err.to_string())
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: wirefilter-lsp <scheme.json>");
+            process::exit(2);
+        }
+    };
+
+    let scheme = load_scheme(&path).unwrap_or_else(|err| {
+        eprintln!("failed to load scheme from {}: {}", path, err);
+        process::exit(2);
+    });
+
+    let mut server = Server::new(&scheme);
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+
+    if let Err(err) = serve(&mut server, &mut input, &mut output) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+
+    // Exiting without a `shutdown` request first is an error.
+    process::exit(server.exit_code().unwrap_or(1));
+}
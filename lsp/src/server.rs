@@ -0,0 +1,467 @@
+use serde_json::{json, Value};
+use std::{collections::HashMap, ops::Range};
+use wirefilter::{CompletionKind, GetType, Metadata, Scheme};
+
+const ERROR: u8 = 1;
+const WARNING: u8 = 2;
+
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// State of the language server: the scheme filters are checked against and
+/// the open documents.
+pub struct Server<'s> {
+    scheme: &'s Scheme,
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    exit_code: Option<i32>,
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+// Converts a position with a character offset in UTF-16 code units, as used
+// by the protocol, to a byte offset in the text.
+fn offset_at(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let line_start = text
+        .split('\n')
+        .take(line)
+        .map(|line| line.len() + 1)
+        .sum::<usize>()
+        .min(text.len());
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= character {
+            return line_start + offset;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn position_at(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, span: Range<usize>) -> Value {
+    json!({
+        "start": position_at(text, span.start),
+        "end": position_at(text, span.end),
+    })
+}
+
+fn diagnostic(text: &str, span: Range<usize>, severity: u8, message: String, code: &str) -> Value {
+    json!({
+        "range": range(text, span),
+        "severity": severity,
+        "code": code,
+        "source": "wirefilter",
+        "message": message,
+    })
+}
+
+fn completion_item_kind(kind: CompletionKind) -> u8 {
+    match kind {
+        CompletionKind::Field => 5,
+        CompletionKind::Function => 3,
+        CompletionKind::List => 6,
+        CompletionKind::Constant => 21,
+        CompletionKind::ComparisonOperator => 24,
+        CompletionKind::LogicalOperator => 14,
+        CompletionKind::Punctuation => 1,
+    }
+}
+
+// Renders metadata of a field or a function in Markdown.
+fn describe(metadata: &Metadata) -> String {
+    let mut out = String::new();
+    if let Some(deprecated) = &metadata.deprecated {
+        out.push_str(&format!("\n\n**Deprecated**: {}", deprecated));
+    }
+    if let Some(description) = &metadata.description {
+        out.push_str(&format!("\n\n{}", description));
+    }
+    if !metadata.examples.is_empty() {
+        out.push_str("\n\nExamples:");
+        for example in &metadata.examples {
+            out.push_str(&format!("\n- `{}`", example));
+        }
+    }
+    if let Some(category) = &metadata.category {
+        out.push_str(&format!("\n\nCategory: {}", category));
+    }
+    out
+}
+
+impl<'s> Server<'s> {
+    /// Creates a server checking filters against the given scheme.
+    pub fn new(scheme: &'s Scheme) -> Self {
+        Server {
+            scheme,
+            documents: HashMap::new(),
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// Returns the exit code once an `exit` notification was received.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handles a request or a notification from the client, and returns
+    /// responses and notifications to send back.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // responses to our requests, which we don't send
+            None => return Vec::new(),
+        };
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": [" ", "$", "(", "."] },
+                },
+                "serverInfo": {
+                    "name": "wirefilter-lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                return Vec::new();
+            }
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                return self.update(&document["uri"], &document["text"]);
+            }
+            "textDocument/didChange" => {
+                // documents are always synced in full
+                let text = match params["contentChanges"].as_array() {
+                    Some(changes) => changes
+                        .last()
+                        .map_or(&Value::Null, |change| &change["text"]),
+                    None => &Value::Null,
+                };
+                return self.update(&params["textDocument"]["uri"], text);
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )];
+            }
+            "textDocument/completion" => self.completion(params),
+            "textDocument/hover" => self.hover(params),
+            _ => {
+                return match id {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("unsupported method {}", method),
+                        },
+                    })],
+                    // unsupported notifications are ignored
+                    None => Vec::new(),
+                };
+            }
+        };
+
+        match id {
+            Some(id) => vec![response(id, result)],
+            None => Vec::new(),
+        }
+    }
+
+    fn document(&self, params: &Value) -> Option<&str> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri).map(String::as_str)
+    }
+
+    // Stores the new text of a document and publishes its diagnostics.
+    fn update(&mut self, uri: &Value, text: &Value) -> Vec<Value> {
+        let (uri, text) = match (uri.as_str(), text.as_str()) {
+            (Some(uri), Some(text)) => (uri, text),
+            _ => return Vec::new(),
+        };
+        self.documents.insert(uri.to_owned(), text.to_owned());
+        vec![notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": self.diagnostics(text) }),
+        )]
+    }
+
+    fn diagnostics(&self, text: &str) -> Vec<Value> {
+        let warnings = match self.scheme.parse_with_warnings(text) {
+            Ok((_, warnings)) => warnings,
+            Err(err) => {
+                return vec![diagnostic(
+                    text,
+                    err.span(),
+                    ERROR,
+                    err.message(),
+                    "parse-error",
+                )];
+            }
+        };
+
+        let mut diagnostics = warnings
+            .iter()
+            .map(|warning| {
+                diagnostic(
+                    text,
+                    warning.span(),
+                    WARNING,
+                    warning.message(),
+                    warning.code(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        if let Ok((ast, spans)) = self.scheme.parse_with_spans(text) {
+            for lint in ast.lint() {
+                for span in lint.spans(&spans) {
+                    diagnostics.push(diagnostic(
+                        text,
+                        span,
+                        WARNING,
+                        lint.to_string(),
+                        lint.code(),
+                    ));
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    fn metadata(&self, name: &str, kind: CompletionKind) -> Option<&'s Metadata> {
+        match kind {
+            CompletionKind::Field => self.scheme.get_field(name).ok()?.metadata(),
+            CompletionKind::Function => self.scheme.function_metadata(name).ok()?,
+            _ => None,
+        }
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let text = match self.document(params) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+
+        let completions = self
+            .scheme
+            .complete(text, offset_at(text, &params["position"]));
+        let range = range(text, completions.span);
+
+        let items = completions
+            .items
+            .into_iter()
+            .map(|item| {
+                let metadata = self.metadata(&item.text, item.kind);
+                let detail = match item.kind {
+                    CompletionKind::Field => self
+                        .scheme
+                        .get_field(&item.text)
+                        .ok()
+                        .map(|field| format!("{:?}", field.get_type())),
+                    CompletionKind::List => self
+                        .scheme
+                        .get_list(&item.text[1..])
+                        .ok()
+                        .map(|list| format!("list of {:?}", list.get_type())),
+                    CompletionKind::Constant => self
+                        .scheme
+                        .get_constant(&item.text)
+                        .ok()
+                        .map(|values| format!("set of {:?}", values.get_type())),
+                    _ => None,
+                };
+                json!({
+                    "label": item.text,
+                    "kind": completion_item_kind(item.kind),
+                    "detail": detail,
+                    "documentation": metadata.and_then(|metadata| metadata.description.clone()),
+                    "deprecated": metadata.map_or(false, |metadata| metadata.deprecated.is_some()),
+                    "textEdit": { "range": range, "newText": item.text },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!(items)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let text = match self.document(params) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+        let offset = offset_at(text, &params["position"]);
+
+        let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+        let start = text[..offset].trim_end_matches(is_name).len();
+        let end = text[offset..]
+            .find(|c: char| !is_name(c))
+            .map_or(text.len(), |len| offset + len);
+        let name = &text[start..end];
+
+        let contents = if text[..start].ends_with('$') {
+            match self.scheme.get_list(name) {
+                Ok(list) => format!("`${}`: list of {:?}", name, list.get_type()),
+                Err(_) => return Value::Null,
+            }
+        } else if let Ok(field) = self.scheme.get_field(name) {
+            let mut contents = format!("`{}`: {:?}", name, field.get_type());
+            if let Some(metadata) = field.metadata() {
+                contents.push_str(&describe(metadata));
+            }
+            contents
+        } else if let Ok(metadata) = self.scheme.function_metadata(name) {
+            let mut contents = format!("`{}()`", name);
+            if let Some(metadata) = metadata {
+                contents.push_str(&describe(metadata));
+            }
+            contents
+        } else if let Ok(values) = self.scheme.get_constant(name) {
+            format!("`{}`: set of {:?}", name, values.get_type())
+        } else {
+            return Value::Null;
+        };
+
+        json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": range(text, start..end),
+        })
+    }
+}
+
+#[test]
+fn test_positions() {
+    let text = "a == \"é\"\n  or 😀b";
+
+    let offset = |line: u64, character: u64| {
+        offset_at(text, &json!({ "line": line, "character": character }))
+    };
+    assert_eq!(offset(0, 0), 0);
+    assert_eq!(offset(0, 7), 8);
+    assert_eq!(offset(0, 100), 9);
+    assert_eq!(offset(1, 5), 15);
+    // `b` after a character taking two UTF-16 code units
+    assert_eq!(offset(1, 7), 19);
+    assert_eq!(offset(5, 0), text.len());
+
+    assert_eq!(position_at(text, 8), json!({ "line": 0, "character": 7 }));
+    assert_eq!(position_at(text, 19), json!({ "line": 1, "character": 7 }));
+}
+
+#[test]
+fn test_server() {
+    let mut scheme: Scheme = serde_json::from_value(json!({
+        "fields": { "http.host": "Bytes", "tcp.port": "Int" },
+        "lists": { "hosts": "Bytes" },
+    }))
+    .unwrap();
+    scheme
+        .set_field_metadata(
+            "http.host",
+            Metadata {
+                description: Some("Host header".into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    let mut server = Server::new(&scheme);
+
+    let replies =
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {
+            "textDocument": { "uri": "file:///rule.wf", "languageId": "wirefilter", "version": 1, "text": "tcp.port == 80 or http.host ==" },
+        },
+    }));
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+    let diagnostic = &replies[0]["params"]["diagnostics"][0];
+    assert_eq!(diagnostic["severity"], ERROR);
+    assert_eq!(
+        diagnostic["range"],
+        json!({
+            "start": { "line": 0, "character": 30 },
+            "end": { "line": 0, "character": 30 },
+        })
+    );
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "textDocument/completion",
+        "params": {
+            "textDocument": { "uri": "file:///rule.wf" },
+            "position": { "line": 0, "character": 21 },
+        },
+    }));
+    assert_eq!(
+        replies[0]["result"][0]["textEdit"],
+        json!({
+            "range": {
+                "start": { "line": 0, "character": 18 },
+                "end": { "line": 0, "character": 21 },
+            },
+            "newText": "http.host",
+        })
+    );
+    assert_eq!(replies[0]["result"][0]["documentation"], "Host header");
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "textDocument/hover",
+        "params": {
+            "textDocument": { "uri": "file:///rule.wf" },
+            "position": { "line": 0, "character": 2 },
+        },
+    }));
+    assert_eq!(replies[0]["result"]["contents"]["value"], "`tcp.port`: Int");
+
+    let replies = server
+        .handle(&json!({ "jsonrpc": "2.0", "id": 4, "method": "workspace/symbol", "params": {} }));
+    assert_eq!(replies[0]["error"]["code"], METHOD_NOT_FOUND);
+
+    server.handle(&json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" }));
+    assert_eq!(server.exit_code(), None);
+    server.handle(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+    assert_eq!(server.exit_code(), Some(0));
+}
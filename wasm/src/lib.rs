@@ -1,15 +1,53 @@
+use serde::Serialize;
+use std::ops::Range;
 use wasm_bindgen::prelude::*;
+use wirefilter::{ExecutionContext, ParseError, ParseWarning};
 
 #[wasm_bindgen]
 pub struct Scheme(wirefilter::Scheme);
 
 #[allow(clippy::needless_pass_by_value)]
-fn into_js_error(err: impl std::error::Error) -> JsValue {
+fn into_js_error(err: impl std::fmt::Display) -> JsValue {
     js_sys::Error::new(&err.to_string()).into()
 }
 
+/// A parsing error or warning with a span in UTF-16 code units, as used by
+/// JavaScript strings.
+#[derive(Serialize)]
+struct Diagnostic {
+    message: String,
+    span: Range<usize>,
+}
+
+impl Diagnostic {
+    fn new(input: &str, message: String, span: Range<usize>) -> Self {
+        let utf16_offset = |offset: usize| input[..offset].encode_utf16().count();
+
+        Diagnostic {
+            message,
+            span: utf16_offset(span.start)..utf16_offset(span.end),
+        }
+    }
+
+    fn from_error(input: &str, err: &ParseError<'_>) -> Self {
+        Diagnostic::new(input, err.message(), err.span())
+    }
+
+    fn from_warning(input: &str, warning: &ParseWarning<'_>) -> Self {
+        Diagnostic::new(input, warning.message(), warning.span())
+    }
+}
+
+#[derive(Serialize)]
+struct Validation {
+    error: Option<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+}
+
 #[wasm_bindgen]
 impl Scheme {
+    /// Creates a scheme from its serialized form, or from a plain map of field
+    /// names to types.
     #[wasm_bindgen(constructor)]
     pub fn try_from(fields: &JsValue) -> Result<Scheme, JsValue> {
         fields.into_serde().map(Scheme).map_err(into_js_error)
@@ -19,4 +57,44 @@ impl Scheme {
         let filter = self.0.parse(s).map_err(into_js_error)?;
         JsValue::from_serde(&filter).map_err(into_js_error)
     }
+
+    /// Checks a filter and returns an object with an `error` (or `null` if the
+    /// filter is valid) and a list of `warnings`, each with a `message` and a
+    /// `span` with `start` and `end` offsets in the input string.
+    pub fn validate(&self, s: &str) -> Result<JsValue, JsValue> {
+        let validation = match self.0.parse_with_warnings(s) {
+            Ok((_, warnings)) => Validation {
+                error: None,
+                warnings: warnings
+                    .iter()
+                    .map(|warning| Diagnostic::from_warning(s, warning))
+                    .collect(),
+            },
+            Err(err) => Validation {
+                error: Some(Diagnostic::from_error(s, &err)),
+                warnings: Vec::new(),
+            },
+        };
+        JsValue::from_serde(&validation).map_err(into_js_error)
+    }
+
+    /// Executes a filter against a context given as an object with field
+    /// values, as accepted by `ExecutionContext::set_from_json`.
+    ///
+    /// Functions of deserialized schemes can't be executed, so filters that
+    /// call them will fail.
+    pub fn execute(&self, s: &str, context: &JsValue) -> Result<bool, JsValue> {
+        let filter = self
+            .0
+            .parse(s)
+            .map_err(into_js_error)?
+            .compile()
+            .map_err(into_js_error)?;
+
+        let document: serde_json::Value = context.into_serde().map_err(into_js_error)?;
+        let mut ctx = ExecutionContext::new(&self.0);
+        ctx.set_from_json(&document).map_err(into_js_error)?;
+
+        filter.execute_catching(&ctx).map_err(into_js_error)
+    }
 }